@@ -0,0 +1,71 @@
+//! Compares the adaptive download loop with the previous fixed 8KB loop.
+//!
+//! Run with `cargo bench --bench download`.
+
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+/// Size of the served test payload.
+const PAYLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Number of timed runs per variant.
+const RUNS: u32 = 5;
+
+/// The download loop as it was before adaptive chunk sizing.
+fn fixed_8k_download(client: &ureq::Agent, url: &str) -> Vec<u8> {
+    let response = client.get(url).call().unwrap();
+    let mut reader = response.into_body().into_reader();
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let bytes_read = reader.read(&mut chunk).unwrap();
+        if bytes_read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..bytes_read]);
+    }
+    buffer
+}
+
+/// Average wall time of `RUNS` runs of `f`.
+fn time(mut f: impl FnMut() -> usize) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        assert_eq!(f(), PAYLOAD_SIZE);
+        total += started.elapsed();
+    }
+    total / RUNS
+}
+
+fn main() {
+    let mut server = mockito::Server::new();
+    let _mock = server
+        .mock("GET", "/payload")
+        .with_status(200)
+        .with_body(vec![0xA5u8; PAYLOAD_SIZE])
+        .create();
+    let url = format!("{}/payload", server.url());
+    let client = ureq::Agent::new_with_defaults();
+
+    let old = time(|| fixed_8k_download(&client, &url).len());
+    let new = time(|| {
+        opencode_updater::download_with_stats(&client, &url, "payload")
+            .unwrap()
+            .0
+            .len()
+    });
+
+    let mib = PAYLOAD_SIZE as f64 / (1024.0 * 1024.0);
+    println!(
+        "fixed 8KB chunks: {:>8.1?} ({:.1} MiB/s)",
+        old,
+        mib / old.as_secs_f64()
+    );
+    println!(
+        "adaptive chunks:  {:>8.1?} ({:.1} MiB/s)",
+        new,
+        mib / new.as_secs_f64()
+    );
+    println!("speedup: {:.2}x", old.as_secs_f64() / new.as_secs_f64());
+}
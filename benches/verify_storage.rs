@@ -0,0 +1,57 @@
+//! Compares sequential and parallel `--verify-storage` hashing.
+//!
+//! Run with `cargo bench --bench verify_storage`.
+
+use opencode_updater::{VersionInfo, VersionManager};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Number of stored versions.
+const VERSIONS: usize = 12;
+
+/// Size of each stored binary.
+const BINARY_SIZE: usize = 32 * 1024 * 1024;
+
+/// Number of timed runs per variant.
+const RUNS: u32 = 3;
+
+/// Average wall time of `RUNS` verifications with `jobs` threads.
+fn time(vm: &VersionManager, jobs: usize) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        assert_eq!(vm.verify_storage_with_jobs(jobs).unwrap().len(), VERSIONS);
+        total += started.elapsed();
+    }
+    total / RUNS
+}
+
+fn main() {
+    let storage = tempfile::tempdir().unwrap();
+    let vm = VersionManager::with_storage_dir(storage.path().to_path_buf()).unwrap();
+    let source_dir = tempfile::tempdir().unwrap();
+    for i in 0..VERSIONS {
+        let binary = source_dir.path().join("opencode");
+        std::fs::write(&binary, vec![i as u8; BINARY_SIZE]).unwrap();
+        let info = VersionInfo {
+            version: format!("1.0.{}", i),
+            tag_name: format!("v1.0.{}", i),
+            release_date: chrono::Utc::now(),
+            installed_at: chrono::Utc::now(),
+            install_path: PathBuf::from("/usr/bin/opencode"),
+            ..Default::default()
+        };
+        vm.save_version(&info, &binary).unwrap();
+    }
+
+    let sequential = time(&vm, 1);
+    let jobs = opencode_updater::default_jobs();
+    let parallel = time(&vm, jobs);
+
+    println!("1 job:    {:>8.1?}", sequential);
+    println!("{} jobs:   {:>8.1?}", jobs, parallel);
+    println!(
+        "speedup: {:.2}x",
+        sequential.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
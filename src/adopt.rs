@@ -0,0 +1,291 @@
+//! `adopt`: bring an opencode installed some other way under management.
+//!
+//! The binary is identified like any install target and hashed. A stored
+//! version with the same hash is simply made current. Otherwise the hash is
+//! looked up among the most recent releases: first in the `digest` GitHub
+//! reports for each asset, then in their SHA-256 checksum files. A match
+//! gives the release's own version, date and notes. Without one, the
+//! binary's `--version` output is taken at its word and the stored version
+//! is marked unverified; when that version is already stored with another
+//! binary, e.g. a patched build copied over it by hand, the build is kept as
+//! a local version beside it instead. The installed file itself is only ever
+//! read.
+
+use crate::{
+    BinaryIdentity, ChecksumAlgorithm, UpdaterError, VersionInfo, VersionManager, hash_file,
+    sanitize_terminal_text,
+};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Releases, newest first, whose checksum files are searched for a match.
+pub const RECENT_RELEASES: usize = 10;
+
+/// How the version of an adopted binary was established.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Provenance {
+    /// Its hash matches one published with the release.
+    Release,
+    /// Only its `--version` output vouches for it.
+    Unverified,
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Provenance::Release => "matched a published checksum",
+            Provenance::Unverified => "unverified",
+        })
+    }
+}
+
+/// A release that published a binary's hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseMatch {
+    /// The release as returned by the GitHub API.
+    pub release: serde_json::Value,
+    /// Asset with the hash, or the checksum file listing it under a name
+    /// the release has no asset for.
+    pub source: String,
+}
+
+impl ReleaseMatch {
+    /// The stored version the match describes.
+    pub fn version_info(&self, binary: &Path, sha256: &str) -> VersionInfo {
+        let tag = self.release["tag_name"].as_str().unwrap_or("unknown");
+        VersionInfo {
+            version: tag.trim_start_matches('v').to_string(),
+            tag_name: tag.to_string(),
+            release_date: self.release["published_at"]
+                .as_str()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map_or_else(Utc::now, |date| date.with_timezone(&Utc)),
+            download_url: crate::find_asset(self.assets(), &self.source)
+                .and_then(|asset| asset["browser_download_url"].as_str())
+                .unwrap_or_default()
+                .to_string(),
+            checksum: sha256.to_string(),
+            installed_at: Utc::now(),
+            install_path: binary.to_path_buf(),
+            release_notes: self.release["body"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            verified_algorithms: vec![ChecksumAlgorithm::Sha256],
+            provenance: Some(Provenance::Release),
+            channel: crate::Channel::of_release(&self.release),
+            ..Default::default()
+        }
+    }
+
+    fn assets(&self) -> &[serde_json::Value] {
+        self.release["assets"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice)
+    }
+}
+
+/// Release assets whose `digest` is `sha256`.
+pub fn match_asset_digest(releases: &[serde_json::Value], sha256: &str) -> Option<ReleaseMatch> {
+    let digest = format!("sha256:{}", sha256.to_lowercase());
+    releases.iter().find_map(|release| {
+        let assets = release["assets"].as_array()?;
+        let asset = assets.iter().find(|asset| {
+            asset["digest"].as_str().map(str::to_lowercase) == Some(digest.clone())
+        })?;
+        Some(ReleaseMatch {
+            release: release.clone(),
+            source: asset["name"].as_str().unwrap_or_default().to_string(),
+        })
+    })
+}
+
+/// Release whose SHA-256 checksum files list `sha256`, among the first
+/// [`RECENT_RELEASES`] of `releases`.
+///
+/// `fetch` downloads a checksum file by URL; one that can't be downloaded
+/// is skipped.
+pub fn match_checksum_files(
+    releases: &[serde_json::Value],
+    sha256: &str,
+    mut fetch: impl FnMut(&str) -> Result<String, UpdaterError>,
+) -> Option<ReleaseMatch> {
+    let sha256 = sha256.to_lowercase();
+    let algorithm = ChecksumAlgorithm::Sha256;
+    for release in releases.iter().take(RECENT_RELEASES) {
+        let Some(assets) = release["assets"].as_array() else {
+            continue;
+        };
+        for asset in assets {
+            let name = asset["name"].as_str().unwrap_or_default();
+            let is_checksum_file = name == algorithm.sums_file()
+                || name.ends_with(&format!(".{}", algorithm.extension()));
+            let Some(url) = asset["browser_download_url"].as_str() else {
+                continue;
+            };
+            if !is_checksum_file {
+                continue;
+            }
+            let Ok(content) = fetch(url) else {
+                continue;
+            };
+            let listed = crate::parse_checksum_file(&content)
+                .into_iter()
+                .find(|(digest, _)| digest.to_lowercase() == sha256);
+            if let Some((_, file)) = listed {
+                // The asset the line names, where the release has it
+                let source = if crate::find_asset(assets, &file).is_some() {
+                    file
+                } else {
+                    name.to_string()
+                };
+                return Some(ReleaseMatch {
+                    release: release.clone(),
+                    source,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Outcome of `adopt`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AdoptReport {
+    pub version: String,
+    pub path: PathBuf,
+    pub sha256: String,
+    pub provenance: Provenance,
+    /// Asset or checksum file the hash matched, or the stored version it
+    /// matched when the binary was already stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<String>,
+    /// What the binary's `--version` printed, when it disagrees with the
+    /// release its hash matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reported_version: Option<String>,
+}
+
+impl std::fmt::Display for AdoptReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Adopted opencode {} from {} ({}",
+            sanitize_terminal_text(&self.version),
+            self.path.display(),
+            self.provenance
+        )?;
+        match (&self.matched, self.provenance) {
+            (Some(matched), _) => write!(f, ": {})", sanitize_terminal_text(matched))?,
+            (None, Provenance::Unverified) => write!(f, "; version from its --version output)")?,
+            (None, Provenance::Release) => write!(f, ")")?,
+        }
+        if let Some(reported) = &self.reported_version {
+            write!(
+                f,
+                "\nNote: the binary reports version {}",
+                sanitize_terminal_text(reported)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Name to store an unmatched build under: the version it reports, or a
+/// local version beside it when that is already stored with another binary.
+pub fn local_version_name(
+    version_manager: &VersionManager,
+    reported: &str,
+    sha256: &str,
+) -> String {
+    if !version_manager.version_dir(reported).exists() {
+        return reported.to_string();
+    }
+    format!("{}+local.{}", reported, &sha256[..sha256.len().min(8)])
+}
+
+/// Store the opencode at `binary` and make it the current version.
+///
+/// `releases` are the latest releases, newest first, and `fetch` downloads a
+/// checksum file, see [`match_checksum_files`].
+pub fn adopt(
+    version_manager: &VersionManager,
+    binary: &Path,
+    releases: &[serde_json::Value],
+    fetch: impl FnMut(&str) -> Result<String, UpdaterError>,
+) -> Result<AdoptReport, UpdaterError> {
+    let probe = match version_manager.identify_binary(binary) {
+        BinaryIdentity::Opencode(probe) => probe,
+        BinaryIdentity::Foreign(probe) => {
+            return Err(UpdaterError::ForeignBinary(format!(
+                "{} printed \"{}\" and doesn't look like opencode; not adopting it",
+                binary.display(),
+                sanitize_terminal_text(&probe.excerpt())
+            )));
+        }
+        BinaryIdentity::Missing | BinaryIdentity::Unknown => {
+            return Err(UpdaterError::Other(format!(
+                "{} is not a runnable opencode binary",
+                binary.display()
+            )));
+        }
+    };
+    let sha256 = hash_file(binary)?;
+    let reported = probe.version().to_string();
+
+    // Already stored, e.g. installed by this tool before its storage was reset
+    if let Some(stored) = version_manager
+        .list_installed_versions()?
+        .into_iter()
+        .find(|stored| stored.binary_sha256.as_deref() == Some(sha256.as_str()))
+    {
+        version_manager.set_current(&stored.version)?;
+        return Ok(AdoptReport {
+            // Versions stored without a checksum were backed up from an install
+            provenance: stored.provenance.unwrap_or(if stored.checksum.is_empty() {
+                Provenance::Unverified
+            } else {
+                Provenance::Release
+            }),
+            matched: Some(format!("stored version {}", stored.version)),
+            reported_version: (reported != stored.version).then_some(reported),
+            version: stored.version,
+            path: binary.to_path_buf(),
+            sha256,
+        });
+    }
+
+    let matched = match_asset_digest(releases, &sha256)
+        .or_else(|| match_checksum_files(releases, &sha256, fetch));
+    let (version_info, source) = match matched {
+        Some(matched) => (matched.version_info(binary, &sha256), Some(matched.source)),
+        None => (
+            VersionInfo {
+                version: local_version_name(version_manager, &reported, &sha256),
+                tag_name: format!("v{}", reported),
+                release_date: Utc::now(),
+                installed_at: Utc::now(),
+                install_path: binary.to_path_buf(),
+                release_notes: format!("Adopted from {}", binary.display()),
+                provenance: Some(Provenance::Unverified),
+                channel: crate::Channel::of_version(&reported),
+                ..Default::default()
+            },
+            None,
+        ),
+    };
+
+    version_manager.save_version(&version_info, binary)?;
+    version_manager.set_current(&version_info.version)?;
+    Ok(AdoptReport {
+        reported_version: (reported != version_info.version).then_some(reported),
+        provenance: version_info.provenance.unwrap_or(Provenance::Unverified),
+        version: version_info.version,
+        path: binary.to_path_buf(),
+        sha256,
+        matched: source,
+    })
+}
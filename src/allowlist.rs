@@ -0,0 +1,132 @@
+//! `strict_sources`: download only over HTTPS, and only from allowed hosts.
+//!
+//! The updater installs what it downloads, often as root, so with
+//! `strict_sources = true` in the config file a plain-HTTP URL or a host
+//! outside the allowlist is refused. The allowlist is GitHub's API, web and
+//! release asset hosts, extended with `allowed_hosts`. Like the trace, the
+//! check is a middleware on the shared client, so release assets, checksum
+//! files, manifests and API requests alike go through it. Redirects are
+//! followed one hop at a time by [`crate::payload::send`], so every hop is
+//! checked before it is requested. The policy holds for the whole process,
+//! including the threads fetching checksums. `--allow-insecure-source` turns
+//! it off for one run.
+
+use crate::{UpdaterError, sanitize_terminal_text};
+use std::sync::OnceLock;
+use ureq::http::{Request, Response, Uri};
+use ureq::middleware::MiddlewareNext;
+use ureq::{Body, SendBody};
+
+/// Hosts allowed by default: GitHub's API and web hosts, and where release
+/// downloads redirect to. `*.` allows every subdomain.
+pub const DEFAULT_HOSTS: [&str; 6] = [
+    "github.com",
+    "api.github.com",
+    "objects.githubusercontent.com",
+    "release-assets.githubusercontent.com",
+    "github-releases.githubusercontent.com",
+    "*.s3.amazonaws.com",
+];
+
+/// Where downloads may come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPolicy {
+    /// Allowed hosts, lowercase; `*.example.com` allows the subdomains of
+    /// `example.com`.
+    pub hosts: Vec<String>,
+}
+
+impl HostPolicy {
+    /// [`DEFAULT_HOSTS`] and `extra`.
+    pub fn new(extra: &[String]) -> Result<Self, UpdaterError> {
+        let mut hosts: Vec<String> = DEFAULT_HOSTS.iter().map(|host| host.to_string()).collect();
+        for host in extra {
+            let host = host.trim().to_ascii_lowercase();
+            let name = host.strip_prefix("*.").unwrap_or(&host);
+            if name.is_empty() || name.contains(['/', ':', '*', '@']) {
+                return Err(UpdaterError::UntrustedSource(format!(
+                    "allowed_hosts entry '{}' is not a host name like mirror.example.com or *.example.com",
+                    sanitize_terminal_text(&host)
+                )));
+            }
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        Ok(Self { hosts })
+    }
+
+    /// Whether `host` is allowed.
+    pub fn permits(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => host == *allowed,
+            })
+    }
+
+    /// Refuse `uri` unless it is HTTPS to an allowed host.
+    pub fn check_uri(&self, uri: &Uri) -> Result<(), UpdaterError> {
+        let url = sanitize_terminal_text(&uri.to_string());
+        if uri.scheme_str() != Some("https") {
+            return Err(UpdaterError::UntrustedSource(format!(
+                "{} is not HTTPS, and strict_sources allows only HTTPS downloads; pass --allow-insecure-source to download it anyway",
+                url
+            )));
+        }
+        match uri.host() {
+            Some(host) if self.permits(host) => Ok(()),
+            host => Err(UpdaterError::UntrustedSource(format!(
+                "{} is on {}, which is not an allowed host; add it to allowed_hosts in the config file, or pass --allow-insecure-source to download it anyway",
+                url,
+                sanitize_terminal_text(host.unwrap_or("no host"))
+            ))),
+        }
+    }
+
+    /// Refuse `url` unless it is HTTPS to an allowed host.
+    pub fn check(&self, url: &str) -> Result<(), UpdaterError> {
+        let uri: Uri = url.parse().map_err(|_| {
+            UpdaterError::UntrustedSource(format!(
+                "{} is not a valid URL",
+                sanitize_terminal_text(url)
+            ))
+        })?;
+        self.check_uri(&uri)
+    }
+}
+
+static POLICY: OnceLock<HostPolicy> = OnceLock::new();
+
+/// Enforce `policy`, if any, on every request of the process from now on.
+///
+/// Once a policy is set it stays; setting another is ignored.
+pub fn set_policy(policy: Option<HostPolicy>) {
+    if let Some(policy) = policy {
+        let _ = POLICY.set(policy);
+    }
+}
+
+/// The policy enforced on the process, if any.
+pub fn policy() -> Option<&'static HostPolicy> {
+    POLICY.get()
+}
+
+fn refused(error: UpdaterError) -> ureq::Error {
+    ureq::Error::Other(Box::new(error))
+}
+
+/// Refuse requests to URLs the policy doesn't allow.
+pub fn middleware(
+    request: Request<SendBody>,
+    next: MiddlewareNext,
+) -> Result<Response<Body>, ureq::Error> {
+    if let Some(policy) = policy() {
+        policy.check_uri(request.uri()).map_err(refused)?;
+    }
+    next.handle(request)
+}
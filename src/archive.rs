@@ -0,0 +1,156 @@
+//! `inspect-archive`: look inside a release asset without installing it.
+//!
+//! Entries are listed straight from the zip central directory or the tar
+//! headers, without writing anything. Only to learn which file
+//! [`find_executable_binary`](crate::find_executable_binary) would pick is
+//! the archive extracted, into a scratch directory that is removed again.
+
+use crate::{
+    ArchiveFormat, UpdaterError, calculate_sha256, extract_archive, find_executable_binary,
+    hash_file,
+};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::path::Path;
+
+/// One file, directory or link in an archive.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub path: String,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+    /// Unix permission bits, if the archive records them.
+    pub mode: Option<u32>,
+    pub is_dir: bool,
+    pub executable: bool,
+}
+
+/// List the entries of a zip or tar.gz archive without extracting it.
+pub fn list_entries(
+    archive_bytes: &[u8],
+    asset_name: &str,
+) -> Result<Vec<ArchiveEntry>, UpdaterError> {
+    let format = ArchiveFormat::of(asset_name);
+    let failed = |source: Box<dyn std::error::Error + Send + Sync>| UpdaterError::ExtractionError {
+        archive: asset_name.to_string(),
+        format,
+        source,
+    };
+    let mut entries = Vec::new();
+    match format {
+        Some(ArchiveFormat::Zip) => {
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(archive_bytes))
+                .map_err(|e| failed(e.into()))?;
+            for index in 0..archive.len() {
+                let file = archive.by_index_raw(index).map_err(|e| failed(e.into()))?;
+                let mode = file.unix_mode().map(|mode| mode & 0o7777);
+                entries.push(ArchiveEntry {
+                    path: file.name().to_string(),
+                    size: file.size(),
+                    mode,
+                    is_dir: file.is_dir(),
+                    executable: !file.is_dir() && mode.is_some_and(|mode| mode & 0o111 != 0),
+                });
+            }
+        }
+        Some(ArchiveFormat::TarGz) => {
+            let mut archive = tar::Archive::new(GzDecoder::new(archive_bytes));
+            for entry in archive.entries()? {
+                let entry = entry?;
+                let header = entry.header();
+                let is_dir = header.entry_type().is_dir();
+                let mode = header.mode().ok().map(|mode| mode & 0o7777);
+                entries.push(ArchiveEntry {
+                    path: entry.path()?.display().to_string(),
+                    size: header.size()?,
+                    mode,
+                    is_dir,
+                    executable: !is_dir && mode.is_some_and(|mode| mode & 0o111 != 0),
+                });
+            }
+        }
+        None => return Err(failed("unsupported archive format".into())),
+    }
+    Ok(entries)
+}
+
+/// What an archive contains and what installing it would put in place.
+#[derive(Serialize, Debug, Clone)]
+pub struct ArchiveInspection {
+    pub asset_name: String,
+    pub archive_sha256: String,
+    pub entries: Vec<ArchiveEntry>,
+    /// Entry that would be installed, if any is executable.
+    pub binary: Option<String>,
+    pub binary_sha256: Option<String>,
+}
+
+impl std::fmt::Display for ArchiveInspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = crate::sanitize_terminal_text(&self.asset_name);
+        writeln!(f, "📦 {} (sha256 {})\n", name, self.archive_sha256)?;
+        for entry in &self.entries {
+            let mode = entry
+                .mode
+                .map_or_else(|| "----".to_string(), |mode| format!("{:04o}", mode));
+            let marker = if self.binary.as_deref() == Some(entry.path.trim_start_matches("./")) {
+                "→"
+            } else if entry.executable {
+                "*"
+            } else {
+                " "
+            };
+            writeln!(
+                f,
+                "  {} {} {:>10}  {}",
+                marker,
+                mode,
+                crate::format_size(entry.size),
+                crate::sanitize_terminal_text(&entry.path)
+            )?;
+        }
+        match (&self.binary, &self.binary_sha256) {
+            (Some(binary), Some(sha256)) => write!(
+                f,
+                "\n🔐 Would install {} (sha256 {})",
+                crate::sanitize_terminal_text(binary),
+                sha256
+            ),
+            _ => write!(f, "\n❌ No executable entry; nothing would be installed"),
+        }
+    }
+}
+
+/// Inspect an archive, extracting it under `scratch` only to find and hash the binary.
+///
+/// `scratch` is left empty; nothing else on disk is touched.
+pub fn inspect_archive(
+    archive_bytes: Vec<u8>,
+    asset_name: &str,
+    scratch: &Path,
+) -> Result<ArchiveInspection, UpdaterError> {
+    let archive_sha256 = calculate_sha256(&archive_bytes);
+    let entries = list_entries(&archive_bytes, asset_name)?;
+
+    let extract_dir = tempfile::Builder::new()
+        .prefix("inspect-")
+        .tempdir_in(scratch)?;
+    extract_archive(archive_bytes, asset_name, extract_dir.path())?;
+    let (binary, binary_sha256) = match find_executable_binary(extract_dir.path()) {
+        Ok(path) => (
+            path.strip_prefix(extract_dir.path())
+                .map(|relative| relative.display().to_string())
+                .ok(),
+            Some(hash_file(&path)?),
+        ),
+        Err(_) => (None, None),
+    };
+
+    Ok(ArchiveInspection {
+        asset_name: asset_name.to_string(),
+        archive_sha256,
+        entries,
+        binary,
+        binary_sha256,
+    })
+}
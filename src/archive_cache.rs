@@ -0,0 +1,78 @@
+//! Verified release archives kept until their version is stored.
+//!
+//! An update that fails after its download, because a scanner refused the
+//! binary, sudo failed or it was interrupted, would otherwise download the
+//! archive again on the next run. The archive is kept as
+//! `cache/archives/<sha256>` once it has been verified, and the next update
+//! to the same build copies it from there instead, checking it against the
+//! published checksums again. It is removed once its version is stored.
+//!
+//! A cached archive that no longer matches is a corrupted cache entry, not a
+//! bad download, so it is evicted, along with any `<sha256>.partial` an
+//! interrupted write left, rather than retried.
+
+use std::path::{Path, PathBuf};
+
+/// Directory of the cached archives, under the cache directory.
+const ARCHIVES_DIR: &str = "archives";
+
+/// Suffix of an archive still being written to the cache.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Where the archive with SHA-256 `sha256` is cached; `None` for something
+/// that isn't a SHA-256 digest.
+pub fn path(cache_dir: &Path, sha256: &str) -> Option<PathBuf> {
+    (sha256.len() == 64 && sha256.bytes().all(|byte| byte.is_ascii_hexdigit())).then(|| {
+        cache_dir
+            .join(ARCHIVES_DIR)
+            .join(sha256.to_ascii_lowercase())
+    })
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Whether any archive is cached; checked before waiting for the checksums
+/// that say which one to look for.
+pub fn is_empty(cache_dir: &Path) -> bool {
+    std::fs::read_dir(cache_dir.join(ARCHIVES_DIR))
+        .map_or(true, |mut entries| entries.next().is_none())
+}
+
+/// The cached archive with SHA-256 `sha256`, if there is one.
+pub fn lookup(cache_dir: &Path, sha256: &str) -> Option<PathBuf> {
+    path(cache_dir, sha256).filter(|path| path.is_file())
+}
+
+/// Keep the verified `archive` as the archive with SHA-256 `sha256`,
+/// replacing any other cached one.
+pub fn store(cache_dir: &Path, sha256: &str, archive: &Path) -> std::io::Result<()> {
+    let Some(path) = path(cache_dir, sha256) else {
+        return Ok(());
+    };
+    let dir = cache_dir.join(ARCHIVES_DIR);
+    std::fs::create_dir_all(&dir)?;
+    for entry in std::fs::read_dir(&dir)? {
+        std::fs::remove_file(entry?.path())?;
+    }
+    let partial = partial_path(&path);
+    std::fs::copy(archive, &partial)?;
+    std::fs::rename(&partial, &path)
+}
+
+/// Remove the archive with SHA-256 `sha256`, and a partial copy of it.
+pub fn evict(cache_dir: &Path, sha256: &str) -> std::io::Result<()> {
+    let Some(path) = path(cache_dir, sha256) else {
+        return Ok(());
+    };
+    for path in [partial_path(&path), path] {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! `--install-as-of DATE`: install the release that was current on a date.
+//!
+//! Reproducing a bug report often needs "whatever was latest on 2024-03-01".
+//! The whole release list is fetched, page by page, and the newest release
+//! published on or before the date is installed like any other update.
+//! Drafts, prereleases and rolling tags were never the latest release, so
+//! they are passed over. A date alone covers that whole day, in UTC.
+
+use crate::{UpdaterError, is_rolling_tag, sanitize_terminal_text};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+/// The point in time given to `--install-as-of`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsOf {
+    /// As given on the command line.
+    pub value: String,
+    /// Start of the day for a date, otherwise the same as `until`.
+    pub from: DateTime<Utc>,
+    /// Latest publication time that counts, inclusive.
+    pub until: DateTime<Utc>,
+}
+
+/// Parse `--install-as-of`: a date, up to the end of that day in UTC, or an
+/// RFC 3339 time.
+pub fn parse_as_of(value: &str) -> Result<AsOf, String> {
+    let value = value.trim();
+    let (from, until) = match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => (
+            date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc(),
+            date.and_hms_nano_opt(23, 59, 59, 999_999_999)
+                .unwrap_or_default()
+                .and_utc(),
+        ),
+        Err(_) => {
+            let time = DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|_| format!("'{}' is neither YYYY-MM-DD nor an RFC 3339 time", value))?;
+            (time, time)
+        }
+    };
+    Ok(AsOf {
+        value: value.to_string(),
+        from,
+        until,
+    })
+}
+
+/// The release chosen for a date, and why.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AsOfSelection {
+    pub tag: String,
+    pub published_at: DateTime<Utc>,
+    pub as_of: String,
+    /// The next release, which came out after the date, if any.
+    pub next_tag: Option<String>,
+    #[serde(skip)]
+    pub release: serde_json::Value,
+}
+
+impl std::fmt::Display for AsOfSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Installing {}, published {}: the newest release as of {}",
+            sanitize_terminal_text(&self.tag),
+            self.published_at.format("%Y-%m-%d %H:%M UTC"),
+            self.as_of
+        )?;
+        if let Some(next) = &self.next_tag {
+            write!(f, " ({} came after)", sanitize_terminal_text(next))?;
+        }
+        Ok(())
+    }
+}
+
+fn published_at(release: &serde_json::Value) -> Option<DateTime<Utc>> {
+    release["published_at"]
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// The newest of `releases`, in any order, published on or before `as_of`.
+///
+/// Fails for a date after `now`, and for one before every release.
+pub fn select_release(
+    releases: &[serde_json::Value],
+    as_of: &AsOf,
+    now: DateTime<Utc>,
+) -> Result<AsOfSelection, UpdaterError> {
+    if as_of.from > now {
+        return Err(UpdaterError::Refused(format!(
+            "{} is in the future; update without --install-as-of for the latest release",
+            as_of.value
+        )));
+    }
+    let mut candidates: Vec<(DateTime<Utc>, &serde_json::Value)> = releases
+        .iter()
+        .filter(|release| {
+            release["draft"].as_bool() != Some(true)
+                && release["prerelease"].as_bool() != Some(true)
+                && !release["tag_name"].as_str().is_none_or(is_rolling_tag)
+        })
+        .filter_map(|release| Some((published_at(release)?, release)))
+        .collect();
+    candidates.sort_by_key(|(published, _)| *published);
+
+    let position = candidates.partition_point(|(published, _)| *published <= as_of.until);
+    let Some(&(published_at, release)) = position.checked_sub(1).map(|i| &candidates[i]) else {
+        return Err(UpdaterError::VersionNotFound(match candidates.first() {
+            Some((first, release)) => format!(
+                "as of {}: the first release, {}, was published {}",
+                as_of.value,
+                sanitize_terminal_text(release["tag_name"].as_str().unwrap_or_default()),
+                first.format("%Y-%m-%d")
+            ),
+            None => format!("as of {}: there are no releases", as_of.value),
+        }));
+    };
+    Ok(AsOfSelection {
+        tag: release["tag_name"].as_str().unwrap_or_default().to_string(),
+        published_at,
+        as_of: as_of.value.clone(),
+        next_tag: candidates
+            .get(position)
+            .and_then(|(_, next)| next["tag_name"].as_str())
+            .map(str::to_string),
+        release: release.clone(),
+    })
+}
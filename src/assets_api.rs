@@ -0,0 +1,234 @@
+//! Release assets of private repositories.
+//!
+//! The `browser_download_url` of an asset in a private repository answers
+//! 404, even to a request carrying a token. Such assets are fetched through
+//! the assets API instead, `/repos/{owner}/{repo}/releases/assets/{id}` with
+//! `Accept: application/octet-stream`, using the token from `GITHUB_TOKEN`.
+//! GitHub answers with a redirect to a signed URL on its storage host, which
+//! is followed without the token: the signature is the credential there, and
+//! the token must not reach another host.
+
+use crate::{Asset, DownloadStats, UpdaterError, encoding, network};
+use ureq::{Agent, ResponseExt};
+
+/// Environment variable holding the token for private assets.
+pub const TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+/// `Accept` asking the assets API for the asset itself rather than its metadata.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// An asset that can be fetched through the assets API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivateAsset {
+    /// The asset's URL in the assets API.
+    pub api_url: String,
+    pub token: String,
+}
+
+impl PrivateAsset {
+    /// `asset` of a release served by the API at `api_base`, when a token
+    /// is set and the asset's id and repository are known.
+    pub fn new(api_base: &str, asset: &Asset) -> Option<Self> {
+        Some(Self {
+            api_url: api_url(api_base, asset)?,
+            token: token()?,
+        })
+    }
+}
+
+/// The token in [`TOKEN_ENV`], if set.
+pub fn token() -> Option<String> {
+    std::env::var(TOKEN_ENV)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+/// URL of `asset` in the assets API at `api_base`.
+///
+/// The repository is taken from the asset's
+/// `https://<host>/{owner}/{repo}/releases/download/{tag}/{name}` URL.
+pub fn api_url(api_base: &str, asset: &Asset) -> Option<String> {
+    let id = asset.id?;
+    let path = asset
+        .browser_download_url
+        .split_once("://")
+        .map_or(asset.browser_download_url.as_str(), |(_, rest)| rest);
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+    match segments.as_slice() {
+        [owner, repo, "releases", "download", ..] if !owner.is_empty() && !repo.is_empty() => {
+            Some(format!(
+                "{}/repos/{}/{}/releases/assets/{}",
+                api_base.trim_end_matches('/'),
+                owner,
+                repo,
+                id
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `error` is a 404 answer.
+pub fn is_not_found(error: &(dyn std::error::Error + 'static)) -> bool {
+    let error = match error.downcast_ref::<UpdaterError>() {
+        Some(UpdaterError::Http { source, .. }) => source,
+        _ => match error.downcast_ref::<ureq::Error>() {
+            Some(error) => error,
+            None => return false,
+        },
+    };
+    matches!(error, ureq::Error::StatusCode(404))
+}
+
+/// Download `filename` from `url`, falling back to the assets API when that
+/// answers 404 and the asset is `private`.
+pub fn download(
+    client: &Agent,
+    url: &str,
+    filename: &str,
+    private: Option<&PrivateAsset>,
+) -> Result<Vec<u8>, UpdaterError> {
+    match crate::download_with_progress(client, url, filename) {
+        Err(e) if is_not_found(&e) => match private {
+            Some(private) => {
+                eprintln!(
+                    "{} not found at its download URL; fetching it through the assets API",
+                    crate::sanitize_terminal_text(filename)
+                );
+                download_private(client, private, filename)
+            }
+            None => Err(e),
+        },
+        result => Ok(result?),
+    }
+}
+
+/// [`download`] to the file `dest`, returning its SHA-256 and the
+/// download's statistics.
+///
+/// The download is streamed to `dest`, through the assets API as well.
+pub fn download_to_file(
+    client: &Agent,
+    url: &str,
+    dest: &std::path::Path,
+    filename: &str,
+    private: Option<&PrivateAsset>,
+) -> Result<(String, DownloadStats), UpdaterError> {
+    match crate::download_to_file(client, url, dest, filename) {
+        Err(e) if is_not_found(&e) => match private {
+            Some(private) => {
+                eprintln!(
+                    "{} not found at its download URL; fetching it through the assets API",
+                    crate::sanitize_terminal_text(filename)
+                );
+                download_private_to_file(client, private, dest, filename)
+            }
+            None => Err(e),
+        },
+        result => result,
+    }
+}
+
+/// How the assets API answered a request for an asset.
+enum Served {
+    /// With a redirect to this URL, to be followed without the token.
+    Redirect(String),
+    /// With the asset itself.
+    Body(ureq::http::Response<ureq::Body>),
+}
+
+/// Download `filename` through the assets API.
+pub fn download_private(
+    client: &Agent,
+    private: &PrivateAsset,
+    filename: &str,
+) -> Result<Vec<u8>, UpdaterError> {
+    match request_private(client, private, filename)? {
+        Served::Redirect(url) => crate::download_with_progress(client, &url, filename),
+        Served::Body(response) => encoding::read_body(response, filename),
+    }
+}
+
+/// [`download_private`] to the file `dest`, returning its SHA-256 and the
+/// download's statistics.
+///
+/// GitHub answers with a redirect, whose target is streamed to `dest` and
+/// hashed on the way; an asset served directly is read whole first.
+pub fn download_private_to_file(
+    client: &Agent,
+    private: &PrivateAsset,
+    dest: &std::path::Path,
+    filename: &str,
+) -> Result<(String, DownloadStats), UpdaterError> {
+    let started = std::time::Instant::now();
+    match request_private(client, private, filename)? {
+        Served::Redirect(url) => crate::download_to_file(client, &url, dest, filename),
+        Served::Body(response) => {
+            let bytes = encoding::read_body(response, filename)?;
+            std::fs::write(dest, &bytes)?;
+            let stats = DownloadStats::new(bytes.len() as u64, started.elapsed(), 0.0);
+            Ok((crate::calculate_sha256(&bytes), stats))
+        }
+    }
+}
+
+/// Ask the assets API for `filename`.
+///
+/// The request goes out like every GitHub API request, see
+/// [`crate::github_get`], except that the redirect is left to the caller.
+fn request_private(
+    client: &Agent,
+    private: &PrivateAsset,
+    filename: &str,
+) -> Result<Served, UpdaterError> {
+    crate::retry::with_retries(|_| request_private_once(client, private, filename))
+}
+
+fn request_private_once(
+    client: &Agent,
+    private: &PrivateAsset,
+    filename: &str,
+) -> Result<Served, UpdaterError> {
+    network::spend_api_request(&private.api_url)?;
+    let response =
+        crate::github_request(client, &private.api_url, OCTET_STREAM, Some(&private.token))
+            .config()
+            // Followed by the caller, without the token
+            .max_redirects(0)
+            .build()
+            .call()
+            .map_err(|e| crate::payload::request_error(&private.api_url, e))?;
+    crate::check_github_response(&response)?;
+
+    let status = response.status();
+    if status.is_redirection() {
+        let location = response
+            .headers()
+            .get("Location")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| {
+                UpdaterError::GitHubApiError(format!(
+                    "the assets API redirected {} without a location",
+                    crate::sanitize_terminal_text(filename)
+                ))
+            })?;
+        let hop = crate::payload::next_hop(response.get_uri(), location)?;
+        return Ok(Served::Redirect(hop.to_string()));
+    }
+    match status.as_u16() {
+        200 => Ok(Served::Body(response)),
+        404 => Err(UpdaterError::GitHubApiError(format!(
+            "{} not found through the assets API either; does the token in {} have access to the repository?",
+            crate::sanitize_terminal_text(filename),
+            TOKEN_ENV
+        ))),
+        status => Err(UpdaterError::ApiStatus {
+            status,
+            message: format!(
+                "the assets API could not serve {}",
+                crate::sanitize_terminal_text(filename)
+            ),
+        }),
+    }
+}
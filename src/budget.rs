@@ -0,0 +1,254 @@
+//! Getting by with few GitHub API requests, for `--api-budget`.
+//!
+//! CI runners behind one NAT address share the unauthenticated rate limit.
+//! With a budget set, the latest release is resolved the way a browser does
+//! it: `github.com/<repo>/releases/latest` redirects to the release's tag
+//! page, and that costs no API request. The release itself is then taken
+//! from the cache, fetched by tag while the budget allows, or once it is
+//! spent, pieced together from the release's `SHA256SUMS`, which names the
+//! assets and their digests. Without a budget the latest release is fetched
+//! from the API as before.
+
+use crate::{
+    HandlesErrorStatus, UpdaterError, encoding, format_count, load_cached_release,
+    load_cached_releases, network, payload, sanitize_terminal_text,
+};
+use serde::Serialize;
+use std::path::Path;
+use ureq::Agent;
+
+/// Checksum file a release is pieced together from.
+pub const SUMS_FILE: &str = "SHA256SUMS";
+
+/// The web host matching the API at `api_base`.
+///
+/// `api.github.com` serves `github.com` and GitHub Enterprise serves its API
+/// under `/api/v3`; any other base, such as a mirror, serves both.
+pub fn web_base(api_base: &str) -> String {
+    let api_base = api_base.trim_end_matches('/');
+    if let Some(base) = api_base.strip_suffix("/api/v3") {
+        return base.to_string();
+    }
+    api_base.replacen("://api.github.com", "://github.com", 1)
+}
+
+/// Tag of the latest release, read from where the web UI redirects to.
+pub fn latest_tag(client: &Agent, web_base: &str) -> Result<String, UpdaterError> {
+    let url = format!("{}/sst/opencode/releases/latest", web_base);
+    let response = client
+        .get(&url)
+        .config()
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .build()
+        .extension(HandlesErrorStatus)
+        .call()
+        .map_err(|e| payload::request_error(&url, e))?;
+    let location = response
+        .headers()
+        .get("Location")
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| response.status().is_redirection())
+        .ok_or_else(|| {
+            UpdaterError::NetworkError(format!(
+                "{} answered HTTP {} instead of redirecting to the latest release",
+                url,
+                response.status().as_u16()
+            ))
+        })?;
+    location
+        .rsplit_once("/releases/tag/")
+        .map(|(_, tag)| tag.trim_end_matches('/').to_string())
+        .filter(|tag| !tag.is_empty() && !tag.contains('/'))
+        .ok_or_else(|| {
+            UpdaterError::NetworkError(format!(
+                "{} redirected to {}, which is not a release",
+                url,
+                sanitize_terminal_text(location)
+            ))
+        })
+}
+
+/// The release `tag` as far as its `SHA256SUMS` tells: every asset it
+/// lists, with its digest, and the checksum file itself.
+pub fn release_from_checksums(
+    client: &Agent,
+    web_base: &str,
+    tag: &str,
+) -> Result<serde_json::Value, UpdaterError> {
+    let download_url = |name: &str| {
+        format!(
+            "{}/sst/opencode/releases/download/{}/{}",
+            web_base, tag, name
+        )
+    };
+    let url = download_url(SUMS_FILE);
+    let request = client
+        .get(&url)
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .extension(HandlesErrorStatus)
+        .header("Accept-Encoding", encoding::ASSET_ACCEPT_ENCODING);
+    let response = payload::send(client, request)?;
+    if response.status() != 200 {
+        return Err(UpdaterError::ApiBudgetExhausted(format!(
+            "no API requests left, release {} isn't cached and its {} can't be downloaded (HTTP {})",
+            sanitize_terminal_text(tag),
+            SUMS_FILE,
+            response.status().as_u16()
+        )));
+    }
+    let content = encoding::read_text(response, SUMS_FILE)?;
+    network::record_download(content.len() as u64);
+
+    let mut assets: Vec<serde_json::Value> = crate::parse_checksum_file(&content)
+        .into_iter()
+        .filter(|(_, name)| !name.is_empty() && !name.contains('/'))
+        .map(|(digest, name)| {
+            serde_json::json!({
+                "name": name,
+                "browser_download_url": download_url(&name),
+                "digest": format!("sha256:{}", digest),
+            })
+        })
+        .collect();
+    assets.push(serde_json::json!({
+        "name": SUMS_FILE,
+        "browser_download_url": url,
+    }));
+    Ok(serde_json::json!({
+        "tag_name": tag,
+        "name": tag,
+        "body": "",
+        "assets": assets,
+    }))
+}
+
+/// The release `tag` from the cache: stored by tag, or in a fresh release list.
+pub fn cached_release(cache_dir: &Path, tag: &str) -> Option<serde_json::Value> {
+    if let Ok(Some(release)) = load_cached_release(cache_dir, tag) {
+        return Some(release);
+    }
+    load_cached_releases(cache_dir)
+        .ok()
+        .flatten()?
+        .into_iter()
+        .find(|release| release["tag_name"] == tag)
+}
+
+/// The latest release, spending as little of the API budget as possible
+/// when there is one.
+pub fn fetch_latest_release(
+    client: &Agent,
+    api_base: &str,
+    cache_dir: &Path,
+) -> Result<serde_json::Value, UpdaterError> {
+    let Some(left) = network::api_budget_left() else {
+        return crate::fetch_release(client, api_base);
+    };
+    let web_base = web_base(api_base);
+    let tag = latest_tag(client, &web_base)?;
+    if let Some(release) = cached_release(cache_dir, &tag) {
+        return Ok(release);
+    }
+    if left == 0 {
+        return release_from_checksums(client, &web_base, &tag);
+    }
+    let release = crate::fetch_release_by_tag(client, api_base, &tag)?;
+    // Published releases don't change, so the next run needs no request for it
+    if let Err(e) = crate::cache_release(&release, cache_dir) {
+        crate::reporter::warn(
+            "release-cache",
+            &format!(
+                "could not cache release {}: {}",
+                sanitize_terminal_text(&tag),
+                e
+            ),
+        );
+    }
+    Ok(release)
+}
+
+/// A lookup an update makes, and the API requests it costs.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedStep {
+    pub what: String,
+    pub api_requests: u64,
+}
+
+/// The API requests an update would make, from `--dry-run`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiPlan {
+    pub steps: Vec<PlannedStep>,
+    pub api_requests: u64,
+    /// What is left of `--api-budget`, if given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_budget_left: Option<u64>,
+    /// Each asset that has to be fetched through the assets API costs one more.
+    pub private_fallback: bool,
+}
+
+impl std::fmt::Display for ApiPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Updating would make {} GitHub API request{}",
+            format_count(self.api_requests),
+            if self.api_requests == 1 { "" } else { "s" }
+        )?;
+        if let Some(left) = self.api_budget_left {
+            write!(f, " ({} left in the budget)", format_count(left))?;
+        }
+        for step in &self.steps {
+            write!(f, "\n  {}: {}", step.what, format_count(step.api_requests))?;
+        }
+        if self.private_fallback {
+            write!(
+                f,
+                "\n  plus 1 for each asset only available through the assets API"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The API requests an update would make now, without making any.
+///
+/// With a budget the latest tag is still resolved, since whether its release
+/// is cached decides the cost; that redirect is not an API request.
+pub fn plan_update(
+    client: &Agent,
+    api_base: &str,
+    cache_dir: &Path,
+) -> Result<ApiPlan, UpdaterError> {
+    let budget = network::api_budget_left();
+    let step = |what: &str, api_requests| PlannedStep {
+        what: what.to_string(),
+        api_requests,
+    };
+    let mut steps = Vec::new();
+    match budget {
+        None => steps.push(step("latest release", 1)),
+        Some(left) => {
+            let tag = latest_tag(client, &web_base(api_base))?;
+            steps.push(step("latest tag, from the release page redirect", 0));
+            let cached = cached_release(cache_dir, &tag).is_some();
+            let tag = sanitize_terminal_text(&tag);
+            steps.push(if cached {
+                step(&format!("release {}, cached", tag), 0)
+            } else if left == 0 {
+                step(&format!("release {}, from its {}", tag, SUMS_FILE), 0)
+            } else {
+                step(&format!("release {}", tag), 1)
+            });
+        }
+    }
+    steps.push(step("checksums and asset downloads", 0));
+    Ok(ApiPlan {
+        api_requests: steps.iter().map(|step| step.api_requests).sum(),
+        steps,
+        api_budget_left: budget,
+        private_fallback: crate::assets_api::token().is_some(),
+    })
+}
@@ -0,0 +1,91 @@
+//! Release channels: which track of releases updates follow.
+//!
+//! `channel = "stable"` in the config file, the default, follows GitHub's
+//! latest release, which is never a prerelease. `channel = "prerelease"`
+//! follows the newest release of either kind. Each stored version records the
+//! channel it came from, so a rollback to a version of the other channel is
+//! noticed: the next update would otherwise quietly move back to the
+//! configured track. Versions saved before channels were recorded are given
+//! one by storage migration, from their version number.
+
+use crate::VersionInfo;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A track of releases.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Prerelease,
+}
+
+impl Channel {
+    /// The channel of `version`, a prerelease when it has a suffix like `-rc.2`.
+    pub fn of_version(version: &str) -> Self {
+        match version.trim_start_matches('v').split_once('-') {
+            Some((_, suffix)) if !suffix.is_empty() => Channel::Prerelease,
+            _ => Channel::Stable,
+        }
+    }
+
+    /// The channel of a release as returned by the GitHub API.
+    pub fn of_release(release: &serde_json::Value) -> Self {
+        if release["prerelease"].as_bool() == Some(true) {
+            return Channel::Prerelease;
+        }
+        Self::of_version(release["tag_name"].as_str().unwrap_or_default())
+    }
+
+    /// Whether updates following this channel take `release`.
+    pub fn follows(self, release: &serde_json::Value) -> bool {
+        release["draft"].as_bool() != Some(true)
+            && (self == Channel::Prerelease || Self::of_release(release) == Channel::Stable)
+    }
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Channel::Stable => "stable",
+            Channel::Prerelease => "prerelease",
+        })
+    }
+}
+
+/// The newest of `releases`, listed newest first, on `channel`.
+pub fn latest(releases: &[serde_json::Value], channel: Channel) -> Option<&serde_json::Value> {
+    releases.iter().find(|release| channel.follows(release))
+}
+
+/// A rollback to a version of another channel than the configured one.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChannelSwitch {
+    pub version: String,
+    /// The channel in the config file.
+    pub configured: Channel,
+    /// The channel `version` came from.
+    pub target: Channel,
+}
+
+impl ChannelSwitch {
+    /// The switch rolling back to `target` makes, if its channel isn't `configured`.
+    pub fn for_rollback(configured: Channel, target: &VersionInfo) -> Option<Self> {
+        (target.channel != configured).then(|| Self {
+            version: target.version.clone(),
+            configured,
+            target: target.channel,
+        })
+    }
+}
+
+impl std::fmt::Display for ChannelSwitch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is a {} version, but updates follow the {} channel; the next update would move back to {}. Pass --switch-channel to follow {} from now on",
+            self.version, self.target, self.configured, self.configured, self.target
+        )
+    }
+}
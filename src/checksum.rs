@@ -0,0 +1,352 @@
+//! Published checksums and their verification.
+
+use crate::UpdaterError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Hash algorithm of a published checksum.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// All supported algorithms, weakest first.
+    pub const ALL: [ChecksumAlgorithm; 2] = [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha512];
+
+    /// Lowercase extension used for per-file checksum assets, e.g. `sha256`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Name of the combined checksum file, e.g. `SHA256SUMS`.
+    pub fn sums_file(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "SHA256SUMS",
+            ChecksumAlgorithm::Sha512 => "SHA512SUMS",
+        }
+    }
+
+    /// Length of a hex digest produced by this algorithm.
+    pub fn hex_len(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Sha256 => 64,
+            ChecksumAlgorithm::Sha512 => 128,
+        }
+    }
+
+    /// Hex digest of the given bytes.
+    pub fn digest(self, bytes: &[u8]) -> String {
+        match self {
+            ChecksumAlgorithm::Sha256 => format!("{:x}", Sha256::digest(bytes)),
+            ChecksumAlgorithm::Sha512 => format!("{:x}", Sha512::digest(bytes)),
+        }
+    }
+
+    /// Hex digest of a file, read in chunks.
+    pub fn digest_file(self, path: &Path) -> Result<String, std::io::Error> {
+        fn stream<D: Digest>(path: &Path) -> Result<String, std::io::Error> {
+            let mut file = std::fs::File::open(path)?;
+            let mut hasher = D::new();
+            let mut buffer = vec![0; 256 * 1024];
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+            }
+            Ok(hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect())
+        }
+        match self {
+            ChecksumAlgorithm::Sha256 => stream::<Sha256>(path),
+            ChecksumAlgorithm::Sha512 => stream::<Sha512>(path),
+        }
+    }
+}
+
+impl std::fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChecksumAlgorithm::Sha256 => write!(f, "SHA-256"),
+            ChecksumAlgorithm::Sha512 => write!(f, "SHA-512"),
+        }
+    }
+}
+
+/// An expected digest for an asset, and where it was published.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    /// Lowercase hex digest.
+    pub digest: String,
+    /// Name of the release asset the digest was read from.
+    pub source: String,
+}
+
+impl Checksum {
+    /// Whether the bytes hash to this digest.
+    pub fn verify(&self, bytes: &[u8]) -> bool {
+        self.algorithm.digest(bytes) == self.digest
+    }
+}
+
+/// Parses `hash  filename` lines as written by `sha256sum` and friends.
+///
+/// Returns `(digest, filename)` pairs with lowercased digests. Binary-mode
+/// markers (`hash *filename`) are accepted, and a file holding only a bare
+/// digest yields one pair with an empty filename.
+pub fn parse_checksum_file(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (digest, filename) = match line.split_once(char::is_whitespace) {
+                Some((digest, rest)) => (digest, rest.trim_start().trim_start_matches('*')),
+                None => (line, ""),
+            };
+            digest
+                .chars()
+                .all(|c| c.is_ascii_hexdigit())
+                .then(|| (digest.to_ascii_lowercase(), filename.to_string()))
+        })
+        .collect()
+}
+
+/// Finds the digest for `asset_name` in a checksum file's contents.
+///
+/// Per-file checksum assets may hold a bare digest or name a different path
+/// for the same file, so a single entry is accepted regardless of its name.
+pub fn find_checksum_for(
+    content: &str,
+    asset_name: &str,
+    algorithm: ChecksumAlgorithm,
+) -> Option<String> {
+    let entries: Vec<_> = parse_checksum_file(content)
+        .into_iter()
+        .filter(|(digest, _)| digest.len() == algorithm.hex_len())
+        .collect();
+
+    let named = entries.iter().find(|(_, filename)| {
+        filename == asset_name || filename.rsplit('/').next() == Some(asset_name)
+    });
+    match named {
+        Some((digest, _)) => Some(digest.clone()),
+        None if entries.len() == 1 && entries[0].1.is_empty() => Some(entries[0].0.clone()),
+        None => None,
+    }
+}
+
+/// A digest that differs from the published one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub algorithm: ChecksumAlgorithm,
+    /// Name of the release asset the expected digest was read from.
+    pub source: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} checksum mismatch (from {}): expected {}, got {}",
+            self.algorithm, self.source, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+impl From<Mismatch> for UpdaterError {
+    fn from(mismatch: Mismatch) -> Self {
+        UpdaterError::ChecksumMismatch(mismatch.expected, mismatch.actual)
+    }
+}
+
+/// Verifies bytes against every checksum, failing on the first mismatch.
+///
+/// Returns the algorithms that passed, deduplicated and sorted.
+pub fn verify_checksums(
+    bytes: &[u8],
+    checksums: &[Checksum],
+) -> Result<Vec<ChecksumAlgorithm>, Mismatch> {
+    let Ok(verified) = verify_digests(checksums, |algorithm| {
+        Ok::<_, std::convert::Infallible>(algorithm.digest(bytes))
+    });
+    verified
+}
+
+/// [`verify_checksums`] for a file, whose SHA-256 is already known as
+/// `sha256`; other digests are computed reading it in chunks.
+pub fn verify_file_checksums(
+    path: &Path,
+    sha256: &str,
+    checksums: &[Checksum],
+) -> std::io::Result<Result<Vec<ChecksumAlgorithm>, Mismatch>> {
+    verify_digests(checksums, |algorithm| match algorithm {
+        ChecksumAlgorithm::Sha256 => Ok(sha256.to_string()),
+        algorithm => algorithm.digest_file(path),
+    })
+}
+
+/// Compare each checksum with the digest `digest` gives for its algorithm.
+fn verify_digests<E>(
+    checksums: &[Checksum],
+    mut digest: impl FnMut(ChecksumAlgorithm) -> Result<String, E>,
+) -> Result<Result<Vec<ChecksumAlgorithm>, Mismatch>, E> {
+    let mut passed = Vec::new();
+    for checksum in checksums {
+        let actual = digest(checksum.algorithm)?;
+        if actual != checksum.digest {
+            return Ok(Err(Mismatch {
+                algorithm: checksum.algorithm,
+                source: checksum.source.clone(),
+                expected: checksum.digest.clone(),
+                actual,
+            }));
+        }
+        passed.push(checksum.algorithm);
+    }
+    passed.sort();
+    passed.dedup();
+    Ok(Ok(passed))
+}
+
+/// One `hash  filename` line, as read back by [`parse_checksum_file`].
+pub fn format_checksum_line(digest: &str, filename: &str) -> String {
+    format!("{}  {}\n", digest, filename)
+}
+
+/// Whether a file name survives a round trip through [`parse_checksum_file`].
+///
+/// Names with line breaks, surrounding whitespace or a leading `*` would be
+/// read back differently.
+fn round_trips(filename: &str) -> bool {
+    !filename.is_empty()
+        && !filename.contains(['\n', '\r'])
+        && filename.trim() == filename
+        && !filename.starts_with('*')
+        && !filename.starts_with('#')
+}
+
+/// Files written by [`generate_checksums`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeneratedChecksums {
+    /// Files that were hashed.
+    pub files: Vec<String>,
+    /// Checksum files written.
+    pub written: Vec<PathBuf>,
+}
+
+impl std::fmt::Display for GeneratedChecksums {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Hashed {} file{}",
+            self.files.len(),
+            if self.files.len() == 1 { "" } else { "s" }
+        )?;
+        for path in &self.written {
+            write!(f, "\nWrote {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `SHA256SUMS` (and the other requested algorithms' sums files) for every file in `dir`.
+///
+/// With `per_file`, a `<file>.<ext>` sibling is written for each file too.
+/// Checksum files from an earlier run are not hashed themselves. Nothing is
+/// written if any output already exists, unless `force` is set.
+pub fn generate_checksums(
+    dir: &Path,
+    algorithms: &[ChecksumAlgorithm],
+    per_file: bool,
+    force: bool,
+) -> Result<GeneratedChecksums, UpdaterError> {
+    let is_output = |name: &str| {
+        ChecksumAlgorithm::ALL.iter().any(|algorithm| {
+            name == algorithm.sums_file() || name.ends_with(&format!(".{}", algorithm.extension()))
+        })
+    };
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().into_string().map_err(|name| {
+            UpdaterError::Other(format!(
+                "File name is not UTF-8: {}",
+                name.to_string_lossy()
+            ))
+        })?;
+        if is_output(&name) {
+            continue;
+        }
+        if !round_trips(&name) {
+            return Err(UpdaterError::Other(format!(
+                "Can't list {:?} in a checksum file: its name would not be read back intact",
+                name
+            )));
+        }
+        files.push(name);
+    }
+    files.sort();
+
+    // Every output is planned before anything is written
+    let mut outputs: Vec<(PathBuf, String)> = Vec::new();
+    for &algorithm in algorithms {
+        let mut sums = String::new();
+        for name in &files {
+            let digest = algorithm.digest_file(&dir.join(name))?;
+            sums.push_str(&format_checksum_line(&digest, name));
+            if per_file {
+                outputs.push((
+                    dir.join(format!("{}.{}", name, algorithm.extension())),
+                    format_checksum_line(&digest, name),
+                ));
+            }
+        }
+        outputs.push((dir.join(algorithm.sums_file()), sums));
+    }
+
+    if !force {
+        let existing: Vec<String> = outputs
+            .iter()
+            .filter(|(path, _)| path.exists())
+            .map(|(path, _)| path.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            return Err(UpdaterError::Refused(format!(
+                "overwriting {}; pass --force to replace them",
+                existing.join(", ")
+            )));
+        }
+    }
+
+    let mut report = GeneratedChecksums {
+        files,
+        written: Vec::new(),
+    };
+    for (path, contents) in outputs {
+        std::fs::write(&path, contents)?;
+        report.written.push(path);
+    }
+    Ok(report)
+}
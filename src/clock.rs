@@ -0,0 +1,117 @@
+//! Coping with a wrong local clock.
+//!
+//! Devices without a real-time clock often boot with a time far in the past
+//! or future. Time-dependent decisions therefore never fail on a negative
+//! duration, and anything measured against GitHub uses the server's `Date`
+//! header rather than local time.
+
+use crate::UpdaterError;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use ureq::Agent;
+
+/// Skew between local and server time beyond which the clock is flagged.
+pub const MAX_CLOCK_SKEW_SECONDS: i64 = 60;
+
+/// Parse an HTTP `Date` header (RFC 2822 / IMF-fixdate).
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// The server's `Date` header of a response, if present and valid.
+pub fn server_date<B>(response: &ureq::http::Response<B>) -> Option<DateTime<Utc>> {
+    response
+        .headers()
+        .get("date")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_http_date)
+}
+
+/// Time until a rate limit resets, measured on the server's clock.
+///
+/// `reset_epoch` is GitHub's `x-ratelimit-reset`, which is in server time, so
+/// it is compared with the response's `Date` rather than local time. A reset
+/// already in the past yields zero.
+pub fn rate_limit_wait(reset_epoch: i64, server_now: DateTime<Utc>) -> std::time::Duration {
+    let wait = reset_epoch - server_now.timestamp();
+    std::time::Duration::from_secs(wait.max(0) as u64)
+}
+
+/// Local time compared with a server's `Date` header.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub local: DateTime<Utc>,
+    pub server: DateTime<Utc>,
+    /// Local minus server time; positive when the local clock is ahead.
+    pub skew_seconds: i64,
+}
+
+impl ClockSkew {
+    pub fn new(local: DateTime<Utc>, server: DateTime<Utc>) -> Self {
+        Self {
+            local,
+            server,
+            skew_seconds: (local - server).num_seconds(),
+        }
+    }
+
+    /// Whether the skew exceeds [`MAX_CLOCK_SKEW_SECONDS`].
+    pub fn is_significant(&self) -> bool {
+        self.skew_seconds.abs() > MAX_CLOCK_SKEW_SECONDS
+    }
+}
+
+impl std::fmt::Display for ClockSkew {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let skew = TimeDelta::seconds(self.skew_seconds.abs());
+        let direction = if self.skew_seconds >= 0 {
+            "ahead of"
+        } else {
+            "behind"
+        };
+        if self.is_significant() {
+            write!(
+                f,
+                "local clock is {} {} GitHub ({}); cache expiry and rate-limit times may be wrong",
+                format_delta(skew),
+                direction,
+                self.server.format("%Y-%m-%d %H:%M:%S UTC")
+            )
+        } else {
+            write!(f, "local clock is within a minute of GitHub")
+        }
+    }
+}
+
+/// Compare local time with the `Date` header of a GitHub API response.
+pub fn measure_skew(client: &Agent, base_url: &str) -> Result<ClockSkew, UpdaterError> {
+    let url = format!("{}/rate_limit", base_url);
+    crate::network::spend_api_request(&url)?;
+    let request = client
+        .get(&url)
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .header("Accept", crate::GITHUB_ACCEPT)
+        .header("X-GitHub-Api-Version", crate::GITHUB_API_VERSION);
+    let response = crate::payload::send(client, request)?;
+    crate::network::record_api_response(&response);
+    let local = Utc::now();
+    let server = server_date(&response).ok_or_else(|| {
+        UpdaterError::GitHubApiError("GitHub sent no valid Date header".to_string())
+    })?;
+    Ok(ClockSkew::new(local, server))
+}
+
+/// Coarse human-readable duration, e.g. `3h 12m` or `45s`.
+pub fn format_delta(delta: TimeDelta) -> String {
+    let seconds = delta.num_seconds().max(0);
+    match seconds {
+        s if s >= 86_400 => format!("{}d {}h", s / 86_400, s % 86_400 / 3600),
+        s if s >= 3600 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
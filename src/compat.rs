@@ -0,0 +1,359 @@
+//! Whether an extracted binary can run on this host, checked before
+//! anything is installed.
+//!
+//! A glibc build on Alpine, or an arm64 build picked with `--bin` on x86_64,
+//! would otherwise only fail once it is installed and first run. The header
+//! of the candidate is read: an ELF binary must be for this host's machine
+//! and, when dynamically linked, ask for a program interpreter (the libc's
+//! dynamic loader) that exists here. Mach-O and PE binaries are recognised
+//! and rejected outright. Anything else is left to later checks.
+
+use crate::{UpdaterError, sanitize_terminal_text};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// `e_machine` values of the architectures releases are built for.
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+/// Program header type naming the interpreter.
+const PT_INTERP: u32 = 3;
+
+/// Longest interpreter path read.
+const MAX_INTERP_LEN: u64 = 4096;
+
+/// Most program headers read.
+const MAX_PROGRAM_HEADERS: u16 = 256;
+
+/// The C library a dynamically linked binary, or this host, uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Libc {
+    Glibc,
+    Musl,
+}
+
+impl Libc {
+    /// The libc whose dynamic loader is at `interpreter`.
+    pub fn of_interpreter(interpreter: &str) -> Option<Self> {
+        let name = Path::new(interpreter).file_name()?.to_str()?;
+        if name.starts_with("ld-musl") {
+            Some(Libc::Musl)
+        } else if name.starts_with("ld-linux") || name.starts_with("ld64.so") {
+            Some(Libc::Glibc)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for Libc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Libc::Glibc => "glibc",
+            Libc::Musl => "musl",
+        })
+    }
+}
+
+/// What the header of an executable says about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryFormat {
+    Elf(ElfInfo),
+    MachO,
+    Pe,
+    /// Not a binary format recognised here, e.g. a script.
+    Unknown,
+}
+
+/// The parts of an ELF header that decide where a binary runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfInfo {
+    /// 32 or 64.
+    pub bits: u8,
+    pub machine: u16,
+    /// `PT_INTERP`, absent for a static binary.
+    pub interpreter: Option<String>,
+}
+
+/// Name of an ELF machine, as `uname -m` would print it.
+pub fn machine_name(machine: u16) -> String {
+    match machine {
+        EM_386 => "x86".to_string(),
+        EM_ARM => "arm".to_string(),
+        EM_X86_64 => "x86_64".to_string(),
+        EM_AARCH64 => "aarch64".to_string(),
+        EM_RISCV => "riscv".to_string(),
+        other => format!("machine {}", other),
+    }
+}
+
+/// ELF machine of an architecture as named by `std::env::consts::ARCH`.
+pub fn machine_of_arch(arch: &str) -> Option<u16> {
+    match arch {
+        "x86" => Some(EM_386),
+        "arm" => Some(EM_ARM),
+        "x86_64" => Some(EM_X86_64),
+        "aarch64" => Some(EM_AARCH64),
+        "riscv64" | "riscv32" => Some(EM_RISCV),
+        _ => None,
+    }
+}
+
+/// Architecture in release asset names, e.g. `opencode-linux-x64.zip`.
+fn asset_arch(machine: u16) -> Option<&'static str> {
+    match machine {
+        EM_X86_64 => Some("x64"),
+        EM_AARCH64 => Some("arm64"),
+        _ => None,
+    }
+}
+
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, buf: &mut [u8]) -> std::io::Result<()> {
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(buf)
+}
+
+/// Read the format of the executable in `reader`.
+///
+/// Only the header, the program headers and the interpreter path are read.
+/// A truncated ELF header is an error, since the file claims to be ELF.
+pub fn parse<R: Read + Seek>(reader: &mut R) -> std::io::Result<BinaryFormat> {
+    let mut ident = [0u8; 16];
+    reader.seek(SeekFrom::Start(0))?;
+    let read = reader.read(&mut ident)?;
+    let magic = &ident[..read.min(4)];
+    match magic {
+        b"\x7fELF" => {}
+        [0xfe, 0xed, 0xfa, 0xce | 0xcf] | [0xce | 0xcf, 0xfa, 0xed, 0xfe] => {
+            return Ok(BinaryFormat::MachO);
+        }
+        // Universal binaries share their magic with Java classes; both are foreign here
+        [0xca, 0xfe, 0xba, 0xbe] => return Ok(BinaryFormat::MachO),
+        [b'M', b'Z', ..] => return Ok(BinaryFormat::Pe),
+        _ => return Ok(BinaryFormat::Unknown),
+    }
+    if read < ident.len() {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let bits = match ident[4] {
+        1 => 32,
+        2 => 64,
+        other => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown ELF class {}", other),
+            ));
+        }
+    };
+    let big_endian = ident[5] == 2;
+    let u16_at = |bytes: &[u8], at: usize| {
+        let field = [bytes[at], bytes[at + 1]];
+        if big_endian {
+            u16::from_be_bytes(field)
+        } else {
+            u16::from_le_bytes(field)
+        }
+    };
+    let u32_at = |bytes: &[u8], at: usize| {
+        let field = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        if big_endian {
+            u32::from_be_bytes(field)
+        } else {
+            u32::from_le_bytes(field)
+        }
+    };
+    let word_at = |bytes: &[u8], at: usize| -> u64 {
+        if bits == 32 {
+            return u32_at(bytes, at) as u64;
+        }
+        let mut field = [0u8; 8];
+        field.copy_from_slice(&bytes[at..at + 8]);
+        if big_endian {
+            u64::from_be_bytes(field)
+        } else {
+            u64::from_le_bytes(field)
+        }
+    };
+
+    let mut header = [0u8; 64];
+    let header_len = if bits == 32 { 52 } else { 64 };
+    read_at(reader, 0, &mut header[..header_len])?;
+    let machine = u16_at(&header, 18);
+    let (phoff, phentsize, phnum) = if bits == 32 {
+        (
+            word_at(&header, 28),
+            u16_at(&header, 42),
+            u16_at(&header, 44),
+        )
+    } else {
+        (
+            word_at(&header, 32),
+            u16_at(&header, 54),
+            u16_at(&header, 56),
+        )
+    };
+
+    let mut interpreter = None;
+    let entry_len = if bits == 32 { 32 } else { 56 };
+    if usize::from(phentsize) >= entry_len {
+        let mut entry = vec![0u8; entry_len];
+        for index in 0..phnum.min(MAX_PROGRAM_HEADERS) {
+            read_at(
+                reader,
+                phoff + u64::from(index) * u64::from(phentsize),
+                &mut entry,
+            )?;
+            if u32_at(&entry, 0) != PT_INTERP {
+                continue;
+            }
+            let (offset, size) = if bits == 32 {
+                (word_at(&entry, 4), word_at(&entry, 16))
+            } else {
+                (word_at(&entry, 8), word_at(&entry, 32))
+            };
+            let mut path = vec![0u8; size.min(MAX_INTERP_LEN) as usize];
+            read_at(reader, offset, &mut path)?;
+            let end = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+            interpreter = Some(String::from_utf8_lossy(&path[..end]).into_owned());
+            break;
+        }
+    }
+
+    Ok(BinaryFormat::Elf(ElfInfo {
+        bits,
+        machine,
+        interpreter,
+    }))
+}
+
+/// What a binary must match to run here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// ELF machine, when this architecture is known.
+    pub machine: Option<u16>,
+    pub libc: Option<Libc>,
+}
+
+impl Host {
+    /// This host: its architecture, and its libc judged by the dynamic
+    /// loaders installed.
+    pub fn detect() -> Self {
+        let loaders = ["/lib", "/lib64", "/usr/lib"]
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                Libc::of_interpreter(&entry.path().to_string_lossy())
+                    .filter(|_| entry.file_name().to_string_lossy().contains(".so"))
+            })
+            .collect::<Vec<_>>();
+        // A glibc host with musl installed alongside can still run glibc builds
+        let libc = if loaders.contains(&Libc::Glibc) {
+            Some(Libc::Glibc)
+        } else {
+            loaders.first().copied()
+        };
+        Self {
+            machine: machine_of_arch(std::env::consts::ARCH),
+            libc,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let arch = self
+            .machine
+            .map_or_else(|| std::env::consts::ARCH.to_string(), machine_name);
+        match self.libc {
+            Some(libc) => format!("{} with {}", arch, libc),
+            None => arch,
+        }
+    }
+}
+
+/// Why a binary in `format` can't run on `host`, if it can't.
+///
+/// `interpreter_exists` says whether a path exists on the host.
+pub fn incompatibility(
+    format: &BinaryFormat,
+    host: &Host,
+    interpreter_exists: impl Fn(&str) -> bool,
+) -> Option<String> {
+    let elf = match format {
+        BinaryFormat::Elf(elf) => elf,
+        BinaryFormat::MachO => return Some("it is a macOS (Mach-O) binary".to_string()),
+        BinaryFormat::Pe => return Some("it is a Windows (PE) binary".to_string()),
+        BinaryFormat::Unknown => return None,
+    };
+    if let Some(machine) = host.machine
+        && elf.machine != machine
+    {
+        return Some(format!(
+            "it is built for {}, but this host is {}",
+            machine_name(elf.machine),
+            host.describe()
+        ));
+    }
+    let interpreter = elf.interpreter.as_deref()?;
+    if interpreter_exists(interpreter) {
+        return None;
+    }
+    let libc =
+        Libc::of_interpreter(interpreter).map_or_else(String::new, |libc| format!("{} ", libc));
+    Some(format!(
+        "it needs the {}loader {}, which this host ({}) doesn't have",
+        libc,
+        sanitize_terminal_text(interpreter),
+        host.describe()
+    ))
+}
+
+/// The asset among `assets` built for `host`, to suggest instead.
+pub fn suggest_asset(assets: &[serde_json::Value], host: &Host) -> Option<String> {
+    let arch = asset_arch(host.machine?)?;
+    let musl = host.libc == Some(Libc::Musl);
+    assets
+        .iter()
+        .filter_map(|asset| asset["name"].as_str())
+        .filter(|name| crate::payload::is_archive_name(name))
+        .find(|name| {
+            let stem = name.split('.').next().unwrap_or_default();
+            let mut parts = stem.split('-');
+            parts.next() == Some("opencode")
+                && parts.next() == Some("linux")
+                && parts.next() == Some(arch)
+                && stem.contains("-musl") == musl
+                // Variants for old CPUs are only a fallback
+                && !stem.contains("-baseline")
+        })
+        .map(str::to_string)
+}
+
+/// Refuse the extracted `binary` if it can't run on this host.
+pub fn check(binary: &Path, assets: &[serde_json::Value]) -> Result<(), UpdaterError> {
+    let format = std::fs::File::open(binary)
+        .and_then(|mut file| parse(&mut file))
+        .map_err(|e| {
+            UpdaterError::Incompatible(format!(
+                "could not read the header of {}: {}",
+                binary.display(),
+                e
+            ))
+        })?;
+    let host = Host::detect();
+    let Some(reason) = incompatibility(&format, &host, |path| Path::new(path).exists()) else {
+        return Ok(());
+    };
+    let suggestion = match suggest_asset(assets, &host) {
+        Some(asset) => format!("; pick {} with --bin", sanitize_terminal_text(&asset)),
+        None => String::new(),
+    };
+    Err(UpdaterError::Incompatible(format!(
+        "the downloaded opencode can't run here: {}{}",
+        reason, suggestion
+    )))
+}
@@ -0,0 +1,203 @@
+//! Shell completion files shipped inside release archives.
+//!
+//! Newer releases may carry completions under `completions/` or `share/`.
+//! With `--install-completions` (or `install_completions = true` in the
+//! config) they are copied to the per-user locations each shell reads. A copy
+//! is kept in the version directory and listed in its metadata, so rolling
+//! back puts that version's completions back as well.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Directories inside an archive that may hold completions.
+const COMPLETION_ROOTS: [&str; 2] = ["completions", "share"];
+
+/// Subdirectory of a version directory holding its completion files.
+const STORED_DIR: &str = "completions";
+
+/// A shell with per-user completion support.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Bash,
+    Fish,
+    Zsh,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Fish => "fish",
+            Shell::Zsh => "zsh",
+        }
+    }
+
+    /// File name the shell looks up for the `opencode` command.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Shell::Bash => "opencode",
+            Shell::Fish => "opencode.fish",
+            Shell::Zsh => "_opencode",
+        }
+    }
+}
+
+impl std::fmt::Display for Shell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which shell a file inside a completions subtree is for, judging by its
+/// name and the directories it sits in.
+pub fn shell_for(relative: &Path) -> Option<Shell> {
+    let name = relative.file_name()?.to_str()?;
+    let dirs: Vec<&str> = relative
+        .parent()
+        .map(|parent| parent.iter().filter_map(|part| part.to_str()).collect())
+        .unwrap_or_default();
+    let in_dir = |candidates: &[&str]| dirs.iter().any(|dir| candidates.contains(dir));
+
+    if name.ends_with(".fish") || in_dir(&["fish", "vendor_completions.d"]) {
+        Some(Shell::Fish)
+    } else if name.starts_with('_') || in_dir(&["zsh", "site-functions"]) {
+        Some(Shell::Zsh)
+    } else if name.ends_with(".bash") || in_dir(&["bash", "bash-completion"]) {
+        Some(Shell::Bash)
+    } else {
+        None
+    }
+}
+
+/// Completion files found in an extracted archive, as paths relative to `root`.
+///
+/// Sorted, so the first file per shell is stable.
+pub fn detect(root: &Path) -> Vec<(Shell, PathBuf)> {
+    fn walk(root: &Path, dir: &Path, found: &mut Vec<(Shell, PathBuf)>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => walk(root, &path, found),
+                Ok(kind) if kind.is_file() => {
+                    let Ok(relative) = path.strip_prefix(root) else {
+                        continue;
+                    };
+                    if let Some(shell) = shell_for(relative) {
+                        found.push((shell, relative.to_path_buf()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    for subtree in COMPLETION_ROOTS {
+        walk(root, &root.join(subtree), &mut found);
+    }
+    found.sort();
+    found
+}
+
+/// Per-user directories completions are installed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionDirs {
+    /// `$XDG_DATA_HOME`, usually `~/.local/share`.
+    pub data_home: PathBuf,
+    /// `$XDG_CONFIG_HOME`, usually `~/.config`.
+    pub config_home: PathBuf,
+}
+
+impl CompletionDirs {
+    /// The current user's directories.
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            data_home: dirs::data_dir()?,
+            config_home: dirs::config_dir()?,
+        })
+    }
+
+    /// Where `shell` picks up the completion file for `opencode`.
+    pub fn destination(&self, shell: Shell) -> PathBuf {
+        let dir = match shell {
+            Shell::Bash => self.data_home.join("bash-completion").join("completions"),
+            Shell::Fish => self.config_home.join("fish").join("completions"),
+            Shell::Zsh => self.data_home.join("zsh").join("site-functions"),
+        };
+        dir.join(shell.file_name())
+    }
+}
+
+/// A completion file installed with a version, as recorded in its metadata.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CompletionFile {
+    pub shell: Shell,
+    /// Path inside the release archive.
+    pub source: PathBuf,
+    /// Where it was installed.
+    pub destination: PathBuf,
+}
+
+impl CompletionFile {
+    /// The copy kept in `version_dir`.
+    pub fn stored_path(&self, version_dir: &Path) -> PathBuf {
+        version_dir
+            .join(STORED_DIR)
+            .join(self.shell.name())
+            .join(self.shell.file_name())
+    }
+}
+
+/// One file per shell from `found`, with its destination under `dirs`.
+pub fn plan(found: &[(Shell, PathBuf)], dirs: &CompletionDirs) -> Vec<CompletionFile> {
+    let mut files: Vec<CompletionFile> = Vec::new();
+    for (shell, source) in found {
+        if files.iter().any(|file| file.shell == *shell) {
+            continue;
+        }
+        files.push(CompletionFile {
+            shell: *shell,
+            source: source.clone(),
+            destination: dirs.destination(*shell),
+        });
+    }
+    files
+}
+
+/// Copy each planned file from the extracted archive into `version_dir`.
+pub fn store(
+    extract_root: &Path,
+    version_dir: &Path,
+    files: &[CompletionFile],
+) -> Result<(), std::io::Error> {
+    for file in files {
+        let stored = file.stored_path(version_dir);
+        if let Some(parent) = stored.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(extract_root.join(&file.source), stored)?;
+    }
+    Ok(())
+}
+
+/// Install the copies kept in `version_dir` to their destinations.
+///
+/// Returns the destinations written.
+pub fn install(
+    version_dir: &Path,
+    files: &[CompletionFile],
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut installed = Vec::new();
+    for file in files {
+        if let Some(parent) = file.destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(file.stored_path(version_dir), &file.destination)?;
+        installed.push(file.destination.clone());
+    }
+    Ok(installed)
+}
@@ -0,0 +1,544 @@
+//! User configuration loaded from `~/.config/opencode-updater/config.toml`.
+//!
+//! Besides settings of its own, the config can say what a bare
+//! `opencode-updater` does (`default_command`) and give flags defaults per
+//! command (`[defaults.update]`, `[defaults.check]`). Those are applied after
+//! the command line is parsed, only to flags it didn't give, so the command
+//! line always wins over the config, which wins over the built-in default.
+
+use crate::{Args, Channel, SortOrder, Track, UpdaterError, Version, VersionConstraint};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the target used when the config file defines none.
+pub const DEFAULT_TARGET_NAME: &str = "system";
+
+/// Location of the system-wide opencode binary.
+pub const SYSTEM_INSTALL_PATH: &str = "/usr/bin/opencode";
+
+/// Commented config file written by `init`.
+pub const DEFAULT_CONFIG: &str = r#"# opencode-updater configuration
+
+# Record how long each update phase takes in the local history log.
+# Nothing is sent anywhere; see `opencode-updater history stats`.
+# record_timings = false
+
+# Install the bash, zsh and fish completions shipped in release archives
+# to your per-user completion directories (same as --install-completions).
+# install_completions = false
+
+# Existing installs are only backed up or replaced if their --version or --help
+# output matches this regex, so another program called opencode isn't clobbered.
+# identity_marker = '\bopencode (run|serve|auth|upgrade|models)\b'
+
+# Command every new binary must pass before it is installed. It runs through
+# `sh -c` with the binary's path appended; a nonzero exit aborts the install.
+# A scanner still running after scan_timeout seconds is killed and the install
+# aborted. --skip-scan bypasses it.
+# scan_command = "clamscan --no-summary"
+# scan_timeout = 300
+
+# Downloads and GitHub API requests that can't connect, time out or get a 5xx
+# answer are tried again, waiting longer each time, up to this many attempts
+# in all. 1 never retries.
+# retry_attempts = 3
+
+# Where the latest release comes from: the GitHub API (the default), or a
+# static manifest with "version", "url" and "sha256" fields, e.g. on a mirror.
+# source = { type = "manifest", url = "https://example.com/opencode/latest.json" }
+
+# Releases updates follow: "stable" (the default), or "prerelease" for the
+# newest release of either kind. `--rollback VER --switch-channel` sets it to
+# the channel of VER.
+# channel = "stable"
+
+# Keep updates to one release line, or below a version (same as --track and
+# --max-version). The newest release allowed is installed instead of the
+# latest one.
+# track = "0.3"
+# max_version = "0.3.200"
+
+# Which stored versions cleanup keeps: the most recently "installed" (the
+# default), or the highest by "version", so rolling back to an old version
+# doesn't get the newest one cleaned up.
+# cleanup_order = "installed"
+
+# Download release archives from a mirror of GitHub's download host, such as
+# an Artifactory remote repository, under the same paths (same as --mirror).
+# When the mirror answers 404 or 5xx, the archive comes from GitHub. Checksums
+# are always fetched from the release, so an altered archive is caught; for a
+# release without any, the archive comes from GitHub too. A plain-HTTP mirror
+# is refused unless --allow-insecure-source is passed.
+# mirror = "https://artifactory.example.com/artifactory/github"
+
+# Download only over HTTPS and only from GitHub's hosts, plus allowed_hosts
+# (a leading "*." allows subdomains). Anything else is refused unless
+# --allow-insecure-source is passed.
+# strict_sources = false
+# allowed_hosts = ["mirror.example.com"]
+
+# Warnings for conditions you have accepted, never shown (same as
+# --suppress-warning KEY). `doctor` still reports them. Keys: corrupt-cache,
+# checksum-fetch, checksum-retry, history, release-cache, migration, clock,
+# rate-limit, force, foreign-target, unparseable-version, unsigned-version,
+# modified-install, mirror.
+# suppress_warnings = ["clock"]
+
+# Target used when --target is not given.
+# default_target = "system"
+
+# What a bare `opencode-updater`, with no command or flags besides global ones
+# like -v, does: "update" (the default) or "check", which never installs.
+# default_command = "update"
+
+# Defaults for the flags of a command, used unless given on the command line.
+#
+# [defaults.update]
+# keep_versions = 3
+# install_bak = true
+# sbom = false
+# json = false
+#
+# [defaults.check]
+# json = true
+
+# Install targets. Without any, opencode is installed to /usr/bin/opencode via sudo.
+# Each target is selected with --target NAME, or all at once with --all-targets.
+#
+# [targets.system]
+# path = "/usr/bin/opencode"
+#
+# [targets.user]
+# path = "/home/you/.local/bin/opencode"
+# sudo = false
+"#;
+
+/// A location opencode gets installed to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstallTarget {
+    /// Full path of the installed binary, e.g. `/usr/bin/opencode`.
+    pub path: PathBuf,
+    /// Whether writing to `path` requires sudo.
+    #[serde(default = "default_sudo")]
+    pub sudo: bool,
+}
+
+fn default_sudo() -> bool {
+    true
+}
+
+impl InstallTarget {
+    /// The built-in system-wide target at `/usr/bin/opencode`.
+    pub fn system() -> Self {
+        Self {
+            path: PathBuf::from(SYSTEM_INSTALL_PATH),
+            sudo: true,
+        }
+    }
+}
+
+/// What a bare invocation runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultCommand {
+    Update,
+    Check,
+}
+
+/// `[defaults.update]`: defaults for the flags of an update.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateDefaults {
+    pub keep_versions: Option<usize>,
+    pub install_bak: Option<bool>,
+    pub sbom: Option<bool>,
+    pub json: Option<bool>,
+}
+
+/// `[defaults.check]`: defaults for the flags of `--check`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CheckDefaults {
+    pub json: Option<bool>,
+}
+
+/// `[defaults]`, one table per command.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CommandDefaults {
+    #[serde(default)]
+    pub update: UpdateDefaults,
+    #[serde(default)]
+    pub check: CheckDefaults,
+}
+
+/// Parsed configuration file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Config {
+    /// Target used when `--target` is not given.
+    #[serde(default)]
+    pub default_target: Option<String>,
+    /// Named install targets, e.g. `[targets.system]` and `[targets.user]`.
+    #[serde(default)]
+    pub targets: BTreeMap<String, InstallTarget>,
+    /// Record per-phase durations of each update in the local history log.
+    #[serde(default)]
+    pub record_timings: bool,
+    /// Install shell completions shipped in release archives, like `--install-completions`.
+    #[serde(default)]
+    pub install_completions: bool,
+    /// Regex the output of an installed binary's `--version`/`--help` must match
+    /// for it to be treated as opencode.
+    #[serde(default)]
+    pub identity_marker: Option<String>,
+    /// Command a new binary must pass before it is installed, see [`crate::scan`].
+    #[serde(default)]
+    pub scan_command: Option<String>,
+    /// Seconds the scanner may run before it is killed.
+    #[serde(default)]
+    pub scan_timeout: Option<u64>,
+    /// Attempts a failing request gets, see [`crate::retry`].
+    #[serde(default)]
+    pub retry_attempts: Option<u32>,
+    /// Store versions here for every user, see [`crate::shared`]; only read
+    /// from the system config.
+    #[serde(default)]
+    pub storage_dir: Option<PathBuf>,
+    /// Group owning the shared store, by name or number.
+    #[serde(default)]
+    pub shared_group: Option<String>,
+    /// What a bare invocation runs.
+    #[serde(default)]
+    pub default_command: Option<DefaultCommand>,
+    /// Flag defaults per command.
+    #[serde(default)]
+    pub defaults: CommandDefaults,
+    /// Where the latest release comes from, GitHub unless set.
+    #[serde(default)]
+    pub source: Option<crate::source::SourceConfig>,
+    /// Warning keys never shown, like `--suppress-warning`.
+    #[serde(default)]
+    pub suppress_warnings: Vec<String>,
+    /// Refuse plain-HTTP downloads and hosts outside the allowlist, see
+    /// [`crate::allowlist`].
+    #[serde(default)]
+    pub strict_sources: bool,
+    /// Hosts allowed with `strict_sources` besides GitHub's.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Releases updates follow, see [`crate::channel`].
+    #[serde(default)]
+    pub channel: Channel,
+    /// Release line updates keep to, like `--track`, see [`crate::constraint`].
+    #[serde(default)]
+    pub track: Option<Track>,
+    /// Newest version updates install, like `--max-version`.
+    #[serde(default)]
+    pub max_version: Option<Version>,
+    /// Which versions cleanup keeps: the most recently installed, or the
+    /// highest.
+    #[serde(default)]
+    pub cleanup_order: SortOrder,
+    /// Mirror release archives are downloaded from, like `--mirror`, see
+    /// [`crate::mirror`].
+    #[serde(default)]
+    pub mirror: Option<crate::mirror::Mirror>,
+}
+
+impl Config {
+    /// Default location of the config file.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("opencode-updater").join("config.toml"))
+    }
+
+    /// Load the config from the default location, or defaults if it doesn't exist.
+    pub fn load() -> Result<Self, UpdaterError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Load the config from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, UpdaterError> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| UpdaterError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Load the system config, or defaults if it doesn't exist.
+    pub fn load_system() -> Result<Self, UpdaterError> {
+        let path = Path::new(crate::shared::SYSTEM_CONFIG_PATH);
+        if path.exists() {
+            Self::load_from(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Write the commented default config to `path` unless a file already exists there.
+    ///
+    /// Returns whether a new file was written.
+    pub fn write_default(path: &Path) -> Result<bool, UpdaterError> {
+        if path.exists() {
+            return Ok(false);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, DEFAULT_CONFIG)?;
+        Ok(true)
+    }
+
+    /// Set `channel` in the config file at `path`, keeping the rest of the
+    /// file as written.
+    pub fn set_channel(path: &Path, channel: Channel) -> Result<(), UpdaterError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let setting = format!("channel = \"{}\"", channel);
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        // Top-level keys come before the first table
+        let tables = lines
+            .iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .unwrap_or(lines.len());
+        let existing = lines[..tables].iter().position(|line| {
+            line.split_once('=')
+                .is_some_and(|(key, _)| key.trim() == "channel")
+        });
+        match existing {
+            Some(index) => lines[index] = setting,
+            None if tables < lines.len() => {
+                lines.insert(tables, String::new());
+                lines.insert(tables, setting);
+            }
+            None => lines.push(setting),
+        }
+        let updated = lines.join("\n") + "\n";
+        // Never leave a config behind that no longer loads
+        Self::parse(&updated)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        crate::write_atomic(path, updated.as_bytes())?;
+        Ok(())
+    }
+
+    /// Parse config from TOML text.
+    pub fn parse(content: &str) -> Result<Self, UpdaterError> {
+        toml::from_str(content).map_err(|e| UpdaterError::InvalidConfig(e.to_string()))
+    }
+
+    /// The arguments in `matches`, completed from the config: a bare
+    /// invocation runs `default_command`, and flags the command line didn't
+    /// give take their value from `[defaults.<command>]`.
+    pub fn args_from(&self, matches: &ArgMatches) -> Result<Args, clap::Error> {
+        let mut args = Args::from_arg_matches(matches)?;
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        let bare = matches.subcommand().is_none()
+            && Args::command()
+                .get_arguments()
+                .filter(|arg| !arg.is_global_set())
+                .all(|arg| !given(arg.get_id().as_str()));
+        if bare && self.default_command == Some(DefaultCommand::Check) {
+            args.check = true;
+        }
+
+        if args.runs_update() || args.runs_check() {
+            if !given("track") {
+                args.track = args.track.or_else(|| self.track.clone());
+            }
+            if !given("max_version") {
+                args.max_version = args.max_version.or_else(|| self.max_version.clone());
+            }
+        }
+
+        if !given("mirror") {
+            args.mirror = args.mirror.or_else(|| self.mirror.clone());
+        }
+        if let Some(mirror) = &args.mirror
+            && !mirror.is_https()
+            && !args.allow_insecure_source
+        {
+            return Err(Args::command().error(
+                clap::error::ErrorKind::InvalidValue,
+                format!(
+                    "mirror {} is plain HTTP, so an archive could be altered on the way to it; use an https:// mirror or pass --allow-insecure-source",
+                    mirror
+                ),
+            ));
+        }
+
+        let mut json = None;
+        if args.runs_update() {
+            let defaults = &self.defaults.update;
+            if let Some(keep_versions) = defaults.keep_versions.filter(|_| !given("keep_versions"))
+            {
+                args.keep_versions = keep_versions;
+            }
+            if !given("install_bak") {
+                args.install_bak = defaults.install_bak.unwrap_or(args.install_bak);
+            }
+            if !given("sbom") {
+                args.sbom = defaults.sbom.unwrap_or(args.sbom);
+            }
+            json = defaults.json;
+        } else if args.runs_check() {
+            json = self.defaults.check.json;
+        }
+        // --quiet on the command line rules out JSON from the config
+        if !given("json") && !given("quiet") {
+            args.json = json.unwrap_or(args.json);
+        }
+        Ok(args)
+    }
+
+    /// The configured release source; GitHub is reached at `api_base`.
+    ///
+    /// Releases are limited to those `constraint` allows, if given.
+    pub fn release_source(
+        &self,
+        api_base: &str,
+        cache_dir: &Path,
+        constraint: Option<VersionConstraint>,
+    ) -> Box<dyn crate::source::ReleaseSource> {
+        self.source
+            .as_ref()
+            .unwrap_or(&crate::source::SourceConfig::Github)
+            .release_source(api_base, cache_dir, self.channel, constraint)
+    }
+
+    /// The warning registry for this run: keys suppressed here or by
+    /// `suppress`, and throttled across runs by `last_shown` if given.
+    pub fn warning_registry(
+        &self,
+        suppress: &[String],
+        last_shown: Option<BTreeMap<String, chrono::DateTime<chrono::Utc>>>,
+    ) -> Result<crate::reporter::WarningRegistry, UpdaterError> {
+        if let Some(unknown) = self
+            .suppress_warnings
+            .iter()
+            .find(|key| !crate::reporter::WARNING_KEYS.contains(&key.as_str()))
+        {
+            return Err(UpdaterError::InvalidConfig(format!(
+                "unknown warning key '{}' in suppress_warnings; known keys: {}",
+                unknown,
+                crate::reporter::WARNING_KEYS.join(", ")
+            )));
+        }
+        let registry = crate::reporter::WarningRegistry::new()
+            .suppress(self.suppress_warnings.iter().chain(suppress).cloned());
+        Ok(match last_shown {
+            Some(last_shown) => registry.throttle(last_shown),
+            None => registry,
+        })
+    }
+
+    /// The retry policy requests follow, with `retry_attempts` if set.
+    pub fn retry_policy(&self) -> crate::retry::RetryPolicy {
+        crate::retry::RetryPolicy {
+            attempts: self
+                .retry_attempts
+                .unwrap_or(crate::retry::DEFAULT_ATTEMPTS)
+                .max(1),
+            ..crate::retry::RetryPolicy::DEFAULT
+        }
+    }
+
+    /// The host policy to enforce: none unless `strict_sources` is set and
+    /// `allow_insecure` (`--allow-insecure-source`) is not.
+    pub fn host_policy(
+        &self,
+        allow_insecure: bool,
+    ) -> Result<Option<crate::allowlist::HostPolicy>, crate::UpdaterError> {
+        if !self.strict_sources {
+            return Ok(None);
+        }
+        let policy = crate::allowlist::HostPolicy::new(&self.allowed_hosts)?;
+        Ok((!allow_insecure).then_some(policy))
+    }
+
+    /// The configured scanner, if any.
+    pub fn scanner(&self) -> Option<crate::scan::Scanner> {
+        let command = self.scan_command.as_deref().map(str::trim)?;
+        (!command.is_empty()).then(|| crate::scan::Scanner {
+            command: command.to_string(),
+            timeout: self.scan_timeout.map_or(
+                crate::scan::DEFAULT_SCAN_TIMEOUT,
+                std::time::Duration::from_secs,
+            ),
+        })
+    }
+
+    /// The shared store configured by `storage_dir`, if any.
+    pub fn shared_storage(&self) -> Option<crate::shared::SharedStorage> {
+        self.storage_dir
+            .as_ref()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(|dir| crate::shared::SharedStorage {
+                dir: dir.clone(),
+                group: self.shared_group.clone(),
+            })
+    }
+
+    /// Compiled identity marker, or the default matching upstream opencode.
+    pub fn identity_marker_regex(&self) -> Result<regex::Regex, UpdaterError> {
+        crate::identity::marker_regex(self.identity_marker.as_deref())
+    }
+
+    /// Configured targets, falling back to the built-in system target when none are defined.
+    pub fn targets(&self) -> BTreeMap<String, InstallTarget> {
+        if self.targets.is_empty() {
+            BTreeMap::from([(DEFAULT_TARGET_NAME.to_string(), InstallTarget::system())])
+        } else {
+            self.targets.clone()
+        }
+    }
+
+    /// Name of the target commands operate on by default.
+    pub fn default_target_name(&self) -> String {
+        if let Some(name) = &self.default_target {
+            return name.clone();
+        }
+        let targets = self.targets();
+        if targets.contains_key(DEFAULT_TARGET_NAME) {
+            DEFAULT_TARGET_NAME.to_string()
+        } else {
+            // Non-empty: targets() always yields at least the system target
+            targets.keys().next().cloned().unwrap_or_default()
+        }
+    }
+
+    /// Resolve which targets a command should act on.
+    ///
+    /// `all` selects every configured target; otherwise `name` (or the default
+    /// target) must exist in the config.
+    pub fn resolve_targets(
+        &self,
+        name: Option<&str>,
+        all: bool,
+    ) -> Result<Vec<(String, InstallTarget)>, UpdaterError> {
+        let targets = self.targets();
+        if all {
+            return Ok(targets.into_iter().collect());
+        }
+
+        let name = name
+            .map(str::to_string)
+            .unwrap_or_else(|| self.default_target_name());
+        match targets.get(&name) {
+            Some(target) => Ok(vec![(name, target.clone())]),
+            None => Err(UpdaterError::InvalidConfig(format!(
+                "unknown install target '{}' (configured: {})",
+                name,
+                targets.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))),
+        }
+    }
+}
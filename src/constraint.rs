@@ -0,0 +1,121 @@
+//! Version constraints: staying on a release line, or below a version.
+//!
+//! `--track 0.3` takes only releases whose version starts with the
+//! components `0.3`, so `0.3.200` but not `0.30.1` or `0.4.0`, and
+//! `--max-version 0.3.200` only releases up to that version. Both can be
+//! given, and both can be set in the config file as `track` and
+//! `max_version` so plain runs keep to them. A constrained update looks
+//! through the whole release list for the newest release allowed, rather
+//! than taking GitHub's latest; tags that aren't versions, like rolling
+//! builds, are never allowed.
+
+use crate::{Channel, Version};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// A release line: the leading components of its versions, like `0.3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Track(pub Vec<u64>);
+
+impl FromStr for Track {
+    type Err = String;
+
+    /// One or more numeric components, like `0.3` or `v1`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .trim()
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Track)
+            .map_err(|_| format!("'{}' is not a version prefix like 0.3", value))
+    }
+}
+
+impl std::fmt::Display for Track {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components: Vec<String> = self.0.iter().map(u64::to_string).collect();
+        write!(f, "{}", components.join("."))
+    }
+}
+
+impl Serialize for Track {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Track {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which releases updates may install.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionConstraint {
+    /// The release line releases must be on.
+    pub track: Option<Track>,
+    /// Newest version allowed.
+    pub max_version: Option<Version>,
+}
+
+impl VersionConstraint {
+    /// The constraint of `track` and `max_version`, or `None` when neither
+    /// is given.
+    pub fn new(track: Option<Track>, max_version: Option<Version>) -> Option<Self> {
+        (track.is_some() || max_version.is_some()).then_some(Self { track, max_version })
+    }
+
+    /// Whether release `tag` is allowed.
+    pub fn allows(&self, tag: &str) -> bool {
+        let Ok(version) = tag.parse::<Version>() else {
+            return false;
+        };
+        if let Some(Track(track)) = &self.track {
+            let components = [version.major, version.minor, version.patch]
+                .into_iter()
+                .chain(version.extra.iter().copied());
+            if !track
+                .iter()
+                .zip(components.chain(std::iter::repeat(0)))
+                .all(|(wanted, actual)| *wanted == actual)
+            {
+                return false;
+            }
+        }
+        self.max_version.as_ref().is_none_or(|max| version <= *max)
+    }
+
+    /// The newest of `releases` allowed, among those `channel` follows.
+    pub fn newest<'a>(
+        &self,
+        releases: &'a [serde_json::Value],
+        channel: Channel,
+    ) -> Option<&'a serde_json::Value> {
+        releases
+            .iter()
+            .filter(|release| channel.follows(release))
+            .filter_map(|release| {
+                let tag = release["tag_name"].as_str()?;
+                self.allows(tag)
+                    .then(|| Some((tag.parse::<Version>().ok()?, release)))?
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, release)| release)
+    }
+}
+
+impl std::fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.track, &self.max_version) {
+            (Some(track), Some(max)) => write!(f, "{}.x up to {}", track, max),
+            (Some(track), None) => write!(f, "{}.x", track),
+            (None, Some(max)) => write!(f, "versions up to {}", max),
+            (None, None) => write!(f, "any version"),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! `digest`: what upstream published since the last update, for skimming.
+//!
+//! The window starts at the last entry of the history log, or at `--since`.
+//! Releases published after it are counted and listed newest first; the
+//! version span is judged by version order, so a backport published late
+//! doesn't end up as the newest, and rolling tags are listed but left out
+//! of it. Asset size growth compares the default asset of the newest release
+//! with that of the last release before the window, or of the oldest in it
+//! when the window reaches back past every release.
+
+use crate::history::HistoryEntry;
+use crate::units::{format_size, format_size_delta};
+use crate::{
+    DEFAULT_ASSET_NAMES, find_asset, is_rolling_tag, sanitize_terminal_text, version_order,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+/// Where the digest window starts.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinceSource {
+    /// The last entry of the history log.
+    LastUpdate,
+    /// `--since`.
+    Requested,
+    /// No history and no `--since`: every release.
+    Beginning,
+}
+
+/// A release in the digest.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DigestRelease {
+    pub tag: String,
+    /// The release's name, or its tag when it has none.
+    pub title: String,
+    pub url: String,
+    pub published_at: DateTime<Utc>,
+    /// Size of the default asset in bytes.
+    pub asset_size: Option<u64>,
+}
+
+/// Releases published since a point in time, aggregated.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Digest {
+    pub since: Option<DateTime<Utc>>,
+    pub since_source: SinceSource,
+    pub release_count: usize,
+    /// Lowest and highest version in the window.
+    pub from_version: Option<String>,
+    pub to_version: Option<String>,
+    /// Default asset size before the window and of its newest release.
+    pub size_before: Option<u64>,
+    pub size_after: Option<u64>,
+    /// Newest first.
+    pub releases: Vec<DigestRelease>,
+}
+
+impl Digest {
+    /// Growth of the default asset across the window, in bytes.
+    pub fn size_growth(&self) -> Option<i64> {
+        Some(self.size_after? as i64 - self.size_before? as i64)
+    }
+}
+
+/// Start of the window: the last update run, if there was one.
+pub fn since_last_update(entries: &[HistoryEntry]) -> Option<DateTime<Utc>> {
+    entries.iter().map(|entry| entry.started_at).max()
+}
+
+/// Parse `--since`: a date, taken as midnight UTC, or an RFC 3339 time.
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("'{}' is neither YYYY-MM-DD nor an RFC 3339 time", value))
+}
+
+fn published_at(release: &serde_json::Value) -> Option<DateTime<Utc>> {
+    release["published_at"]
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+fn default_asset_size(release: &serde_json::Value) -> Option<u64> {
+    let assets = release["assets"].as_array()?;
+    DEFAULT_ASSET_NAMES
+        .iter()
+        .find_map(|name| find_asset(assets, name))
+        .and_then(|asset| asset["size"].as_u64())
+}
+
+/// Aggregate the published `releases` since `since`.
+pub fn build(
+    releases: &[serde_json::Value],
+    since: Option<DateTime<Utc>>,
+    since_source: SinceSource,
+) -> Digest {
+    // Drafts have no publication date and are left out with it
+    let mut dated: Vec<(DateTime<Utc>, &serde_json::Value)> = releases
+        .iter()
+        .filter_map(|release| Some((published_at(release)?, release)))
+        .collect();
+    dated.sort_by_key(|(published, _)| std::cmp::Reverse(*published));
+    let (window, before): (Vec<_>, Vec<_>) = dated
+        .into_iter()
+        .partition(|(published, _)| since.is_none_or(|since| *published > since));
+
+    let versions: Vec<&str> = window
+        .iter()
+        .filter_map(|(_, release)| release["tag_name"].as_str())
+        .filter(|tag| !is_rolling_tag(tag))
+        .collect();
+    let by_version = |a: &&str, b: &&str| version_order(a, b).ordering;
+    let from_version = versions.iter().copied().min_by(by_version);
+    let to_version = versions.iter().copied().max_by(by_version);
+
+    let size_before = match before.first() {
+        Some((_, release)) => default_asset_size(release),
+        None => window
+            .last()
+            .and_then(|(_, release)| default_asset_size(release)),
+    };
+    let size_after = window
+        .first()
+        .and_then(|(_, release)| default_asset_size(release));
+
+    let releases: Vec<DigestRelease> = window
+        .iter()
+        .map(|(published_at, release)| {
+            let tag = release["tag_name"].as_str().unwrap_or("unknown");
+            DigestRelease {
+                tag: tag.to_string(),
+                title: release["name"]
+                    .as_str()
+                    .filter(|name| !name.trim().is_empty())
+                    .unwrap_or(tag)
+                    .to_string(),
+                url: release["html_url"].as_str().unwrap_or_default().to_string(),
+                published_at: *published_at,
+                asset_size: default_asset_size(release),
+            }
+        })
+        .collect();
+
+    Digest {
+        since,
+        since_source,
+        release_count: releases.len(),
+        from_version: from_version.map(str::to_string),
+        to_version: to_version.map(str::to_string),
+        size_before: size_before.filter(|_| !releases.is_empty()),
+        size_after,
+        releases,
+    }
+}
+
+/// "since 2025-10-01 (last update)" and the like.
+fn describe_since(digest: &Digest) -> String {
+    match (digest.since, digest.since_source) {
+        (Some(since), SinceSource::LastUpdate) => {
+            format!("since {} (last update)", since.format("%Y-%m-%d"))
+        }
+        (Some(since), _) => format!("since {}", since.format("%Y-%m-%d")),
+        (None, _) => "in total".to_string(),
+    }
+}
+
+/// "v1.2.0 → v1.4.0", or the one version.
+fn describe_span(digest: &Digest) -> Option<String> {
+    match (&digest.from_version, &digest.to_version) {
+        (Some(from), Some(to)) if from != to => Some(format!("{} → {}", from, to)),
+        (_, Some(to)) => Some(to.clone()),
+        _ => None,
+    }
+}
+
+fn describe_size(digest: &Digest) -> Option<String> {
+    Some(format!(
+        "{} → {} ({})",
+        format_size(digest.size_before?),
+        format_size(digest.size_after?),
+        format_size_delta(digest.size_growth()?)
+    ))
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.releases.is_empty() {
+            return write!(f, "No new releases {}", describe_since(self));
+        }
+        write!(
+            f,
+            "{} release(s) {}",
+            self.release_count,
+            describe_since(self)
+        )?;
+        if let Some(span) = describe_span(self) {
+            write!(f, ": {}", sanitize_terminal_text(&span))?;
+        }
+        if let Some(size) = describe_size(self) {
+            write!(f, "\nAsset size: {}", size)?;
+        }
+        for release in &self.releases {
+            write!(
+                f,
+                "\n  • {} ({})",
+                sanitize_terminal_text(&release.title),
+                release.published_at.format("%Y-%m-%d")
+            )?;
+            if !release.url.is_empty() {
+                write!(f, " {}", sanitize_terminal_text(&release.url))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Text of a markdown link, with what would end it escaped.
+fn markdown_text(text: &str) -> String {
+    sanitize_terminal_text(text)
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// The digest as markdown, for pasting into a chat.
+pub fn render_markdown(digest: &Digest) -> String {
+    let mut heading = format!(
+        "**opencode: {} release(s) {}**",
+        digest.release_count,
+        describe_since(digest)
+    );
+    if digest.releases.is_empty() {
+        heading = format!("**opencode: no new releases {}**", describe_since(digest));
+    }
+    let details: Vec<String> = describe_span(digest)
+        .map(|span| markdown_text(&span))
+        .into_iter()
+        .chain(describe_size(digest).map(|size| format!("asset size {}", size)))
+        .collect();
+    if !details.is_empty() {
+        heading.push_str(&format!(" ({})", details.join(", ")));
+    }
+
+    let mut lines = vec![heading];
+    if !digest.releases.is_empty() {
+        lines.push(String::new());
+    }
+    for release in &digest.releases {
+        let title = markdown_text(&release.title);
+        let date = release.published_at.format("%Y-%m-%d");
+        lines.push(if release.url.is_empty() {
+            format!("- {} ({})", title, date)
+        } else {
+            format!(
+                "- [{}]({}) ({})",
+                title,
+                sanitize_terminal_text(&release.url),
+                date
+            )
+        });
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,192 @@
+//! `doctor`: diagnose the environment the updater runs in.
+//!
+//! Each check produces a [`DoctorCheck`]; checks never fail the command, they
+//! only report what they found.
+
+use crate::clock;
+use crate::network::{self, RateLimit};
+use serde::Serialize;
+use ureq::Agent;
+
+/// Result of a single check.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+/// One diagnostic finding.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl DoctorCheck {
+    fn new(name: &str, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// All findings of a `doctor` run.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    /// Whether any check found something worth attention.
+    pub fn has_problems(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.status != CheckStatus::Ok)
+    }
+}
+
+impl std::fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, check) in self.checks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let icon = match check.status {
+                CheckStatus::Ok => "✅",
+                CheckStatus::Warning => "⚠️ ",
+                CheckStatus::Failed => "❌",
+            };
+            write!(f, "{} {}: {}", icon, check.name, check.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the local clock with GitHub's `Date` header.
+pub fn check_clock(client: &Agent, base_url: &str) -> DoctorCheck {
+    match clock::measure_skew(client, base_url) {
+        Ok(skew) if skew.is_significant() => {
+            DoctorCheck::new("clock", CheckStatus::Warning, skew.to_string())
+        }
+        Ok(skew) => DoctorCheck::new("clock", CheckStatus::Ok, skew.to_string()),
+        Err(e) => DoctorCheck::new(
+            "clock",
+            CheckStatus::Failed,
+            format!("could not compare with GitHub: {}", e),
+        ),
+    }
+}
+
+/// Report the GitHub API rate limit, as seen by the last request.
+pub fn check_rate_limit(rate_limit: Option<RateLimit>) -> DoctorCheck {
+    const NAME: &str = "GitHub API";
+    match rate_limit {
+        Some(rate_limit) if rate_limit.remaining == 0 => DoctorCheck::new(
+            NAME,
+            CheckStatus::Failed,
+            format!("{}; requests fail until then", rate_limit),
+        ),
+        Some(rate_limit) => match rate_limit.warning() {
+            Some(warning) => DoctorCheck::new(NAME, CheckStatus::Warning, warning),
+            None => DoctorCheck::new(NAME, CheckStatus::Ok, rate_limit.to_string()),
+        },
+        None => DoctorCheck::new(
+            NAME,
+            CheckStatus::Warning,
+            "GitHub didn't report a rate limit",
+        ),
+    }
+}
+
+/// Report entries of the versions directory that aren't stored versions.
+pub fn check_foreign_entries(entries: &[crate::ForeignEntry]) -> DoctorCheck {
+    const NAME: &str = "storage";
+    if entries.is_empty() {
+        return DoctorCheck::new(
+            NAME,
+            CheckStatus::Ok,
+            "only stored versions in the versions directory",
+        );
+    }
+    let listed: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+    DoctorCheck::new(
+        NAME,
+        CheckStatus::Warning,
+        format!(
+            "{} foreign entr{} in the versions directory, never touched by cleanup; remove with `clean --foreign`:\n  {}",
+            entries.len(),
+            if entries.len() == 1 { "y" } else { "ies" },
+            listed.join("\n  ")
+        ),
+    )
+}
+
+/// Report stored binaries that lost their executable bit.
+pub fn check_modes(problems: &[crate::ModeProblem]) -> DoctorCheck {
+    const NAME: &str = "modes";
+    if problems.is_empty() {
+        return DoctorCheck::new(NAME, CheckStatus::Ok, "every stored binary is executable");
+    }
+    let listed: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+    DoctorCheck::new(
+        NAME,
+        CheckStatus::Warning,
+        format!(
+            "{} stored binar{} not executable; restore with `--verify-storage --repair`:\n  {}",
+            problems.len(),
+            if problems.len() == 1 {
+                "y is"
+            } else {
+                "ies are"
+            },
+            listed.join("\n  ")
+        ),
+    )
+}
+
+/// Report install targets holding a build no stored version has.
+pub fn check_divergence(divergences: &[crate::Divergence]) -> DoctorCheck {
+    const NAME: &str = "installs";
+    if divergences.is_empty() {
+        return DoctorCheck::new(
+            NAME,
+            CheckStatus::Ok,
+            "every installed binary matches a stored version",
+        );
+    }
+    let listed: Vec<String> = divergences
+        .iter()
+        .map(|divergence| {
+            format!(
+                "{}; keep it with `{}`",
+                divergence,
+                divergence.adopt_command()
+            )
+        })
+        .collect();
+    DoctorCheck::new(NAME, CheckStatus::Warning, listed.join("\n"))
+}
+
+/// Report what the startup sweep cleared up after crashed runs.
+pub fn check_recovery(report: &crate::recovery::RecoveryReport) -> DoctorCheck {
+    let status = if report.is_empty() {
+        CheckStatus::Ok
+    } else {
+        CheckStatus::Warning
+    };
+    DoctorCheck::new("recovery", status, report.to_string())
+}
+
+/// Run every check.
+pub fn run_doctor(client: &Agent, base_url: &str) -> DoctorReport {
+    // The clock check's request also reports the rate limit
+    let clock = check_clock(client, base_url);
+    DoctorReport {
+        checks: vec![clock, check_rate_limit(network::usage().rate_limit())],
+    }
+}
@@ -0,0 +1,187 @@
+//! Compressed HTTP responses.
+//!
+//! API responses are requested gzip-compressed, since the release listing is
+//! megabytes of JSON that compresses well. Release assets are requested
+//! uncompressed, as they are archives already, but a mirror may gzip them
+//! regardless, so a gzip body is decoded wherever it turns up.
+//!
+//! `Content-Length` counts the bytes on the wire, so download progress and
+//! the check for a transfer cut short count those too, and the body is only
+//! decoded once it is complete.
+
+use crate::{UpdaterError, sanitize_terminal_text};
+use flate2::read::GzDecoder;
+use std::io::Read;
+use ureq::ResponseExt;
+use ureq::http::HeaderMap;
+
+/// `Accept-Encoding` of API requests.
+pub const API_ACCEPT_ENCODING: &str = "gzip";
+
+/// `Accept-Encoding` of asset downloads.
+pub const ASSET_ACCEPT_ENCODING: &str = "identity";
+
+/// Leading bytes of gzip data.
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// How a response body is encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// The encoding named by a response's `Content-Encoding` header.
+    pub fn of(headers: &HeaderMap) -> Result<Self, UpdaterError> {
+        let Some(value) = headers.get("Content-Encoding") else {
+            return Ok(Self::Identity);
+        };
+        let value = value
+            .to_str()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match value.as_str() {
+            "" | "identity" => Ok(Self::Identity),
+            "gzip" | "x-gzip" => Ok(Self::Gzip),
+            other => Err(UpdaterError::NetworkError(format!(
+                "server sent the unsupported Content-Encoding '{}'",
+                sanitize_terminal_text(other)
+            ))),
+        }
+    }
+
+    /// Decode a complete body received in this encoding.
+    pub fn decode(self, body: Vec<u8>) -> Result<Vec<u8>, UpdaterError> {
+        match self {
+            Self::Identity => Ok(body),
+            Self::Gzip => {
+                let mut decoded = Vec::new();
+                GzDecoder::new(&body[..])
+                    .read_to_end(&mut decoded)
+                    .map_err(|e| {
+                        UpdaterError::NetworkError(format!(
+                            "could not decompress the gzip-encoded response: {}",
+                            e
+                        ))
+                    })?;
+                Ok(decoded)
+            }
+        }
+    }
+
+    /// Decode the complete body of the asset `filename`.
+    ///
+    /// A server labelling a `.gz` file with its own compression as
+    /// `Content-Encoding: gzip` is a common misconfiguration, so such an
+    /// asset is only decoded if what comes out is still gzip.
+    pub fn decode_asset(self, body: Vec<u8>, filename: &str) -> Result<Vec<u8>, UpdaterError> {
+        let gzip_file = [".gz", ".tgz"]
+            .iter()
+            .any(|suffix| filename.ends_with(suffix));
+        if self != Self::Gzip || !gzip_file {
+            return self.decode(body);
+        }
+        match self.decode(body.clone()) {
+            Ok(decoded) if decoded.starts_with(GZIP_MAGIC) => Ok(decoded),
+            _ => Ok(body),
+        }
+    }
+}
+
+/// `Content-Length` of a response, the size of its body on the wire.
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("Content-Length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// A body that ended before all the bytes its `Content-Length` announced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CutShort {
+    pub what: String,
+    /// Bytes that arrived, on the wire.
+    pub received: u64,
+    pub expected: u64,
+    pub encoding: ContentEncoding,
+}
+
+impl std::fmt::Display for CutShort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} was cut short: received {} of {} bytes{}",
+            sanitize_terminal_text(&self.what),
+            self.received,
+            self.expected,
+            if self.encoding == ContentEncoding::Gzip {
+                " of gzip-encoded data"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+impl std::error::Error for CutShort {}
+
+impl CutShort {
+    /// The error of a request to `url` whose connection closed early, kept
+    /// transient so the download is retried.
+    pub fn into_request_error(self, url: &str) -> UpdaterError {
+        crate::payload::request_error(
+            url,
+            ureq::Error::Io(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, self)),
+        )
+    }
+}
+
+/// Error unless all `expected` bytes of `what` arrived, counted as received
+/// on the wire in `encoding`.
+pub fn check_complete(
+    what: &str,
+    received: u64,
+    expected: Option<u64>,
+    encoding: ContentEncoding,
+) -> Result<(), UpdaterError> {
+    match expected {
+        Some(expected) if received != expected => Err(UpdaterError::NetworkError(
+            CutShort {
+                what: what.to_string(),
+                received,
+                expected,
+                encoding,
+            }
+            .to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Read the whole body of the response to `what`, check it is complete and
+/// decode it.
+pub fn read_body(
+    response: ureq::http::Response<ureq::Body>,
+    what: &str,
+) -> Result<Vec<u8>, UpdaterError> {
+    let encoding = ContentEncoding::of(response.headers())?;
+    let expected = content_length(response.headers());
+    let url = response.get_uri().to_string();
+    let body = response
+        .into_body()
+        .read_to_vec()
+        .map_err(|e| crate::payload::request_error(&url, e))?;
+    check_complete(what, body.len() as u64, expected, encoding)?;
+    encoding.decode(body)
+}
+
+/// [`read_body`] as text.
+pub fn read_text(
+    response: ureq::http::Response<ureq::Body>,
+    what: &str,
+) -> Result<String, UpdaterError> {
+    String::from_utf8(read_body(response, what)?)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into())
+}
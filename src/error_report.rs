@@ -0,0 +1,98 @@
+//! Failures under `--json`: the error as a document on stderr.
+//!
+//! A playbook or script parsing the output gets the error's kind, as the
+//! history records it, its message and hint, the exit code, whether a retry
+//! may succeed, and the fields of the error itself, such as the expected and
+//! actual checksums of a mismatch.
+
+use crate::{UpdaterError, exit_code, hint, history};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The `error` document.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ErrorDocument {
+    pub error: ErrorReport,
+}
+
+/// A failure, as written under `--json`.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+    /// As in `history`, e.g. `checksum` or `version_not_found`.
+    pub kind: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    pub exit_code: i32,
+    /// Whether running again later may succeed.
+    pub transient: bool,
+    /// The error's own fields, by kind: `expected` and `actual` of a
+    /// checksum mismatch, `url` and `status` of a failed request, and so on.
+    #[serde(flatten)]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ErrorReport {
+    pub fn new(error: &(dyn std::error::Error + 'static)) -> Self {
+        let updater_error = error.downcast_ref::<UpdaterError>();
+        Self {
+            kind: history::error_kind(error).to_string(),
+            message: error.to_string(),
+            hint: updater_error.and_then(hint::error_hint),
+            exit_code: exit_code::for_error(error),
+            transient: updater_error.is_some_and(UpdaterError::is_transient),
+            details: updater_error.map(details).unwrap_or_default(),
+        }
+    }
+}
+
+/// The fields of `error` beyond its message.
+pub fn details(error: &UpdaterError) -> serde_json::Map<String, serde_json::Value> {
+    let details = match error {
+        UpdaterError::VersionNotFound(version) => serde_json::json!({ "version": version }),
+        UpdaterError::ChecksumMismatch(expected, actual) => serde_json::json!({
+            "expected": expected,
+            "actual": actual,
+        }),
+        UpdaterError::RepeatedChecksumMismatch {
+            file,
+            expected,
+            actual,
+        } => serde_json::json!({
+            "file": file,
+            "expected": expected,
+            "actual": actual,
+        }),
+        UpdaterError::InvalidVersionFormat(version) => serde_json::json!({ "version": version }),
+        UpdaterError::ApiStatus { status, .. } => serde_json::json!({ "status": status }),
+        UpdaterError::RateLimited {
+            reset_at,
+            remaining,
+        } => serde_json::json!({
+            "reset_at": reset_at,
+            "remaining": remaining,
+        }),
+        UpdaterError::Http { url, source } => match source {
+            ureq::Error::StatusCode(status) => serde_json::json!({
+                "url": url,
+                "status": status,
+            }),
+            _ => serde_json::json!({ "url": url }),
+        },
+        UpdaterError::Io(e) => serde_json::json!({ "io_kind": e.kind().to_string() }),
+        UpdaterError::ExtractionError {
+            archive, format, ..
+        } => serde_json::json!({
+            "archive": archive,
+            "format": format.map(|format| format.to_string()),
+        }),
+        UpdaterError::MetadataError {
+            path: Some(path), ..
+        } => serde_json::json!({ "path": path }),
+        _ => serde_json::json!({}),
+    };
+    match details {
+        serde_json::Value::Object(fields) => fields,
+        _ => serde_json::Map::new(),
+    }
+}
@@ -0,0 +1,61 @@
+//! Process exit codes, so scripts can tell failures apart.
+//!
+//! `maintain` has codes of its own, see `MaintainReport::exit_code`.
+
+use crate::{STAGED_EXIT_CODE, UpdaterError, progress};
+
+/// Any failure without a code of its own.
+pub const FAILURE: i32 = 1;
+/// `--check` found nothing newer than the installed version.
+pub const NO_UPDATE: i32 = 2;
+/// GitHub or a download host couldn't be reached, answered with an error,
+/// or the API budget or rate limit ran out.
+pub const NETWORK: i32 = 3;
+/// A download didn't match its checksum, or a stored binary its HMAC.
+pub const CHECKSUM: i32 = 4;
+/// A file couldn't be written for lack of permission, or sudo failed.
+pub const PERMISSION: i32 = 5;
+/// The version asked for isn't stored or released.
+pub const VERSION_NOT_FOUND: i32 = 6;
+
+/// Exit code of a run that failed with `error`.
+pub fn for_error(error: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(error) = error.downcast_ref::<UpdaterError>() {
+        return of(error);
+    }
+    if let Some(error) = error.downcast_ref::<std::io::Error>() {
+        return of_io(error);
+    }
+    if error.downcast_ref::<ureq::Error>().is_some() {
+        return NETWORK;
+    }
+    FAILURE
+}
+
+/// Exit code of a run that failed with `error`.
+pub fn of(error: &UpdaterError) -> i32 {
+    match error {
+        UpdaterError::NetworkError(_)
+        | UpdaterError::GitHubApiError(_)
+        | UpdaterError::ApiStatus { .. }
+        | UpdaterError::RateLimited { .. }
+        | UpdaterError::ApiBudgetExhausted(_)
+        | UpdaterError::Http { .. } => NETWORK,
+        UpdaterError::ChecksumMismatch(..)
+        | UpdaterError::RepeatedChecksumMismatch { .. }
+        | UpdaterError::IntegrityError(_) => CHECKSUM,
+        UpdaterError::PermissionError(_) => PERMISSION,
+        UpdaterError::VersionNotFound(_) => VERSION_NOT_FOUND,
+        UpdaterError::Io(e) => of_io(e),
+        UpdaterError::InstallStaged(_) => STAGED_EXIT_CODE,
+        UpdaterError::Interrupted(_) => progress::INTERRUPTED_EXIT_CODE,
+        _ => FAILURE,
+    }
+}
+
+fn of_io(error: &std::io::Error) -> i32 {
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied => PERMISSION,
+        _ => FAILURE,
+    }
+}
@@ -0,0 +1,108 @@
+//! Safety checks an update can be told to skip, one flag per check.
+//!
+//! `--force` used to bypass everything at once. Each [`Guard`] now has its
+//! own flag, and every refusal names the flag that would bypass it, so a
+//! script can grant exactly what it needs. `--force` still implies all of
+//! them.
+
+use crate::Args;
+use std::path::Path;
+use std::process::Command;
+
+/// A check that refuses an update unless its flag is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guard {
+    /// The release is already installed.
+    Reinstall,
+    /// The release is older than the installed version.
+    Downgrade,
+    /// The install target belongs to a system package.
+    Packaged,
+    /// The install target is a different program called `opencode`.
+    Foreign,
+    /// The install target holds a build no stored version has.
+    Modified,
+}
+
+impl Guard {
+    pub const ALL: [Guard; 5] = [
+        Guard::Reinstall,
+        Guard::Downgrade,
+        Guard::Packaged,
+        Guard::Foreign,
+        Guard::Modified,
+    ];
+
+    /// Command-line flag that bypasses this guard.
+    pub fn flag(self) -> &'static str {
+        match self {
+            Guard::Reinstall => "--force-reinstall",
+            Guard::Downgrade => "--allow-downgrade",
+            Guard::Packaged => "--overwrite-packaged",
+            Guard::Foreign => "--replace-foreign",
+            Guard::Modified => "--overwrite-modified",
+        }
+    }
+}
+
+/// Which guards the command line bypasses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub force_reinstall: bool,
+    pub allow_downgrade: bool,
+    pub overwrite_packaged: bool,
+    pub replace_foreign: bool,
+    pub overwrite_modified: bool,
+}
+
+impl Overrides {
+    /// Overrides requested by `args`; `--force` implies all of them.
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            force_reinstall: args.force || args.force_reinstall,
+            allow_downgrade: args.force || args.allow_downgrade,
+            overwrite_packaged: args.force || args.overwrite_packaged,
+            replace_foreign: args.force || args.replace_foreign,
+            overwrite_modified: args.force || args.overwrite_modified,
+        }
+    }
+
+    /// Whether `guard` is bypassed.
+    pub fn allows(&self, guard: Guard) -> bool {
+        match guard {
+            Guard::Reinstall => self.force_reinstall,
+            Guard::Downgrade => self.allow_downgrade,
+            Guard::Packaged => self.overwrite_packaged,
+            Guard::Foreign => self.replace_foreign,
+            Guard::Modified => self.overwrite_modified,
+        }
+    }
+}
+
+/// Warning shown when `--force` is used instead of specific flags.
+pub fn force_warning() -> String {
+    let flags: Vec<&str> = Guard::ALL.iter().map(|guard| guard.flag()).collect();
+    format!(
+        "--force implies {}; prefer the specific flag you need",
+        flags.join(", ")
+    )
+}
+
+/// Name of the system package that owns `path`, if any.
+///
+/// Asks pacman, dpkg and rpm in turn; missing package managers are skipped.
+pub fn packaged_by(path: &Path) -> Option<String> {
+    let query = |program: &str, args: &[&str]| {
+        let output = Command::new(program).args(args).arg(path).output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let first = stdout.lines().next()?.trim();
+        (output.status.success() && !first.is_empty()).then(|| first.to_string())
+    };
+    query("pacman", &["-Qqo"])
+        .or_else(|| {
+            // dpkg prints "package: /path"
+            query("dpkg-query", &["-S"])
+                .and_then(|line| line.split(':').next().map(|name| name.trim().to_string()))
+        })
+        .or_else(|| query("rpm", &["-qf", "--queryformat", "%{NAME}\n"]))
+}
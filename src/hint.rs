@@ -0,0 +1,84 @@
+//! What to do about an error, printed below it.
+//!
+//! Errors say what went wrong; a hint says what to try next, such as the
+//! command that lists what exists or the flag that fixes it. Errors whose
+//! message already names the flag that bypasses them get no hint.
+
+use crate::{UpdaterError, assets_api, extraction_advice};
+
+/// Guidance for `error`, if there is any beyond its message.
+///
+/// Every variant is matched by name, so a new one has to be given a hint,
+/// or `None`, here.
+pub fn error_hint(error: &UpdaterError) -> Option<String> {
+    let hint = match error {
+        UpdaterError::VersionNotFound(_) => {
+            "Run --list-versions to see the versions stored locally, or --list-versions --available for published releases"
+        }
+        UpdaterError::NetworkError(_) => {
+            "Check the network connection; --trace-http shows each request and its answer"
+        }
+        UpdaterError::Http {
+            source: ureq::Error::Timeout(_),
+            ..
+        } => {
+            "This may pass; try again in a while. On a slow network, raise --timeout or --connect-timeout"
+        }
+        UpdaterError::Http { .. } if error.is_transient() => {
+            "This may pass; try again in a while. --trace-http shows each request and its answer"
+        }
+        UpdaterError::Http { .. } => "--trace-http shows each request and its answer",
+        UpdaterError::StorageError(_) => "Run doctor to check the storage directory",
+        UpdaterError::PermissionError(_) => {
+            "Check that you can write the install target, or set sudo = true for it in the config file"
+        }
+        UpdaterError::ChecksumMismatch(..) => {
+            "Run the update again to download it again; if it fails the same way, the file served differs from the published one"
+        }
+        UpdaterError::RepeatedChecksumMismatch { .. } => {
+            "Report the release's checksum to its maintainers, or stay below it with --max-version"
+        }
+        UpdaterError::InvalidVersionFormat(_) => {
+            "Versions look like 1.2.3, v1.2.3 or 1.2.3-rc.1; --list-versions shows the stored ones"
+        }
+        UpdaterError::RollbackFailed(_) => {
+            "Run doctor to check the stored versions and the install targets"
+        }
+        UpdaterError::IntegrityError(_) => "Run --verify-storage to check every stored version",
+        UpdaterError::ApiBudgetExhausted(_) => {
+            "--dry-run shows the API requests an update would make"
+        }
+        UpdaterError::InvalidManifest(_) => {
+            "The manifest set as source in the config file needs version, url and sha256"
+        }
+        UpdaterError::ApiStatus { status: 401, .. } => {
+            "GitHub rejected the token in GITHUB_TOKEN; check it, or unset it"
+        }
+        UpdaterError::ApiStatus { .. } if error.is_transient() => {
+            "GitHub may be having trouble; try again in a while"
+        }
+        UpdaterError::RateLimited { .. } if assets_api::token().is_none() => {
+            "Set GITHUB_TOKEN to a GitHub token to raise the limit from 60 requests an hour to 5000"
+        }
+        UpdaterError::ExtractionError { .. } => extraction_advice(error)?,
+        UpdaterError::MetadataError { path: Some(_), .. } => {
+            "Run doctor to list what in the storage directory doesn't parse"
+        }
+        // Their messages say what to do, or there is nothing to add
+        UpdaterError::GitHubApiError(_)
+        | UpdaterError::ForeignBinary(_)
+        | UpdaterError::Refused(_)
+        | UpdaterError::ScanRejected(_)
+        | UpdaterError::InstallStaged(_)
+        | UpdaterError::Incompatible(_)
+        | UpdaterError::Interrupted(_)
+        | UpdaterError::UntrustedSource(_)
+        | UpdaterError::InvalidConfig(_)
+        | UpdaterError::ApiStatus { .. }
+        | UpdaterError::RateLimited { .. }
+        | UpdaterError::Io(_)
+        | UpdaterError::MetadataError { path: None, .. }
+        | UpdaterError::Other(_) => return None,
+    };
+    Some(hint.to_string())
+}
@@ -0,0 +1,365 @@
+//! Local log of update runs, kept in `history.jsonl` in the storage directory.
+//!
+//! Nothing here is sent anywhere. Per-phase timings are only recorded when
+//! `record_timings = true` is set in the config file.
+
+use crate::units::{format_count, format_rate};
+use crate::{CleanupFailure, NetworkUsage, UpdaterError};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A step of the update pipeline that gets timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Fetch,
+    Download,
+    Verify,
+    Extract,
+    Install,
+    Cleanup,
+}
+
+/// Seconds spent in each phase of an update run.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimings {
+    #[serde(default)]
+    pub fetch: f64,
+    #[serde(default)]
+    pub download: f64,
+    #[serde(default)]
+    pub verify: f64,
+    #[serde(default)]
+    pub extract: f64,
+    #[serde(default)]
+    pub install: f64,
+    #[serde(default)]
+    pub cleanup: f64,
+}
+
+impl PhaseTimings {
+    /// Add time spent in a phase; phases entered more than once accumulate.
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        let slot = match phase {
+            Phase::Fetch => &mut self.fetch,
+            Phase::Download => &mut self.download,
+            Phase::Verify => &mut self.verify,
+            Phase::Extract => &mut self.extract,
+            Phase::Install => &mut self.install,
+            Phase::Cleanup => &mut self.cleanup,
+        };
+        *slot += elapsed.as_secs_f64();
+    }
+
+    /// Run `f` and record how long it took under `phase`.
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.record(phase, started.elapsed());
+        result
+    }
+
+    /// Seconds spent across all phases.
+    pub fn total(&self) -> f64 {
+        self.fetch + self.download + self.verify + self.extract + self.install + self.cleanup
+    }
+}
+
+impl std::fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "fetch {:.1}s, download {:.1}s, verify {:.1}s, extract {:.1}s, install {:.1}s, cleanup {:.1}s (total {:.1}s)",
+            self.fetch,
+            self.download,
+            self.verify,
+            self.extract,
+            self.install,
+            self.cleanup,
+            self.total()
+        )
+    }
+}
+
+/// Whether an update run succeeded.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure,
+    /// Downloaded and stored, but left for `resume-install` because
+    /// installing needed a sudo password and nobody was there to type it.
+    Staged,
+}
+
+/// One line of `history.jsonl`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub started_at: DateTime<Utc>,
+    pub outcome: Outcome,
+    #[serde(default)]
+    pub from_version: Option<String>,
+    #[serde(default)]
+    pub to_version: Option<String>,
+    /// Size of the downloaded asset in bytes.
+    #[serde(default)]
+    pub asset_size: Option<u64>,
+    /// Kind of error a failed run stopped with, e.g. `network`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<PhaseTimings>,
+    /// API requests and bytes the run used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkUsage>,
+    /// Old versions cleanup could not remove.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cleanup_failures: Vec<CleanupFailure>,
+}
+
+impl HistoryEntry {
+    /// Download speed in bytes per second, when size and timing are known.
+    pub fn download_bps(&self) -> Option<f64> {
+        let size = self.asset_size? as f64;
+        let seconds = self.timings?.download;
+        (seconds > 0.0).then(|| size / seconds)
+    }
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let from = self.from_version.as_deref().unwrap_or("none");
+        let to = self.to_version.as_deref().unwrap_or("?");
+        write!(
+            f,
+            "{} {} -> {}",
+            self.started_at.format("%Y-%m-%d %H:%M"),
+            from,
+            to
+        )?;
+        match self.outcome {
+            Outcome::Success => write!(f, " ok")?,
+            Outcome::Failure => write!(
+                f,
+                " failed ({})",
+                self.error_kind.as_deref().unwrap_or("other")
+            )?,
+            Outcome::Staged => write!(f, " staged (privileged install required)")?,
+        }
+        if let Some(timings) = &self.timings {
+            write!(f, " in {:.1}s", timings.total())?;
+        }
+        Ok(())
+    }
+}
+
+/// Longest line, newline included, that [`append`] writes: `PIPE_BUF` on
+/// Linux, up to which an `O_APPEND` write lands in one piece, never
+/// interleaved with another append or seen half-done by a reader.
+pub const MAX_LINE_BYTES: usize = 4096;
+
+/// The line recording `entry`, shortened to [`MAX_LINE_BYTES`] by dropping
+/// cleanup failures from the end and then cutting the error message.
+pub fn line(entry: &HistoryEntry) -> Result<String, UpdaterError> {
+    let mut entry = entry.clone();
+    loop {
+        let line = serde_json::to_string(&entry)? + "\n";
+        if line.len() <= MAX_LINE_BYTES {
+            return Ok(line);
+        }
+        if entry.cleanup_failures.pop().is_some() {
+            continue;
+        }
+        match &mut entry.error {
+            Some(error) if !error.is_empty() => {
+                let mut end = error.len() / 2;
+                while !error.is_char_boundary(end) {
+                    end -= 1;
+                }
+                error.truncate(end);
+            }
+            _ => {
+                return Err(UpdaterError::StorageError(
+                    "history entry too large to append atomically".to_string(),
+                ));
+            }
+        }
+    }
+}
+
+/// Append an entry to the history file, creating it if needed.
+///
+/// The entry is written with a single `O_APPEND` write of at most
+/// [`MAX_LINE_BYTES`], so concurrent appends and readers only ever see whole
+/// lines.
+pub fn append(path: &Path, entry: &HistoryEntry) -> Result<(), UpdaterError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let line = line(entry)?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let written = file.write(line.as_bytes())?;
+    if written != line.len() {
+        return Err(UpdaterError::StorageError(format!(
+            "history entry only partly written ({} of {} bytes)",
+            written,
+            line.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Parse history entries, oldest first, skipping lines that aren't valid entries.
+pub fn parse(content: &str) -> Vec<HistoryEntry> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Load the history file; a missing file is an empty history.
+pub fn load(path: &Path) -> Result<Vec<HistoryEntry>, UpdaterError> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(parse(&content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Aggregates across history entries, shown by `history stats`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct HistoryStats {
+    pub runs: usize,
+    pub successes: usize,
+    pub failures: usize,
+    /// Runs that stored an update for `resume-install`.
+    pub staged: usize,
+    /// Median download speed in bytes per second over timed runs.
+    pub median_download_bps: Option<f64>,
+    /// Average total seconds over timed successful runs.
+    pub average_total_seconds: Option<f64>,
+    pub failures_by_kind: BTreeMap<String, usize>,
+}
+
+impl HistoryStats {
+    /// Aggregate the given entries.
+    pub fn from_entries(entries: &[HistoryEntry]) -> Self {
+        let mut stats = Self {
+            runs: entries.len(),
+            ..Self::default()
+        };
+
+        for entry in entries {
+            match entry.outcome {
+                Outcome::Success => stats.successes += 1,
+                Outcome::Failure => {
+                    stats.failures += 1;
+                    let kind = entry.error_kind.clone().unwrap_or_else(|| "other".into());
+                    *stats.failures_by_kind.entry(kind).or_default() += 1;
+                }
+                Outcome::Staged => stats.staged += 1,
+            }
+        }
+
+        let mut speeds: Vec<f64> = entries
+            .iter()
+            .filter_map(HistoryEntry::download_bps)
+            .collect();
+        speeds.sort_by(f64::total_cmp);
+        stats.median_download_bps = match speeds.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(speeds[n / 2]),
+            n => Some((speeds[n / 2 - 1] + speeds[n / 2]) / 2.0),
+        };
+
+        let totals: Vec<f64> = entries
+            .iter()
+            .filter(|entry| entry.outcome == Outcome::Success)
+            .filter_map(|entry| entry.timings.map(|timings| timings.total()))
+            .collect();
+        stats.average_total_seconds =
+            (!totals.is_empty()).then(|| totals.iter().sum::<f64>() / totals.len() as f64);
+
+        stats
+    }
+}
+
+impl std::fmt::Display for HistoryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Runs: {} ({} succeeded, {} failed",
+            format_count(self.runs as u64),
+            format_count(self.successes as u64),
+            format_count(self.failures as u64)
+        )?;
+        if self.staged > 0 {
+            write!(f, ", {} staged", format_count(self.staged as u64))?;
+        }
+        writeln!(f, ")")?;
+        match self.median_download_bps {
+            Some(bps) => writeln!(f, "Median download speed: {}", format_rate(bps))?,
+            None => writeln!(f, "Median download speed: unknown (no timed runs)")?,
+        }
+        match self.average_total_seconds {
+            Some(seconds) => write!(f, "Average total time: {:.1}s", seconds)?,
+            None => write!(f, "Average total time: unknown (no timed runs)")?,
+        }
+        for (kind, count) in &self.failures_by_kind {
+            write!(f, "\nFailures ({}): {}", kind, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Classify an error for `history stats`.
+pub fn error_kind(error: &(dyn std::error::Error + 'static)) -> &'static str {
+    if let Some(error) = error.downcast_ref::<crate::UpdaterError>() {
+        return match error {
+            crate::UpdaterError::VersionNotFound(_) => "version_not_found",
+            crate::UpdaterError::NetworkError(_) => "network",
+            crate::UpdaterError::StorageError(_) => "storage",
+            crate::UpdaterError::PermissionError(_) => "permission",
+            crate::UpdaterError::ChecksumMismatch(_, _)
+            | crate::UpdaterError::RepeatedChecksumMismatch { .. } => "checksum",
+            crate::UpdaterError::InvalidVersionFormat(_) => "invalid_version",
+            crate::UpdaterError::RollbackFailed(_) => "rollback",
+            crate::UpdaterError::GitHubApiError(_) => "github_api",
+            crate::UpdaterError::IntegrityError(_) => "integrity",
+            crate::UpdaterError::ForeignBinary(_) => "foreign_binary",
+            crate::UpdaterError::Refused(_) => "refused",
+            crate::UpdaterError::ScanRejected(_) => "scan",
+            crate::UpdaterError::InstallStaged(_) => "staged",
+            crate::UpdaterError::Incompatible(_) => "incompatible",
+            crate::UpdaterError::ApiBudgetExhausted(_) => "api_budget",
+            crate::UpdaterError::Interrupted(_) => "interrupted",
+            crate::UpdaterError::InvalidManifest(_) => "manifest",
+            crate::UpdaterError::UntrustedSource(_) => "untrusted_source",
+            crate::UpdaterError::InvalidConfig(_) => "config",
+            crate::UpdaterError::ApiStatus { .. } => "github_api",
+            crate::UpdaterError::RateLimited { .. } => "rate_limit",
+            crate::UpdaterError::Http { .. } => "network",
+            crate::UpdaterError::Io(_) => "io",
+            crate::UpdaterError::ExtractionError { .. } => "extraction",
+            crate::UpdaterError::MetadataError { .. } => "metadata",
+            crate::UpdaterError::Other(_) => "other",
+        };
+    }
+    if error.downcast_ref::<ureq::Error>().is_some() {
+        "network"
+    } else if error.downcast_ref::<std::io::Error>().is_some() {
+        "io"
+    } else {
+        "other"
+    }
+}
@@ -0,0 +1,71 @@
+//! Recognising whether an installed `opencode` is the one this tool manages.
+//!
+//! Other projects ship binaries called `opencode` too. Before backing up or
+//! overwriting an existing install, its `--version` and `--help` output must
+//! contain a marker identifying upstream opencode.
+
+use crate::UpdaterError;
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+
+/// Default marker: the subcommands listed in upstream opencode's `--help`.
+pub const DEFAULT_IDENTITY_MARKER: &str = r"\bopencode (run|serve|auth|upgrade|models)\b";
+
+/// Maximum number of output characters quoted when explaining a mismatch.
+const EXCERPT_LEN: usize = 200;
+
+/// Output of an installed binary's `--version` and `--help`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryProbe {
+    pub version_output: String,
+    pub help_output: String,
+}
+
+impl BinaryProbe {
+    /// Run `binary --version` and `binary --help`, or `None` if it can't be run at all.
+    pub fn run(binary: &Path) -> Option<Self> {
+        let capture = |arg: &str| {
+            Command::new(binary).arg(arg).output().ok().map(|output| {
+                let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+                (output.status.success(), text)
+            })
+        };
+        let (version_ok, version_output) = capture("--version")?;
+        let (_, help_output) = capture("--help")?;
+        version_ok.then_some(Self {
+            version_output,
+            help_output,
+        })
+    }
+
+    /// The reported version, without a leading `v`.
+    pub fn version(&self) -> &str {
+        self.version_output.trim().trim_start_matches('v')
+    }
+
+    /// Short excerpt of what the binary printed, for error messages.
+    pub fn excerpt(&self) -> String {
+        let combined = format!("{} {}", self.version_output.trim(), self.help_output.trim());
+        let flat: String = combined.split_whitespace().collect::<Vec<_>>().join(" ");
+        match flat.char_indices().nth(EXCERPT_LEN) {
+            Some((end, _)) => format!("{}…", &flat[..end]),
+            None => flat,
+        }
+    }
+}
+
+/// Whether a binary's `--version` or `--help` output contains the marker.
+pub fn matches_marker(version_output: &str, help_output: &str, marker: &Regex) -> bool {
+    marker.is_match(version_output) || marker.is_match(help_output)
+}
+
+/// Compile the marker regex, falling back to [`DEFAULT_IDENTITY_MARKER`].
+pub fn marker_regex(pattern: Option<&str>) -> Result<Regex, UpdaterError> {
+    let pattern = pattern.unwrap_or(DEFAULT_IDENTITY_MARKER);
+    Regex::new(pattern).map_err(|e| {
+        UpdaterError::InvalidConfig(format!("invalid identity_marker '{}': {}", pattern, e))
+    })
+}
@@ -0,0 +1,322 @@
+//! Placing a binary at an install target.
+//!
+//! File operations go through the [`Installer`] trait so that the same
+//! sequence runs directly for user-writable targets, through `sudo` for
+//! system ones, and against a recording mock in tests.
+//!
+//! The new binary is staged as `<target>.new` next to the target and renamed
+//! over it, so the target is never left half-written. With `--install-bak`
+//! the previous binary is renamed to `<target>.bak` first.
+//!
+//! Callers pass the hash the source had when it was verified. The bytes are
+//! hashed as they are copied to `<target>.new`, and the copy is renamed into
+//! place only if they match, so a file swapped in before or during the copy
+//! (by another local user, or by our own bug) is never installed.
+//!
+//! The copy is streamed in chunks, which a target on a slow network mount
+//! makes worth watching: each chunk is reported to a [`ProgressSink`], and
+//! a cancellation seen between chunks removes the half-written `<target>.new`
+//! and leaves the target as it was. Once the copy is complete the renames
+//! run to the end.
+
+use crate::progress::ProgressSink;
+use crate::{InstallTarget, UpdaterError};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::io::{IsTerminal, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// File operations needed to install a binary.
+pub trait Installer {
+    /// Create a directory and its parents.
+    fn create_dir_all(&self, path: &Path) -> Result<(), UpdaterError>;
+    /// Copy a file, replacing the destination, with [`copy_stream`], and
+    /// return the hex SHA-256 of the bytes written.
+    fn copy(
+        &self,
+        source: &Path,
+        dest: &Path,
+        progress: &dyn ProgressSink,
+    ) -> Result<String, UpdaterError>;
+    /// Make a file executable (0755).
+    fn set_executable(&self, path: &Path) -> Result<(), UpdaterError>;
+    /// Rename a file, replacing the destination.
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), UpdaterError>;
+    /// Remove a file.
+    fn remove(&self, path: &Path) -> Result<(), UpdaterError>;
+}
+
+/// Bytes copied between progress reports and cancellation checks.
+pub const COPY_CHUNK: usize = 256 * 1024;
+
+/// Copy `reader` to `writer` in [`COPY_CHUNK`]s, reporting the bytes copied
+/// to `progress` and failing with [`UpdaterError::Interrupted`] as soon as
+/// it is cancelled. Returns the hex SHA-256 of the bytes written.
+pub fn copy_stream(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    progress: &dyn ProgressSink,
+) -> Result<String, UpdaterError> {
+    let mut buffer = vec![0u8; COPY_CHUNK];
+    let mut hasher = Sha256::new();
+    let mut copied = 0;
+    loop {
+        if progress.cancelled() {
+            return Err(UpdaterError::Interrupted(format!(
+                "cancelled after {} bytes",
+                copied
+            )));
+        }
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        writer.write_all(&buffer[..read])?;
+        hasher.update(&buffer[..read]);
+        copied += read as u64;
+        progress.set_position(copied);
+    }
+    writer.flush()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Installs with plain filesystem calls.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DirectInstaller;
+
+impl Installer for DirectInstaller {
+    fn create_dir_all(&self, path: &Path) -> Result<(), UpdaterError> {
+        Ok(std::fs::create_dir_all(path)?)
+    }
+
+    fn copy(
+        &self,
+        source: &Path,
+        dest: &Path,
+        progress: &dyn ProgressSink,
+    ) -> Result<String, UpdaterError> {
+        let mut dest_file = std::fs::File::create(dest)?;
+        let sha256 = copy_stream(&mut std::fs::File::open(source)?, &mut dest_file, progress)?;
+        // On the mount, not in a cache, before it is renamed over the target
+        dest_file.sync_all()?;
+        Ok(sha256)
+    }
+
+    fn set_executable(&self, path: &Path) -> Result<(), UpdaterError> {
+        Ok(std::fs::set_permissions(
+            path,
+            std::fs::Permissions::from_mode(0o755),
+        )?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), UpdaterError> {
+        Ok(std::fs::rename(from, to)?)
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), UpdaterError> {
+        Ok(std::fs::remove_file(path)?)
+    }
+}
+
+/// Installs by running coreutils through `sudo`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SudoInstaller;
+
+impl SudoInstaller {
+    fn run(&self, args: &[&std::ffi::OsStr]) -> Result<(), UpdaterError> {
+        let status = Command::new("sudo").args(args).status()?;
+        if !status.success() {
+            let command: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+            return Err(UpdaterError::PermissionError(format!(
+                "`sudo {}` failed",
+                command.join(" ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Installer for SudoInstaller {
+    fn create_dir_all(&self, path: &Path) -> Result<(), UpdaterError> {
+        self.run(&["mkdir".as_ref(), "-p".as_ref(), path.as_os_str()])
+    }
+
+    /// Streams the file into `sudo dd`, so progress and cancellation work
+    /// as for a direct copy.
+    fn copy(
+        &self,
+        source: &Path,
+        dest: &Path,
+        progress: &dyn ProgressSink,
+    ) -> Result<String, UpdaterError> {
+        let mut of = OsString::from("of=");
+        of.push(dest);
+        let mut child = Command::new("sudo")
+            .args(["dd".as_ref(), of.as_os_str()])
+            .args(["bs=1M", "conv=fsync", "status=none"])
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| UpdaterError::PermissionError("sudo dd has no stdin".to_string()))?;
+        let copied = copy_stream(&mut std::fs::File::open(source)?, &mut stdin, progress);
+        // Closing stdin ends dd, also when the copy stopped early
+        drop(stdin);
+        if copied.is_err() {
+            let _ = child.kill();
+        }
+        let status = child.wait()?;
+        match copied {
+            Err(e @ UpdaterError::Interrupted(_)) => Err(e),
+            // A write failing because dd exited is dd's failure
+            _ if !status.success() => Err(UpdaterError::PermissionError(format!(
+                "`sudo dd of={}` failed",
+                dest.display()
+            ))),
+            copied => copied,
+        }
+    }
+
+    fn set_executable(&self, path: &Path) -> Result<(), UpdaterError> {
+        self.run(&["chmod".as_ref(), "0755".as_ref(), path.as_os_str()])
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), UpdaterError> {
+        self.run(&[
+            "mv".as_ref(),
+            "-f".as_ref(),
+            from.as_os_str(),
+            to.as_os_str(),
+        ])
+    }
+
+    fn remove(&self, path: &Path) -> Result<(), UpdaterError> {
+        self.run(&["rm".as_ref(), "-f".as_ref(), path.as_os_str()])
+    }
+}
+
+/// Whether an update to `targets` has to be stored and left for
+/// `resume-install`: one of them needs sudo, and `sudo_would_block`, only
+/// asked then, says it would wait for a password.
+pub fn needs_staging(
+    targets: &[(String, InstallTarget)],
+    sudo_would_block: impl FnOnce() -> bool,
+) -> bool {
+    targets.iter().any(|(_, target)| target.sudo) && sudo_would_block()
+}
+
+/// Whether sudo would stop at a password prompt nobody can answer, as under a
+/// systemd timer: there is no terminal, and `sudo -n` can't go ahead without one.
+pub fn sudo_would_block() -> bool {
+    if rustix::process::geteuid().is_root() || std::io::stdin().is_terminal() {
+        return false;
+    }
+    !Command::new("sudo")
+        .args(["-n", "true"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The installer appropriate for a target.
+pub fn installer_for(target: &InstallTarget) -> Box<dyn Installer> {
+    if target.sudo {
+        Box::new(SudoInstaller)
+    } else {
+        Box::new(DirectInstaller)
+    }
+}
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Where `--install-bak` keeps the previous binary of a target.
+pub fn bak_path(dest: &Path) -> PathBuf {
+    with_suffix(dest, ".bak")
+}
+
+/// Install `source` at `dest`, optionally keeping the previous binary as `<dest>.bak`.
+///
+/// The bytes copied to `<dest>.new` must hash to `expected_sha256`, or it is
+/// removed and `dest` left alone. Otherwise it is renamed into place.
+/// With `keep_bak`, the existing binary is renamed to `.bak` just before that
+/// rename, and renamed back if placing the new one fails. The copy reports
+/// to `progress`, and if it is cancelled `<dest>.new` is removed.
+pub fn install_binary(
+    installer: &dyn Installer,
+    source: &Path,
+    expected_sha256: &str,
+    dest: &Path,
+    keep_bak: bool,
+    progress: &dyn ProgressSink,
+) -> Result<(), UpdaterError> {
+    if let Some(parent) = dest.parent() {
+        installer.create_dir_all(parent)?;
+    }
+    let staged = with_suffix(dest, ".new");
+    let actual = match installer.copy(source, &staged, progress) {
+        Ok(actual) => actual,
+        Err(e) => {
+            let _ = installer.remove(&staged);
+            if let UpdaterError::Interrupted(_) = e {
+                return Err(UpdaterError::Interrupted(format!(
+                    "cancelled while installing to {}, which was left as it was",
+                    dest.display()
+                )));
+            }
+            return Err(e);
+        }
+    };
+    if actual != expected_sha256 {
+        let _ = installer.remove(&staged);
+        return Err(UpdaterError::IntegrityError(format!(
+            "{} changed after it was verified (expected sha256 {}, found {}); not installing it",
+            source.display(),
+            expected_sha256,
+            actual
+        )));
+    }
+    if let Err(e) = installer.set_executable(&staged) {
+        let _ = installer.remove(&staged);
+        return Err(e);
+    }
+
+    let bak = bak_path(dest);
+    let backed_up = keep_bak && dest.exists();
+    if backed_up && let Err(e) = installer.rename(dest, &bak) {
+        let _ = installer.remove(&staged);
+        return Err(e);
+    }
+    if let Err(e) = installer.rename(&staged, dest) {
+        if backed_up {
+            let _ = installer.rename(&bak, dest);
+        }
+        let _ = installer.remove(&staged);
+        return Err(e);
+    }
+    progress.finish(format!("{} installed", dest.display()));
+    Ok(())
+}
+
+/// Put `<dest>.bak` back in place of `dest`.
+pub fn restore_bak(installer: &dyn Installer, dest: &Path) -> Result<(), UpdaterError> {
+    let bak = bak_path(dest);
+    if !bak.exists() {
+        return Err(UpdaterError::RollbackFailed(format!(
+            "no backup binary at {}",
+            bak.display()
+        )));
+    }
+    installer.rename(&bak, dest)
+}
@@ -0,0 +1,217 @@
+//! Tamper detection for stored versions.
+//!
+//! Each version's metadata records the SHA-256 of its stored binary and an
+//! HMAC-SHA256 over the version and that hash, keyed by a machine-local secret
+//! kept outside the data directory. Rollback installs stored binaries with
+//! sudo, so it refuses any version whose binary or metadata no longer matches.
+//!
+//! By default the key is [`SYSTEM_KEY_PATH`], owned by root and readable only
+//! by it, and is read and created through sudo. Next to the key, a ledger
+//! lists every version saved with an HMAC, so stripping a version's HMAC
+//! doesn't pass it off as one saved before integrity protection.
+
+use crate::UpdaterError;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::io::{Read, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Length in bytes of the generated HMAC key.
+const KEY_LEN: usize = 32;
+
+/// Where the key is kept unless storage is in `OPENCODE_UPDATER_HOME` or a
+/// shared store.
+pub const SYSTEM_KEY_PATH: &str = "/etc/opencode-updater/integrity.key";
+
+/// Name of the ledger of signed versions, next to the key.
+const LEDGER_FILE: &str = "signed-versions";
+
+/// Result of checking a stored version against its recorded HMAC.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    /// Binary hash and HMAC both match.
+    Verified,
+    /// Metadata predates integrity protection and has no HMAC.
+    Unsigned,
+    /// The stored binary no longer matches the recorded hash.
+    BinaryModified,
+    /// The metadata was changed or signed with a different key.
+    MetadataModified,
+    /// The binary or metadata couldn't be read.
+    Unreadable(String),
+}
+
+impl std::fmt::Display for IntegrityStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            IntegrityStatus::Verified => "verified",
+            IntegrityStatus::Unsigned => "unsigned (saved before integrity protection)",
+            IntegrityStatus::BinaryModified => "binary modified after it was saved",
+            IntegrityStatus::MetadataModified => "metadata modified or signed with another key",
+            IntegrityStatus::Unreadable(e) => return write!(f, "unreadable: {}", e),
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Load the HMAC key, or `None` if it hasn't been created yet.
+///
+/// A key only root can read is read with sudo. Verification uses this
+/// rather than [`load_or_create_key`], so checking storage never creates a
+/// key the stored versions weren't signed with.
+pub fn load_key(path: &Path) -> Result<Option<Vec<u8>>, UpdaterError> {
+    match std::fs::read(path) {
+        Ok(key) => Ok(Some(key)),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            sudo(path, "read", "cat -- \"$1\"", &[]).map(Some)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Error for a version signed with the key at `path`, which is missing.
+pub fn missing_key_error(path: &Path, version: &str) -> UpdaterError {
+    UpdaterError::IntegrityError(format!(
+        "version {} was saved with an HMAC, but the integrity key {} is missing, so it can't be verified",
+        version,
+        path.display()
+    ))
+}
+
+/// Load the HMAC key, generating one readable only by its owner if it doesn't exist.
+///
+/// A key only root can read, or create, is read or written with sudo.
+pub fn load_or_create_key(path: &Path) -> Result<Vec<u8>, UpdaterError> {
+    if let Some(key) = load_key(path)? {
+        return Ok(key);
+    }
+
+    let mut key = vec![0u8; KEY_LEN];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut key)?;
+    let created = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)?
+                .write_all(&key)
+        });
+    match created {
+        Ok(()) => std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?,
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            // Refuses to overwrite a key created meanwhile
+            sudo(
+                path,
+                "create",
+                "set -C && mkdir -p -m 755 -- \"$(dirname -- \"$1\")\" && umask 077 && cat > \"$1\"",
+                &key,
+            )?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(key)
+}
+
+/// Whether the key at `path` can be read without sudo, or sudo can be run
+/// without a password prompt nobody can answer.
+pub fn key_available(path: &Path) -> bool {
+    std::fs::File::open(path).is_ok() || !crate::installer::sudo_would_block()
+}
+
+/// Run `script` with sudo on `path`, passed as `$1`, feeding it `input`;
+/// `action` names what it does to the file in the error.
+fn sudo(path: &Path, action: &str, script: &str, input: &[u8]) -> Result<Vec<u8>, UpdaterError> {
+    let failed = || {
+        UpdaterError::PermissionError(format!("could not {} {} with sudo", action, path.display()))
+    };
+    let mut child = Command::new("sudo")
+        .args(["sh", "-c", script, "sh"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|_| failed())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(failed());
+    }
+    Ok(output.stdout)
+}
+
+/// The ledger of versions saved with an HMAC by the key at `key`.
+pub fn ledger_path(key: &Path) -> PathBuf {
+    key.with_file_name(LEDGER_FILE)
+}
+
+/// Add `version` to the ledger next to `key`, with sudo if only root can
+/// write it.
+pub fn record_signed(key: &Path, version: &str) -> Result<(), UpdaterError> {
+    if was_signed(key, version)? {
+        return Ok(());
+    }
+    let path = ledger_path(key);
+    let line = format!("{}\n", version);
+    let appended = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o644)
+        .open(&path)
+        .and_then(|mut ledger| ledger.write_all(line.as_bytes()));
+    match appended {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            sudo(
+                &path,
+                "append to",
+                "umask 022 && cat >> \"$1\"",
+                line.as_bytes(),
+            )?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether `version` was saved with an HMAC by the key at `key`.
+pub fn was_signed(key: &Path, version: &str) -> Result<bool, UpdaterError> {
+    match std::fs::read_to_string(ledger_path(key)) {
+        Ok(ledger) => Ok(ledger.lines().any(|line| line == version)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Compute the hex HMAC binding a version to its stored binary's hash.
+pub fn compute_hmac(key: &[u8], version: &str, tag_name: &str, binary_sha256: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}\n{}\n{}", version, tag_name, binary_sha256).as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+/// Compare a recorded HMAC against the expected one in constant time.
+pub fn verify_hmac(
+    key: &[u8],
+    version: &str,
+    tag_name: &str,
+    binary_sha256: &str,
+    recorded: &str,
+) -> bool {
+    let expected = compute_hmac(key, version, tag_name, binary_sha256);
+    expected.len() == recorded.len()
+        && expected
+            .bytes()
+            .zip(recorded.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
@@ -1,17 +1,93 @@
 use chrono::{DateTime, Utc};
-use clap::Parser;
-use dialoguer::{Select, theme::ColorfulTheme};
+use clap::{Parser, Subcommand};
 use flate2::read::GzDecoder;
-use indicatif::{ProgressBar, ProgressStyle};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::io::Read;
+use std::io::{IsTerminal, Read};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use tar::Archive;
 use ureq::Agent;
 
+pub mod adopt;
+pub mod allowlist;
+pub mod archive;
+pub mod archive_cache;
+pub mod as_of;
+pub mod assets_api;
+pub mod budget;
+pub mod channel;
+pub mod checksum;
+pub mod clock;
+pub mod compat;
+pub mod completions;
+pub mod config;
+pub mod constraint;
+pub mod digest;
+pub mod doctor;
+pub mod encoding;
+pub mod error_report;
+pub mod exit_code;
+pub mod guard;
+pub mod hint;
+pub mod history;
+pub mod identity;
+pub mod installer;
+pub mod integrity;
+pub mod lock;
+pub mod maintain;
+pub mod metadata;
+pub mod migration;
+pub mod mirror;
+pub mod network;
+pub mod payload;
+pub mod progress;
+pub mod prompt;
+pub mod rate_limit;
+pub mod recovery;
+pub mod remote;
+pub mod reporter;
+pub mod retry;
+pub mod rolling;
+pub mod sbom;
+pub mod scan;
+pub mod schema;
+pub mod scratch;
+pub mod shared;
+pub mod source;
+pub mod staging;
+pub mod status;
+pub mod template;
+pub mod trace;
+pub mod units;
+pub mod version;
+pub mod version_dir;
+pub mod version_ref;
+
+pub use archive::{ArchiveEntry, ArchiveInspection};
+pub use channel::Channel;
+pub use checksum::{Checksum, ChecksumAlgorithm, parse_checksum_file, verify_checksums};
+pub use clock::ClockSkew;
+pub use completions::{CompletionFile, Shell};
+pub use config::{Config, InstallTarget};
+pub use constraint::{Track, VersionConstraint};
+pub use doctor::{CheckStatus, DoctorCheck, DoctorReport, run_doctor};
+pub use guard::{Guard, Overrides};
+pub use history::{HistoryEntry, HistoryStats, Outcome, Phase, PhaseTimings};
+pub use identity::BinaryProbe;
+pub use installer::{DirectInstaller, Installer, SudoInstaller};
+pub use integrity::IntegrityStatus;
+pub use migration::MigrationReport;
+pub use network::NetworkUsage;
+pub use remote::{HostState, HostStatus, RemoteReport, RemoteTransport, SshTransport};
+pub use reporter::{Level, OutputMode, Reporter};
+pub use rolling::{RollingBuild, is_rolling_tag, is_unparseable_version};
+pub use template::Template;
+pub use units::{SizeUnits, format_count, format_size};
+pub use version::Version;
+pub use version_ref::{ResolvedVersion, VersionRef, VersionSource};
+
 /// Custom error types for the updater
 #[derive(Debug)]
 pub enum UpdaterError {
@@ -20,9 +96,70 @@ pub enum UpdaterError {
     StorageError(String),
     PermissionError(String),
     ChecksumMismatch(String, String),
+    /// Two independent downloads of `file` failed verification, so the
+    /// published checksum is more likely wrong than the download corrupted.
+    RepeatedChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
     InvalidVersionFormat(String),
     RollbackFailed(String),
     GitHubApiError(String),
+    IntegrityError(String),
+    ForeignBinary(String),
+    Refused(String),
+    ScanRejected(String),
+    /// The update was stored for `resume-install` instead of installed.
+    InstallStaged(String),
+    /// The downloaded binary can't run on this host.
+    Incompatible(String),
+    /// A request would exceed `--api-budget`.
+    ApiBudgetExhausted(String),
+    /// Ctrl-C stopped the run at a point where it could stop cleanly.
+    Interrupted(String),
+    /// A release manifest is missing a field or has a malformed one.
+    InvalidManifest(String),
+    /// A URL refused by `strict_sources`.
+    UntrustedSource(String),
+    /// The config file, or a value in it, is invalid.
+    InvalidConfig(String),
+    /// GitHub's API answered with an error `status`, explained by `message`.
+    ApiStatus {
+        status: u16,
+        message: String,
+    },
+    /// GitHub's API rate limit is used up, with `remaining` requests left,
+    /// until `reset_at` on the local clock.
+    RateLimited {
+        reset_at: DateTime<Utc>,
+        remaining: u32,
+    },
+    /// A request to `url` failed; `source` is the client's error, e.g. a
+    /// timeout, a TLS failure or an error status.
+    Http {
+        url: String,
+        source: ureq::Error,
+    },
+    /// A file could not be read or written.
+    Io(std::io::Error),
+    /// The release archive `archive` could not be unpacked, or held no
+    /// usable binary; `source` says why, e.g. a corrupt zip or a full disk.
+    ExtractionError {
+        archive: String,
+        /// `None` if the name is of no format that can be unpacked, or for an
+        /// unpacked directory holding no binary.
+        format: Option<ArchiveFormat>,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// JSON that doesn't parse: stored metadata at `path`, or a response
+    /// when there is no path.
+    MetadataError {
+        path: Option<PathBuf>,
+        source: serde_json::Error,
+    },
+    /// Any other failure, e.g. from a helper module, by its message.
+    Other(String),
 }
 
 impl std::fmt::Display for UpdaterError {
@@ -39,17 +176,188 @@ impl std::fmt::Display for UpdaterError {
                     expected, actual
                 )
             }
+            UpdaterError::RepeatedChecksumMismatch {
+                file,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch on two independent downloads of {}: expected {}, got {}. \
+                 The published checksum is likely wrong rather than the download corrupted.",
+                file, expected, actual
+            ),
             UpdaterError::InvalidVersionFormat(v) => write!(f, "Invalid version format: {}", v),
             UpdaterError::RollbackFailed(e) => write!(f, "Rollback failed: {}", e),
             UpdaterError::GitHubApiError(e) => write!(f, "GitHub API error: {}", e),
+            UpdaterError::IntegrityError(e) => write!(f, "Integrity error: {}", e),
+            UpdaterError::ForeignBinary(e) => write!(f, "Not opencode: {}", e),
+            UpdaterError::Refused(e) => write!(f, "Refused: {}", e),
+            UpdaterError::ScanRejected(e) => write!(f, "Scan rejected: {}", e),
+            UpdaterError::InstallStaged(e) => write!(f, "Not installed: {}", e),
+            UpdaterError::Incompatible(e) => write!(f, "Incompatible binary: {}", e),
+            UpdaterError::ApiBudgetExhausted(e) => write!(f, "API budget exhausted: {}", e),
+            UpdaterError::Interrupted(e) => write!(f, "Interrupted: {}", e),
+            UpdaterError::InvalidManifest(e) => write!(f, "Invalid manifest: {}", e),
+            UpdaterError::UntrustedSource(e) => write!(f, "Untrusted source: {}", e),
+            UpdaterError::InvalidConfig(e) => write!(f, "Invalid config: {}", e),
+            UpdaterError::ApiStatus { status, message } => {
+                write!(f, "GitHub API error: HTTP {}: {}", status, message)
+            }
+            UpdaterError::RateLimited { reset_at, .. } => {
+                // Rounded up, so a limit that hasn't reset never reads 0
+                let minutes = ((*reset_at - Utc::now()).num_seconds().max(0) + 59) / 60;
+                write!(
+                    f,
+                    "GitHub API rate limit exceeded; resets in {} minute{} ({})",
+                    minutes,
+                    if minutes == 1 { "" } else { "s" },
+                    reset_at.format("%H:%M UTC")
+                )
+            }
+            UpdaterError::Http { url, source } => {
+                write!(f, "Network error: {}: {}", url, http_failure(source))
+            }
+            UpdaterError::Io(e) => write!(f, "I/O error: {}", e),
+            UpdaterError::ExtractionError {
+                archive, source, ..
+            } => write!(f, "Extraction failed: {}: {}", archive, source),
+            UpdaterError::MetadataError {
+                path: Some(path),
+                source,
+            } => write!(f, "Invalid metadata: {}: {}", path.display(), source),
+            UpdaterError::MetadataError { path: None, source } => {
+                write!(f, "Invalid JSON: {}", source)
+            }
+            UpdaterError::Other(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for UpdaterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UpdaterError::Http { source, .. } => Some(source),
+            UpdaterError::Io(e) => Some(e),
+            UpdaterError::ExtractionError { source, .. } => Some(source.as_ref()),
+            UpdaterError::MetadataError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl UpdaterError {
+    /// Whether running again later may succeed: a timeout, a dropped or
+    /// refused connection, a 5xx answer or a used up rate limit.
+    ///
+    /// Checksum mismatches, missing versions, permission errors and any error
+    /// whose cause isn't known are permanent.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            UpdaterError::Http { source, .. } => is_transient_request_error(source),
+            UpdaterError::ApiStatus { status, .. } => is_transient_status(*status),
+            UpdaterError::RateLimited { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Whether an answer with `status` may be different later.
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500..=599)
+}
+
+pub(crate) fn is_transient_request_error(error: &ureq::Error) -> bool {
+    use std::io::ErrorKind;
+
+    match error {
+        ureq::Error::StatusCode(status) => is_transient_status(*status),
+        ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::BodyStalled => true,
+        ureq::Error::Io(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut
+                | ErrorKind::Interrupted
+                | ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+impl From<std::io::Error> for UpdaterError {
+    fn from(error: std::io::Error) -> Self {
+        UpdaterError::Io(error)
+    }
+}
+
+/// What went wrong with a request, in a few words; the full error is the
+/// `source` of [`UpdaterError::Http`].
+fn http_failure(error: &ureq::Error) -> String {
+    match error {
+        ureq::Error::StatusCode(status) => format!("HTTP {}", status),
+        ureq::Error::Timeout(timeout) => match timeout_phase(*timeout) {
+            Some(phase) => format!("timed out {}", phase),
+            None => "timed out".to_string(),
+        },
+        ureq::Error::HostNotFound => "host not found".to_string(),
+        ureq::Error::Io(e) => match e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<encoding::CutShort>())
+        {
+            Some(cut_short) => cut_short.to_string(),
+            None => "connection failed".to_string(),
+        },
+        ureq::Error::ConnectionFailed => "connection failed".to_string(),
+        ureq::Error::TooManyRedirects | ureq::Error::RedirectFailed => {
+            "redirect failed".to_string()
+        }
+        ureq::Error::BodyStalled => "response stalled".to_string(),
+        _ => "request failed".to_string(),
+    }
+}
+
+/// What a request was doing when `timeout` ran out, unless it was a limit
+/// on the whole request.
+fn timeout_phase(timeout: ureq::Timeout) -> Option<&'static str> {
+    Some(match timeout {
+        ureq::Timeout::Resolve => "looking up the host",
+        ureq::Timeout::Connect => "connecting",
+        ureq::Timeout::SendRequest | ureq::Timeout::SendBody | ureq::Timeout::Await100 => {
+            "sending the request"
+        }
+        ureq::Timeout::RecvResponse => "waiting for the response",
+        ureq::Timeout::RecvBody => "reading the response",
+        _ => return None,
+    })
+}
+
+/// For JSON whose file isn't known; metadata read from a file should name
+/// it in the [`UpdaterError::MetadataError`].
+impl From<serde_json::Error> for UpdaterError {
+    fn from(error: serde_json::Error) -> Self {
+        UpdaterError::MetadataError {
+            path: None,
+            source: error,
         }
     }
 }
 
-impl std::error::Error for UpdaterError {}
+/// For client errors outside a request, which have no URL; requests go
+/// through [`payload::request_error`], which keeps the error as the source.
+impl From<ureq::Error> for UpdaterError {
+    fn from(error: ureq::Error) -> Self {
+        UpdaterError::NetworkError(error.to_string())
+    }
+}
 
 /// Version information stored in metadata
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct VersionInfo {
     pub version: String,
     pub tag_name: String,
@@ -59,267 +367,1673 @@ pub struct VersionInfo {
     pub installed_at: DateTime<Utc>,
     pub install_path: PathBuf,
     pub release_notes: String,
+    /// Size in bytes of the release asset this version was installed from, when known.
+    #[serde(default)]
+    pub asset_size: Option<u64>,
+    /// SHA-256 of the stored binary, recorded by `save_version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_sha256: Option<String>,
+    /// HMAC over the version and `binary_sha256`, recorded by `save_version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac: Option<String>,
+    /// Checksum algorithms the downloaded asset was verified against.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verified_algorithms: Vec<ChecksumAlgorithm>,
+    /// Identity of a rolling build, see [`RollingBuild::build_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+    /// Shell completions installed with this version, restored on rollback.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completions: Vec<CompletionFile>,
+    /// `<scan_command> ok` when the binary passed the configured scanner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanned: Option<String>,
+    /// Login name of the user who saved this version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_by: Option<String>,
+    /// How the version of a binary brought in by `adopt` was established.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<adopt::Provenance>,
+    /// Channel the release came from, see [`channel`].
+    #[serde(default)]
+    pub channel: Channel,
+    /// Version of the metadata format; files without one are schema 1.
+    ///
+    /// Schema 3 and later split it into `release.json` and `install.json`,
+    /// see [`metadata`].
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
 }
 
-/// Manages version storage and operations
-pub struct VersionManager {
-    storage_dir: PathBuf,
-    versions_dir: PathBuf,
-    cache_dir: PathBuf,
+fn legacy_schema_version() -> u32 {
+    1
 }
 
-impl VersionManager {
-    /// Initialize version manager with default storage directory
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let data_dir = dirs::data_dir()
-            .ok_or("Could not find data directory")?
-            .join("opencode-updater");
+impl VersionInfo {
+    /// The version, parsed; `None` for a rolling build or a tag that isn't
+    /// a version.
+    pub fn parsed(&self) -> Option<Version> {
+        self.version.parse().ok()
+    }
+}
 
-        let versions_dir = data_dir.join("versions");
-        let cache_dir = data_dir.join("cache");
+/// How a list of stored versions is ordered, newest first either way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Most recently installed first, then by version.
+    #[default]
+    Installed,
+    /// Highest version first. Versions that don't parse, like rolling
+    /// builds, come last, most recently installed first.
+    Version,
+}
 
-        // Create directories if they don't exist
-        std::fs::create_dir_all(&versions_dir)?;
-        std::fs::create_dir_all(&cache_dir)?;
+/// Sort `versions` newest first by `order`.
+pub fn sort_versions(versions: &mut [VersionInfo], order: SortOrder) {
+    versions.sort_by(|a, b| {
+        let by_installed = || {
+            b.installed_at
+                .cmp(&a.installed_at)
+                .then_with(|| b.parsed().cmp(&a.parsed()))
+        };
+        match order {
+            SortOrder::Installed => by_installed(),
+            SortOrder::Version => b.parsed().cmp(&a.parsed()).then_with(by_installed),
+        }
+        .then_with(|| a.version.cmp(&b.version))
+    });
+}
 
-        Ok(Self {
-            storage_dir: data_dir,
-            versions_dir,
-            cache_dir,
-        })
+/// A release asset as returned by the GitHub API.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Asset {
+    /// Id in the assets API, see [`assets_api`].
+    #[serde(default)]
+    pub id: Option<u64>,
+    pub name: String,
+    pub browser_download_url: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Install state of a single target, stored in `targets/<name>.json`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct TargetState {
+    pub version: String,
+    pub install_path: PathBuf,
+    pub installed_at: DateTime<Utc>,
+}
+
+/// A stored version removed by cleanup.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RemovedVersion {
+    pub version: String,
+    pub bytes: u64,
+}
+
+/// Something in the versions directory that isn't a stored version, e.g. a
+/// stray file or a sync tool's "conflicted copy" of a version directory.
+///
+/// Foreign entries are listed but never removed by cleanup; only
+/// `clean --foreign` removes them.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ForeignEntry {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Why it isn't treated as a version.
+    pub reason: String,
+}
+
+impl std::fmt::Display for ForeignEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}",
+            self.path.display(),
+            units::format_size(self.bytes),
+            self.reason
+        )
     }
+}
 
-    /// Get storage directory path
-    pub fn storage_dir(&self) -> &Path {
-        &self.storage_dir
+/// A stored binary that isn't executable, e.g. after a backup and restore
+/// that didn't keep modes.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModeProblem {
+    pub version: String,
+    pub path: PathBuf,
+    /// Permission bits found.
+    pub mode: u32,
+}
+
+impl std::fmt::Display for ModeProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} has mode {:04o}, not executable",
+            self.version,
+            self.path.display(),
+            self.mode
+        )
     }
+}
 
-    /// Get versions directory path
-    pub fn versions_dir(&self) -> &Path {
-        &self.versions_dir
+/// An install target whose binary is none of the stored versions, e.g. a
+/// patched build copied over it by hand.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub target: String,
+    pub path: PathBuf,
+    /// The version the target is recorded to have.
+    pub version: String,
+    pub installed_sha256: String,
+    pub stored_sha256: String,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "installed binary {} (target {}) differs from stored copy of {}",
+            self.path.display(),
+            self.target,
+            self.version
+        )
     }
+}
 
-    /// Get cache directory path
-    pub fn cache_dir(&self) -> &Path {
-        &self.cache_dir
+impl Divergence {
+    /// The command that keeps the installed build as a stored version.
+    pub fn adopt_command(&self) -> String {
+        format!("opencode-updater adopt --path {}", self.path.display())
     }
+}
 
-    /// Get list of installed versions
-    pub fn list_installed_versions(&self) -> Result<Vec<VersionInfo>, Box<dyn std::error::Error>> {
-        let mut versions = Vec::new();
+/// Outcome of `clean --foreign`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForeignCleanReport {
+    pub removed: Vec<ForeignEntry>,
+}
 
-        if !self.versions_dir.exists() {
-            return Ok(versions);
+impl std::fmt::Display for ForeignCleanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.removed.is_empty() {
+            return write!(f, "No foreign entries in the versions directory");
         }
+        let lines: Vec<String> = self
+            .removed
+            .iter()
+            .map(|entry| format!("Removed foreign entry: {}", entry))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
 
-        for entry in std::fs::read_dir(&self.versions_dir)? {
-            let entry = entry?;
-            let path = entry.path();
+/// A stored version cleanup tried and failed to remove.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct CleanupFailure {
+    pub version: String,
+    pub path: PathBuf,
+    pub error: String,
+    /// Whether the failure was a permission problem, e.g. a root-owned file.
+    #[serde(default)]
+    pub permission_denied: bool,
+}
 
-            if path.is_dir() {
-                let metadata_file = path.join("metadata.json");
-                if metadata_file.exists() {
-                    let content = std::fs::read_to_string(metadata_file)?;
-                    let version_info: VersionInfo = serde_json::from_str(&content)?;
-                    versions.push(version_info);
-                }
-            }
+impl std::fmt::Display for CleanupFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to remove {}: {}", self.version, self.error)?;
+        if self.permission_denied {
+            write!(f, " — try `sudo rm -rf {}`", self.path.display())?;
         }
-
-        // Sort by installation date (newest first)
-        versions.sort_by(|a, b| b.installed_at.cmp(&a.installed_at));
-        Ok(versions)
+        Ok(())
     }
+}
 
-    /// Get current active version
-    pub fn get_current_version(&self) -> Result<Option<VersionInfo>, Box<dyn std::error::Error>> {
-        // First check if we have a local storage symlink
-        let current_link = self.storage_dir.join("current");
+/// Outcome of [`VersionManager::cleanup_old_versions`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub removed: Vec<RemovedVersion>,
+    /// Versions that should have been removed but couldn't be.
+    pub failed: Vec<CleanupFailure>,
+}
 
-        if current_link.exists() {
-            let target = std::fs::read_link(&current_link)?;
-            let metadata_file = target.join("metadata.json");
+impl CleanupReport {
+    /// Whether versions were due for removal and none of them could be removed.
+    pub fn nothing_cleaned(&self) -> bool {
+        self.removed.is_empty() && !self.failed.is_empty()
+    }
+}
 
-            if metadata_file.exists() {
-                let content = std::fs::read_to_string(metadata_file)?;
-                let version_info: VersionInfo = serde_json::from_str(&content)?;
-                return Ok(Some(version_info));
-            }
+impl std::fmt::Display for CleanupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines: Vec<String> = self
+            .removed
+            .iter()
+            .map(|r| {
+                format!(
+                    "Removed old version: {} ({})",
+                    r.version,
+                    units::format_size(r.bytes)
+                )
+            })
+            .collect();
+        if self.failed.is_empty() {
+            return write!(f, "{}", lines.join("\n"));
         }
-
-        // Fallback: Check if system binary exists and try to detect its version
-        let system_binary = Path::new("/usr/bin/opencode");
-        if system_binary.exists()
-            && let Some(version_info) = self.detect_system_version()?
-        {
-            return Ok(Some(version_info));
+        if !lines.is_empty() {
+            writeln!(f, "{}", lines.join("\n"))?;
         }
-
-        Ok(None)
+        let failures: Vec<String> = self.failed.iter().map(|e| e.to_string()).collect();
+        write!(
+            f,
+            "Cleanup: removed {} version{}, {}",
+            self.removed.len(),
+            if self.removed.len() == 1 { "" } else { "s" },
+            failures.join("; ")
+        )
     }
+}
 
-    /// Detect version of system-installed binary
-    fn detect_system_version(&self) -> Result<Option<VersionInfo>, Box<dyn std::error::Error>> {
-        let output = Command::new("opencode").arg("--version").output().ok();
+/// Outcome of [`VersionManager::backup_current_version`].
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BackupReport {
+    pub version: String,
+    pub source: PathBuf,
+}
 
-        if let Some(output) = output
-            && output.status.success()
-        {
-            let version_str = String::from_utf8_lossy(&output.stdout);
-            let version = version_str.trim().trim_start_matches('v');
+impl std::fmt::Display for BackupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Backed up current version: {}", self.version)
+    }
+}
 
-            // Create version info for detected system binary
-            let version_info = VersionInfo {
-                version: version.to_string(),
-                tag_name: format!("v{}", version),
-                release_date: Utc::now(), // Unknown, use current time
-                download_url: String::new(),
-                checksum: String::new(),
-                installed_at: Utc::now(), // Unknown, use current time
-                install_path: PathBuf::from("/usr/bin/opencode"),
-                release_notes: "Currently installed version (release notes unknown)".to_string(),
-            };
+/// Outcome of a rollback.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RollbackReport {
+    pub version: String,
+    pub targets: Vec<String>,
+    pub integrity: IntegrityStatus,
+    /// Shell completion files put back along with the binary.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub completions: Vec<PathBuf>,
+}
 
-            return Ok(Some(version_info));
+impl std::fmt::Display for RollbackReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Successfully rolled back to version {}", self.version)?;
+        for path in &self.completions {
+            write!(f, "\nRestored completions: {}", path.display())?;
         }
-
-        Ok(None)
+        Ok(())
     }
+}
 
-    /// Save version with metadata
-    pub fn save_version(
-        &self,
-        version: &VersionInfo,
-        binary_path: &Path,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let version_dir = self.versions_dir.join(&version.version);
-        std::fs::create_dir_all(&version_dir)?;
-
-        // Copy binary to version directory
-        let version_binary = version_dir.join("opencode");
-        std::fs::copy(binary_path, &version_binary)?;
-
-        // Make it executable
-        let mut perms = std::fs::metadata(&version_binary)?.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(&version_binary, perms)?;
-
-        // Save metadata
-        let metadata_file = version_dir.join("metadata.json");
-        let metadata_json = serde_json::to_string_pretty(version)?;
-        std::fs::write(metadata_file, metadata_json)?;
+/// Outcome of `resume-install`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ResumeReport {
+    #[serde(flatten)]
+    pub install: RollbackReport,
+}
 
+impl std::fmt::Display for ResumeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Installed staged update {} to {}",
+            self.install.version,
+            self.install.targets.join(", ")
+        )?;
+        for path in &self.install.completions {
+            write!(f, "\nRestored completions: {}", path.display())?;
+        }
         Ok(())
     }
+}
 
-    /// Rollback to specific version
-    pub fn rollback_to(&self, version: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let version_dir = self.versions_dir.join(version);
-        let version_binary = version_dir.join("opencode");
-        let metadata_file = version_dir.join("metadata.json");
+/// Total size in bytes of the files under a directory.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(m) if m.is_dir() => dir_size(&entry.path()),
+            Ok(m) => m.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
 
-        if !version_binary.exists() {
-            return Err(UpdaterError::VersionNotFound(version.to_string()).into());
-        }
+/// Environment variable overriding the storage directory.
+pub const HOME_ENV: &str = "OPENCODE_UPDATER_HOME";
 
-        // Read metadata to verify
-        let _version_info: VersionInfo =
-            serde_json::from_str(&std::fs::read_to_string(metadata_file)?)?;
-
-        // Install the binary to system location
-        Command::new("sudo")
-            .arg("cp")
-            .arg(&version_binary)
-            .arg("/usr/bin/opencode")
-            .status()?;
-
-        // Ensure executable permissions
-        Command::new("sudo")
-            .arg("chmod")
-            .arg("+x")
-            .arg("/usr/bin/opencode")
-            .status()?;
+/// Resolved on-disk locations used by the updater.
+///
+/// Resolving paths never writes to the filesystem, so it is safe on a
+/// read-only home; [`VersionManager`] creates the directories it needs.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StoragePaths {
+    pub storage_dir: PathBuf,
+    pub versions_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub targets_dir: PathBuf,
+    pub history_file: PathBuf,
+    /// Latest GitHub API rate limit, for later commands and other tools.
+    pub status_file: PathBuf,
+    pub integrity_key: PathBuf,
+    /// Where archives are extracted, on the same filesystem as `versions_dir`.
+    pub tmp_dir: PathBuf,
+    /// The shared store `versions_dir`, `tmp_dir` and `integrity_key` are in, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared: Option<shared::SharedStorage>,
+}
 
-        // Update current symlink
-        let current_link = self.storage_dir.join("current");
-        if current_link.exists() {
-            std::fs::remove_file(&current_link)?;
+impl StoragePaths {
+    /// Default locations, honouring `OPENCODE_UPDATER_HOME` for the storage directory.
+    ///
+    /// The integrity key is never stored alongside the metadata it protects:
+    /// it is [`integrity::SYSTEM_KEY_PATH`], readable only by root, or in the
+    /// config directory with `OPENCODE_UPDATER_HOME`. Without
+    /// `OPENCODE_UPDATER_HOME`, a `storage_dir` in the system config moves
+    /// versions into a store shared by every user, see [`shared`].
+    pub fn resolve() -> Result<Self, UpdaterError> {
+        let home = std::env::var_os(HOME_ENV).filter(|home| !home.is_empty());
+        let data_dir = match &home {
+            Some(home) => PathBuf::from(home),
+            None => dirs::data_dir()
+                .ok_or_else(|| {
+                    UpdaterError::StorageError("could not find the data directory".to_string())
+                })?
+                .join("opencode-updater"),
+        };
+        let mut paths = Self::for_storage_dir(data_dir);
+        if home.is_none() {
+            paths.integrity_key = PathBuf::from(integrity::SYSTEM_KEY_PATH);
+        } else if let Some(config_dir) = dirs::config_dir() {
+            paths.integrity_key = config_dir.join("opencode-updater").join("integrity.key");
         }
-        std::os::unix::fs::symlink(&version_dir, &current_link)?;
-
-        println!("Successfully rolled back to version {}", version);
-        Ok(())
+        if home.is_none()
+            && let Some(storage) = Config::load_system()?.shared_storage()
+        {
+            paths = paths.with_shared_storage(storage);
+        }
+        Ok(paths)
     }
 
-    /// Clean up old versions (keep only N most recent, default: 2)
-    pub fn cleanup_old_versions(
-        &self,
-        keep_count: usize,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut versions = self.list_installed_versions()?;
+    /// Locations rooted at a custom storage directory, with the integrity key inside it.
+    pub fn for_storage_dir(data_dir: PathBuf) -> Self {
+        Self {
+            versions_dir: data_dir.join("versions").join(migration::TOOL_DIR),
+            cache_dir: data_dir.join("cache"),
+            targets_dir: data_dir.join("targets"),
+            history_file: data_dir.join("history.jsonl"),
+            status_file: data_dir.join("status.json"),
+            integrity_key: data_dir.join("integrity.key"),
+            tmp_dir: data_dir.join("tmp"),
+            storage_dir: data_dir,
+            shared: None,
+        }
+    }
 
-        if versions.len() <= keep_count {
-            return Ok(());
+    /// Keep versions, and what protects and builds them, in a shared store.
+    ///
+    /// The integrity key moves with them so every user verifies against it.
+    pub fn with_shared_storage(self, storage: shared::SharedStorage) -> Self {
+        Self {
+            versions_dir: storage.dir.join("versions").join(migration::TOOL_DIR),
+            integrity_key: storage.dir.join("integrity.key"),
+            tmp_dir: storage.dir.join("tmp"),
+            shared: Some(storage),
+            ..self
         }
+    }
+}
 
-        // Remove versions beyond keep_count (excluding current version)
-        let current_version = self.get_current_version()?;
-        versions.retain(|v| {
-            current_version
-                .as_ref()
-                .is_none_or(|curr| curr.version != v.version)
-        });
+/// Output of the `paths` subcommand.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PathsReport {
+    #[serde(flatten)]
+    pub storage: StoragePaths,
+    pub config_file: Option<PathBuf>,
+    /// Install targets the current arguments select, by name.
+    pub install_targets: std::collections::BTreeMap<String, PathBuf>,
+}
 
-        versions.sort_by(|a, b| b.installed_at.cmp(&a.installed_at));
+impl PathsReport {
+    /// Resolve every path for the given arguments without creating anything.
+    pub fn resolve(args: &Args) -> Result<Self, UpdaterError> {
+        let config = Config::load()?;
+        let install_targets = config
+            .resolve_targets(args.target.as_deref(), args.all_targets)?
+            .into_iter()
+            .map(|(name, target)| (name, target.path))
+            .collect();
+        Ok(Self {
+            storage: StoragePaths::resolve()?,
+            config_file: Config::default_path(),
+            install_targets,
+        })
+    }
+}
 
-        for version in versions.iter().skip(keep_count) {
-            let version_dir = self.versions_dir.join(&version.version);
-            std::fs::remove_dir_all(&version_dir)?;
-            println!("Removed old version: {}", version.version);
+impl std::fmt::Display for PathsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "storage_dir={}", self.storage.storage_dir.display())?;
+        writeln!(f, "versions_dir={}", self.storage.versions_dir.display())?;
+        writeln!(f, "cache_dir={}", self.storage.cache_dir.display())?;
+        writeln!(f, "targets_dir={}", self.storage.targets_dir.display())?;
+        writeln!(f, "history_file={}", self.storage.history_file.display())?;
+        writeln!(f, "status_file={}", self.storage.status_file.display())?;
+        writeln!(f, "tmp_dir={}", self.storage.tmp_dir.display())?;
+        writeln!(f, "integrity_key={}", self.storage.integrity_key.display())?;
+        if let Some(shared) = &self.storage.shared {
+            writeln!(f, "shared_dir={}", shared.dir.display())?;
+            if let Some(group) = &shared.group {
+                writeln!(f, "shared_group={}", group)?;
+            }
         }
+        if let Some(config_file) = &self.config_file {
+            writeln!(f, "config_file={}", config_file.display())?;
+        }
+        let targets: Vec<String> = self
+            .install_targets
+            .iter()
+            .map(|(name, path)| format!("install_target.{}={}", name, path.display()))
+            .collect();
+        write!(f, "{}", targets.join("\n"))
+    }
+}
 
-        Ok(())
+/// Manages version storage and operations
+pub struct VersionManager {
+    storage_dir: PathBuf,
+    versions_dir: PathBuf,
+    cache_dir: PathBuf,
+    tmp_dir: PathBuf,
+    integrity_key: PathBuf,
+    allow_unsigned: bool,
+    identity_marker: regex::Regex,
+    #[cfg(feature = "fault-injection")]
+    save_fault: Option<staging::SaveStep>,
+    sharing: Option<shared::Sharing>,
+    migrated: Option<migration::MigrationReport>,
+    recovered: recovery::RecoveryReport,
+}
+
+/// What is installed at a path, judged by [`VersionManager::identify_binary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryIdentity {
+    /// Nothing is installed there.
+    Missing,
+    /// A file exists but `--version` could not be run successfully.
+    Unknown,
+    /// The output contains the opencode identity marker.
+    Opencode(BinaryProbe),
+    /// Something else called opencode.
+    Foreign(BinaryProbe),
+}
+
+impl VersionManager {
+    /// Initialize version manager with default storage directory
+    ///
+    /// `OPENCODE_UPDATER_HOME` overrides the storage directory.
+    pub fn new() -> Result<Self, UpdaterError> {
+        Self::from_paths(StoragePaths::resolve()?)
     }
 
-    /// Backup current version before updating
-    pub fn backup_current_version(
-        &self,
-    ) -> Result<Option<VersionInfo>, Box<dyn std::error::Error>> {
-        // Check if current binary exists
-        if !Path::new("/usr/bin/opencode").exists() {
-            return Ok(None);
+    /// Initialize version manager rooted at a custom storage directory
+    ///
+    /// The integrity key defaults to `integrity.key` inside the storage
+    /// directory; use [`VersionManager::with_integrity_key`] to keep it elsewhere.
+    pub fn with_storage_dir(data_dir: PathBuf) -> Result<Self, UpdaterError> {
+        Self::from_paths(StoragePaths::for_storage_dir(data_dir))
+    }
+
+    /// Initialize version manager at resolved paths, creating its directories
+    ///
+    /// Storage in an older layout is migrated first.
+    pub fn from_paths(paths: StoragePaths) -> Result<Self, UpdaterError> {
+        let migrated = migration::migrate_storage(&paths.storage_dir)?;
+
+        // The shared store's directories first, so those below them inherit its group
+        let sharing = paths
+            .shared
+            .as_ref()
+            .map(shared::Sharing::new)
+            .transpose()?;
+        if let (Some(sharing), Some(storage)) = (&sharing, &paths.shared) {
+            for dir in [
+                &storage.dir,
+                &storage.dir.join("versions"),
+                &paths.versions_dir,
+                &paths.tmp_dir,
+            ] {
+                sharing.prepare_dir(dir)?;
+            }
+            integrity::load_or_create_key(&paths.integrity_key)?;
+            sharing.share_key(&paths.integrity_key)?;
         }
 
-        // Try to get version info from running binary
-        let output = Command::new("opencode").arg("--version").output().ok();
+        // Create directories if they don't exist
+        std::fs::create_dir_all(&paths.versions_dir)?;
+        std::fs::create_dir_all(&paths.cache_dir)?;
+        if migration::storage_version(&paths.storage_dir).is_none() {
+            migration::write_marker(&paths.storage_dir)?;
+        }
 
-        if let Some(output) = output
-            && output.status.success()
-        {
-            let version_str = String::from_utf8_lossy(&output.stdout);
-            let version = version_str.trim().trim_start_matches('v');
+        let recovered = recovery::sweep(
+            &paths.versions_dir.with_file_name(lock::LOCK_FILE),
+            &paths.versions_dir,
+            &paths.tmp_dir,
+        )?;
 
-            // Create version info
-            let version_info = VersionInfo {
-                version: version.to_string(),
+        Ok(Self {
+            storage_dir: paths.storage_dir,
+            versions_dir: paths.versions_dir,
+            cache_dir: paths.cache_dir,
+            tmp_dir: paths.tmp_dir,
+            integrity_key: paths.integrity_key,
+            allow_unsigned: false,
+            identity_marker: identity::marker_regex(None)?,
+            #[cfg(feature = "fault-injection")]
+            save_fault: None,
+            sharing,
+            migrated,
+            recovered,
+        })
+    }
+
+    /// The versions moved to the current layout when this manager was created
+    pub fn migrated(&self) -> Option<&migration::MigrationReport> {
+        self.migrated.as_ref()
+    }
+
+    /// What the startup sweep cleared up after crashed runs, for the caller to report
+    pub fn recovered(&self) -> &recovery::RecoveryReport {
+        &self.recovered
+    }
+
+    /// Directory for extraction scratch space inside the storage directory
+    pub fn tmp_dir(&self) -> &Path {
+        &self.tmp_dir
+    }
+
+    /// Create a temporary directory for extracting an archive of `archive_size` bytes
+    ///
+    /// Lives under [`Self::tmp_dir`] unless `override_dir` is given or that
+    /// filesystem lacks the space and the system temp dir has more.
+    pub fn create_extract_dir(
+        &self,
+        override_dir: Option<&Path>,
+        archive_size: u64,
+    ) -> Result<tempfile::TempDir, UpdaterError> {
+        let root = scratch::choose_root(
+            &self.tmp_dir,
+            scratch::space_needed(archive_size),
+            override_dir,
+        );
+        std::fs::create_dir_all(&root)?;
+        Ok(tempfile::Builder::new()
+            .prefix(&scratch::extract_prefix())
+            .tempdir_in(&root)?)
+    }
+
+    /// Use a different marker to recognise upstream opencode's output
+    pub fn with_identity_marker(mut self, marker: regex::Regex) -> Self {
+        self.identity_marker = marker;
+        self
+    }
+
+    /// Make every save fail right after `step`, to exercise its recovery
+    ///
+    /// Only built with the `fault-injection` feature, which the tests enable.
+    #[cfg(feature = "fault-injection")]
+    pub fn with_save_fault(mut self, step: staging::SaveStep) -> Self {
+        self.save_fault = Some(step);
+        self
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn save_checkpoint(&self, step: staging::SaveStep) -> Result<(), UpdaterError> {
+        match self.save_fault {
+            Some(fault) if fault == step => Err(staging::injected_failure(step)),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn save_checkpoint(&self, _step: staging::SaveStep) -> Result<(), UpdaterError> {
+        Ok(())
+    }
+
+    /// Check whether the binary at a path is upstream opencode
+    pub fn identify_binary(&self, binary: &Path) -> BinaryIdentity {
+        if !binary.exists() {
+            return BinaryIdentity::Missing;
+        }
+        match BinaryProbe::run(binary) {
+            None => BinaryIdentity::Unknown,
+            Some(probe) => self.classify_probe(probe),
+        }
+    }
+
+    /// Refuse to replace a binary at `path` that isn't opencode, unless forced
+    ///
+    /// Returns the foreign binary's output when `force` overrides the check,
+    /// so the caller can warn about it.
+    pub fn ensure_replaceable(
+        &self,
+        path: &Path,
+        force: bool,
+    ) -> Result<Option<BinaryProbe>, UpdaterError> {
+        match self.identify_binary(path) {
+            BinaryIdentity::Foreign(probe) if force => Ok(Some(probe)),
+            BinaryIdentity::Foreign(probe) => Err(UpdaterError::ForeignBinary(format!(
+                "{} printed \"{}\", which doesn't look like opencode. \
+                 Refusing to back it up or overwrite it; pass {} to replace it anyway, \
+                 or set identity_marker in the config file if this is opencode",
+                path.display(),
+                sanitize_terminal_text(&probe.excerpt()),
+                Guard::Foreign.flag()
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    fn classify_probe(&self, probe: BinaryProbe) -> BinaryIdentity {
+        if identity::matches_marker(
+            &probe.version_output,
+            &probe.help_output,
+            &self.identity_marker,
+        ) {
+            BinaryIdentity::Opencode(probe)
+        } else {
+            BinaryIdentity::Foreign(probe)
+        }
+    }
+
+    /// Use a different file for the HMAC key protecting stored metadata
+    pub fn with_integrity_key(mut self, path: PathBuf) -> Self {
+        self.integrity_key = path;
+        self
+    }
+
+    /// Let rollback install versions saved without an HMAC
+    pub fn with_unsigned_allowed(mut self, allow: bool) -> Self {
+        self.allow_unsigned = allow;
+        self
+    }
+
+    /// Get storage directory path
+    pub fn storage_dir(&self) -> &Path {
+        &self.storage_dir
+    }
+
+    /// Get versions directory path
+    pub fn versions_dir(&self) -> &Path {
+        &self.versions_dir
+    }
+
+    /// Directory of the stored `version`, found through its metadata, see
+    /// [`version_dir`].
+    pub fn find_version_dir(&self, version: &str) -> Option<PathBuf> {
+        version_dir::candidates(version)
+            .into_iter()
+            .map(|name| self.versions_dir.join(name))
+            .find(|dir| metadata::read(dir).is_ok_and(|info| info.version == version))
+    }
+
+    /// Directory of `version`: where it is stored, or else where it would be.
+    pub fn version_dir(&self, version: &str) -> PathBuf {
+        self.find_version_dir(version)
+            .unwrap_or_else(|| self.versions_dir.join(version_dir::dir_name(version)))
+    }
+
+    /// Get cache directory path
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Get the file locked while the versions directory changes, see [`lock`]
+    pub fn lock_file(&self) -> PathBuf {
+        self.versions_dir.with_file_name(lock::LOCK_FILE)
+    }
+
+    /// Get the update history log
+    pub fn history_file(&self) -> PathBuf {
+        self.storage_dir.join("history.jsonl")
+    }
+
+    /// Get directory holding per-target install state
+    pub fn targets_dir(&self) -> PathBuf {
+        self.storage_dir.join("targets")
+    }
+
+    /// Record that a version was installed to the named target
+    pub fn record_target_install(
+        &self,
+        target_name: &str,
+        version: &str,
+        install_path: &Path,
+    ) -> Result<(), UpdaterError> {
+        let targets_dir = self.targets_dir();
+        std::fs::create_dir_all(&targets_dir)?;
+
+        let state = TargetState {
+            version: version.to_string(),
+            install_path: install_path.to_path_buf(),
+            installed_at: Utc::now(),
+        };
+        let state_file = targets_dir.join(format!("{}.json", target_name));
+        std::fs::write(state_file, serde_json::to_string_pretty(&state)?)?;
+        Ok(())
+    }
+
+    /// Get recorded install state of every target, keyed by target name
+    pub fn target_states(
+        &self,
+    ) -> Result<std::collections::BTreeMap<String, TargetState>, UpdaterError> {
+        let mut states = std::collections::BTreeMap::new();
+        let targets_dir = self.targets_dir();
+        if !targets_dir.exists() {
+            return Ok(states);
+        }
+
+        for entry in std::fs::read_dir(&targets_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json")
+                && let Some(name) = path.file_stem().and_then(|n| n.to_str())
+            {
+                let state: TargetState = serde_json::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|source| UpdaterError::MetadataError {
+                        path: Some(path.clone()),
+                        source,
+                    })?;
+                states.insert(name.to_string(), state);
+            }
+        }
+        Ok(states)
+    }
+
+    /// Get list of installed versions
+    pub fn list_installed_versions(&self) -> Result<Vec<VersionInfo>, UpdaterError> {
+        Ok(self.scan_versions_dir()?.0)
+    }
+
+    /// Stored versions, newest first by `order`.
+    pub fn list_installed_versions_by(
+        &self,
+        order: SortOrder,
+    ) -> Result<Vec<VersionInfo>, UpdaterError> {
+        let mut versions = self.list_installed_versions()?;
+        sort_versions(&mut versions, order);
+        Ok(versions)
+    }
+
+    /// Entries of the versions directory that aren't stored versions
+    pub fn foreign_entries(&self) -> Result<Vec<ForeignEntry>, UpdaterError> {
+        Ok(self.scan_versions_dir()?.1)
+    }
+
+    /// Split the versions directory into stored versions (newest first) and
+    /// foreign entries (by path).
+    ///
+    /// Only a directory holding metadata that parses and names a version
+    /// stored under the directory's name counts as a version.
+    fn scan_versions_dir(&self) -> Result<(Vec<VersionInfo>, Vec<ForeignEntry>), UpdaterError> {
+        let mut versions = Vec::new();
+        let mut foreign = Vec::new();
+
+        if !self.versions_dir.exists() {
+            return Ok((versions, foreign));
+        }
+
+        for entry in std::fs::read_dir(&self.versions_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            // Symlinks are not followed: a link is foreign whatever it points at
+            let reason = if !entry.file_type()?.is_dir() {
+                "not a directory".to_string()
+            } else if staging::is_leftover(&name) {
+                "left behind by an interrupted save".to_string()
+            } else {
+                match metadata::read(&path) {
+                    // The entry is listed by its path already
+                    Err(e) => match &e {
+                        UpdaterError::MetadataError {
+                            path: Some(file),
+                            source,
+                        } => format!(
+                            "{} doesn't parse: {}",
+                            file.file_name().unwrap_or_default().to_string_lossy(),
+                            source
+                        ),
+                        _ => e.to_string(),
+                    },
+                    Ok(info) if !version_dir::names(&name, &info.version) => format!(
+                        "metadata is for version {}",
+                        sanitize_terminal_text(&info.version)
+                    ),
+                    Ok(info) => {
+                        versions.push(info);
+                        continue;
+                    }
+                }
+            };
+            let bytes = match entry.file_type()?.is_dir() {
+                true => dir_size(&path),
+                false => std::fs::symlink_metadata(&path).map_or(0, |m| m.len()),
+            };
+            foreign.push(ForeignEntry {
+                path,
+                bytes,
+                reason,
+            });
+        }
+
+        // Versions saved in the same instant still list newest version first
+        sort_versions(&mut versions, SortOrder::Installed);
+        foreign.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((versions, foreign))
+    }
+
+    /// Remove every foreign entry from the versions directory
+    pub fn remove_foreign_entries(&self) -> Result<ForeignCleanReport, UpdaterError> {
+        let _lock = lock::StoreLock::acquire(&self.lock_file())?;
+        let mut report = ForeignCleanReport::default();
+        for entry in self.foreign_entries()? {
+            if std::fs::symlink_metadata(&entry.path)?.is_dir() {
+                std::fs::remove_dir_all(&entry.path)?;
+            } else {
+                std::fs::remove_file(&entry.path)?;
+            }
+            report.removed.push(entry);
+        }
+        Ok(report)
+    }
+
+    /// Get current active version
+    pub fn get_current_version(&self) -> Result<Option<VersionInfo>, UpdaterError> {
+        // First check if we have a local storage symlink
+        let current_link = self.storage_dir.join("current");
+
+        if current_link.exists() {
+            let target = std::fs::read_link(&current_link)?;
+
+            if metadata::is_present(&target) {
+                return Ok(Some(metadata::read(&target)?));
+            }
+        }
+
+        // Fallback: Check if system binary exists and try to detect its version
+        let system_binary = Path::new("/usr/bin/opencode");
+        if system_binary.exists()
+            && let Some(version_info) = self.detect_system_version()?
+        {
+            return Ok(Some(version_info));
+        }
+
+        Ok(None)
+    }
+
+    /// Detect version of system-installed binary
+    fn detect_system_version(&self) -> Result<Option<VersionInfo>, UpdaterError> {
+        let probe = BinaryProbe::run(Path::new("opencode")).map(|probe| self.classify_probe(probe));
+
+        // A different program named opencode has no version worth reporting
+        if let Some(BinaryIdentity::Opencode(probe)) = probe {
+            let version = probe.version();
+
+            // Create version info for detected system binary
+            let version_info = VersionInfo {
+                version: version.to_string(),
+                tag_name: format!("v{}", version),
+                release_date: Utc::now(), // Unknown, use current time
+                download_url: String::new(),
+                checksum: String::new(),
+                installed_at: Utc::now(), // Unknown, use current time
+                install_path: PathBuf::from("/usr/bin/opencode"),
+                release_notes: "Currently installed version (release notes unknown)".to_string(),
+                asset_size: None,
+                channel: Channel::of_version(version),
+                ..Default::default()
+            };
+
+            return Ok(Some(version_info));
+        }
+
+        Ok(None)
+    }
+
+    /// Save version with metadata
+    pub fn save_version(
+        &self,
+        version: &VersionInfo,
+        binary_path: &Path,
+    ) -> Result<(), UpdaterError> {
+        self.store_version(version, binary_path, false, None, None, None)
+    }
+
+    /// Save a version whose binary is a temporary file, moving it into storage
+    ///
+    /// When the file is on the same filesystem (e.g. extracted under
+    /// [`Self::tmp_dir`]) this is a rename rather than a copy.
+    ///
+    /// The stored binary must hash to `binary_sha256`, taken right after
+    /// extraction; otherwise it is removed again and nothing is saved. The
+    /// version's completion files are copied from `extract_root` with it, and
+    /// `sbom` is stored beside it, see [`sbom`].
+    pub fn save_extracted_version(
+        &self,
+        version: &VersionInfo,
+        binary_path: &Path,
+        binary_sha256: &str,
+        extract_root: &Path,
+        sbom: Option<&[u8]>,
+    ) -> Result<(), UpdaterError> {
+        self.store_version(
+            version,
+            binary_path,
+            true,
+            Some(binary_sha256),
+            Some(extract_root),
+            sbom,
+        )
+    }
+
+    fn store_version(
+        &self,
+        version: &VersionInfo,
+        binary_path: &Path,
+        move_binary: bool,
+        expected_sha256: Option<&str>,
+        extract_root: Option<&Path>,
+        sbom: Option<&[u8]>,
+    ) -> Result<(), UpdaterError> {
+        version_dir::validate(&version.version)?;
+        let _lock = lock::StoreLock::acquire(&self.lock_file())?;
+        let dest = self.version_dir(&version.version);
+        // Only the version itself is replaced, never another one that shares
+        // its directory on a case-insensitive filesystem
+        if let Ok(other) = metadata::read(&dest)
+            && other.version != version.version
+        {
+            return Err(UpdaterError::StorageError(format!(
+                "{} already holds version {}; not replacing it with {}",
+                dest.display(),
+                sanitize_terminal_text(&other.version),
+                sanitize_terminal_text(&version.version)
+            )));
+        }
+        // Built aside and renamed into place; dropping it on error cleans up
+        let dir_name = dest.file_name().unwrap_or_default().to_string_lossy();
+        let staged = staging::staging_dir(&self.versions_dir, &dir_name)?;
+        let version_dir = staged.path();
+
+        // Copy binary to version directory
+        let version_binary = version_dir.join("opencode");
+        if move_binary {
+            scratch::move_file(binary_path, &version_binary)?;
+        } else {
+            std::fs::copy(binary_path, &version_binary)?;
+        }
+
+        // Make it executable
+        let mut perms = std::fs::metadata(&version_binary)?.permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&version_binary, perms)?;
+
+        // Bind the metadata to the stored binary
+        let binary_sha256 = calculate_sha256(&std::fs::read(&version_binary)?);
+        if let Some(expected) = expected_sha256
+            && binary_sha256 != expected
+        {
+            return Err(UpdaterError::IntegrityError(format!(
+                "{} changed after it was extracted (expected sha256 {}, found {}); not saving it",
+                binary_path.display(),
+                expected,
+                binary_sha256
+            )));
+        }
+        let mut version = version.clone();
+        version.schema_version = migration::METADATA_SCHEMA_VERSION;
+        // Without a terminal, a key only root can read may be out of reach
+        if integrity::key_available(&self.integrity_key) {
+            let key = integrity::load_or_create_key(&self.integrity_key)?;
+            version.hmac = Some(integrity::compute_hmac(
+                &key,
+                &version.version,
+                &version.tag_name,
+                &binary_sha256,
+            ));
+            integrity::record_signed(&self.integrity_key, &version.version)?;
+            if let Some(sharing) = &self.sharing {
+                sharing.share_file(&integrity::ledger_path(&self.integrity_key))?;
+            }
+        } else {
+            reporter::warn(
+                "unsigned-version",
+                &format!(
+                    "{} can't be read without a password prompt; version {} is saved \
+                     without an HMAC, and rolling back to it takes --allow-unsigned",
+                    self.integrity_key.display(),
+                    version.version
+                ),
+            );
+        }
+        version.binary_sha256 = Some(binary_sha256);
+        if version.installed_by.is_none() {
+            version.installed_by = Some(shared::current_user());
+        }
+        self.save_checkpoint(staging::SaveStep::Binary)?;
+
+        if let Some(root) = extract_root {
+            completions::store(root, version_dir, &version.completions)?;
+        }
+        if let Some(sbom) = sbom {
+            std::fs::write(version_dir.join(sbom::SBOM_FILE), sbom)?;
+        }
+        self.save_checkpoint(staging::SaveStep::Completions)?;
+
+        metadata::write(version_dir, &version)?;
+        if let Some(sharing) = &self.sharing {
+            sharing.share_tree(version_dir)?;
+        }
+        self.save_checkpoint(staging::SaveStep::Metadata)?;
+
+        staging::sync_tree(version_dir)?;
+        self.save_checkpoint(staging::SaveStep::Sync)?;
+
+        staging::commit(staged, &dest, |step| self.save_checkpoint(step))
+    }
+
+    /// A stored version's release as far as the changelog needs it, from its
+    /// metadata and `notes.md`, for when GitHub can't be reached
+    pub fn stored_release(&self, version: &str) -> Result<Option<serde_json::Value>, UpdaterError> {
+        let version_dir = self.version_dir(version);
+        if !metadata::is_present(&version_dir) {
+            return Ok(None);
+        }
+        let info = metadata::read(&version_dir)?;
+        Ok(Some(serde_json::json!({
+            "tag_name": info.tag_name,
+            "published_at": info.release_date.to_rfc3339(),
+            "body": info.release_notes,
+        })))
+    }
+
+    /// The SBOM stored with a version, as published
+    pub fn read_sbom(&self, version: &str) -> Result<String, UpdaterError> {
+        let version_dir = self.version_dir(version);
+        if !metadata::is_present(&version_dir) {
+            return Err(UpdaterError::VersionNotFound(version.to_string()));
+        }
+        match std::fs::read_to_string(version_dir.join(sbom::SBOM_FILE)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(UpdaterError::StorageError(format!(
+                    "version {} was saved without an SBOM; updates fetch one with --sbom",
+                    version
+                )))
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Check a stored version's binary and metadata against its recorded HMAC
+    ///
+    /// A version with an HMAC fails with [`UpdaterError::IntegrityError`]
+    /// when the key is missing; the key is never created here.
+    pub fn verify_version_integrity(&self, version: &str) -> Result<IntegrityStatus, UpdaterError> {
+        let key = integrity::load_key(&self.integrity_key)?;
+        self.verify_version_with_key(version, key.as_deref())
+    }
+
+    fn verify_version_with_key(
+        &self,
+        version: &str,
+        key: Option<&[u8]>,
+    ) -> Result<IntegrityStatus, UpdaterError> {
+        let version_dir = self.version_dir(version);
+        let version_binary = version_dir.join("opencode");
+        if !version_binary.exists() {
+            return Err(UpdaterError::VersionNotFound(version.to_string()));
+        }
+        let version_info = metadata::read(&version_dir)?;
+
+        let (Some(recorded_hash), Some(recorded_hmac)) =
+            (&version_info.binary_sha256, &version_info.hmac)
+        else {
+            // The ledger knows which versions were saved with an HMAC
+            return Ok(
+                if integrity::was_signed(&self.integrity_key, &version_info.version)? {
+                    IntegrityStatus::MetadataModified
+                } else {
+                    IntegrityStatus::Unsigned
+                },
+            );
+        };
+
+        let key = key.ok_or_else(|| integrity::missing_key_error(&self.integrity_key, version))?;
+        if !integrity::verify_hmac(
+            key,
+            &version_info.version,
+            &version_info.tag_name,
+            recorded_hash,
+            recorded_hmac,
+        ) {
+            return Ok(IntegrityStatus::MetadataModified);
+        }
+        if hash_file(&version_binary)? != *recorded_hash {
+            return Ok(IntegrityStatus::BinaryModified);
+        }
+        Ok(IntegrityStatus::Verified)
+    }
+
+    /// Integrity status of every stored version, keyed by version
+    pub fn verify_storage(&self) -> Result<Vec<(String, IntegrityStatus)>, UpdaterError> {
+        self.verify_storage_with_jobs(default_jobs())
+    }
+
+    /// Integrity status of every stored version, hashing on up to `jobs` threads.
+    ///
+    /// Results are sorted by version regardless of which thread finished first.
+    /// A version that can't be read is reported as
+    /// [`IntegrityStatus::Unreadable`] without stopping the others.
+    pub fn verify_storage_with_jobs(
+        &self,
+        jobs: usize,
+    ) -> Result<Vec<(String, IntegrityStatus)>, UpdaterError> {
+        let versions: Vec<String> = self
+            .list_installed_versions()?
+            .into_iter()
+            .map(|info| info.version)
+            .collect();
+        // Loaded once up front rather than once per worker
+        let key = integrity::load_key(&self.integrity_key)?;
+
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let results = std::sync::Mutex::new(Vec::with_capacity(versions.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, versions.len().max(1)) {
+                scope.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let Some(version) = versions.get(i) else {
+                            break;
+                        };
+                        let status = self
+                            .verify_version_with_key(version, key.as_deref())
+                            .unwrap_or_else(|e| IntegrityStatus::Unreadable(e.to_string()));
+                        results
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .push((version.clone(), status));
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(results)
+    }
+
+    /// Mode stored binaries are given: 0755, or the shared store's.
+    fn exec_mode(&self) -> u32 {
+        if self.sharing.is_some() {
+            shared::EXEC_MODE
+        } else {
+            0o755
+        }
+    }
+
+    /// Stored binaries missing an executable bit, sorted by version like
+    /// [`Self::verify_storage`].
+    pub fn non_executable_versions(&self) -> Result<Vec<ModeProblem>, UpdaterError> {
+        let mut problems = Vec::new();
+        for info in self.list_installed_versions()? {
+            let path = self.version_dir(&info.version).join("opencode");
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let mode = metadata.permissions().mode() & 0o7777;
+            if mode & 0o111 != 0o111 {
+                problems.push(ModeProblem {
+                    version: info.version,
+                    path,
+                    mode,
+                });
+            }
+        }
+        problems.sort_by(|a, b| a.version.cmp(&b.version));
+        Ok(problems)
+    }
+
+    /// Install targets whose binary matches no stored version, though the
+    /// target is recorded to have one.
+    ///
+    /// A target is compared with the version recorded for it, or the current
+    /// version when nothing is recorded; missing binaries are skipped.
+    pub fn divergent_installs(
+        &self,
+        targets: &[(String, InstallTarget)],
+    ) -> Result<Vec<Divergence>, UpdaterError> {
+        let states = self.target_states()?;
+        let current = self.get_current_version()?.map(|info| info.version);
+        let stored = self.list_installed_versions()?;
+        let mut stored_hashes = std::collections::BTreeMap::new();
+        for info in &stored {
+            let hash = match &info.binary_sha256 {
+                Some(hash) => hash.clone(),
+                None => match hash_file(&self.version_dir(&info.version).join("opencode")) {
+                    Ok(hash) => hash,
+                    Err(_) => continue,
+                },
+            };
+            stored_hashes.insert(info.version.clone(), hash);
+        }
+
+        let mut divergences = Vec::new();
+        for (name, target) in targets {
+            let Some(version) = states
+                .get(name)
+                .map(|state| state.version.clone())
+                .or_else(|| current.clone())
+            else {
+                continue;
+            };
+            let Some(stored_sha256) = stored_hashes.get(&version) else {
+                continue;
+            };
+            let Ok(installed_sha256) = hash_file(&target.path) else {
+                continue;
+            };
+            if stored_hashes.values().any(|hash| *hash == installed_sha256) {
+                continue;
+            }
+            divergences.push(Divergence {
+                target: name.clone(),
+                path: target.path.clone(),
+                version,
+                installed_sha256,
+                stored_sha256: stored_sha256.clone(),
+            });
+        }
+        Ok(divergences)
+    }
+
+    /// Refuse to overwrite a target holding a build no stored version has,
+    /// unless `allowed`.
+    pub fn ensure_no_divergence(
+        &self,
+        targets: &[(String, InstallTarget)],
+        allowed: bool,
+    ) -> Result<(), UpdaterError> {
+        if allowed {
+            return Ok(());
+        }
+        match self.divergent_installs(targets)?.first() {
+            Some(divergence) => Err(UpdaterError::Refused(format!(
+                "{}, and it would be lost; keep it as a local version with `{}` first, or pass {} to overwrite it",
+                divergence,
+                divergence.adopt_command(),
+                Guard::Modified.flag()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Make every stored binary executable again; returns those that weren't.
+    pub fn repair_modes(&self) -> Result<Vec<ModeProblem>, UpdaterError> {
+        let problems = self.non_executable_versions()?;
+        for problem in &problems {
+            self.ensure_executable(&problem.path)?;
+        }
+        Ok(problems)
+    }
+
+    /// Give a stored binary its executable mode, if it lost it.
+    fn ensure_executable(&self, binary: &Path) -> Result<(), UpdaterError> {
+        let mode = std::fs::metadata(binary)?.permissions().mode();
+        if mode & 0o111 != 0o111 {
+            std::fs::set_permissions(binary, std::fs::Permissions::from_mode(self.exec_mode()))
+                .map_err(|e| {
+                    UpdaterError::PermissionError(format!(
+                        "{} is not executable and its mode can't be restored: {}",
+                        binary.display(),
+                        e
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// The stored version `version` names: itself when stored, otherwise the
+    /// newest stored version it is a prefix of, so `0.3` finds `0.3.10`.
+    ///
+    /// A prefix ends at a component, so `0.3` doesn't match `0.30.1`. When
+    /// nothing matches, the error lists the stored versions either side of
+    /// the one asked for.
+    pub fn resolve_stored_version(&self, version: &str) -> Result<String, UpdaterError> {
+        if self.find_version_dir(version).is_some() {
+            return Ok(version.to_string());
+        }
+        let stored = self.list_installed_versions()?;
+        let newest = stored
+            .iter()
+            .filter(|info| {
+                info.version
+                    .strip_prefix(version)
+                    .is_some_and(|rest| rest.starts_with(['.', '-']))
+            })
+            .max_by(|a, b| {
+                a.parsed()
+                    .cmp(&b.parsed())
+                    .then_with(|| a.version.cmp(&b.version))
+            });
+        if let Some(info) = newest {
+            return Ok(info.version.clone());
+        }
+
+        // The nearest stored versions below and above the one asked for
+        let Ok(wanted) = version.parse::<Version>() else {
+            return Err(UpdaterError::VersionNotFound(version.to_string()));
+        };
+        let mut parsed: Vec<(Version, &str)> = stored
+            .iter()
+            .filter_map(|info| Some((info.parsed()?, info.version.as_str())))
+            .collect();
+        parsed.sort();
+        let split = parsed.partition_point(|(stored, _)| *stored < wanted);
+        let closest: Vec<String> = [split.checked_sub(1), Some(split)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| parsed.get(i).map(|(_, name)| sanitize_terminal_text(name)))
+            .collect();
+        if closest.is_empty() {
+            return Err(UpdaterError::VersionNotFound(version.to_string()));
+        }
+        Err(UpdaterError::VersionNotFound(format!(
+            "{} (closest stored: {})",
+            version,
+            closest.join(", ")
+        )))
+    }
+
+    /// Rollback to specific version on the system target
+    pub fn rollback_to(&self, version: &str) -> Result<RollbackReport, UpdaterError> {
+        let targets = [(
+            config::DEFAULT_TARGET_NAME.to_string(),
+            InstallTarget::system(),
+        )];
+        self.rollback_to_targets(version, &targets)
+    }
+
+    /// Rollback to specific version on each of the given targets
+    ///
+    /// A partial version is resolved by [`Self::resolve_stored_version`].
+    pub fn rollback_to_targets(
+        &self,
+        version: &str,
+        targets: &[(String, InstallTarget)],
+    ) -> Result<RollbackReport, UpdaterError> {
+        version_dir::validate(version)?;
+        let resolved = self.resolve_stored_version(version)?;
+        let version = resolved.as_str();
+        let version_dir = self.version_dir(version);
+        let version_binary = version_dir.join("opencode");
+
+        if !version_binary.exists() {
+            return Err(UpdaterError::VersionNotFound(version.to_string()));
+        }
+
+        // Read metadata to verify
+        let version_info = metadata::read(&version_dir)?;
+
+        // Refuse to install a stored binary that changed since it was saved
+        let integrity = self.verify_version_integrity(version)?;
+        match integrity {
+            IntegrityStatus::Verified => {}
+            IntegrityStatus::Unsigned if self.allow_unsigned => {}
+            IntegrityStatus::Unsigned => {
+                return Err(UpdaterError::IntegrityError(format!(
+                    "stored version {} has no HMAC, so a binary swapped in since it was saved \
+                     would go unnoticed; refusing to install it with sudo. \
+                     Pass --allow-unsigned to install it anyway",
+                    version
+                )));
+            }
+            status => {
+                return Err(UpdaterError::IntegrityError(format!(
+                    "stored version {} failed verification ({}); refusing to install it with sudo. \
+                     This check detects changes by anyone who cannot read {}, \
+                     but not by processes running as your own user.",
+                    version,
+                    status,
+                    self.integrity_key.display()
+                )));
+            }
+        }
+
+        // The mode isn't covered by the hash, and a target's chmod doesn't fix the stored copy
+        self.ensure_executable(&version_binary)?;
+
+        // The hash just verified is the one the installer checks again before copying
+        let binary_sha256 = match (&integrity, version_info.binary_sha256) {
+            (IntegrityStatus::Verified, Some(recorded)) => recorded,
+            _ => hash_file(&version_binary)?,
+        };
+
+        // Install the binary to each target location
+        for (name, target) in targets {
+            install_to_target(&version_binary, &binary_sha256, target)?;
+            self.record_target_install(name, version, &target.path)?;
+        }
+
+        // Put back the completions that shipped with this version
+        let completions = completions::install(&version_dir, &version_info.completions)?;
+
+        // Update current symlink
+        self.set_current(version)?;
+
+        Ok(RollbackReport {
+            version: version.to_string(),
+            targets: targets.iter().map(|(name, _)| name.clone()).collect(),
+            integrity,
+            completions,
+        })
+    }
+
+    /// Point the `current` symlink at a stored version
+    pub fn set_current(&self, version: &str) -> Result<(), UpdaterError> {
+        let current_link = self.storage_dir.join("current");
+        if current_link.exists() || current_link.is_symlink() {
+            std::fs::remove_file(&current_link)?;
+        }
+        std::os::unix::fs::symlink(self.version_dir(version), &current_link)?;
+        Ok(())
+    }
+
+    /// Clean up old versions (keep only N most recently installed, default: 2)
+    ///
+    /// A version that can't be removed doesn't stop the others; it is listed
+    /// in [`CleanupReport::failed`] instead.
+    pub fn cleanup_old_versions(&self, keep_count: usize) -> Result<CleanupReport, UpdaterError> {
+        self.cleanup_old_versions_by(keep_count, SortOrder::Installed)
+    }
+
+    /// Clean up old versions, keeping the N newest by `order`.
+    ///
+    /// With [`SortOrder::Version`] the highest versions are kept even when
+    /// an older one was installed more recently, e.g. by a rollback.
+    pub fn cleanup_old_versions_by(
+        &self,
+        keep_count: usize,
+        order: SortOrder,
+    ) -> Result<CleanupReport, UpdaterError> {
+        let _lock = lock::StoreLock::acquire(&self.lock_file())?;
+        let mut report = CleanupReport::default();
+        let mut versions = self.list_installed_versions_by(order)?;
+
+        // Remove versions beyond keep_count (excluding current version)
+        let current_version = self.get_current_version()?;
+        versions.retain(|v| {
+            current_version
+                .as_ref()
+                .is_none_or(|curr| curr.version != v.version)
+        });
+
+        // Builds of each rolling tag are kept separately from versioned releases
+        let mut kept: std::collections::HashMap<Option<&str>, usize> =
+            std::collections::HashMap::new();
+        for version in &versions {
+            let channel = is_rolling_tag(&version.tag_name).then_some(version.tag_name.as_str());
+            let count = kept.entry(channel).or_default();
+            if *count < keep_count {
+                *count += 1;
+                continue;
+            }
+
+            let version_dir = self.version_dir(&version.version);
+            let bytes = dir_size(&version_dir);
+            match std::fs::remove_dir_all(&version_dir) {
+                Ok(()) => report.removed.push(RemovedVersion {
+                    version: version.version.clone(),
+                    bytes,
+                }),
+                Err(e) => report.failed.push(CleanupFailure {
+                    version: version.version.clone(),
+                    path: version_dir,
+                    error: e.kind().to_string(),
+                    permission_denied: e.kind() == std::io::ErrorKind::PermissionDenied,
+                }),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Backup current version before updating
+    pub fn backup_current_version(&self) -> Result<Option<BackupReport>, UpdaterError> {
+        let source = Path::new(config::SYSTEM_INSTALL_PATH);
+        Ok(self.backup_binary(source)?.map(|info| BackupReport {
+            version: info.version,
+            source: source.to_path_buf(),
+        }))
+    }
+
+    /// Store a copy of an installed binary, using its `--version` output as the version
+    pub fn backup_binary(&self, binary: &Path) -> Result<Option<VersionInfo>, UpdaterError> {
+        // Only back up binaries that identify as opencode
+        if let BinaryIdentity::Opencode(probe) = self.identify_binary(binary) {
+            let version = probe.version();
+
+            // Create version info
+            let version_info = VersionInfo {
+                version: version.to_string(),
                 tag_name: format!("v{}", version),
                 release_date: Utc::now(),
                 download_url: String::new(),
                 checksum: String::new(),
                 installed_at: Utc::now(),
-                install_path: PathBuf::from("/usr/bin/opencode"),
+                install_path: binary.to_path_buf(),
                 release_notes: "Current installation".to_string(),
+                asset_size: None,
+                channel: Channel::of_version(version),
+                ..Default::default()
             };
 
             // Save current binary
-            let current_binary = Path::new("/usr/bin/opencode");
-            self.save_version(&version_info, current_binary)?;
+            self.save_version(&version_info, binary)?;
 
             return Ok(Some(version_info));
         }
 
         Ok(None)
     }
+
+    /// Back up an existing install and register it as the current version
+    pub fn adopt_binary(&self, binary: &Path) -> Result<Option<VersionInfo>, UpdaterError> {
+        let adopted = self.backup_binary(binary)?;
+        if let Some(version_info) = &adopted {
+            self.set_current(&version_info.version)?;
+        }
+        Ok(adopted)
+    }
+
+    /// Whether `init` has run or versions were already stored by earlier releases
+    pub fn is_initialized(&self) -> bool {
+        self.storage_dir.join(INITIALIZED_MARKER).exists()
+            || std::fs::read_dir(&self.versions_dir)
+                .is_ok_and(|mut entries| entries.next().is_some())
+    }
+
+    /// Record that the storage layout has been initialized
+    pub fn mark_initialized(&self) -> Result<(), UpdaterError> {
+        std::fs::write(self.storage_dir.join(INITIALIZED_MARKER), "")?;
+        Ok(())
+    }
+
+    /// Returns true the first time it's called on an uninitialized storage dir
+    ///
+    /// Used to hint at `init` once instead of on every run.
+    pub fn take_init_hint(&self) -> bool {
+        if self.is_initialized() {
+            return false;
+        }
+        let hint_marker = self.storage_dir.join(INIT_HINT_MARKER);
+        if hint_marker.exists() {
+            return false;
+        }
+        std::fs::write(hint_marker, "").is_ok()
+    }
+}
+
+/// Marker file written by `init`.
+const INITIALIZED_MARKER: &str = ".initialized";
+
+/// Marker file recording that the `init` hint was shown.
+const INIT_HINT_MARKER: &str = ".init-hint-shown";
+
+/// Finds an executable by name in a `PATH`-style list of directories.
+pub fn find_in_path(name: &str, path_var: &std::ffi::OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path_var)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
+
+/// Copies a binary to an install target and makes it executable.
+///
+/// Targets marked `sudo` are written through `sudo`; others are written
+/// directly, creating the parent directory if needed. `source` must hash to
+/// `expected_sha256` when it is copied.
+pub fn install_to_target(
+    source: &Path,
+    expected_sha256: &str,
+    target: &InstallTarget,
+) -> Result<(), UpdaterError> {
+    let length = std::fs::metadata(source)?.len();
+    progress::defer_interrupts(|| {
+        installer::install_binary(
+            &*installer::installer_for(target),
+            source,
+            expected_sha256,
+            &target.path,
+            false,
+            &*progress::install_progress(length, &target.path),
+        )
+    })
 }
 
 /// Calculates the SHA-256 hash of the given bytes.
@@ -329,15 +2043,108 @@ pub fn calculate_sha256(bytes: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Calculates the SHA-256 hash of a file, reading it in chunks.
+pub fn hash_file(path: &Path) -> Result<String, std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0; HASH_CHUNK_SIZE];
+    loop {
+        let bytes_read = file.read(&mut chunk)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..bytes_read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Read size used by [`hash_file`].
+const HASH_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Default number of threads for `--verify-storage`: one per CPU, at most 4.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(4)
+}
+
 /// Verifies if the SHA-256 hash of bytes matches the expected hash.
 pub fn verify_checksum(bytes: &[u8], expected: &str) -> bool {
     calculate_sha256(bytes) == expected
 }
 
+/// Initial read buffer size for downloads.
+pub const MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest read buffer size downloads grow to on fast links.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Throughput (bytes/sec) above which the read buffer keeps growing.
+pub const HIGH_THROUGHPUT: f64 = 16.0 * 1024.0 * 1024.0;
+
+/// Minimum interval between progress bar updates (~10 per second).
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Window over which peak throughput is measured.
+const PEAK_WINDOW: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Picks the next read buffer size from the current size and recent throughput.
+///
+/// The buffer doubles while a read filled it completely and throughput is at
+/// least [`HIGH_THROUGHPUT`], up to [`MAX_CHUNK_SIZE`].
+pub fn next_chunk_size(current: usize, filled: bool, bytes_per_sec: f64) -> usize {
+    if filled && bytes_per_sec >= HIGH_THROUGHPUT {
+        (current * 2).min(MAX_CHUNK_SIZE)
+    } else {
+        current
+    }
+}
+
+/// Statistics about a completed download.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct DownloadStats {
+    pub bytes: u64,
+    pub elapsed: std::time::Duration,
+    /// Average throughput in bytes per second.
+    pub average_bps: f64,
+    /// Highest throughput over any 250ms window, in bytes per second.
+    pub peak_bps: f64,
+}
+
+impl DownloadStats {
+    /// Statistics of `bytes` received in `elapsed`, at most `peak_bps` over
+    /// any [`PEAK_WINDOW`].
+    pub fn new(bytes: u64, elapsed: std::time::Duration, peak_bps: f64) -> Self {
+        let average_bps = bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        Self {
+            bytes,
+            elapsed,
+            average_bps,
+            // Downloads shorter than one window never complete a measurement
+            peak_bps: peak_bps.max(average_bps),
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in {:.1}s (average {}, peak {})",
+            units::format_size(self.bytes),
+            self.elapsed.as_secs_f64(),
+            units::format_rate(self.average_bps),
+            units::format_rate(self.peak_bps)
+        )
+    }
+}
+
 /// Downloads a file with real-time progress display.
 ///
 /// This function downloads a file from the given URL while displaying a progress bar
-/// that shows the download progress, speed, and estimated time remaining.
+/// that shows the download progress, speed, and estimated time remaining. Use
+/// [`download_with_stats`] for the transfer statistics.
 ///
 /// # Arguments
 ///
@@ -356,45 +2163,354 @@ pub fn download_with_progress(
     client: &Agent,
     url: &str,
     filename: &str,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+) -> Result<Vec<u8>, UpdaterError> {
+    Ok(download_with_stats(client, url, filename)?.0)
+}
+
+/// Downloads a file with progress display and returns it with transfer statistics.
+///
+/// Reads start at [`MIN_CHUNK_SIZE`] and grow towards [`MAX_CHUNK_SIZE`] on fast
+/// links to cut per-call overhead; the progress bar is updated at most ~10
+/// times per second. A download that fails for a moment starts over, see
+/// [`retry`].
+pub fn download_with_stats(
+    client: &Agent,
+    url: &str,
+    filename: &str,
+) -> Result<(Vec<u8>, DownloadStats), UpdaterError> {
+    retry::with_retries(|attempt| {
+        let mut buffer = Vec::new();
+        let (stats, content_encoding) =
+            stream_download(client, url, filename, attempt, &mut |chunk| {
+                buffer.extend_from_slice(chunk);
+                Ok(())
+            })?;
+        Ok((content_encoding.decode_asset(buffer, filename)?, stats))
+    })
+}
+
+/// Downloads a file to `dest` with progress display, without holding it in
+/// memory, and returns its SHA-256, computed as it arrives, with the transfer
+/// statistics.
+///
+/// `dest` is created, or truncated, and left holding whatever arrived if the
+/// download fails. As with [`download_with_stats`], a download that fails
+/// for a moment starts over.
+pub fn download_to_file(
+    client: &Agent,
+    url: &str,
+    dest: &Path,
+    filename: &str,
+) -> Result<(String, DownloadStats), UpdaterError> {
+    use std::io::Write;
+
+    let (sha256, stats) = retry::with_retries(|attempt| {
+        let mut file = std::fs::File::create(dest)?;
+        let mut hasher = Sha256::new();
+        let (stats, content_encoding) =
+            stream_download(client, url, filename, attempt, &mut |chunk| {
+                hasher.update(chunk);
+                Ok(file.write_all(chunk)?)
+            })?;
+        if content_encoding != encoding::ContentEncoding::Identity {
+            // A mirror compressing an archive is rare enough to decode in memory
+            let body = content_encoding.decode_asset(std::fs::read(dest)?, filename)?;
+            std::fs::write(dest, &body)?;
+            return Ok((calculate_sha256(&body), stats));
+        }
+        Ok((format!("{:x}", hasher.finalize()), stats))
+    })?;
+    Ok((sha256, stats))
+}
+
+/// Attempt number `attempt` at downloading `url`, passing each chunk of the
+/// body to `write` as it arrives, still in the encoding it was sent in.
+fn stream_download(
+    client: &Agent,
+    url: &str,
+    filename: &str,
+    attempt: u32,
+    write: &mut dyn FnMut(&[u8]) -> Result<(), UpdaterError>,
+) -> Result<(DownloadStats, encoding::ContentEncoding), UpdaterError> {
+    use std::time::Instant;
+    use ureq::ResponseExt;
+
     // Make the request to get headers first
-    let response = client.get(url).call()?;
+    let response = payload::send(
+        client,
+        client
+            .get(url)
+            .header("Accept-Encoding", encoding::ASSET_ACCEPT_ENCODING),
+    )?;
+    if reporter::verbose() {
+        eprintln!(
+            "Downloading {} from {}",
+            sanitize_terminal_text(filename),
+            sanitize_terminal_text(&trace::redact_url(&response.get_uri().to_string()))
+        );
+    }
+    let content_encoding = encoding::ContentEncoding::of(response.headers())?;
 
-    // Get content length for progress bar
-    let content_length = response
+    // A web page in place of an archive is a portal or proxy; don't download it
+    let html = response
         .headers()
-        .get("Content-Length")
-        .and_then(|len| len.to_str().ok())
-        .and_then(|len| len.parse::<u64>().ok())
-        .unwrap_or(0);
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(payload::is_html_content_type);
+    if html && payload::is_archive_name(filename) {
+        return Err(payload::html_error(filename));
+    }
+
+    // Progress counts bytes on the wire, compressed if the server compressed them
+    let expected_length = encoding::content_length(response.headers());
+    let content_length = expected_length.unwrap_or(0);
 
     // Create progress bar
-    let progress = ProgressBar::new(content_length);
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-")
-    );
-    progress.set_message(format!("Downloading {}", filename));
+    let started = Instant::now();
+    let throughput = std::sync::Arc::new(std::sync::Mutex::new(
+        progress::ThroughputEstimator::new(started),
+    ));
+    let progress = match attempt {
+        1 => progress::download_progress(content_length, filename, throughput.clone()),
+        attempt => progress::download_retry_progress(
+            content_length,
+            filename,
+            throughput.clone(),
+            attempt,
+            retry::policy().attempts,
+        ),
+    };
 
     // Start the download with progress tracking
     let mut reader = response.into_body().into_reader();
-    let mut buffer = Vec::new();
-    let mut chunk = [0; 8192]; // 8KB chunks
+    let mut received = 0u64;
+    let mut chunk = vec![0; MIN_CHUNK_SIZE];
+
+    let mut last_draw = started;
+    let mut window_start = started;
+    let mut window_bytes = 0u64;
+    let mut peak_bps = 0f64;
+    let throttle = rate_limit::Throttle::for_download(started);
 
     loop {
-        let bytes_read = reader.read(&mut chunk)?;
+        // A limited download reads little at a time, so the buffer never fills and grows
+        let read_len =
+            throttle.map_or(chunk.len(), |throttle| chunk.len().min(throttle.max_read()));
+        let bytes_read = reader.read(&mut chunk[..read_len]).map_err(|e| {
+            match expected_length {
+                // How ureq ends a body shorter than its Content-Length
+                Some(expected) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    encoding::CutShort {
+                        what: filename.to_string(),
+                        received,
+                        expected,
+                        encoding: content_encoding,
+                    }
+                    .into_request_error(url)
+                }
+                _ => payload::request_error(url, ureq::Error::Io(e)),
+            }
+        })?;
         if bytes_read == 0 {
             break;
         }
+        write(&chunk[..bytes_read])?;
+        received += bytes_read as u64;
+        window_bytes += bytes_read as u64;
+        if let Some(throttle) = &throttle {
+            throttle.pace(received);
+        }
+
+        let now = Instant::now();
+        let window = now.duration_since(window_start);
+        if window >= PEAK_WINDOW {
+            let bps = window_bytes as f64 / window.as_secs_f64();
+            peak_bps = peak_bps.max(bps);
+            let next = next_chunk_size(chunk.len(), bytes_read == chunk.len(), bps);
+            if next != chunk.len() {
+                chunk.resize(next, 0);
+            }
+            window_start = now;
+            window_bytes = 0;
+        } else if bytes_read == chunk.len() && chunk.len() < MAX_CHUNK_SIZE {
+            // Short windows that keep filling the buffer are fast by definition
+            let elapsed = window.as_secs_f64().max(f64::EPSILON);
+            let next = next_chunk_size(chunk.len(), true, window_bytes as f64 / elapsed);
+            if next != chunk.len() {
+                chunk.resize(next, 0);
+            }
+        }
 
-        buffer.extend_from_slice(&chunk[..bytes_read]);
-        progress.inc(bytes_read as u64);
+        if now.duration_since(last_draw) >= PROGRESS_INTERVAL {
+            if let Ok(mut throughput) = throughput.lock() {
+                throughput.record(now, received);
+            }
+            progress.set_position(received);
+            last_draw = now;
+        }
     }
 
-    progress.finish_with_message(format!("Downloaded {}", filename));
-    Ok(buffer)
+    progress.set_position(received);
+    progress.finish(format!("Downloaded {}", filename));
+
+    let elapsed = started.elapsed();
+    let bytes = received;
+    network::record_download(bytes);
+    encoding::check_complete(filename, bytes, expected_length, content_encoding)?;
+    Ok((
+        DownloadStats::new(bytes, elapsed, peak_bps),
+        content_encoding,
+    ))
+}
+
+/// Exit code of an update stored for `resume-install` instead of installed,
+/// EX_TEMPFAIL from sysexits.h.
+pub const STAGED_EXIT_CODE: i32 = 75;
+
+/// Delay before re-downloading an asset whose checksum didn't match.
+pub const CHECKSUM_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Downloads a file and verifies it against every published checksum.
+///
+/// A corrupted transfer (e.g. a flaky middlebox) is retried exactly once from
+/// scratch after `retry_delay`. If the second download mismatches as well, the
+/// published checksum itself is the likely problem and the error says so.
+/// With no checksums the download is returned unverified.
+pub fn download_verified(
+    client: &Agent,
+    url: &str,
+    filename: &str,
+    checksums: &[Checksum],
+    retry_delay: std::time::Duration,
+) -> Result<Vec<u8>, UpdaterError> {
+    let mut timings = PhaseTimings::default();
+    download_checked_twice(
+        filename,
+        retry_delay,
+        &mut timings,
+        || assets_api::download(client, url, filename, None),
+        |bytes| {
+            payload::check_archive(bytes, filename, !checksums.is_empty())?;
+            Ok(verify_checksums(bytes, checksums))
+        },
+    )
+}
+
+/// [`download_verified`] to the file `dest`, without holding the download
+/// in memory, recording download and verification time separately. The file
+/// is written by `download`, which returns its SHA-256. Returns the checksums
+/// the file was verified against, once `checksums` has them. A mismatch is
+/// retried after [`CHECKSUM_RETRY_DELAY`].
+fn download_verified_to_file(
+    dest: &Path,
+    filename: &str,
+    mut checksums: PendingChecksums<'_>,
+    timings: &mut PhaseTimings,
+    download: impl FnMut() -> Result<String, UpdaterError>,
+) -> Result<Vec<Checksum>, UpdaterError> {
+    download_checked_twice(
+        filename,
+        CHECKSUM_RETRY_DELAY,
+        timings,
+        download,
+        |sha256| check_archive_file(dest, filename, checksums.wait(), sha256),
+    )?;
+    Ok(checksums.wait().to_vec())
+}
+
+/// Check the archive `filename` at `dest`, whose SHA-256 is `sha256`, is
+/// one and matches `checksums`.
+fn check_archive_file(
+    dest: &Path,
+    filename: &str,
+    checksums: &[Checksum],
+    sha256: &str,
+) -> Result<Result<Vec<ChecksumAlgorithm>, checksum::Mismatch>, UpdaterError> {
+    let mut head = Vec::with_capacity(payload::HEAD_LEN);
+    std::fs::File::open(dest)?
+        .take(payload::HEAD_LEN as u64)
+        .read_to_end(&mut head)?;
+    payload::check_archive(&head, filename, !checksums.is_empty())?;
+    Ok(checksum::verify_file_checksums(dest, sha256, checksums)?)
+}
+
+/// Copy the archive [`archive_cache`] holds for `checksums` to `dest`, if
+/// there is one and it still matches them, and return whether it did.
+///
+/// A cached archive that doesn't match is evicted, not retried.
+fn take_cached_archive(
+    cache_dir: &Path,
+    dest: &Path,
+    filename: &str,
+    checksums: &[Checksum],
+) -> Result<bool, UpdaterError> {
+    let Some(sha256) = checksums
+        .iter()
+        .find(|checksum| checksum.algorithm == ChecksumAlgorithm::Sha256)
+        .map(|checksum| checksum.digest.as_str())
+    else {
+        return Ok(false);
+    };
+    let Some(cached) = archive_cache::lookup(cache_dir, sha256) else {
+        return Ok(false);
+    };
+    std::fs::copy(&cached, dest)?;
+    let problem = match check_archive_file(dest, filename, checksums, &hash_file(dest)?) {
+        Ok(Ok(_)) => return Ok(true),
+        Ok(Err(mismatch)) => mismatch.to_string(),
+        Err(e) => e.to_string(),
+    };
+    reporter::warn(
+        "corrupt-cache",
+        &format!(
+            "removed the cached copy of {} at {}: {}",
+            sanitize_terminal_text(filename),
+            cached.display(),
+            problem
+        ),
+    );
+    archive_cache::evict(cache_dir, sha256)?;
+    Ok(false)
+}
+
+/// Run `download`, then `check` on what it got: an error from `check` fails
+/// at once, while a checksum mismatch it reports is retried exactly once,
+/// from scratch, after `retry_delay`.
+fn download_checked_twice<T>(
+    filename: &str,
+    retry_delay: std::time::Duration,
+    timings: &mut PhaseTimings,
+    mut download: impl FnMut() -> Result<T, UpdaterError>,
+    mut check: impl FnMut(
+        &T,
+    )
+        -> Result<Result<Vec<ChecksumAlgorithm>, checksum::Mismatch>, UpdaterError>,
+) -> Result<T, UpdaterError> {
+    let downloaded = timings.time(Phase::Download, &mut download)?;
+    let Err(mismatch) = timings.time(Phase::Verify, || check(&downloaded))? else {
+        return Ok(downloaded);
+    };
+
+    reporter::warn(
+        "checksum-retry",
+        &format!(
+            "{} for {}; retrying download once",
+            mismatch,
+            sanitize_terminal_text(filename)
+        ),
+    );
+    drop(downloaded);
+    std::thread::sleep(retry_delay);
+
+    let downloaded = timings.time(Phase::Download, &mut download)?;
+    match timings.time(Phase::Verify, || check(&downloaded))? {
+        Ok(_) => Ok(downloaded),
+        Err(mismatch) => Err(UpdaterError::RepeatedChecksumMismatch {
+            file: filename.to_string(),
+            expected: mismatch.expected,
+            actual: mismatch.actual,
+        }),
+    }
 }
 
 /// Finds an asset by name in the list of assets.
@@ -405,20 +2521,224 @@ pub fn find_asset<'a>(
     assets.iter().find(|a| a["name"] == name)
 }
 
-/// Fetches the latest release information from GitHub API.
-pub fn fetch_release(
+/// Media type requested from the GitHub REST API.
+pub const GITHUB_ACCEPT: &str = "application/vnd.github+json";
+
+/// GitHub REST API version the response parsing is written against.
+pub const GITHUB_API_VERSION: &str = "2022-11-28";
+
+/// HTTP client for all requests, giving up after [`payload::MAX_REDIRECTS`] redirects.
+///
+/// Requests are traced with `--trace-http` and held to `strict_sources`. Error statuses become errors in
+/// a middleware instead of inside ureq, so the trace still sees them. Timeouts
+/// are [`DEFAULT_CONNECT_TIMEOUT`] and [`DEFAULT_READ_TIMEOUT`], and a proxy
+/// is taken from the environment.
+pub fn http_client() -> Agent {
+    build_client(DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT, None)
+}
+
+/// Parse `--proxy`: `http://`, `https://` or, without a scheme, an HTTP
+/// proxy, optionally with `user:password@`.
+pub fn parse_proxy(value: &str) -> Result<ureq::Proxy, String> {
+    ureq::Proxy::new(value.trim()).map_err(|_| format!("'{}' is not a proxy URL", value))
+}
+
+/// Seconds to connect, including the DNS lookup, unless `--connect-timeout` says otherwise.
+pub const DEFAULT_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Seconds to wait for the answer to a request, unless `--timeout` says
+/// otherwise.
+pub const DEFAULT_READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// [`http_client`] with its own timeouts and proxy.
+///
+/// `connect_timeout` bounds the DNS lookup and the connection each.
+/// `read_timeout` bounds the wait for an answer once a request is sent, and
+/// the whole body of a GitHub API answer. An asset's body has
+/// no limit, since a large one takes a while on a slow link; the progress
+/// bar shows when it stalls.
+///
+/// Every request, API call or download, goes through `proxy`. Without one,
+/// the proxy comes from `ALL_PROXY`, `HTTPS_PROXY` or `HTTP_PROXY`, skipping
+/// the hosts in `NO_PROXY`.
+pub fn build_client(
+    connect_timeout: std::time::Duration,
+    read_timeout: std::time::Duration,
+    proxy: Option<ureq::Proxy>,
+) -> Agent {
+    Agent::config_builder()
+        .proxy(proxy.or_else(ureq::Proxy::try_from_env))
+        .timeout_resolve(Some(connect_timeout))
+        .timeout_connect(Some(connect_timeout))
+        .timeout_recv_response(Some(read_timeout))
+        // Followed by payload::send, which checks each hop before requesting it
+        .max_redirects(0)
+        .http_status_as_error(false)
+        .middleware(error_status_middleware)
+        .middleware(trace::middleware)
+        .middleware(allowlist::middleware)
+        .build()
+        .into()
+}
+
+/// Marks a request whose caller handles 4xx and 5xx responses itself.
+#[derive(Debug, Clone, Copy)]
+pub struct HandlesErrorStatus;
+
+/// Turn 4xx and 5xx responses into [`ureq::Error::StatusCode`], as ureq does
+/// by default, unless the request is marked [`HandlesErrorStatus`].
+fn error_status_middleware(
+    request: ureq::http::Request<ureq::SendBody>,
+    next: ureq::middleware::MiddlewareNext,
+) -> Result<ureq::http::Response<ureq::Body>, ureq::Error> {
+    let handled = request.extensions().get::<HandlesErrorStatus>().is_some();
+    let response = next.handle(request)?;
+    let status = response.status();
+    if !handled && (status.is_client_error() || status.is_server_error()) {
+        return Err(ureq::Error::StatusCode(status.as_u16()));
+    }
+    Ok(response)
+}
+
+/// Performs a GET request against the GitHub API and returns the response body.
+///
+/// Every GitHub API call goes through this helper so the `Accept` and
+/// `X-GitHub-Api-Version` headers, and the token in `GITHUB_TOKEN` if set,
+/// are always sent, and `--api-budget` is enforced. A 406 or 415 response means
+/// GitHub no longer serves the pinned API version, which only a newer build of
+/// this tool can fix.
+///
+/// A request that fails for a moment is made again, see [`retry`].
+pub fn github_get(client: &Agent, url: &str) -> Result<String, UpdaterError> {
+    retry::with_retries(|_| github_get_once(client, url))
+}
+
+fn github_get_once(client: &Agent, url: &str) -> Result<String, UpdaterError> {
+    network::spend_api_request(url)?;
+    // A token raises the rate limit from 60 requests an hour to 5000
+    let request = github_request(client, url, GITHUB_ACCEPT, assets_api::token().as_deref())
+        .config()
+        .timeout_recv_body(client.config().timeouts().recv_response)
+        .build()
+        .header("Accept-Encoding", encoding::API_ACCEPT_ENCODING);
+    let response = match payload::send(client, request) {
+        Ok(response) => response,
+        Err(UpdaterError::Http {
+            source: ureq::Error::StatusCode(status @ (406 | 415)),
+            ..
+        }) => return Err(unsupported_api_version(status)),
+        Err(e) => return Err(e),
+    };
+    check_github_response(&response)?;
+
+    let status = response.status().as_u16();
+    if status != 200 {
+        let body = encoding::read_text(response, url).unwrap_or_default();
+        return Err(http_error(status, &body));
+    }
+    encoding::read_text(response, url)
+}
+
+/// A GET request to the GitHub API for `url`, asking for `accept`, with the
+/// pinned API version and `token`, if any.
+///
+/// Error statuses are left to the caller, which checks the response with
+/// [`check_github_response`].
+pub(crate) fn github_request(
     client: &Agent,
-    base_url: &str,
-) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    url: &str,
+    accept: &str,
+    token: Option<&str>,
+) -> ureq::RequestBuilder<ureq::typestate::WithoutBody> {
+    let request = client
+        .get(url)
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .extension(HandlesErrorStatus)
+        .header("Accept", accept)
+        .header("X-GitHub-Api-Version", GITHUB_API_VERSION);
+    match token {
+        Some(token) => request.header("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+/// Record a GitHub API `response` and fail if it says the pinned API
+/// version is no longer served, or the rate limit is used up.
+pub(crate) fn check_github_response<B>(
+    response: &ureq::http::Response<B>,
+) -> Result<(), UpdaterError> {
+    network::record_api_response(response);
+    let status = response.status().as_u16();
+    if status == 406 || status == 415 {
+        return Err(unsupported_api_version(status));
+    }
+    match rate_limit_error(response) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Builds the error for an unexpected status, quoting GitHub's explanation.
+///
+/// The details travel in the error rather than being printed, so they end up
+/// on stderr together with it.
+fn http_error(status: u16, body: &str) -> UpdaterError {
+    let message = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| truncate_text(body.trim(), 5, 200).0);
+    UpdaterError::ApiStatus {
+        status,
+        message: sanitize_terminal_text(&message),
+    }
+}
+
+/// Builds the error for a rate-limited response, if this is one.
+///
+/// The wait is computed against the response's `Date` header, since
+/// `x-ratelimit-reset` is in server time and the local clock may be off.
+/// Secondary limits, for requests made too quickly, send `retry-after`
+/// instead.
+fn rate_limit_error<B>(response: &ureq::http::Response<B>) -> Option<UpdaterError> {
+    let status = response.status().as_u16();
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    if !(status == 403 || status == 429) {
+        return None;
+    }
+    let remaining = header("x-ratelimit-remaining").and_then(|value| value.parse::<u32>().ok());
+    let reset = header("x-ratelimit-reset").and_then(|value| value.parse::<i64>().ok());
+    let wait = match (remaining, reset) {
+        (Some(0), Some(reset)) => {
+            let server_now = clock::server_date(response).unwrap_or_else(Utc::now);
+            clock::rate_limit_wait(reset, server_now)
+        }
+        _ => std::time::Duration::from_secs(header("retry-after")?.parse().ok()?),
+    };
+    Some(UpdaterError::RateLimited {
+        reset_at: Utc::now() + chrono::TimeDelta::from_std(wait).unwrap_or_default(),
+        remaining: remaining.unwrap_or(0),
+    })
+}
+
+/// Builds the error returned when GitHub rejects the pinned API version.
+fn unsupported_api_version(status: u16) -> UpdaterError {
+    UpdaterError::GitHubApiError(format!(
+        "GitHub rejected API version {} (HTTP {}); please update opencode-updater",
+        GITHUB_API_VERSION, status
+    ))
+}
+
+/// Fetches the latest release information from GitHub API.
+pub fn fetch_release(client: &Agent, base_url: &str) -> Result<serde_json::Value, UpdaterError> {
     let release_url = format!("{}/repos/sst/opencode/releases/latest", base_url);
-    let response = client.get(&release_url).call()?;
-    if response.status() != 200 {
-        let status = response.status();
-        let body = response.into_body().read_to_string()?;
-        eprintln!("HTTP error: {} {}", status, body);
-        return Err("HTTP error".into());
-    }
-    let body = response.into_body().read_to_string()?;
+    let body = github_get(client, &release_url)?;
     let release: serde_json::Value = serde_json::from_str(&body)?;
     Ok(release)
 }
@@ -427,43 +2747,178 @@ pub fn fetch_release(
 pub fn fetch_all_releases(
     client: &Agent,
     base_url: &str,
-) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+) -> Result<Vec<serde_json::Value>, UpdaterError> {
     let releases_url = format!("{}/repos/sst/opencode/releases", base_url);
-    let response = client.get(&releases_url).call()?;
-    if response.status() != 200 {
-        let status = response.status();
-        let body = response.into_body().read_to_string()?;
-        eprintln!("HTTP error: {} {}", status, body);
-        return Err("HTTP error".into());
-    }
-    let body = response.into_body().read_to_string()?;
+    let body = github_get(client, &releases_url)?;
     let releases: Vec<serde_json::Value> = serde_json::from_str(&body)?;
     Ok(releases)
 }
 
+/// Releases asked for per page by [`fetch_release_pages`], the most the API allows.
+pub const RELEASES_PER_PAGE: usize = 100;
+
+/// Fetch every release from GitHub API, page by page, newest first.
+pub fn fetch_release_pages(
+    client: &Agent,
+    base_url: &str,
+) -> Result<Vec<serde_json::Value>, UpdaterError> {
+    let mut releases = Vec::new();
+    for page in 1.. {
+        let url = format!(
+            "{}/repos/sst/opencode/releases?per_page={}&page={}",
+            base_url, RELEASES_PER_PAGE, page
+        );
+        let batch: Vec<serde_json::Value> = serde_json::from_str(&github_get(client, &url)?)?;
+        let last = batch.len() < RELEASES_PER_PAGE;
+        releases.extend(batch);
+        if last {
+            break;
+        }
+    }
+    Ok(releases)
+}
+
 /// Fetch specific release by tag from GitHub API.
 pub fn fetch_release_by_tag(
     client: &Agent,
     base_url: &str,
     tag: &str,
-) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+) -> Result<serde_json::Value, UpdaterError> {
     let release_url = format!("{}/repos/sst/opencode/releases/tags/{}", base_url, tag);
-    let response = client.get(&release_url).call()?;
-    if response.status() != 200 {
-        let status = response.status();
-        let body = response.into_body().read_to_string()?;
-        eprintln!("HTTP error: {} {}", status, body);
-        return Err("HTTP error".into());
-    }
-    let body = response.into_body().read_to_string()?;
+    let body = github_get(client, &release_url)?;
     let release: serde_json::Value = serde_json::from_str(&body)?;
     Ok(release)
 }
 
+/// Fetch the release a user means by `input`: the one tagged `input`, or
+/// else the one tagged with its `v` prefix added or removed, since not every
+/// release is tagged alike.
+pub fn resolve_release(
+    client: &Agent,
+    base_url: &str,
+    input: &str,
+) -> Result<serde_json::Value, UpdaterError> {
+    let input = input.trim();
+    let other = match input.strip_prefix('v') {
+        Some(bare) => bare.to_string(),
+        None => format!("v{}", input),
+    };
+    for tag in [input, other.as_str()] {
+        match fetch_release_by_tag(client, base_url, tag) {
+            Err(UpdaterError::ApiStatus { status: 404, .. }) => continue,
+            result => return result,
+        }
+    }
+    Err(UpdaterError::VersionNotFound(format!(
+        "{} (no release is tagged {} or {})",
+        input, input, other
+    )))
+}
+
+/// Tag of the release a user means by `input`, as GitHub has it, see
+/// [`resolve_release`].
+pub fn resolve_tag(client: &Agent, base_url: &str, input: &str) -> Result<String, UpdaterError> {
+    let release = resolve_release(client, base_url, input)?;
+    Ok(release_tag(&release)?.to_string())
+}
+
+/// Maximum number of release note lines shown without `--full`.
+pub const MAX_NOTES_LINES: usize = 200;
+
+/// Maximum number of release note bytes shown without `--full`.
+pub const MAX_NOTES_BYTES: usize = 64 * 1024;
+
+/// Strips ANSI escape sequences and control characters from text shown in the terminal.
+///
+/// Release notes and asset names come from anyone who can get text into a
+/// release, so they must not be able to move the cursor, retitle the window,
+/// or otherwise drive the terminal. Newlines and tabs are kept.
+pub fn sanitize_terminal_text(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC, DCS, APC, PM, SOS: string terminated by BEL or ESC \
+                Some(']' | 'P' | '_' | '^' | 'X') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' {
+                            break;
+                        }
+                        if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Two-character escape
+                _ => {}
+            },
+            '\n' | '\t' => output.push(c),
+            c if c.is_control() => {}
+            c => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Truncates text to at most `max_lines` lines and `max_bytes` bytes.
+///
+/// Returns the kept text and the number of lines that were cut off.
+pub fn truncate_text(text: &str, max_lines: usize, max_bytes: usize) -> (String, usize) {
+    let total_lines = text.lines().count();
+    let mut kept = String::new();
+    let mut kept_lines = 0;
+
+    for line in text.lines() {
+        if kept_lines == max_lines || kept.len() + line.len() + 1 > max_bytes {
+            break;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+        kept_lines += 1;
+    }
+
+    if kept_lines == total_lines {
+        return (text.to_string(), 0);
+    }
+    (kept, total_lines - kept_lines)
+}
+
+/// Sanitizes a release body and, unless `full` is set, caps its length.
+fn display_body(body: &str, full: bool) -> String {
+    let body = sanitize_terminal_text(body);
+    if full {
+        return body;
+    }
+    let (mut shown, remaining) = truncate_text(&body, MAX_NOTES_LINES, MAX_NOTES_BYTES);
+    if remaining > 0 {
+        shown.push_str(&format!(
+            "\n… truncated, {} more lines; use --full to show everything",
+            remaining
+        ));
+    }
+    shown
+}
+
 /// Format release notes for display.
+///
+/// Text is sanitized for the terminal and, unless `full` is set, truncated to
+/// [`MAX_NOTES_LINES`] lines / [`MAX_NOTES_BYTES`] bytes.
 pub fn format_release_notes(
     release: &serde_json::Value,
-) -> Result<String, Box<dyn std::error::Error>> {
+    full: bool,
+) -> Result<String, UpdaterError> {
     let tag_name = release["tag_name"].as_str().unwrap_or("Unknown");
     let name = release["name"].as_str().unwrap_or(tag_name);
     let published_at = release["published_at"].as_str().unwrap_or("Unknown date");
@@ -473,102 +2928,961 @@ pub fn format_release_notes(
 
     let formatted = format!(
         "📦 Release: {} ({})\n📅 Published: {}\n\n{}",
-        name, tag_name, published_at, body
+        sanitize_terminal_text(name),
+        sanitize_terminal_text(tag_name),
+        sanitize_terminal_text(published_at),
+        display_body(body, full)
     );
 
     Ok(formatted)
 }
 
+/// How long the cached release list is used before it is fetched again.
+pub const RELEASES_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
+
 /// Cache releases locally to avoid API rate limits.
 pub fn cache_releases(
     releases: &[serde_json::Value],
     cache_dir: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), UpdaterError> {
     let cache_file = cache_dir.join("releases.json");
     let json = serde_json::to_string_pretty(releases)?;
-    std::fs::write(cache_file, json)?;
+    write_atomic(&cache_file, json.as_bytes())?;
+    Ok(())
+}
+
+/// Write a file via a temporary sibling and a rename, so readers never see it half-written.
+///
+/// The directory is synced after the rename so the new entry survives a
+/// crash, and so network filesystems publish it to other clients.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    std::io::Write::write_all(&mut temp, contents)?;
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|e| e.error)?;
+    // Not every filesystem can sync a directory; the rename stands regardless
+    let _ = std::fs::File::open(dir).and_then(|dir| dir.sync_all());
     Ok(())
 }
 
-/// Load cached releases if available and recent (< 1 hour).
-pub fn load_cached_releases(
-    cache_dir: &Path,
-) -> Result<Option<Vec<serde_json::Value>>, Box<dyn std::error::Error>> {
-    let cache_file = cache_dir.join("releases.json");
-    if !cache_file.exists() {
-        return Ok(None);
+/// Parse a cache file, treating corrupt content as a cache miss.
+///
+/// A corrupt file (e.g. truncated by a power cut) is renamed to
+/// `<name>.corrupt` so it is refetched next time but can still be inspected.
+fn read_cache_file<T: serde::de::DeserializeOwned>(
+    cache_file: &Path,
+) -> Result<Option<T>, UpdaterError> {
+    let content = std::fs::read_to_string(cache_file)?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => {
+            let mut quarantine = cache_file.as_os_str().to_owned();
+            quarantine.push(".corrupt");
+            reporter::warn(
+                "corrupt-cache",
+                &format!(
+                    "ignoring corrupt cache file {} ({}); moved it to {}",
+                    cache_file.display(),
+                    e,
+                    Path::new(&quarantine).display()
+                ),
+            );
+            if std::fs::rename(cache_file, &quarantine).is_err() {
+                let _ = std::fs::remove_file(cache_file);
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Path of the per-tag release cache file.
+fn cached_release_path(cache_dir: &Path, tag: &str) -> PathBuf {
+    let file_name = format!("{}.json", tag.replace(['/', '\\'], "_"));
+    cache_dir.join("releases").join(file_name)
+}
+
+/// Cache a single release by tag.
+///
+/// Published releases are assumed immutable, so per-tag entries never expire.
+pub fn cache_release(release: &serde_json::Value, cache_dir: &Path) -> Result<(), UpdaterError> {
+    let tag = release_tag(release)?;
+    let cache_file = cached_release_path(cache_dir, tag);
+    if let Some(parent) = cache_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_atomic(
+        &cache_file,
+        serde_json::to_string_pretty(release)?.as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Load a cached release by tag, if one was stored.
+pub fn load_cached_release(
+    cache_dir: &Path,
+    tag: &str,
+) -> Result<Option<serde_json::Value>, UpdaterError> {
+    let cache_file = cached_release_path(cache_dir, tag);
+    if !cache_file.exists() {
+        return Ok(None);
+    }
+    let release = read_cache_file(&cache_file)?;
+    if release.is_some() {
+        network::record_cache_hit();
+    }
+    Ok(release)
+}
+
+/// Remove cache files that will never be read again: an expired release
+/// list and quarantined `.corrupt` files. Per-tag entries are kept.
+///
+/// Returns the files removed.
+pub fn prune_cache(cache_dir: &Path) -> Result<Vec<PathBuf>, UpdaterError> {
+    let mut removed = Vec::new();
+    let releases_file = cache_dir.join("releases.json");
+    let expired = std::fs::metadata(&releases_file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| std::time::SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > RELEASES_CACHE_TTL);
+    if expired {
+        std::fs::remove_file(&releases_file)?;
+        removed.push(releases_file);
+    }
+
+    for dir in [cache_dir.to_path_buf(), cache_dir.join("releases")] {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "corrupt") {
+                std::fs::remove_file(&path)?;
+                removed.push(path);
+            }
+        }
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+/// Unified diff of a release's notes between a cached and a live copy.
+///
+/// Returns `None` when the notes are unchanged.
+pub fn diff_release_notes(cached: &serde_json::Value, live: &serde_json::Value) -> Option<String> {
+    let old = cached["body"].as_str().unwrap_or("");
+    let new = live["body"].as_str().unwrap_or("");
+    if old == new {
+        return None;
+    }
+
+    let diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header("cached", "live")
+        .to_string();
+    Some(diff)
+}
+
+/// Load cached releases if available and recent (< 1 hour).
+///
+/// A cache modified in the future is used with a warning rather than
+/// rejected, since that only means the clock is off. A corrupt cache is
+/// treated as missing.
+pub fn load_cached_releases(
+    cache_dir: &Path,
+) -> Result<Option<Vec<serde_json::Value>>, UpdaterError> {
+    let cache_file = cache_dir.join("releases.json");
+    if !cache_file.exists() {
+        return Ok(None);
+    }
+
+    let metadata = std::fs::metadata(&cache_file)?;
+    let modified = metadata.modified()?;
+    let age = match std::time::SystemTime::now().duration_since(modified) {
+        Ok(age) => age,
+        Err(e) => {
+            // Written "in the future": the clock was wrong then or is wrong now
+            reporter::warn(
+                "clock",
+                &format!(
+                    "{} was modified {} in the future; the system clock may be wrong. Treating the cache as fresh.",
+                    cache_file.display(),
+                    clock::format_delta(chrono::TimeDelta::seconds(e.duration().as_secs() as i64))
+                ),
+            );
+            std::time::Duration::ZERO
+        }
+    };
+
+    if age > RELEASES_CACHE_TTL {
+        return Ok(None); // Cache expired
+    }
+
+    let releases = read_cache_file(&cache_file)?;
+    if releases.is_some() {
+        network::record_cache_hit();
+    }
+    Ok(releases)
+}
+
+/// Parse semantic version string for comparison.
+pub fn parse_version(version: &str) -> Result<Version, UpdaterError> {
+    version.parse()
+}
+
+/// Compare two versions: `Less` when `v1` is older than `v2`.
+pub fn compare_versions(v1: &str, v2: &str) -> Result<std::cmp::Ordering, UpdaterError> {
+    Ok(v1.parse::<Version>()?.cmp(&v2.parse()?))
+}
+
+/// Whether version `a` is newer than version `b`.
+pub fn is_newer(a: &str, b: &str) -> Result<bool, UpdaterError> {
+    Ok(compare_versions(a, b)?.is_gt())
+}
+
+/// Whether version `a` is older than version `b`.
+pub fn is_older(a: &str, b: &str) -> Result<bool, UpdaterError> {
+    Ok(compare_versions(a, b)?.is_lt())
+}
+
+/// How two version strings compare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionOrder {
+    pub ordering: std::cmp::Ordering,
+    /// False when either side isn't `major.minor.patch`, with or without a
+    /// prerelease suffix, and the order is only that of the text, which may
+    /// not match release order.
+    pub exact: bool,
+}
+
+/// Compare two versions, falling back to comparing the text (without a
+/// leading `v`) when either doesn't parse.
+pub fn version_order(v1: &str, v2: &str) -> VersionOrder {
+    match (parse_version(v1), parse_version(v2)) {
+        (Ok(a), Ok(b)) => VersionOrder {
+            ordering: a.cmp(&b),
+            exact: true,
+        },
+        _ => VersionOrder {
+            ordering: v1.trim_start_matches('v').cmp(v2.trim_start_matches('v')),
+            exact: false,
+        },
+    }
+}
+
+/// Note shown wherever `tag` had to be compared as text.
+pub fn text_comparison_note(tag: &str) -> String {
+    format!(
+        "{} isn't a major.minor.patch version; compared as text",
+        sanitize_terminal_text(tag)
+    )
+}
+
+/// Suffix for an available release in `--list-versions`: whether it is newer
+/// than `current`, with a note when that was judged by comparing text.
+pub fn describe_available_release(tag: &str, current: Option<&str>) -> String {
+    let Some(current) = current.filter(|current| !is_rolling_tag(current)) else {
+        return String::new();
+    };
+    if is_rolling_tag(tag) {
+        return String::new();
+    }
+    let order = version_order(current, tag);
+    let newer = if order.ordering.is_lt() {
+        " ⬆ newer"
+    } else {
+        ""
+    };
+    if order.exact {
+        newer.to_string()
+    } else {
+        let odd = if parse_version(current).is_err() {
+            current
+        } else {
+            tag
+        };
+        format!("{} ({})", newer, text_comparison_note(odd))
+    }
+}
+
+/// Display comparison between two versions.
+pub fn display_version_comparison(
+    from_release: &serde_json::Value,
+    to_release: &serde_json::Value,
+) -> Result<String, UpdaterError> {
+    let text = |release: &serde_json::Value, key: &str| {
+        sanitize_terminal_text(release[key].as_str().unwrap_or("Unknown"))
+    };
+    let from_tag = text(from_release, "tag_name");
+    let to_tag = text(to_release, "tag_name");
+    // Default asset size, when the release lists one
+    let size = |release: &serde_json::Value| {
+        resolve_default_asset(release)
+            .ok()
+            .filter(|asset| asset.size > 0)
+            .map_or_else(String::new, |asset| {
+                format!(", {}", units::format_size(asset.size))
+            })
+    };
+    let from_date = text(from_release, "published_at") + &size(from_release);
+    let to_date = text(to_release, "published_at") + &size(to_release);
+
+    let order = version_order(&from_tag, &to_tag);
+    let mut direction = match order.ordering {
+        std::cmp::Ordering::Less => "upgrade",
+        std::cmp::Ordering::Equal => "same version",
+        std::cmp::Ordering::Greater => "downgrade",
+    }
+    .to_string();
+    if !order.exact {
+        let odd = if parse_version(&from_tag).is_err() {
+            &from_tag
+        } else {
+            &to_tag
+        };
+        direction.push_str(&format!(" ({})", text_comparison_note(odd)));
+    }
+
+    let comparison = format!(
+        "🔄 Version Comparison\n\n\
+         📦 From: {} (published: {})\n\
+         📦 To: {} (published: {})\n\
+         ↕️  Direction: {}\n\n\
+         📝 Changes in {}:\n\
+         {}",
+        from_tag,
+        from_date,
+        to_tag,
+        to_date,
+        direction,
+        to_tag,
+        display_body(
+            to_release["body"]
+                .as_str()
+                .unwrap_or("No release notes available."),
+            false
+        )
+    );
+
+    Ok(comparison)
+}
+
+/// Longest part of a release quoted by [`malformed_release`].
+const RELEASE_SNIPPET_CHARS: usize = 200;
+
+/// Builds the error for a release without a usable `what`, quoting its start:
+/// what came back may be an error object rather than a release.
+pub fn malformed_release(release: &serde_json::Value, what: &str) -> UpdaterError {
+    let body = release.to_string();
+    let mut snippet: String = body.chars().take(RELEASE_SNIPPET_CHARS).collect();
+    if snippet.len() < body.len() {
+        snippet.push('…');
+    }
+    UpdaterError::GitHubApiError(format!(
+        "release has no {}: {}",
+        what,
+        sanitize_terminal_text(&snippet)
+    ))
+}
+
+/// A release's assets.
+pub fn release_assets(
+    release: &serde_json::Value,
+) -> Result<&Vec<serde_json::Value>, UpdaterError> {
+    release["assets"]
+        .as_array()
+        .ok_or_else(|| malformed_release(release, "assets"))
+}
+
+/// A release's tag.
+pub fn release_tag(release: &serde_json::Value) -> Result<&str, UpdaterError> {
+    release["tag_name"]
+        .as_str()
+        .ok_or_else(|| malformed_release(release, "tag_name"))
+}
+
+/// Default asset names in order of preference.
+pub const DEFAULT_ASSET_NAMES: [&str; 2] = ["opencode-linux-x64.zip", "opencode-linux-x64.tar.gz"];
+
+/// Resolves the default Linux x64 asset of a release, preferring zip over tar.gz.
+pub fn resolve_default_asset(release: &serde_json::Value) -> Result<Asset, UpdaterError> {
+    let assets = release_assets(release)?;
+    for name in DEFAULT_ASSET_NAMES {
+        if let Some(asset) = find_asset(assets, name) {
+            return Ok(serde_json::from_value(asset.clone())?);
+        }
+    }
+    // A release with a single archive, like one read from a manifest, leaves no choice
+    if let [asset] = assets.as_slice()
+        && asset["name"].as_str().is_some_and(payload::is_archive_name)
+    {
+        return Ok(serde_json::from_value(asset.clone())?);
+    }
+    Err(UpdaterError::GitHubApiError(
+        "Neither 'opencode-linux-x64.zip' nor 'opencode-linux-x64.tar.gz' found in release assets"
+            .to_string(),
+    ))
+}
+
+/// Downloads the published SHA-256 checksum for an asset, if the release has one.
+pub fn fetch_asset_checksum(
+    client: &Agent,
+    assets: &[serde_json::Value],
+    asset_name: &str,
+) -> Result<Option<String>, UpdaterError> {
+    Ok(fetch_asset_checksums(client, assets, asset_name)?
+        .into_iter()
+        .find(|checksum| checksum.algorithm == ChecksumAlgorithm::Sha256)
+        .map(|checksum| checksum.digest))
+}
+
+/// Collects every published checksum for an asset.
+///
+/// Looks for per-asset files (`<asset>.sha256`, `<asset>.sha512`) and combined
+/// files (`SHA256SUMS`, `SHA512SUMS`), and takes the SHA-256 `digest` GitHub
+/// reports for the asset, or a manifest gives inline. Each digest found is
+/// returned, so a release publishing several algorithms gets all of them verified.
+pub fn fetch_asset_checksums(
+    client: &Agent,
+    assets: &[serde_json::Value],
+    asset_name: &str,
+) -> Result<Vec<Checksum>, UpdaterError> {
+    let mut checksums = Vec::new();
+    for algorithm in ChecksumAlgorithm::ALL {
+        let candidates = [
+            format!("{}.{}", asset_name, algorithm.extension()),
+            algorithm.sums_file().to_string(),
+        ];
+        for source in candidates {
+            let Some(asset) = find_asset(assets, &source) else {
+                continue;
+            };
+            let url = asset["browser_download_url"].as_str().ok_or_else(|| {
+                UpdaterError::GitHubApiError(format!("{} has no download URL", source))
+            })?;
+            let response = payload::send(
+                client,
+                client
+                    .get(url)
+                    .header("Accept-Encoding", encoding::ASSET_ACCEPT_ENCODING),
+            )?;
+            if response.status() != 200 {
+                continue;
+            }
+            let content = encoding::read_text(response, &source)?;
+            network::record_download(content.len() as u64);
+            if let Some(digest) = checksum::find_checksum_for(&content, asset_name, algorithm) {
+                checksums.push(Checksum {
+                    algorithm,
+                    digest,
+                    source,
+                });
+            }
+        }
+    }
+    if let Some(reported) = reported_checksum(assets, asset_name)
+        && !checksums.iter().any(|checksum| {
+            checksum.algorithm == ChecksumAlgorithm::Sha256
+                && checksum.digest.eq_ignore_ascii_case(&reported.digest)
+        })
+    {
+        checksums.push(reported);
+    }
+    Ok(checksums)
+}
+
+/// The SHA-256 `digest` GitHub reports for an asset, or a manifest gives
+/// inline; no request needed.
+fn reported_checksum(assets: &[serde_json::Value], asset_name: &str) -> Option<Checksum> {
+    find_asset(assets, asset_name)
+        .and_then(|asset| asset["digest"].as_str())
+        .and_then(|digest| digest.split_once(':'))
+        .filter(|(algorithm, _)| algorithm.eq_ignore_ascii_case("sha256"))
+        .map(|(_, digest)| Checksum {
+            algorithm: ChecksumAlgorithm::Sha256,
+            digest: digest.to_lowercase(),
+            source: "digest".to_string(),
+        })
+}
+
+/// Checksums for an asset that may still be on their way, see
+/// [`PendingChecksums::fetch`].
+pub struct PendingChecksums<'scope> {
+    checksums: Vec<Checksum>,
+    /// The fetch, until joined.
+    fetch: Option<ChecksumFetch<'scope>>,
+}
+
+/// A fetch of checksums on another thread.
+struct ChecksumFetch<'scope> {
+    /// Also reports why the fetch failed, and its network usage.
+    thread:
+        std::thread::ScopedJoinHandle<'scope, (Vec<Checksum>, Option<UpdaterError>, NetworkUsage)>,
+    asset_name: &'scope str,
+    /// Where a failure is reported once joined.
+    reporter: &'scope Reporter,
+}
+
+impl<'scope> PendingChecksums<'scope> {
+    /// Checksums already at hand.
+    pub fn ready(checksums: Vec<Checksum>) -> Self {
+        Self {
+            checksums,
+            fetch: None,
+        }
+    }
+
+    /// [`fetch_asset_checksums`] on a thread of `scope`, so they arrive while
+    /// the asset downloads instead of a round trip before it.
+    ///
+    /// The thread follows this thread's retry policy, and its network usage
+    /// is added to this thread's when it is joined.
+    /// When the fetch fails, the update goes on with the digest GitHub
+    /// reports, if any, after a `checksum-fetch` warning through `reporter`,
+    /// as it does for a release that publishes no checksum files.
+    pub fn fetch<'env>(
+        scope: &'scope std::thread::Scope<'scope, 'env>,
+        client: &'env Agent,
+        assets: &'env [serde_json::Value],
+        asset_name: &'env str,
+        reporter: &'env Reporter,
+    ) -> Self {
+        let retry_policy = retry::policy();
+        let thread = scope.spawn(move || {
+            retry::set_policy(retry_policy);
+            let (checksums, error) = match fetch_asset_checksums(client, assets, asset_name) {
+                Ok(checksums) => (checksums, None),
+                Err(e) => (
+                    reported_checksum(assets, asset_name).into_iter().collect(),
+                    Some(e),
+                ),
+            };
+            (checksums, error, network::usage())
+        });
+        Self {
+            checksums: Vec::new(),
+            fetch: Some(ChecksumFetch {
+                thread,
+                asset_name,
+                reporter,
+            }),
+        }
+    }
+
+    /// The checksums, waiting for the fetch if it is still going.
+    pub fn wait(&mut self) -> &[Checksum] {
+        if let Some(fetch) = self.fetch.take() {
+            let (checksums, error, usage) = fetch
+                .thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            network::record_usage(&usage);
+            if let Some(e) = error {
+                fetch.reporter.warn(
+                    "checksum-fetch",
+                    &format!(
+                        "could not fetch the published checksums for {}: {}",
+                        sanitize_terminal_text(fetch.asset_name),
+                        e
+                    ),
+                );
+            }
+            self.checksums = checksums;
+        }
+        &self.checksums
+    }
+}
+
+/// Comparison between the installed version and an update candidate.
+#[derive(Serialize, Debug, Clone)]
+pub struct InspectReport {
+    pub installed_version: Option<String>,
+    pub candidate_version: String,
+    pub installed_release_date: Option<DateTime<Utc>>,
+    pub candidate_release_date: Option<DateTime<Utc>>,
+    pub days_between_releases: Option<i64>,
+    pub asset_name: String,
+    pub installed_asset_size: Option<u64>,
+    pub candidate_asset_size: u64,
+    pub asset_size_delta: Option<i64>,
+    pub installed_checksum: Option<String>,
+    pub candidate_checksum: Option<String>,
+    /// `None` when either checksum is unknown.
+    pub checksum_differs: Option<bool>,
+}
+
+/// Builds an inspection report from the installed version and candidate release metadata.
+pub fn build_inspect_report(
+    installed: Option<&VersionInfo>,
+    release: &serde_json::Value,
+    asset: &Asset,
+    candidate_checksum: Option<String>,
+) -> InspectReport {
+    let candidate_version = release["tag_name"]
+        .as_str()
+        .unwrap_or("unknown")
+        .trim_start_matches('v')
+        .to_string();
+    let candidate_release_date = release["published_at"]
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc));
+
+    // Versions detected from the system binary carry placeholder dates and no checksum.
+    let installed_release_date = installed
+        .filter(|v| !v.download_url.is_empty())
+        .map(|v| v.release_date);
+    let installed_checksum = installed
+        .map(|v| v.checksum.clone())
+        .filter(|c| !c.is_empty());
+    let installed_asset_size = installed.and_then(|v| v.asset_size);
+
+    let days_between_releases = installed_release_date
+        .zip(candidate_release_date)
+        .map(|(from, to)| (to - from).num_days());
+    let asset_size_delta = installed_asset_size.map(|size| asset.size as i64 - size as i64);
+    let checksum_differs = installed_checksum
+        .as_ref()
+        .zip(candidate_checksum.as_ref())
+        .map(|(a, b)| a != b);
+
+    InspectReport {
+        installed_version: installed.map(|v| v.version.clone()),
+        candidate_version,
+        installed_release_date,
+        candidate_release_date,
+        days_between_releases,
+        asset_name: asset.name.clone(),
+        installed_asset_size,
+        candidate_asset_size: asset.size,
+        asset_size_delta,
+        installed_checksum,
+        candidate_checksum,
+        checksum_differs,
+    }
+}
+
+/// Format an inspection report for display.
+pub fn format_inspect_report(report: &InspectReport) -> String {
+    let unknown = || "unknown".to_string();
+    let date = |d: Option<DateTime<Utc>>| d.map(|d| d.format("%Y-%m-%d").to_string());
+
+    let mut output = String::from("🔍 Update Inspection\n\n");
+    output.push_str(&format!(
+        "📦 Installed: {} (released: {})\n",
+        report
+            .installed_version
+            .clone()
+            .unwrap_or_else(|| "none".to_string()),
+        date(report.installed_release_date).unwrap_or_else(unknown)
+    ));
+    output.push_str(&format!(
+        "📦 Candidate: {} (released: {})\n",
+        report.candidate_version,
+        date(report.candidate_release_date).unwrap_or_else(unknown)
+    ));
+    if let Some(days) = report.days_between_releases {
+        output.push_str(&format!("📅 Days between releases: {}\n", days));
+    }
+    output.push_str(&format!(
+        "📁 Asset: {} ({}",
+        report.asset_name,
+        units::format_size(report.candidate_asset_size)
+    ));
+    match report.asset_size_delta {
+        Some(delta) => output.push_str(&format!(", {})\n", units::format_size_delta(delta))),
+        None => output.push_str(")\n"),
+    }
+    let verdict = match report.checksum_differs {
+        Some(true) => "differs, updating would change the binary",
+        Some(false) => "identical, nothing would change",
+        None => "unknown",
+    };
+    output.push_str(&format!("🔐 Checksum: {}\n", verdict));
+    output
+}
+
+/// Outcome of `--check`.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    pub installed_version: Option<String>,
+    pub latest_tag: String,
+    pub update_available: bool,
+    /// Build time of the latest build, for rolling tags such as `nightly`.
+    pub built_at: Option<DateTime<Utc>>,
+    /// Set when the latest tag isn't a version and was compared as text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl std::fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tag = sanitize_terminal_text(&self.latest_tag);
+        match (self.built_at, self.update_available) {
+            (Some(built_at), true) => write!(
+                f,
+                "new {} build available (built {})",
+                tag,
+                built_at.format("%Y-%m-%d")
+            ),
+            (Some(built_at), false) => write!(
+                f,
+                "{} build is up to date (built {})",
+                tag,
+                built_at.format("%Y-%m-%d")
+            ),
+            (None, true) => write!(
+                f,
+                "update available: {} -> {}",
+                self.installed_version.as_deref().unwrap_or("none"),
+                tag
+            ),
+            (None, false) => write!(f, "opencode {} is up to date", tag),
+        }?;
+        match &self.note {
+            Some(note) => write!(f, " ({})", note),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What an update did, as the `update` document of `--json`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct UpdateOutcome {
+    pub result: UpdateResult,
+    /// The version installed now.
+    pub version: String,
+    /// The version installed before, if another.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateResult {
+    Updated,
+    UpToDate,
+}
+
+impl std::fmt::Display for UpdateOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.result {
+            UpdateResult::Updated => write!(f, "Updated opencode to version {}.", self.version),
+            UpdateResult::UpToDate => write!(f, "opencode {} is already installed.", self.version),
+        }
+    }
+}
+
+/// `--list-versions --json`: stored versions, install targets and releases.
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct VersionList {
+    pub current: Option<String>,
+    /// Stored versions, newest install first.
+    pub installed: Vec<ListedVersion>,
+    /// Recorded install state of each target.
+    pub targets: std::collections::BTreeMap<String, TargetState>,
+    /// Published releases, newest first; empty when they couldn't be fetched.
+    pub available: Vec<AvailableRelease>,
+}
+
+/// A stored version in [`VersionList`].
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct ListedVersion {
+    pub version: String,
+    pub tag_name: String,
+    pub release_date: DateTime<Utc>,
+    pub installed_at: DateTime<Utc>,
+    /// Size of the downloaded asset in bytes.
+    pub asset_size: Option<u64>,
+    pub installed_by: Option<String>,
+    pub provenance: Option<adopt::Provenance>,
+    pub channel: Channel,
+}
+
+/// A published release in [`VersionList`].
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct AvailableRelease {
+    pub tag: String,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+impl VersionList {
+    /// The list of `installed` versions, of which `current` is active, and
+    /// `available` releases.
+    pub fn new(
+        installed: &[VersionInfo],
+        available: &[serde_json::Value],
+        current: Option<&VersionInfo>,
+        targets: &std::collections::BTreeMap<String, TargetState>,
+    ) -> Self {
+        Self {
+            current: current.map(|info| info.version.clone()),
+            installed: installed
+                .iter()
+                .map(|info| ListedVersion {
+                    version: info.version.clone(),
+                    tag_name: info.tag_name.clone(),
+                    release_date: info.release_date,
+                    installed_at: info.installed_at,
+                    asset_size: info.asset_size,
+                    installed_by: info.installed_by.clone(),
+                    provenance: info.provenance,
+                    channel: info.channel,
+                })
+                .collect(),
+            targets: targets.clone(),
+            available: available
+                .iter()
+                .filter_map(|release| {
+                    Some(AvailableRelease {
+                        tag: release["tag_name"].as_str()?.to_string(),
+                        published_at: release["published_at"]
+                            .as_str()
+                            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                            .map(|date| date.with_timezone(&Utc)),
+                    })
+                })
+                .collect(),
+        }
     }
+}
 
-    let metadata = std::fs::metadata(&cache_file)?;
-    let modified = metadata.modified()?;
-    let age = std::time::SystemTime::now().duration_since(modified)?;
+/// Decide whether the latest release is newer than what is installed.
+///
+/// Versioned releases compare version numbers; rolling releases compare the
+/// build identity, since their tag never changes.
+pub fn build_check_report(
+    current: Option<&VersionInfo>,
+    release: &serde_json::Value,
+    asset: &Asset,
+    checksum: Option<&str>,
+) -> CheckReport {
+    let latest_tag = release["tag_name"]
+        .as_str()
+        .unwrap_or("unknown")
+        .to_string();
+    let installed_version = current.map(|info| info.version.clone());
 
-    if age > std::time::Duration::from_secs(3600) {
-        return Ok(None); // Cache expired
+    if let Some(build) = RollingBuild::from_release(release, asset, checksum) {
+        return CheckReport {
+            installed_version,
+            update_available: !current.is_some_and(|info| build.matches(info)),
+            latest_tag,
+            built_at: Some(build.built_at),
+            note: None,
+        };
     }
 
-    let content = std::fs::read_to_string(cache_file)?;
-    let releases: Vec<serde_json::Value> = serde_json::from_str(&content)?;
-    Ok(Some(releases))
-}
-
-/// Parse semantic version string for comparison.
-pub fn parse_version(version: &str) -> Result<(u64, u64, u64), Box<dyn std::error::Error>> {
-    let clean = version.trim_start_matches('v');
-    let parts: Vec<&str> = clean.split('.').collect();
-
-    if parts.len() != 3 {
-        return Err("Invalid version format".into());
+    let order = current.map(|info| version_order(&info.version, &latest_tag));
+    let update_available = order.is_none_or(|order| order.ordering.is_lt());
+    let note = order
+        .filter(|order| !order.exact)
+        .map(|_| text_comparison_note(&latest_tag));
+    CheckReport {
+        installed_version,
+        latest_tag,
+        update_available,
+        built_at: None,
+        note,
     }
+}
 
-    let major = parts[0].parse()?;
-    let minor = parts[1].parse()?;
-    let patch = parts[2].parse()?;
+/// Check the latest release against the current version without downloading it.
+pub fn check_for_update(
+    version_manager: &VersionManager,
+    client: &Agent,
+    base_url: &str,
+) -> Result<CheckReport, UpdaterError> {
+    let github = source::GitHubSource {
+        api_base: base_url.to_string(),
+        cache_dir: version_manager.cache_dir().to_path_buf(),
+        channel: Channel::Stable,
+        constraint: None,
+    };
+    check_for_update_from(version_manager, client, &github)
+}
 
-    Ok((major, minor, patch))
+/// [`check_for_update`] against the latest release of `source`.
+pub fn check_for_update_from(
+    version_manager: &VersionManager,
+    client: &Agent,
+    source: &dyn source::ReleaseSource,
+) -> Result<CheckReport, UpdaterError> {
+    let release = source.latest_release(client)?;
+    let asset = resolve_default_asset(&release)?;
+    let assets = release_assets(&release)?;
+    let checksum = fetch_asset_checksum(client, assets, &asset.name)?;
+    let current = version_manager.get_current_version()?;
+    Ok(build_check_report(
+        current.as_ref(),
+        &release,
+        &asset,
+        checksum.as_deref(),
+    ))
 }
 
-/// Compare two versions (-1, 0, 1).
-pub fn compare_versions(v1: &str, v2: &str) -> Result<i8, Box<dyn std::error::Error>> {
-    let (major1, minor1, patch1) = parse_version(v1)?;
-    let (major2, minor2, patch2) = parse_version(v2)?;
+/// Format of a release archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
 
-    if major1 != major2 {
-        return Ok(major1.cmp(&major2) as i8);
-    }
-    if minor1 != minor2 {
-        return Ok(minor1.cmp(&minor2) as i8);
+impl ArchiveFormat {
+    /// The format of the archive called `name`, if it is one that can be unpacked.
+    pub fn of(name: &str) -> Option<Self> {
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
     }
-    Ok(patch1.cmp(&patch2) as i8)
 }
 
-/// Display comparison between two versions.
-pub fn display_version_comparison(
-    from_release: &serde_json::Value,
-    to_release: &serde_json::Value,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let from_tag = from_release["tag_name"].as_str().unwrap_or("Unknown");
-    let to_tag = to_release["tag_name"].as_str().unwrap_or("Unknown");
-    let from_date = from_release["published_at"].as_str().unwrap_or("Unknown");
-    let to_date = to_release["published_at"].as_str().unwrap_or("Unknown");
+impl std::fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        })
+    }
+}
 
-    let comparison = format!(
-        "🔄 Version Comparison\n\n\
-         📦 From: {} (published: {})\n\
-         📦 To: {} (published: {})\n\n\
-         📝 Changes in {}:\n\
-         {}",
-        from_tag,
-        from_date,
-        to_tag,
-        to_date,
-        to_tag,
-        to_release["body"]
-            .as_str()
-            .unwrap_or("No release notes available.")
-    );
+/// Whether `error`, or an error it wraps, is a full disk or quota.
+pub fn is_out_of_space(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut cause = Some(error);
+    while let Some(error) = cause {
+        if let Some(io) = error.downcast_ref::<std::io::Error>()
+            && matches!(
+                io.kind(),
+                std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded
+            )
+        {
+            return true;
+        }
+        cause = error.source();
+    }
+    false
+}
 
-    Ok(comparison)
+/// What to do about an archive that could not be extracted.
+///
+/// Unlike a checksum mismatch, which says the download is not the published
+/// file, a corrupt archive after a verified checksum is rare, so downloading
+/// it again is the first thing to try; a full disk needs space instead.
+pub fn extraction_advice(error: &UpdaterError) -> Option<&'static str> {
+    match error {
+        UpdaterError::ExtractionError { source, .. } if is_out_of_space(source.as_ref()) => Some(
+            "The disk ran out of space while unpacking; free some, or pass --tmp-dir to extract elsewhere.",
+        ),
+        UpdaterError::ExtractionError {
+            format: Some(_), ..
+        } => Some("The archive may be damaged; run the update again to download it again."),
+        _ => None,
+    }
 }
 
 /// Extracts an archive (zip or tar.gz) to the given directory.
@@ -576,26 +3890,48 @@ pub fn extract_archive(
     archive_bytes: Vec<u8>,
     asset_name: &str,
     temp_dir: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if asset_name.ends_with(".zip") {
-        // Extract zip archive
-        let cursor = std::io::Cursor::new(archive_bytes);
-        let mut archive = zip::ZipArchive::new(cursor)?;
-        archive.extract(temp_dir)?;
-    } else if asset_name.ends_with(".tar.gz") {
-        // Extract tar.gz archive
-        let cursor = std::io::Cursor::new(archive_bytes);
-        let gz_decoder = GzDecoder::new(cursor);
-        let mut archive = Archive::new(gz_decoder);
-        archive.unpack(temp_dir)?;
-    } else {
-        return Err(format!("Unsupported archive format: {}", asset_name).into());
+) -> Result<(), UpdaterError> {
+    extract_from(std::io::Cursor::new(archive_bytes), asset_name, temp_dir)
+}
+
+/// [`extract_archive`] for an archive in the file `archive`, read as it is
+/// extracted.
+pub fn extract_archive_file(
+    archive: &Path,
+    asset_name: &str,
+    temp_dir: &Path,
+) -> Result<(), UpdaterError> {
+    let file = std::fs::File::open(archive)?;
+    extract_from(std::io::BufReader::new(file), asset_name, temp_dir)
+}
+
+fn extract_from(
+    reader: impl Read + std::io::Seek,
+    asset_name: &str,
+    temp_dir: &Path,
+) -> Result<(), UpdaterError> {
+    let format = ArchiveFormat::of(asset_name);
+    let failed = |source: Box<dyn std::error::Error + Send + Sync>| UpdaterError::ExtractionError {
+        archive: asset_name.to_string(),
+        format,
+        source,
+    };
+    match format {
+        Some(ArchiveFormat::Zip) => {
+            let mut archive = zip::ZipArchive::new(reader).map_err(|e| failed(e.into()))?;
+            archive.extract(temp_dir).map_err(|e| failed(e.into()))?;
+        }
+        Some(ArchiveFormat::TarGz) => {
+            let mut archive = Archive::new(GzDecoder::new(reader));
+            archive.unpack(temp_dir).map_err(|e| failed(e.into()))?;
+        }
+        None => return Err(failed("unsupported archive format".into())),
     }
     Ok(())
 }
 
 /// Finds the executable binary in the given directory.
-pub fn find_executable_binary(temp_dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+pub fn find_executable_binary(temp_dir: &Path) -> Result<PathBuf, UpdaterError> {
     for entry in std::fs::read_dir(temp_dir)? {
         let entry = entry?;
         let path = entry.path();
@@ -607,11 +3943,15 @@ pub fn find_executable_binary(temp_dir: &Path) -> Result<PathBuf, Box<dyn std::e
             return Ok(path);
         }
     }
-    Err("No executable binary found".into())
+    Err(UpdaterError::ExtractionError {
+        archive: temp_dir.display().to_string(),
+        format: None,
+        source: "no executable binary found".into(),
+    })
 }
 
 /// Command line arguments for the opencode updater.
-#[derive(Parser)]
+#[derive(Parser, Default)]
 #[command(name = "opencode-updater")]
 #[command(about = "Update opencode to the latest version")]
 pub struct Args {
@@ -619,54 +3959,617 @@ pub struct Args {
     #[arg(long)]
     pub bin: bool,
 
-    /// Rollback to a previous version
+    /// Rollback to a stored version (or `previous`), or `bak` to restore the binary kept by --install-bak
     #[arg(long, value_name = "VERSION")]
-    pub rollback: Option<String>,
+    pub rollback: Option<VersionRef>,
+
+    /// Install the newest release published on or before DATE (YYYY-MM-DD or RFC 3339)
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_parser = as_of::parse_as_of,
+        conflicts_with_all = ["rollback", "check", "dry_run"]
+    )]
+    pub install_as_of: Option<as_of::AsOf>,
+
+    /// Only update to releases on this line, e.g. 0.3 for 0.3.x
+    #[arg(long, value_name = "PREFIX", conflicts_with = "install_as_of")]
+    pub track: Option<Track>,
+
+    /// Only update to releases up to this version
+    #[arg(long, value_name = "VERSION", conflicts_with = "install_as_of")]
+    pub max_version: Option<Version>,
+
+    /// With --rollback, make updates follow the channel of the version rolled back to
+    #[arg(long, requires = "rollback")]
+    pub switch_channel: bool,
+
+    /// Let --rollback and resume-install install a version saved without an HMAC; --force doesn't
+    #[arg(long, global = true)]
+    pub allow_unsigned: bool,
 
     /// List all installed and available versions
     #[arg(long)]
     pub list_versions: bool,
 
-    /// Show changelog for a specific version (or latest if not specified)
+    /// With --list-versions --format, list available releases instead of installed versions
+    #[arg(long, requires = "format")]
+    pub available: bool,
+
+    /// Print one line per item from a template, e.g. '{version}\t{installed_at}\t{current}'
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    pub format: Option<String>,
+
+    /// Show changelog for a version; also accepts latest, installed and previous
     #[arg(long, value_name = "VERSION")]
-    pub changelog: Option<String>,
+    pub changelog: Option<VersionRef>,
 
-    /// Compare two versions to see differences
+    /// Compare two versions to see differences; also accepts latest, installed and previous
     #[arg(long, value_names = ["FROM", "TO"])]
-    pub compare: Option<Vec<String>>,
+    pub compare: Option<Vec<VersionRef>>,
 
     /// Maximum number of versions to keep locally (default: 2)
     #[arg(long, default_value = "2")]
     pub keep_versions: usize,
 
-    /// Force update even if already on latest version
+    /// Bypass every safety check below (prefer the specific flag)
     #[arg(long)]
     pub force: bool,
+
+    /// Reinstall even if the latest release is already installed
+    #[arg(long)]
+    pub force_reinstall: bool,
+
+    /// Install the latest release even if it is older than the installed version
+    #[arg(long)]
+    pub allow_downgrade: bool,
+
+    /// Overwrite an install target that belongs to a system package
+    #[arg(long)]
+    pub overwrite_packaged: bool,
+
+    /// Replace an install target that isn't opencode
+    #[arg(long)]
+    pub replace_foreign: bool,
+
+    /// Overwrite an install target that differs from every stored version
+    #[arg(long)]
+    pub overwrite_modified: bool,
+
+    /// Keep the replaced binary as `<target>.bak` next to each install target
+    #[arg(long)]
+    pub install_bak: bool,
+
+    /// Install shell completions shipped in the release archive for bash, zsh and fish
+    #[arg(long)]
+    pub install_completions: bool,
+
+    /// Install without running the configured scan_command (not implied by --force)
+    #[arg(long)]
+    pub skip_scan: bool,
+
+    /// Download and store the release's SBOM (sbom.spdx.json) when it publishes one
+    #[arg(long)]
+    pub sbom: bool,
+
+    /// Refuse to install a release that publishes no SBOM; implies --sbom
+    #[arg(long)]
+    pub require_sbom: bool,
+
+    /// Extract downloads here instead of the storage directory's tmp/
+    #[arg(long, value_name = "DIR")]
+    pub tmp_dir: Option<PathBuf>,
+
+    /// Compare the installed version with the latest release without downloading it
+    #[arg(long)]
+    pub inspect: bool,
+
+    /// Report whether an update is available without downloading it
+    #[arg(long)]
+    pub check: bool,
+
+    /// Check every stored version against its recorded integrity HMAC
+    #[arg(long)]
+    pub verify_storage: bool,
+
+    /// With --verify-storage, make stored binaries that lost their executable bit executable again
+    #[arg(long, requires = "verify_storage")]
+    pub repair: bool,
+
+    /// With --verify-storage, number of versions hashed in parallel (default: CPUs, at most 4)
+    #[arg(long, requires = "verify_storage", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Show release notes in full instead of truncating long ones
+    #[arg(long)]
+    pub full: bool,
+
+    /// With --changelog, fetch release notes from GitHub even when cached
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// Show how many GitHub API requests the update would make, without updating
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Make at most N GitHub API requests, resolving the latest release
+    /// through its web redirect and the cache where possible
+    #[arg(long, global = true, value_name = "N")]
+    pub api_budget: Option<u64>,
+
+    /// Seconds to wait for an answer to a request before giving up [default: 60]
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub timeout: Option<u64>,
+
+    /// Seconds to wait for a connection, and for the DNS lookup before it, before giving up [default: 10]
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub connect_timeout: Option<u64>,
+
+    /// Limit downloads to this many bytes per second, e.g. 500k or 2m; 0 means no limit
+    #[arg(long, global = true, value_name = "RATE", value_parser = rate_limit::parse_rate)]
+    pub limit_rate: Option<u64>,
+
+    /// Download release archives from this mirror of GitHub's download host, falling back to GitHub when it answers 404 or 5xx
+    #[arg(long, global = true, value_name = "BASE_URL")]
+    pub mirror: Option<mirror::Mirror>,
+
+    /// Send every request through this proxy, e.g. http://proxy.example:3128; takes precedence over HTTPS_PROXY, HTTP_PROXY and NO_PROXY
+    #[arg(long, global = true, value_name = "URL", value_parser = parse_proxy)]
+    pub proxy: Option<ureq::Proxy>,
+
+    /// Download from plain-HTTP URLs and hosts outside the allowlist despite strict_sources, and from a plain-HTTP --mirror
+    #[arg(long, global = true)]
+    pub allow_insecure_source: bool,
+
+    /// Never show the warning with this key (repeatable); doctor still reports it
+    #[arg(
+        long = "suppress-warning",
+        global = true,
+        value_name = "KEY",
+        value_parser = clap::builder::PossibleValuesParser::new(reporter::WARNING_KEYS)
+    )]
+    pub suppress_warnings: Vec<String>,
+
+    /// Emit machine-readable JSON output where supported
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "json")]
+    pub quiet: bool,
+
+    /// Show extra detail, such as API calls and bytes downloaded after each command; -vvv also traces HTTP requests
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log every HTTP request and response to stderr, with credentials redacted
+    #[arg(long, global = true)]
+    pub trace_http: bool,
+
+    /// Show sizes in decimal units (kB, MB) instead of binary ones (KiB, MiB)
+    #[arg(long, global = true)]
+    pub si: bool,
+
+    /// Install target from the config file to operate on (default: the configured default)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub target: Option<String>,
+
+    /// Update or rollback every configured install target
+    #[arg(long, global = true, conflicts_with = "target")]
+    pub all_targets: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+impl Args {
+    /// Whether no subcommand or other mode was selected, so the run updates.
+    pub fn runs_update(&self) -> bool {
+        self.command.is_none()
+            && self.rollback.is_none()
+            && !self.list_versions
+            && self.changelog.is_none()
+            && self.compare.is_none()
+            && !self.verify_storage
+            && !self.check
+            && !self.inspect
+    }
+
+    /// The constraint of `--track` and `--max-version`, if either is given.
+    pub fn version_constraint(&self) -> Option<VersionConstraint> {
+        VersionConstraint::new(self.track.clone(), self.max_version.clone())
+    }
+
+    /// Whether the run is `--check`.
+    pub fn runs_check(&self) -> bool {
+        self.check
+            && self.command.is_none()
+            && self.rollback.is_none()
+            && !self.list_versions
+            && self.changelog.is_none()
+            && self.compare.is_none()
+            && !self.verify_storage
+    }
+}
+
+/// Subcommands of the opencode updater.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Commands {
+    /// Set up storage and a default config file, and adopt an existing opencode install
+    Init {
+        /// Adopt an existing install without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Print where storage, cache, config and install targets are, one `name=value` per line
+    Paths,
+    /// Print the JSON Schema of a `--json` output
+    Schema {
+        /// Output to describe
+        #[arg(value_enum)]
+        kind: schema::SchemaKind,
+    },
+    /// Diagnose the environment, e.g. a local clock that disagrees with GitHub
+    Doctor,
+    /// Compare the opencode installed on other machines (over ssh) with the latest release
+    RemoteCheck {
+        /// Hosts as given to ssh, e.g. `web1` or `admin@10.0.0.5`
+        #[arg(required = true, value_name = "HOST")]
+        hosts: Vec<String>,
+    },
+    /// Write SHA256SUMS for every file in a directory, e.g. a release mirror
+    GenerateChecksums {
+        /// Directory holding the release assets
+        dir: PathBuf,
+        /// Write SHA512SUMS as well
+        #[arg(long)]
+        sha512: bool,
+        /// Also write a `<file>.sha256` next to each file
+        #[arg(long)]
+        per_file: bool,
+        /// Overwrite existing checksum files
+        #[arg(long)]
+        force: bool,
+    },
+    /// List what a release asset contains without installing it
+    InspectArchive {
+        /// Release to download (`latest` by default)
+        #[arg(value_name = "VERSION", conflicts_with = "file")]
+        version: Option<VersionRef>,
+        /// Inspect a local archive instead of downloading one
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+    /// Check, update if newer, verify, prune the cache and remove old versions in one go
+    ///
+    /// Exits 1 if a step warned and 2 if a step failed.
+    Maintain,
+    /// Install an update that a run without a terminal stored but couldn't
+    /// install, because the targets need sudo and nobody could enter a password
+    ResumeInstall,
+    /// Store an opencode installed some other way and make it the current
+    /// version, without touching the installed file
+    Adopt {
+        /// Binary to adopt (default: the opencode on PATH, then /usr/bin/opencode)
+        #[arg(long, value_name = "PATH")]
+        path: Option<PathBuf>,
+    },
+    /// Remove clutter from storage
+    Clean {
+        /// Remove entries of the versions directory that aren't stored versions
+        /// (stray files, sync conflict copies), as listed by `doctor`
+        #[arg(long, required = true)]
+        foreign: bool,
+    },
+    /// Summarise the SBOM stored with a version: packages and licenses
+    Sbom {
+        /// Stored version; also accepts installed and previous
+        version: VersionRef,
+        /// Print the SPDX document as published instead
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Summarise the releases published since the last update: how many,
+    /// the version span, asset size growth and a linked list of titles
+    ///
+    /// `--format markdown` renders it for pasting into a chat.
+    Digest {
+        /// Start from this date (YYYY-MM-DD or RFC 3339) instead of the last update
+        #[arg(long, value_name = "DATE", value_parser = digest::parse_since)]
+        since: Option<DateTime<Utc>>,
+    },
+    /// Show past update runs from the local history log
+    History {
+        #[command(subcommand)]
+        action: Option<HistoryCommand>,
+    },
+}
+
+/// Subcommands of `history`.
+#[derive(Subcommand, Debug, Clone)]
+pub enum HistoryCommand {
+    /// Aggregate download speed, run time and failures across all runs
+    Stats,
 }
 
 /// Runs the update process: Fetches the latest opencode release, downloads the binary,
 /// extracts it, and optionally installs it.
 /// If skip_install is true, skips the installation steps.
 /// If asset_override is Some, uses the provided asset name and URL instead of selecting.
+/// Installing runs are recorded in the history log, whether they succeed or fail.
 pub fn run_update(
     args: &Args,
     client: &Agent,
     base_url: &str,
     asset_override: Option<(String, String)>,
     skip_install: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), UpdaterError> {
+    let started_at = Utc::now();
+    let network_before = network::usage();
+    let mut run = UpdateRun::default();
+    let result = update_pipeline(
+        args,
+        client,
+        base_url,
+        asset_override,
+        skip_install,
+        &mut run,
+    );
+
+    let staged = run.staged.take().filter(|_| result.is_ok());
+
+    // Runs that found nothing to install are not worth a history entry
+    if !skip_install && (result.is_err() || run.to_version.is_some()) {
+        let entry = HistoryEntry {
+            started_at,
+            outcome: match (&result, &staged) {
+                (Err(_), _) => Outcome::Failure,
+                (Ok(()), Some(_)) => Outcome::Staged,
+                (Ok(()), None) => Outcome::Success,
+            },
+            from_version: run.from_version,
+            to_version: run.to_version,
+            asset_size: run.asset_size,
+            error_kind: result
+                .as_ref()
+                .err()
+                .map(|e| history::error_kind(e).to_string()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            timings: run.record_timings.then_some(run.timings),
+            network: Some(network::usage().since(&network_before)),
+            cleanup_failures: run.cleanup_failures,
+        };
+        let recorded =
+            StoragePaths::resolve().and_then(|paths| history::append(&paths.history_file, &entry));
+        if let Err(e) = recorded {
+            reporter::warn(
+                "history",
+                &format!("could not record update history: {}", e),
+            );
+        }
+    }
+    match staged {
+        Some(pending) => Err(UpdaterError::InstallStaged(pending.to_string())),
+        None => result,
+    }
+}
+
+/// Install the update a run without a terminal stored for later, to the
+/// targets that run was updating, and record it in the history log.
+pub fn resume_install(
+    version_manager: &VersionManager,
+    status_file: &Path,
+    config: &Config,
+) -> Result<ResumeReport, UpdaterError> {
+    let started_at = Utc::now();
+    let pending = status::load(status_file).pending_install.ok_or_else(|| {
+        UpdaterError::VersionNotFound("No staged update is waiting to be installed".to_string())
+    })?;
+    let configured = config.targets();
+    let targets = pending
+        .targets
+        .iter()
+        .map(|name| {
+            configured
+                .get(name)
+                .map(|target| (name.clone(), target.clone()))
+                .ok_or_else(|| {
+                    UpdaterError::Refused(format!(
+                        "install target '{}' of the staged update is no longer configured",
+                        name
+                    ))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let from_version = version_manager
+        .get_current_version()?
+        .map(|current| current.version);
+    let report = version_manager.rollback_to_targets(&pending.version, &targets)?;
+    status::clear_pending_install(status_file)?;
+
+    let entry = HistoryEntry {
+        started_at,
+        outcome: Outcome::Success,
+        from_version,
+        to_version: Some(pending.version),
+        asset_size: None,
+        error_kind: None,
+        error: None,
+        timings: None,
+        network: None,
+        cleanup_failures: Vec::new(),
+    };
+    if let Err(e) = history::append(&version_manager.history_file(), &entry) {
+        reporter::warn(
+            "history",
+            &format!("could not record update history: {}", e),
+        );
+    }
+    Ok(ResumeReport { install: report })
+}
+
+/// Refuse to install `tag` over `current` if it would be a downgrade, or if
+/// `tag` isn't a version and so can't be told apart from one.
+pub fn check_release_guards(
+    current: &str,
+    tag: &str,
+    overrides: &Overrides,
+) -> Result<(), UpdaterError> {
+    if overrides.allows(Guard::Downgrade) {
+        return Ok(());
+    }
+    if is_unparseable_version(tag) {
+        return Err(UpdaterError::Refused(format!(
+            "can't tell whether latest release {} is newer than installed {}; pass {} to install it anyway",
+            sanitize_terminal_text(tag),
+            sanitize_terminal_text(current),
+            Guard::Downgrade.flag()
+        )));
+    }
+    if matches!(is_older(tag, current), Ok(true)) {
+        return Err(UpdaterError::Refused(format!(
+            "latest release {} is older than installed {}, so updating would downgrade {} → {}; pass {} or --force to install it anyway",
+            sanitize_terminal_text(tag),
+            sanitize_terminal_text(current),
+            sanitize_terminal_text(current),
+            sanitize_terminal_text(tag),
+            Guard::Downgrade.flag()
+        )));
+    }
+    Ok(())
+}
+
+/// What an update run did, collected for its history entry.
+#[derive(Debug, Default)]
+struct UpdateRun {
+    from_version: Option<String>,
+    to_version: Option<String>,
+    asset_size: Option<u64>,
+    cleanup_failures: Vec<CleanupFailure>,
+    record_timings: bool,
+    timings: PhaseTimings,
+    /// Set when the update was stored but its install left for `resume-install`.
+    staged: Option<status::PendingInstall>,
+}
+
+fn update_pipeline(
+    args: &Args,
+    client: &Agent,
+    base_url: &str,
+    asset_override: Option<(String, String)>,
+    skip_install: bool,
+    run: &mut UpdateRun,
+) -> Result<(), UpdaterError> {
+    let reporter = Reporter::new(Reporter::mode_for(args.json, args.quiet));
+    let config = if skip_install {
+        Config::default()
+    } else {
+        Config::load()?
+    };
+    run.record_timings = config.record_timings;
+
     // Initialize version manager
-    let version_manager = VersionManager::new()?;
+    let version_manager =
+        VersionManager::new()?.with_identity_marker(config.identity_marker_regex()?);
+
+    // Resolve install targets up front so a typo fails before downloading
+    let targets = if skip_install {
+        Vec::new()
+    } else {
+        config.resolve_targets(args.target.as_deref(), args.all_targets)?
+    };
+
+    let overrides = Overrides::from_args(args);
+    if args.force {
+        reporter.warn("force", &guard::force_warning());
+    }
+
+    for (name, target) in &targets {
+        // A package manager would fight over a file it owns
+        if !overrides.allows(Guard::Packaged)
+            && let Some(package) = guard::packaged_by(&target.path)
+        {
+            return Err(UpdaterError::Refused(format!(
+                "{} (target {}) belongs to the {} package; update it with your package manager, or pass {} to overwrite it",
+                target.path.display(),
+                name,
+                sanitize_terminal_text(&package),
+                Guard::Packaged.flag()
+            )));
+        }
+
+        // Never clobber a different program that happens to be called opencode
+        if let Some(probe) =
+            version_manager.ensure_replaceable(&target.path, overrides.allows(Guard::Foreign))?
+        {
+            reporter.warn(
+                "foreign-target",
+                &format!(
+                    "replacing {} (target {}), which printed \"{}\" and doesn't look like opencode",
+                    target.path.display(),
+                    name,
+                    sanitize_terminal_text(&probe.excerpt())
+                ),
+            );
+        }
+    }
+    // A hand-patched build would be lost along with the stored copy it replaced
+    version_manager.ensure_no_divergence(&targets, overrides.allows(Guard::Modified))?;
+
+    // Nobody can answer a sudo prompt under a timer, so the download goes ahead
+    // and the install waits for `resume-install`
+    let stage_only = installer::needs_staging(&targets, installer::sudo_would_block);
+    if stage_only {
+        reporter.notice(
+            "No terminal to ask for a sudo password; the update will be stored but not installed",
+        );
+    }
 
     // Backup current version before updating
-    if !skip_install && let Some(backup_info) = version_manager.backup_current_version()? {
-        println!("Backed up current version: {}", backup_info.version);
+    if !skip_install {
+        run.from_version = version_manager
+            .get_current_version()?
+            .map(|current| current.version);
+        if let Some(backup) = version_manager.backup_current_version()? {
+            reporter.result("backup", &backup);
+        }
     }
 
-    let release = fetch_release(client, base_url)?;
+    let release = match &args.install_as_of {
+        Some(as_of) => {
+            let releases = run
+                .timings
+                .time(Phase::Fetch, || fetch_release_pages(client, base_url))?;
+            let selection = as_of::select_release(&releases, as_of, Utc::now())?;
+            reporter.result("install_as_of", &selection);
+            selection.release
+        }
+        None => {
+            let source = config.release_source(
+                base_url,
+                version_manager.cache_dir(),
+                args.version_constraint(),
+            );
+            run.timings
+                .time(Phase::Fetch, || source.latest_release(client))?
+        }
+    };
 
     // Step 2: Select the asset to download.
-    let assets = release["assets"].as_array().unwrap();
+    let assets = release_assets(&release)?;
+    let tag = release["tag_name"].as_str().unwrap_or("unknown");
+    version_dir::validate(tag.trim_start_matches('v'))?;
     let (asset_name, download_url) = if let Some((name, url)) = asset_override {
         (name, url)
     } else if args.bin {
@@ -674,139 +4577,421 @@ pub fn run_update(
         let binary_assets: Vec<_> = assets
             .iter()
             .filter(|a| {
-                let name = a["name"].as_str().unwrap();
-                name.ends_with(".zip") || name.ends_with(".tar.gz") || name.contains("linux")
+                a["name"].as_str().is_some_and(|name| {
+                    name.ends_with(".zip") || name.ends_with(".tar.gz") || name.contains("linux")
+                })
             })
             .collect();
         if binary_assets.is_empty() {
-            return Err("No binary assets found in release".into());
+            return Err(UpdaterError::GitHubApiError(
+                "no binary assets found in release".to_string(),
+            ));
         }
         let options: Vec<String> = binary_assets
             .iter()
-            .map(|a| a["name"].as_str().unwrap().to_string())
+            .map(|a| {
+                let name = sanitize_terminal_text(a["name"].as_str().unwrap_or_default());
+                match a["size"].as_u64() {
+                    Some(size) => format!("{} ({})", name, units::format_size(size)),
+                    None => name,
+                }
+            })
             .collect();
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Select a binary to install")
-            .default(0)
-            .items(&options)
-            .interact()
-            .unwrap();
+        let selection = prompt::prompter().select("Select a binary to install", &options, 0)?;
         let selected_asset = &binary_assets[selection];
-        let asset_name = selected_asset["name"].as_str().unwrap().to_string();
+        let asset_name = selected_asset["name"].as_str().unwrap_or_default();
         let download_url = selected_asset["browser_download_url"]
             .as_str()
-            .unwrap()
-            .to_string();
-        (asset_name, download_url)
+            .ok_or_else(|| {
+                UpdaterError::GitHubApiError(format!(
+                    "{} has no download URL",
+                    sanitize_terminal_text(asset_name)
+                ))
+            })?;
+        (asset_name.to_string(), download_url.to_string())
     } else {
         // Try zip first, then fallback to tar.gz
-        let (asset_name, download_url) = if let Some(asset) =
-            find_asset(assets, "opencode-linux-x64.zip")
-        {
-            let asset_name = "opencode-linux-x64.zip".to_string();
-            let download_url = asset["browser_download_url"].as_str().unwrap().to_string();
-            (asset_name, download_url)
-        } else if let Some(asset) = find_asset(assets, "opencode-linux-x64.tar.gz") {
-            let asset_name = "opencode-linux-x64.tar.gz".to_string();
-            let download_url = asset["browser_download_url"].as_str().unwrap().to_string();
-            (asset_name, download_url)
-        } else {
-            return Err("Neither 'opencode-linux-x64.zip' nor 'opencode-linux-x64.tar.gz' found in release assets".into());
-        };
-        (asset_name, download_url)
+        let asset = resolve_default_asset(&release)?;
+        (asset.name, asset.browser_download_url)
     };
 
-    // Step 2.1: Locate the checksum file for the selected asset (if available).
-    let checksum_name = format!("{}.sha256", asset_name);
-    let checksum_asset = find_asset(assets, &checksum_name);
-    let expected_checksum = match checksum_asset {
-        Some(asset) => {
-            let checksum_url = asset["browser_download_url"].as_str().unwrap();
-            let checksum_response = client.get(checksum_url).call()?;
-            if checksum_response.status() != 200 {
-                None
-            } else {
-                let checksum_text = checksum_response.into_body().read_to_string()?;
-                Some(checksum_text.trim().to_string())
-            }
-        }
-        None => None,
+    // Step 2.1: Collect every published checksum for the selected asset. A
+    // rolling build is told apart by its checksum, so it needs them now; for
+    // other releases they are fetched while the archive downloads.
+    let prefetched = if is_rolling_tag(tag) {
+        Some(run.timings.time(Phase::Fetch, || {
+            fetch_asset_checksums(client, assets, &asset_name)
+        })?)
+    } else {
+        None
+    };
+    let sha256_of = |checksums: &[Checksum]| {
+        checksums
+            .iter()
+            .find(|checksum| checksum.algorithm == ChecksumAlgorithm::Sha256)
+            .map(|checksum| checksum.digest.clone())
     };
 
-    // Step 3: Download the selected archive with progress display.
-    let zip_bytes = download_with_progress(client, &download_url, &asset_name)?;
+    // Rolling tags such as `nightly` never change, so builds are told apart by identity.
+    let asset = find_asset(assets, &asset_name)
+        .and_then(|asset| serde_json::from_value::<Asset>(asset.clone()).ok())
+        .unwrap_or_else(|| Asset {
+            name: asset_name.clone(),
+            ..Default::default()
+        });
+    let rolling = RollingBuild::from_release(
+        &release,
+        &asset,
+        prefetched.as_deref().and_then(sha256_of).as_deref(),
+    );
+    if !overrides.allows(Guard::Reinstall)
+        && !skip_install
+        && let Some(build) = &rolling
+        && version_manager
+            .get_current_version()?
+            .is_some_and(|current| build.matches(&current))
+    {
+        reporter.info(&format!(
+            "opencode {} build from {} is already installed; pass {} to reinstall it.",
+            sanitize_terminal_text(&build.tag),
+            build.built_at.format("%Y-%m-%d"),
+            Guard::Reinstall.flag()
+        ));
+        if reporter.mode() == OutputMode::Json {
+            reporter.result(
+                "update",
+                &UpdateOutcome {
+                    result: UpdateResult::UpToDate,
+                    version: run.from_version.clone().unwrap_or_default(),
+                    previous_version: None,
+                },
+            );
+        }
+        return Ok(());
+    }
+    if rolling.is_none() && !skip_install {
+        if !overrides.allows(Guard::Reinstall)
+            && run.from_version.as_deref() == Some(tag.trim_start_matches('v'))
+        {
+            reporter.info(&format!(
+                "opencode {} is already installed; pass {} to reinstall it.",
+                sanitize_terminal_text(tag),
+                Guard::Reinstall.flag()
+            ));
+            if reporter.mode() == OutputMode::Json {
+                reporter.result(
+                    "update",
+                    &UpdateOutcome {
+                        result: UpdateResult::UpToDate,
+                        version: tag.trim_start_matches('v').to_string(),
+                        previous_version: None,
+                    },
+                );
+            }
+            return Ok(());
+        }
+        if is_unparseable_version(tag) {
+            reporter.warn(
+                "unparseable-version",
+                &format!(
+                    "{}; skipping the up-to-date check",
+                    text_comparison_note(tag)
+                ),
+            );
+        }
+        if let Some(current) = &run.from_version
+            && let Err(refusal) = check_release_guards(current, tag, &overrides)
+        {
+            // Only an unparseable tag is worth asking about; a downgrade is known
+            let interactive = prompt::available()
+                && reporter.mode() == OutputMode::Human
+                && std::io::stdin().is_terminal();
+            if !(is_unparseable_version(tag) && interactive) {
+                return Err(refusal);
+            }
+            let proceed = prompt::prompter().confirm(
+                &format!(
+                    "Install {} over {}?",
+                    sanitize_terminal_text(tag),
+                    sanitize_terminal_text(current)
+                ),
+                false,
+            )?;
+            if !proceed {
+                reporter.info("Not installing.");
+                return Ok(());
+            }
+        }
+    }
 
-    // Step 3.1: Verify checksum if available.
-    if let Some(expected) = &expected_checksum {
-        if !verify_checksum(&zip_bytes, expected) {
-            return Err(format!(
-                "Checksum mismatch: expected {}, got {}",
-                expected,
-                calculate_sha256(&zip_bytes)
-            )
-            .into());
+    // Step 3: Download the selected archive and verify its checksum if available.
+    // Only the release's own asset can be fetched through the assets API
+    let private = assets_api::PrivateAsset::new(base_url, &asset)
+        .filter(|_| asset.browser_download_url == download_url);
+    // Streamed to disk beside where it is extracted, never held in memory
+    let download_dir = version_manager.create_extract_dir(args.tmp_dir.as_deref(), asset.size)?;
+    let archive_path = download_dir.path().join("archive");
+    let cache_dir = version_manager.cache_dir();
+    let (checksums, cached) = std::thread::scope(|scope| {
+        let mut checksums = match prefetched {
+            Some(checksums) => PendingChecksums::ready(checksums),
+            None => PendingChecksums::fetch(scope, client, assets, &asset_name, &reporter),
+        };
+        // Which archive to look for takes the checksums, only worth waiting for if any is cached
+        if !archive_cache::is_empty(cache_dir)
+            && take_cached_archive(cache_dir, &archive_path, &asset_name, checksums.wait())?
+        {
+            reporter.info(&format!(
+                "Using the cached {}",
+                sanitize_terminal_text(&asset_name)
+            ));
+            return Ok((checksums.wait().to_vec(), true));
         }
+        // A mirror's copy is only taken when there is a checksum to catch
+        // it being altered, so that has to be known before downloading
+        let mirror = match &args.mirror {
+            Some(mirror) if checksums.wait().is_empty() => {
+                reporter.warn(
+                    "mirror",
+                    &format!(
+                        "{} has no published checksum to verify a copy from {} against; downloading it from GitHub",
+                        sanitize_terminal_text(&asset_name),
+                        sanitize_terminal_text(&trace::redact_url(&mirror.to_string()))
+                    ),
+                );
+                None
+            }
+            mirror => mirror.as_ref(),
+        };
+        // From the mirror if there is one and it has the archive; a private
+        // asset whose URL answers 404 comes through the assets API
+        download_verified_to_file(
+            &archive_path,
+            &asset_name,
+            checksums,
+            &mut run.timings,
+            || {
+                let (sha256, stats) = mirror::with_fallback(
+                    mirror,
+                    &download_url,
+                    &asset_name,
+                    |mirrored| download_to_file(client, mirrored, &archive_path, &asset_name),
+                    || {
+                        assets_api::download_to_file(
+                            client,
+                            &download_url,
+                            &archive_path,
+                            &asset_name,
+                            private.as_ref(),
+                        )
+                    },
+                )?;
+                reporter.info(&format!(
+                    "Downloaded {}: {}",
+                    sanitize_terminal_text(&asset_name),
+                    stats
+                ));
+                Ok(sha256)
+            },
+        )
+        .map(|checksums| (checksums, false))
+    })?;
+    let sha256 = sha256_of(&checksums);
+    // Kept for the next run in case this one fails before the version is stored
+    if !cached
+        && !skip_install
+        && let Some(sha256) = &sha256
+    {
+        let _ = archive_cache::store(cache_dir, sha256, &archive_path);
     }
 
+    // Step 3.1: Fetch the SBOM alongside it.
+    let sbom = if args.sbom || args.require_sbom {
+        let sbom = run.timings.time(Phase::Download, || {
+            sbom::download(client, assets, CHECKSUM_RETRY_DELAY)
+        })?;
+        if sbom.is_none() && args.require_sbom {
+            return Err(sbom::missing(tag));
+        }
+        sbom
+    } else {
+        None
+    };
+
+    let mut verified_algorithms: Vec<_> = checksums
+        .iter()
+        .map(|checksum| checksum.algorithm)
+        .collect();
+    verified_algorithms.sort();
+    verified_algorithms.dedup();
+
     // Step 4: Extract the archive to a temporary directory.
-    let temp_dir = tempfile::tempdir()?;
-    extract_archive(zip_bytes, &asset_name, temp_dir.path())?;
+    let asset_size = std::fs::metadata(&archive_path)?.len();
+    run.asset_size = Some(asset_size);
+    let temp_dir = version_manager.create_extract_dir(args.tmp_dir.as_deref(), asset_size)?;
+    let (binary_path, binary_sha256) = run.timings.time(Phase::Extract, || {
+        extract_archive_file(&archive_path, &asset_name, temp_dir.path())?;
+        drop(download_dir);
 
-    // Step 5: Locate the executable binary within the extracted files.
-    let binary_path = find_executable_binary(temp_dir.path())?;
+        // Step 5: Locate the executable binary within the extracted files.
+        let binary_path = find_executable_binary(temp_dir.path())?;
+        // Before anything is stored or installed, let alone run
+        compat::check(&binary_path, assets)?;
+        // Hashed while only we know the path; every later step checks against it
+        let binary_sha256 = hash_file(&binary_path)?;
+        Ok::<_, UpdaterError>((binary_path, binary_sha256))
+    })?;
+
+    let scanned = match config.scanner() {
+        Some(scanner) if args.skip_scan => {
+            // Shown even with --quiet
+            eprintln!("{}", scan::skip_warning(&scanner.command));
+            None
+        }
+        Some(scanner) => {
+            reporter.info(&format!(
+                "Scanning with {}...",
+                sanitize_terminal_text(&scanner.command)
+            ));
+            scanner.scan(&binary_path)?;
+            Some(scanner.passed())
+        }
+        None => None,
+    };
+
+    let install_completions = args.install_completions || config.install_completions;
+    let completion_files = match completions::CompletionDirs::from_env() {
+        Some(dirs) if install_completions => {
+            completions::plan(&completions::detect(temp_dir.path()), &dirs)
+        }
+        _ => Vec::new(),
+    };
 
     if !skip_install {
         // Create version info for the new version
-        let version = release["tag_name"].as_str().unwrap_or("unknown");
-        let version_clean = version.trim_start_matches('v');
+        let version_clean = tag.trim_start_matches('v');
 
         let version_info = VersionInfo {
-            version: version_clean.to_string(),
-            tag_name: version.to_string(),
-            release_date: release["published_at"]
-                .as_str()
-                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(Utc::now),
+            version: rolling
+                .as_ref()
+                .map(RollingBuild::version_name)
+                .unwrap_or_else(|| version_clean.to_string()),
+            tag_name: tag.to_string(),
+            release_date: match &rolling {
+                Some(build) => build.built_at,
+                None => release["published_at"]
+                    .as_str()
+                    .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(Utc::now),
+            },
             download_url: download_url.clone(),
-            checksum: expected_checksum.unwrap_or_default(),
+            checksum: sha256.unwrap_or_default(),
             installed_at: Utc::now(),
-            install_path: PathBuf::from("/usr/bin/opencode"),
+            install_path: targets
+                .first()
+                .map(|(_, target)| target.path.clone())
+                .unwrap_or_else(|| PathBuf::from(config::SYSTEM_INSTALL_PATH)),
             release_notes: release["body"]
                 .as_str()
                 .unwrap_or("No release notes available.")
                 .to_string(),
+            asset_size: Some(asset_size),
+            verified_algorithms,
+            channel: Channel::of_release(&release),
+            build_id: rolling.map(|build| build.build_id),
+            completions: completion_files,
+            scanned,
+            ..Default::default()
         };
 
-        // Save the new version to storage
-        version_manager.save_version(&version_info, &binary_path)?;
+        run.to_version = Some(version_info.version.clone());
+        // Ctrl-C from here on finishes or undoes the copy in progress first
+        run.timings.time(Phase::Install, || {
+            progress::defer_interrupts(|| {
+                // Save the new version to storage
+                version_manager.save_extracted_version(
+                    &version_info,
+                    &binary_path,
+                    &binary_sha256,
+                    temp_dir.path(),
+                    sbom.as_deref(),
+                )?;
+                let _ = archive_cache::evict(cache_dir, &version_info.checksum);
+                if stage_only {
+                    return Ok(());
+                }
+                let version_dir = version_manager.version_dir(&version_info.version);
 
-        // Step 6: Install the binary to /usr/bin/opencode (system-wide).
-        Command::new("sudo")
-            .arg("mv")
-            .arg(&binary_path)
-            .arg("/usr/bin/opencode")
-            .status()?;
+                // Step 6: Install the stored binary to every selected target.
+                let stored_binary = version_dir.join("opencode");
+                let length = std::fs::metadata(&stored_binary)?.len();
+                for (name, target) in &targets {
+                    installer::install_binary(
+                        &*installer::installer_for(target),
+                        &stored_binary,
+                        &binary_sha256,
+                        &target.path,
+                        args.install_bak,
+                        &*progress::install_progress(length, &target.path),
+                    )?;
+                    version_manager.record_target_install(
+                        name,
+                        &version_info.version,
+                        &target.path,
+                    )?;
+                }
 
-        // Step 7: Ensure the installed binary has execute permissions.
-        Command::new("sudo")
-            .arg("chmod")
-            .arg("+x")
-            .arg("/usr/bin/opencode")
-            .status()?;
+                for path in completions::install(&version_dir, &version_info.completions)? {
+                    reporter.info(&format!("Installed completions: {}", path.display()));
+                }
 
-        // Update current symlink
-        let current_link = version_manager.storage_dir.join("current");
-        let version_dir = version_manager.versions_dir.join(&version_info.version);
-        if current_link.exists() {
-            std::fs::remove_file(&current_link)?;
+                // Update current symlink
+                version_manager.set_current(&version_info.version)
+            })
+        })?;
+
+        if stage_only {
+            let pending = status::PendingInstall {
+                version: version_info.version.clone(),
+                targets: targets.iter().map(|(name, _)| name.clone()).collect(),
+                staged_at: Utc::now(),
+            };
+            status::record_pending_install(&StoragePaths::resolve()?.status_file, &pending)?;
+            run.staged = Some(pending);
+            return Ok(());
         }
-        std::os::unix::fs::symlink(&version_dir, &current_link)?;
 
         // Clean up old versions
-        version_manager.cleanup_old_versions(args.keep_versions)?;
+        let cleanup = run.timings.time(Phase::Cleanup, || {
+            version_manager.cleanup_old_versions_by(args.keep_versions, config.cleanup_order)
+        })?;
+        reporter.result("cleanup", &cleanup);
+        run.cleanup_failures = cleanup.failed.clone();
+        if cleanup.nothing_cleaned() {
+            return Err(UpdaterError::StorageError(format!(
+                "could not remove any of {} old version{} due for cleanup",
+                cleanup.failed.len(),
+                if cleanup.failed.len() == 1 { "" } else { "s" }
+            )));
+        }
 
-        println!("Updated opencode to version {}.", version_clean);
+        let outcome = UpdateOutcome {
+            result: UpdateResult::Updated,
+            version: version_info.version.clone(),
+            previous_version: run
+                .from_version
+                .clone()
+                .filter(|from| *from != version_info.version),
+        };
+        // Shown as progress, unless the document is asked for
+        match reporter.mode() {
+            OutputMode::Json => reporter.result("update", &outcome),
+            _ => reporter.info(&outcome.to_string()),
+        }
+        if run.record_timings {
+            reporter.info(&format!("Timings: {}", run.timings));
+        }
     }
     Ok(())
 }
@@ -0,0 +1,112 @@
+//! The lock serialising changes to the versions directory.
+//!
+//! Saving a version, cleaning up old ones and removing foreign entries each
+//! hold it, so two runs, possibly by different users of a shared store,
+//! never see each other's half-finished work. It is an advisory `flock` on
+//! `versions/.lock`, released when the holder exits, however it exits.
+//!
+//! The holder writes its pid into the file and empties it again on release,
+//! so a pid found there by the next holder was left by a run that crashed.
+
+use rustix::fs::FlockOperation;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+/// Name of the lock file, next to the tool's versions directory.
+pub const LOCK_FILE: &str = ".lock";
+
+/// Holds the lock until dropped.
+#[derive(Debug)]
+pub struct StoreLock {
+    file: File,
+    stale_holder: Option<u32>,
+}
+
+impl StoreLock {
+    /// Take the lock at `path`, waiting for whoever holds it.
+    pub fn acquire(path: &Path) -> std::io::Result<Self> {
+        let file = open(path)?;
+        if rustix::fs::flock(&file, FlockOperation::NonBlockingLockExclusive).is_err() {
+            eprintln!(
+                "Waiting for another opencode-updater to finish with the versions directory..."
+            );
+            rustix::fs::flock(&file, FlockOperation::LockExclusive)?;
+        }
+        Ok(Self::locked(file))
+    }
+
+    /// Take the lock at `path` if nobody holds it.
+    pub fn try_acquire(path: &Path) -> std::io::Result<Option<Self>> {
+        let file = open(path)?;
+        match rustix::fs::flock(&file, FlockOperation::NonBlockingLockExclusive) {
+            Ok(()) => Ok(Some(Self::locked(file))),
+            Err(rustix::io::Errno::WOULDBLOCK) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Pid of a previous holder that crashed without releasing the lock.
+    pub fn stale_holder(&self) -> Option<u32> {
+        self.stale_holder
+    }
+
+    fn locked(mut file: File) -> Self {
+        let mut recorded = String::new();
+        file.read_to_string(&mut recorded).ok();
+        // A lock file someone else created may be read-only to us
+        let _ = file
+            .set_len(0)
+            .and_then(|()| file.rewind())
+            .and_then(|()| write!(file, "{}", std::process::id()));
+        Self {
+            file,
+            stale_holder: recorded.trim().parse().ok(),
+        }
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Whether a process with `pid` is running, possibly as another user.
+pub fn is_running(pid: u32) -> bool {
+    let Some(pid) = i32::try_from(pid)
+        .ok()
+        .and_then(rustix::process::Pid::from_raw)
+    else {
+        return false;
+    };
+    !matches!(
+        rustix::process::test_kill_process(pid),
+        Err(rustix::io::Errno::SRCH)
+    )
+}
+
+fn open(path: &Path) -> std::io::Result<File> {
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(0o664)
+                .open(path)?;
+            // Past the umask, so other users of a shared store can take it too
+            file.set_permissions(std::fs::Permissions::from_mode(0o664))?;
+            Ok(file)
+        }
+        // Reading is enough for flock
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => File::open(path),
+        result => result,
+    }
+}
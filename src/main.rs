@@ -4,142 +4,1021 @@
 //! Security Note: This downloads and installs executables with sudo—verify the GitHub source.
 //! Integrity: Performs SHA-256 checksum verification against GitHub release checksums.
 
-use clap::Parser;
+use clap::CommandFactory;
 use opencode_updater::{
-    Args, VersionManager, cache_releases, display_version_comparison, fetch_all_releases,
-    fetch_release_by_tag, format_release_notes, load_cached_releases, run_update,
+    Args, CHECKSUM_RETRY_DELAY, ChecksumAlgorithm, Commands, Config, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_READ_TIMEOUT, Guard, HistoryCommand, HistoryStats, InstallTarget, IntegrityStatus,
+    OutputMode, Overrides, PathsReport, Reporter, SizeUnits, SortOrder, SshTransport, StoragePaths,
+    TargetState, Template, UpdaterError, VersionList, VersionManager, adopt,
+    archive::inspect_archive,
+    budget, build_client, build_inspect_report, cache_release, cache_releases,
+    channel::ChannelSwitch,
+    check_for_update_from, checksum, default_jobs, describe_available_release, diff_release_notes,
+    digest, display_version_comparison, doctor, download_verified, encoding,
+    error_report::{ErrorDocument, ErrorReport},
+    exit_code, fetch_all_releases, fetch_asset_checksum, fetch_asset_checksums, find_in_path,
+    format_inspect_report, format_release_notes, hint, history, installer, load_cached_release,
+    load_cached_releases,
+    maintain::{LiveMaintenance, maintain},
+    network, progress, prompt,
+    remote::remote_check,
+    resolve_default_asset, resolve_release, run_doctor, run_update, sanitize_terminal_text, sbom,
+    sort_versions, template, trace, units,
+    version_ref::{LiveVersions, ResolvedVersion, VersionRef},
 };
-use ureq::Agent;
+use std::io::IsTerminal;
 
 /// Main entry point: Parses arguments and runs the appropriate command.
-/// Requires sudo for installation. Panics on errors for simplicity.
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+/// Requires sudo for installation. Exits with a code telling what kind of
+/// failure stopped the run, see [`exit_code`].
+fn main() {
+    // Parsed before the config is read, so --help works with a broken one
+    let matches = Args::command().get_matches();
+    let status_file = StoragePaths::resolve().ok().map(|paths| paths.status_file);
+    let args = match configure(&matches, status_file.as_deref()) {
+        Ok(args) => args,
+        Err(e) => exit_with(e.as_ref(), false, matches.get_flag("json")),
+    };
+    let result = run(&args);
+
+    // With -v, summarize what the command cost in API quota and bandwidth
+    if args.verbose > 0 {
+        let usage = network::usage();
+        match Reporter::mode_for(args.json, args.quiet) {
+            OutputMode::Json => Reporter::new(OutputMode::Json).result("network_usage", &usage),
+            OutputMode::Human => eprintln!("{}", usage),
+            OutputMode::Quiet => {}
+        }
+    }
+    if let Some(path) = &status_file
+        && let Err(e) = opencode_updater::status::record_warnings_shown(
+            path,
+            &opencode_updater::reporter::warnings_shown(),
+        )
+    {
+        eprintln!("Warning: could not record shown warnings: {}", e);
+    }
+    // Keep the last rate limit seen for later commands and other tools
+    if let Some(rate_limit) = network::usage().rate_limit()
+        && let Ok(paths) = StoragePaths::resolve()
+        && let Err(e) = opencode_updater::status::record_rate_limit(&paths.status_file, rate_limit)
+    {
+        eprintln!("Warning: could not record API status: {}", e);
+    }
+    if let Err(e) = result {
+        exit_with(e.as_ref(), args.verbose > 0, args.json);
+    }
+}
+
+/// Read the config and apply the process-wide settings of `matches`.
+fn configure(
+    matches: &clap::ArgMatches,
+    status_file: Option<&std::path::Path>,
+) -> Result<Args, Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+    let args = config.args_from(matches)?;
+    // Under a timer a recurring warning is repeated at most hourly
+    let last_shown = status_file
+        .filter(|_| !std::io::stderr().is_terminal())
+        .map(|path| opencode_updater::status::load(path).warnings_shown);
+    opencode_updater::reporter::set_warning_registry(
+        config.warning_registry(&args.suppress_warnings, last_shown)?,
+    );
+    // Don't leave a half-drawn bar and a hidden cursor behind
+    progress::install_panic_hook();
+    if let Err(e) = progress::install_interrupt_handler() {
+        eprintln!("Warning: could not install the Ctrl-C handler: {}", e);
+    }
+    network::set_api_budget(args.api_budget);
+    opencode_updater::retry::set_policy(config.retry_policy());
+    opencode_updater::rate_limit::set_limit(args.limit_rate);
+    let host_policy = config.host_policy(args.allow_insecure_source)?;
+    if config.strict_sources && host_policy.is_none() {
+        eprintln!(
+            "Warning: --allow-insecure-source is set; downloads are NOT limited to HTTPS and the allowed hosts, and whatever they fetch will be installed"
+        );
+    }
+    opencode_updater::allowlist::set_policy(host_policy);
+    if args.si {
+        units::set_units(SizeUnits::Decimal);
+    }
+    opencode_updater::reporter::set_output_mode(Reporter::mode_for(args.json, args.quiet));
+    opencode_updater::reporter::set_verbose(args.verbose > 0);
+    if args.trace_http || args.verbose >= 3 {
+        trace::set_enabled(true);
+    }
+    Ok(args)
+}
+
+/// Report `error`, unless the command already did, and exit with its code.
+///
+/// With `json`, the report is an `error` document on stderr.
+fn exit_with(error: &(dyn std::error::Error + 'static), verbose: bool, json: bool) -> ! {
+    // The command already reported why; only the exit code is left to set
+    if let Some(ExitStatus(code)) = error.downcast_ref::<ExitStatus>() {
+        std::process::exit(*code);
+    }
+    if json {
+        if let Some(UpdaterError::Interrupted(_)) = error.downcast_ref::<UpdaterError>() {
+            progress::restore_terminal();
+        }
+        let document = ErrorDocument {
+            error: ErrorReport::new(error),
+        };
+        eprintln!("{}", Reporter::document("error", &document));
+        std::process::exit(document.error.exit_code);
+    }
+    match error.downcast_ref::<UpdaterError>() {
+        Some(UpdaterError::Interrupted(message)) => {
+            progress::restore_terminal();
+            eprintln!("Interrupted: {}", message);
+        }
+        // Not a failure, but a timer needs to tell it from a successful update
+        Some(UpdaterError::InstallStaged(message)) => eprintln!("{}", message),
+        Some(updater_error) => {
+            print_error(error, verbose);
+            if let Some(hint) = hint::error_hint(updater_error) {
+                print_hint(&hint);
+            }
+        }
+        None => print_error(error, verbose),
+    }
+    std::process::exit(exit_code::for_error(error));
+}
+
+/// Print `error`, and with `verbose` every error it wraps, innermost last.
+fn print_error(error: &(dyn std::error::Error + 'static), verbose: bool) {
+    eprintln!("Error: {}", error);
+    if !verbose {
+        return;
+    }
+    let mut shown = error.to_string();
+    let mut cause = error.source();
+    while let Some(error) = cause {
+        let message = error.to_string();
+        // Errors quoting their cause in full would repeat it
+        if !shown.ends_with(&message) {
+            eprintln!("  caused by: {}", message);
+        }
+        shown = message;
+        cause = error.source();
+    }
+}
+
+/// Print `hint` below an error, dimmed on a terminal unless `NO_COLOR` is set.
+fn print_hint(hint: &str) {
+    if std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+        eprintln!("\x1b[2m{}\x1b[0m", hint);
+    } else {
+        eprintln!("{}", hint);
+    }
+}
+
+/// Exit code chosen by a command that has already reported its outcome.
+#[derive(Debug)]
+struct ExitStatus(i32);
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for ExitStatus {}
+
+/// Runs the command selected by `args`.
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    // Resolving paths must not create any directories
+    if let Some(Commands::Schema { kind }) = &args.command {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&opencode_updater::schema::schema(*kind))?
+        );
+        return Ok(());
+    }
+
+    if let Some(Commands::Paths) = &args.command {
+        let reporter = Reporter::new(Reporter::mode_for(args.json, args.quiet));
+        reporter.result("paths", &PathsReport::resolve(args)?);
+        return Ok(());
+    }
+
+    if let Some(Commands::GenerateChecksums {
+        dir,
+        sha512,
+        per_file,
+        force,
+    }) = &args.command
+    {
+        let reporter = Reporter::new(Reporter::mode_for(args.json, args.quiet));
+        let algorithms: &[ChecksumAlgorithm] = if *sha512 {
+            &ChecksumAlgorithm::ALL
+        } else {
+            &[ChecksumAlgorithm::Sha256]
+        };
+        reporter.result(
+            "checksums",
+            &checksum::generate_checksums(dir, algorithms, *per_file, *force)?,
+        );
+        return Ok(());
+    }
+
+    if let Some(Commands::History { action }) = &args.command {
+        return handle_history(args, action.as_ref());
+    }
+
+    // Restoring a .bak works without the storage directory, which may be unavailable
+    if matches!(&args.rollback, Some(VersionRef::Exact(v)) if v == "bak") {
+        return handle_rollback_bak(args);
+    }
 
     // Initialize version manager
-    let version_manager = VersionManager::new()?;
+    let version_manager = VersionManager::new()?
+        .with_identity_marker(Config::load()?.identity_marker_regex()?)
+        .with_unsigned_allowed(args.allow_unsigned);
 
     // Create HTTP client
-    let client = Agent::new_with_defaults();
+    let client = build_client(
+        args.connect_timeout
+            .map_or(DEFAULT_CONNECT_TIMEOUT, std::time::Duration::from_secs),
+        args.timeout
+            .map_or(DEFAULT_READ_TIMEOUT, std::time::Duration::from_secs),
+        args.proxy.clone(),
+    );
+    let reporter = Reporter::new(Reporter::mode_for(args.json, args.quiet));
+    if let Some(report) = version_manager.migrated() {
+        reporter.info(&report.to_string());
+    }
+    if !version_manager.recovered().is_empty() {
+        reporter.info(&version_manager.recovered().to_string());
+    }
+
+    // Handle subcommands
+    if let Some(Commands::Doctor) = &args.command {
+        let mut report = run_doctor(&client, "https://api.github.com");
+        report.checks.push(doctor::check_foreign_entries(
+            &version_manager.foreign_entries()?,
+        ));
+        report.checks.push(doctor::check_modes(
+            &version_manager.non_executable_versions()?,
+        ));
+        let targets: Vec<_> = Config::load()?.targets().into_iter().collect();
+        report.checks.push(doctor::check_divergence(
+            &version_manager.divergent_installs(&targets)?,
+        ));
+        report
+            .checks
+            .push(doctor::check_recovery(version_manager.recovered()));
+        reporter.result("doctor", &report);
+        return Ok(());
+    }
+
+    if let Some(Commands::ResumeInstall) = &args.command {
+        let report = opencode_updater::resume_install(
+            &version_manager,
+            &StoragePaths::resolve()?.status_file,
+            &Config::load()?,
+        )?;
+        reporter.result("resume_install", &report);
+        return Ok(());
+    }
+
+    if let Some(Commands::Clean { .. }) = &args.command {
+        reporter.result("clean", &version_manager.remove_foreign_entries()?);
+        return Ok(());
+    }
+
+    if let Some(Commands::RemoteCheck { hosts }) = &args.command {
+        // The latest release is fetched once, locally; hosts only report what they have
+        let release = opencode_updater::fetch_release(&client, "https://api.github.com")?;
+        let latest_tag = release["tag_name"]
+            .as_str()
+            .ok_or("Latest release has no tag_name")?;
+        reporter.result(
+            "remote_check",
+            &remote_check(&SshTransport::default(), hosts, latest_tag),
+        );
+        return Ok(());
+    }
+
+    if let Some(Commands::Maintain) = &args.command {
+        let report = maintain(&mut LiveMaintenance {
+            args,
+            client: &client,
+            base_url: "https://api.github.com",
+            version_manager: &version_manager,
+        });
+        reporter.result("maintain", &report);
+        return match report.exit_code() {
+            0 => Ok(()),
+            code => Err(ExitStatus(code).into()),
+        };
+    }
+
+    if let Some(Commands::InspectArchive { version, file }) = &args.command {
+        return handle_inspect_archive(
+            &version_manager,
+            &client,
+            &reporter,
+            args,
+            version.as_ref(),
+            file.as_deref(),
+        );
+    }
+
+    if let Some(Commands::Init { yes }) = &args.command {
+        return handle_init(&version_manager, *yes);
+    }
+
+    if version_manager.take_init_hint() {
+        eprintln!(
+            "Hint: run `opencode-updater init` to set up storage and adopt an existing install."
+        );
+    }
 
     // Handle different commands
+    let versions = LiveVersions {
+        version_manager: &version_manager,
+        client: &client,
+        base_url: "https://api.github.com",
+    };
+
+    if let Some(Commands::Digest { since }) = &args.command {
+        return handle_digest(&version_manager, &client, &reporter, args, *since);
+    }
+
+    if let Some(Commands::Adopt { path }) = &args.command {
+        let binary = path
+            .clone()
+            .or_else(existing_opencode)
+            .ok_or("No opencode found on PATH or at /usr/bin/opencode; pass --path")?;
+        let releases = available_releases(&version_manager, &client);
+        let fetch = |url: &str| -> Result<String, UpdaterError> {
+            let response = opencode_updater::payload::send(
+                &client,
+                client
+                    .get(url)
+                    .header("Accept-Encoding", encoding::ASSET_ACCEPT_ENCODING),
+            )?;
+            encoding::read_text(response, url)
+        };
+        let report = adopt::adopt(&version_manager, &binary, &releases, fetch)?;
+        reporter.result("adopt", &report);
+        return Ok(());
+    }
+
+    if let Some(Commands::Sbom { version, raw }) = &args.command {
+        let version = version.resolve(&versions)?.version;
+        let document = version_manager.read_sbom(&version)?;
+        if *raw {
+            print!("{}", document);
+        } else {
+            reporter.result("sbom", &sbom::summarize(&version, &document)?);
+        }
+        return Ok(());
+    }
+
     if let Some(version) = &args.rollback {
-        return handle_rollback(&version_manager, version);
+        let version = version.resolve(&versions)?.version;
+        return handle_rollback(&version_manager, &reporter, args, &version);
     }
 
     if args.list_versions {
-        return handle_list_versions(&version_manager, &client);
+        return handle_list_versions(&version_manager, &client, args);
     }
 
     if let Some(version) = &args.changelog {
-        return handle_changelog(&client, version);
+        return handle_changelog(&version_manager, &client, version, args);
     }
 
-    if let Some(versions) = &args.compare {
-        return handle_compare(&client, versions);
+    if let Some(refs) = &args.compare {
+        return handle_compare(&client, &versions, refs);
+    }
+
+    if args.verify_storage {
+        let jobs = args.jobs.map_or_else(default_jobs, usize::from);
+        return handle_verify_storage(&version_manager, args.json, jobs, args.repair);
+    }
+
+    if args.check {
+        let source = Config::load()?.release_source(
+            "https://api.github.com",
+            version_manager.cache_dir(),
+            args.version_constraint(),
+        );
+        let report = check_for_update_from(&version_manager, &client, &*source)?;
+        reporter.result("check", &report);
+        return match report.update_available {
+            true => Ok(()),
+            false => Err(ExitStatus(exit_code::NO_UPDATE).into()),
+        };
+    }
+
+    if args.inspect {
+        return handle_inspect(&version_manager, &client, args.json);
+    }
+
+    if args.dry_run {
+        reporter.result(
+            "api_plan",
+            &budget::plan_update(
+                &client,
+                "https://api.github.com",
+                version_manager.cache_dir(),
+            )?,
+        );
+        return Ok(());
     }
 
     // Default: update to latest
-    run_update(&args, &client, "https://api.github.com", None, false)
+    Ok(run_update(
+        args,
+        &client,
+        "https://api.github.com",
+        None,
+        false,
+    )?)
+}
+
+/// Handle history command
+fn handle_history(
+    args: &Args,
+    action: Option<&HistoryCommand>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = Reporter::new(Reporter::mode_for(args.json, args.quiet));
+    let template = match (&args.format, action) {
+        (Some(format), None) => Some(Template::parse(format, template::HISTORY_PLACEHOLDERS)?),
+        _ => None,
+    };
+    let entries = history::load(&StoragePaths::resolve()?.history_file)?;
+    if let Some(template) = template {
+        for line in template::render_history(&template, &entries) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    match action {
+        Some(HistoryCommand::Stats) => {
+            reporter.result("history_stats", &HistoryStats::from_entries(&entries));
+        }
+        None if entries.is_empty() => reporter.info("No updates recorded yet."),
+        None => {
+            for entry in &entries {
+                reporter.result("history_entry", entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The opencode on PATH, or the system install, that `init` and `adopt` look at
+fn existing_opencode() -> Option<std::path::PathBuf> {
+    std::env::var_os("PATH")
+        .and_then(|path| find_in_path("opencode", &path))
+        .or_else(|| {
+            let system = std::path::PathBuf::from(opencode_updater::config::SYSTEM_INSTALL_PATH);
+            system.exists().then_some(system)
+        })
+}
+
+/// Published releases, from the cache when it's fresh
+///
+/// Empty, with a warning, when they can't be fetched.
+fn available_releases(
+    version_manager: &VersionManager,
+    client: &ureq::Agent,
+) -> Vec<serde_json::Value> {
+    // Try to load cached releases first
+    if let Ok(Some(releases)) = load_cached_releases(version_manager.cache_dir()) {
+        return releases;
+    }
+    // Fetch from GitHub if cache is empty or expired
+    match fetch_all_releases(client, "https://api.github.com") {
+        Ok(releases) => {
+            // Cache the releases
+            let _ = cache_releases(&releases, version_manager.cache_dir());
+            releases
+        }
+        Err(e) => {
+            // If network fails, continue without them
+            eprintln!("Warning: could not fetch available releases: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Handle digest command
+fn handle_digest(
+    version_manager: &VersionManager,
+    client: &ureq::Agent,
+    reporter: &Reporter,
+    args: &Args,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let markdown = match args.format.as_deref() {
+        None => false,
+        Some("markdown") => true,
+        Some(other) => {
+            return Err(format!("digest supports --format markdown, not '{}'", other).into());
+        }
+    };
+    let (since, source) = match since {
+        Some(since) => (Some(since), digest::SinceSource::Requested),
+        None => {
+            match digest::since_last_update(&history::load(&StoragePaths::resolve()?.history_file)?)
+            {
+                Some(since) => (Some(since), digest::SinceSource::LastUpdate),
+                None => (None, digest::SinceSource::Beginning),
+            }
+        }
+    };
+    // Without releases there is nothing to summarise, so a failed fetch is an error
+    let releases = match load_cached_releases(version_manager.cache_dir())? {
+        Some(releases) => releases,
+        None => {
+            let releases = fetch_all_releases(client, "https://api.github.com")?;
+            let _ = cache_releases(&releases, version_manager.cache_dir());
+            releases
+        }
+    };
+    let digest = digest::build(&releases, since, source);
+    if markdown {
+        println!("{}", digest::render_markdown(&digest));
+    } else {
+        reporter.result("digest", &digest);
+    }
+    Ok(())
+}
+
+/// Handle init command
+fn handle_init(
+    version_manager: &VersionManager,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("📁 Storage: {}", version_manager.storage_dir().display());
+
+    let config_path = Config::default_path().ok_or("Could not find config directory")?;
+    if Config::write_default(&config_path)? {
+        eprintln!("📝 Wrote default config: {}", config_path.display());
+    } else {
+        eprintln!("📝 Config already exists: {}", config_path.display());
+    }
+
+    // Offer to adopt an opencode binary that isn't managed yet
+    match existing_opencode() {
+        // A `current` symlink means a stored version is already active
+        Some(binary) if !version_manager.storage_dir().join("current").exists() => {
+            let adopt = yes
+                || prompt::prompter().confirm(
+                    &format!("Adopt existing opencode at {}?", binary.display()),
+                    true,
+                )?;
+            if adopt {
+                match version_manager.adopt_binary(&binary)? {
+                    Some(info) => eprintln!("✅ Adopted opencode {}", info.version),
+                    None => eprintln!(
+                        "⚠️  {} did not identify as opencode; not adopted",
+                        binary.display()
+                    ),
+                }
+            }
+        }
+        Some(_) => eprintln!("✅ opencode is already managed"),
+        None => eprintln!("ℹ️  No existing opencode install found"),
+    }
+
+    version_manager.mark_initialized()?;
+
+    eprintln!("\nNext steps:");
+    eprintln!("  opencode-updater                  # install or update to the latest release");
+    eprintln!("  opencode-updater --list-versions  # see stored and available versions");
+    eprintln!("  opencode-updater --rollback VER   # switch back to a stored version");
+    eprintln!("  edit {} to add install targets", config_path.display());
+
+    Ok(())
 }
 
 /// Handle rollback command
 fn handle_rollback(
     version_manager: &VersionManager,
+    reporter: &Reporter,
+    args: &Args,
     version: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    version_manager.rollback_to(version)
+    let config = Config::load()?;
+    let targets = config.resolve_targets(args.target.as_deref(), args.all_targets)?;
+    version_manager
+        .ensure_no_divergence(&targets, Overrides::from_args(args).allows(Guard::Modified))?;
+
+    // A partial version such as 0.3 picks the newest stored match
+    let resolved = match version_manager.resolve_stored_version(version) {
+        Ok(resolved) => resolved,
+        Err(e) => return Err(rollback_failed(reporter, &targets, e)),
+    };
+    if resolved != version {
+        reporter.notice(&format!(
+            "{} matches stored version {}; rolling back to it",
+            sanitize_terminal_text(version),
+            sanitize_terminal_text(&resolved)
+        ));
+    }
+    let version = resolved.as_str();
+
+    // Updates keep following the configured channel unless told otherwise
+    let switch = version_manager
+        .list_installed_versions()?
+        .iter()
+        .find(|info| info.version == version)
+        .and_then(|info| ChannelSwitch::for_rollback(config.channel, info));
+    if let Some(switch) = &switch
+        && !args.switch_channel
+    {
+        reporter.notice(&format!("Warning: {}", switch));
+        let confirmed = args.force
+            || (std::io::stdin().is_terminal()
+                && prompt::prompter()
+                    .confirm(&format!("Roll back to {} anyway?", version), false)?);
+        if !confirmed {
+            return Err(UpdaterError::Refused(format!(
+                "not rolling back to {} across channels; pass --switch-channel to follow the {} channel, or --force to keep following {}",
+                version, switch.target, switch.configured
+            ))
+            .into());
+        }
+    }
+
+    let report = match version_manager.rollback_to_targets(version, &targets) {
+        Ok(report) => report,
+        Err(e) => return Err(rollback_failed(reporter, &targets, e)),
+    };
+    if report.integrity == IntegrityStatus::Unsigned {
+        reporter.warn(
+            "unsigned-version",
+            &format!(
+                "version {} was saved without integrity protection; installed unverified because of --allow-unsigned",
+                report.version
+            ),
+        );
+    }
+    reporter.result("rollback", &report);
+    if let Some(switch) = switch
+        && args.switch_channel
+    {
+        let path = Config::default_path().ok_or("Could not find config directory")?;
+        Config::set_channel(&path, switch.target)?;
+        reporter.notice(&format!(
+            "Updates now follow the {} channel (set in {})",
+            switch.target,
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// The error of a failed rollback, pointing out `--rollback bak` when a
+/// target has a `.bak` binary to restore.
+fn rollback_failed(
+    reporter: &Reporter,
+    targets: &[(String, InstallTarget)],
+    error: UpdaterError,
+) -> Box<dyn std::error::Error> {
+    if targets
+        .iter()
+        .any(|(_, target)| installer::bak_path(&target.path).exists())
+    {
+        reporter.notice("Hint: `--rollback bak` restores the binary kept by --install-bak.");
+    }
+    error.into()
+}
+
+/// Handle `--rollback bak`: put each target's `.bak` binary back in place
+fn handle_rollback_bak(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = Reporter::new(Reporter::mode_for(args.json, args.quiet));
+    let targets = Config::load()?.resolve_targets(args.target.as_deref(), args.all_targets)?;
+    for (name, target) in &targets {
+        installer::restore_bak(&*installer::installer_for(target), &target.path)?;
+        reporter.info(&format!(
+            "Restored {} from {} ({})",
+            target.path.display(),
+            installer::bak_path(&target.path).display(),
+            name
+        ));
+    }
+    Ok(())
 }
 
 /// Handle list versions command
 fn handle_list_versions(
     version_manager: &VersionManager,
     client: &ureq::Agent,
+    args: &Args,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let installed = version_manager.list_installed_versions()?;
     let current = version_manager.get_current_version()?;
+    let targets = version_manager.target_states()?;
 
-    // Try to load cached releases first
-    let available = match load_cached_releases(version_manager.cache_dir())? {
-        Some(releases) => releases,
-        None => {
-            // Fetch from GitHub if cache is empty or expired
-            match fetch_all_releases(client, "https://api.github.com") {
-                Ok(releases) => {
-                    // Cache the releases
-                    let _ = cache_releases(&releases, version_manager.cache_dir());
-                    releases
-                }
-                Err(_) => {
-                    // If network fails, continue with empty list
-                    vec![]
-                }
-            }
+    // Installed versions don't need the network; check the template before fetching
+    if let Some(format) = &args.format
+        && !args.available
+    {
+        let template = Template::parse(format, template::INSTALLED_PLACEHOLDERS)?;
+        for line in template::render_installed(&template, &installed, current.as_ref()) {
+            println!("{}", line);
         }
+        return Ok(());
+    }
+    let release_template = match &args.format {
+        Some(format) => Some(Template::parse(format, template::RELEASE_PLACEHOLDERS)?),
+        None => None,
     };
 
-    let output = format_version_list(&installed, &available, current.as_ref());
+    let available = available_releases(version_manager, client);
+
+    if args.json {
+        let list = VersionList::new(&installed, &available, current.as_ref(), &targets);
+        println!("{}", Reporter::document("list", &list));
+        return Ok(());
+    }
+
+    if let Some(template) = release_template {
+        for line in template::render_releases(&template, &available, &installed) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let output = format_version_list(&installed, &available, current.as_ref(), &targets);
     println!("{}", output);
 
+    let configured: Vec<_> = Config::load()?.targets().into_iter().collect();
+    for divergence in version_manager.divergent_installs(&configured)? {
+        opencode_updater::reporter::warn(
+            "modified-install",
+            &format!(
+                "{}; keep it with `{}` before updating over it",
+                divergence,
+                divergence.adopt_command()
+            ),
+        );
+    }
+
     Ok(())
 }
 
 /// Handle changelog command
-fn handle_changelog(client: &ureq::Agent, version: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let release = if version == "latest" || version.is_empty() {
-        // Fetch latest release
+fn handle_changelog(
+    version_manager: &VersionManager,
+    client: &ureq::Agent,
+    version: &VersionRef,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cache_dir = version_manager.cache_dir();
+    let release = if *version == VersionRef::Latest {
+        // The latest release already carries its notes; no second request needed
         opencode_updater::fetch_release(client, "https://api.github.com")?
     } else {
-        // Fetch specific release by tag
-        let tag = if !version.starts_with('v') {
-            format!("v{}", version)
-        } else {
-            version.to_string()
+        let versions = LiveVersions {
+            version_manager,
+            client,
+            base_url: "https://api.github.com",
         };
-        fetch_release_by_tag(client, "https://api.github.com", &tag)?
+        let resolved = version.resolve(&versions)?;
+        match load_cached_release(cache_dir, &resolved.tag)? {
+            Some(cached) if !args.refresh => cached,
+            _ => match fetch_resolved_release(client, version, &resolved) {
+                Ok(release) => release,
+                // Offline, a stored version still has its notes
+                Err(e) => match version_manager.stored_release(&resolved.version)? {
+                    Some(stored) => {
+                        eprintln!(
+                            "Could not fetch release notes ({}); showing the copy stored with {}",
+                            e, resolved.version
+                        );
+                        return print_changelog(&stored, args.full);
+                    }
+                    None => return Err(e.into()),
+                },
+            },
+        }
     };
 
-    let changelog = format_release_notes(&release)?;
-    println!("{}", changelog);
+    // Show what changed if upstream edited the notes since they were cached
+    if let Some(tag) = release["tag_name"].as_str()
+        && let Some(cached) = load_cached_release(cache_dir, tag)?
+        && let Some(diff) = diff_release_notes(&cached, &release)
+    {
+        eprintln!(
+            "⚠️  Release notes for {} were updated since they were cached:\n",
+            sanitize_terminal_text(tag)
+        );
+        eprintln!("{}", sanitize_terminal_text(&diff));
+    }
+    let _ = cache_release(&release, cache_dir);
+    print_changelog(&release, args.full)
+}
+
+/// Release of `version`, which resolved to `resolved`. An exact version is
+/// looked up as typed, so a release tagged without its `v`, or with one the
+/// user left off, is found too.
+fn fetch_resolved_release(
+    client: &ureq::Agent,
+    version: &VersionRef,
+    resolved: &ResolvedVersion,
+) -> Result<serde_json::Value, opencode_updater::UpdaterError> {
+    let input = match version {
+        VersionRef::Exact(input) => input,
+        _ => &resolved.tag,
+    };
+    resolve_release(client, "https://api.github.com", input)
+}
 
+fn print_changelog(
+    release: &serde_json::Value,
+    full: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", format_release_notes(release, full)?);
     Ok(())
 }
 
 /// Handle compare command
 fn handle_compare(
     client: &ureq::Agent,
-    versions: &[String],
+    versions: &LiveVersions,
+    refs: &[VersionRef],
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if versions.len() != 2 {
+    if refs.len() != 2 {
         return Err("Compare requires exactly two version arguments".into());
     }
 
-    let from_tag = if !versions[0].starts_with('v') {
-        format!("v{}", versions[0])
+    let from_release = fetch_resolved_release(client, &refs[0], &refs[0].resolve(versions)?)?;
+    let to_release = fetch_resolved_release(client, &refs[1], &refs[1].resolve(versions)?)?;
+
+    let comparison = display_version_comparison(&from_release, &to_release)?;
+    println!("{}", comparison);
+
+    Ok(())
+}
+
+/// Handle verify storage command
+fn handle_verify_storage(
+    version_manager: &VersionManager,
+    json: bool,
+    jobs: usize,
+    repair: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let results = version_manager.verify_storage_with_jobs(jobs)?;
+    let repaired = if repair {
+        version_manager.repair_modes()?
     } else {
-        versions[0].clone()
+        Vec::new()
     };
+    let mode_problems = version_manager.non_executable_versions()?;
 
-    let to_tag = if !versions[1].starts_with('v') {
-        format!("v{}", versions[1])
+    if json {
+        let entries: Vec<_> = results
+            .iter()
+            .map(|(version, status)| {
+                serde_json::json!({
+                    "version": version,
+                    "status": status,
+                    "executable": !mode_problems.iter().any(|problem| &problem.version == version),
+                    "repaired": repaired.iter().any(|problem| &problem.version == version),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if results.is_empty() {
+        eprintln!("📁 No versions stored locally");
     } else {
-        versions[1].clone()
-    };
+        println!("🔐 Storage Integrity\n");
+        for (version, status) in &results {
+            let marker = match status {
+                IntegrityStatus::Verified => "✅",
+                IntegrityStatus::Unsigned => "⚠️ ",
+                _ => "❌",
+            };
+            println!("  {} {}: {}", marker, version, status);
+        }
+        for problem in &repaired {
+            println!(
+                "  🔧 {}: made executable again (was {:04o})",
+                problem.version, problem.mode
+            );
+        }
+        for problem in &mode_problems {
+            println!("  ❌ {}", problem);
+        }
+    }
 
-    let from_release = fetch_release_by_tag(client, "https://api.github.com", &from_tag)?;
-    let to_release = fetch_release_by_tag(client, "https://api.github.com", &to_tag)?;
+    let tampered = results.iter().any(|(_, status)| {
+        matches!(
+            status,
+            IntegrityStatus::BinaryModified
+                | IntegrityStatus::MetadataModified
+                | IntegrityStatus::Unreadable(_)
+        )
+    });
+    if tampered {
+        return Err("One or more stored versions failed integrity verification".into());
+    }
+    if !mode_problems.is_empty() {
+        return Err(
+            "One or more stored binaries are not executable; pass --repair to fix their modes"
+                .into(),
+        );
+    }
+    Ok(())
+}
 
-    let comparison = display_version_comparison(&from_release, &to_release)?;
-    println!("{}", comparison);
+/// Handle inspect command
+fn handle_inspect(
+    version_manager: &VersionManager,
+    client: &ureq::Agent,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let release = opencode_updater::fetch_release(client, "https://api.github.com")?;
+    let asset = resolve_default_asset(&release)?;
+    let assets = release["assets"]
+        .as_array()
+        .ok_or("Release has no assets")?;
+
+    // Only the checksum is downloaded; the archive itself is never fetched.
+    let candidate_checksum = fetch_asset_checksum(client, assets, &asset.name)?;
+    let installed = version_manager.get_current_version()?;
+
+    let report = build_inspect_report(installed.as_ref(), &release, &asset, candidate_checksum);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", format_inspect_report(&report));
+    }
 
     Ok(())
 }
 
+/// Handle inspect-archive command
+fn handle_inspect_archive(
+    version_manager: &VersionManager,
+    client: &ureq::Agent,
+    reporter: &Reporter,
+    args: &Args,
+    version: Option<&VersionRef>,
+    file: Option<&std::path::Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (bytes, asset_name) = match file {
+        Some(path) => {
+            let name = path
+                .file_name()
+                .ok_or("Archive path has no file name")?
+                .to_string_lossy()
+                .into_owned();
+            (std::fs::read(path)?, name)
+        }
+        None => {
+            let release = match version.unwrap_or(&VersionRef::Latest) {
+                VersionRef::Latest => {
+                    opencode_updater::fetch_release(client, "https://api.github.com")?
+                }
+                version => {
+                    let versions = LiveVersions {
+                        version_manager,
+                        client,
+                        base_url: "https://api.github.com",
+                    };
+                    fetch_resolved_release(client, version, &version.resolve(&versions)?)?
+                }
+            };
+            let asset = resolve_default_asset(&release)?;
+            let assets = release["assets"]
+                .as_array()
+                .ok_or("Release has no assets")?;
+            let checksums = fetch_asset_checksums(client, assets, &asset.name)?;
+            let bytes = download_verified(
+                client,
+                &asset.browser_download_url,
+                &asset.name,
+                &checksums,
+                CHECKSUM_RETRY_DELAY,
+            )?;
+            (bytes, asset.name)
+        }
+    };
+
+    // The scratch directory is removed on drop; versions and targets are never touched
+    let scratch =
+        version_manager.create_extract_dir(args.tmp_dir.as_deref(), bytes.len() as u64)?;
+    let inspection = inspect_archive(bytes, &asset_name, scratch.path())?;
+    reporter.result("archive_inspection", &inspection);
+    Ok(())
+}
+
 /// Format version list for display
 fn format_version_list(
     installed: &[opencode_updater::VersionInfo],
     available: &[serde_json::Value],
     current: Option<&opencode_updater::VersionInfo>,
+    targets: &std::collections::BTreeMap<String, TargetState>,
 ) -> String {
     let mut output = String::new();
 
@@ -156,10 +1035,26 @@ fn format_version_list(
         output.push_str("❌ No version currently installed\n\n");
     }
 
-    // Installed versions
+    // Per-target install state
+    if !targets.is_empty() {
+        output.push_str("🎯 Targets:\n");
+        for (name, state) in targets {
+            output.push_str(&format!(
+                "  {}: {} ({})\n",
+                name,
+                state.version,
+                state.install_path.display()
+            ));
+        }
+        output.push('\n');
+    }
+
+    // Installed versions, highest first
     if !installed.is_empty() {
         output.push_str("📁 Installed Versions:\n");
-        for version in installed {
+        let mut installed = installed.to_vec();
+        sort_versions(&mut installed, SortOrder::Version);
+        for version in &installed {
             let marker = if current
                 .as_ref()
                 .is_some_and(|c| c.version == version.version)
@@ -168,11 +1063,29 @@ fn format_version_list(
             } else {
                 " "
             };
+            let size = version.asset_size.map_or_else(String::new, |size| {
+                format!(", {}", units::format_size(size))
+            });
+            let installer = version
+                .installed_by
+                .as_deref()
+                .map_or_else(String::new, |user| {
+                    format!(", by {}", sanitize_terminal_text(user))
+                });
+            let unverified = if version.provenance == Some(adopt::Provenance::Unverified) {
+                ", unverified"
+            } else {
+                ""
+            };
             output.push_str(&format!(
-                "  {} {} ({})\n",
+                "  {} {} ({}{}{}{}) [{}]\n",
                 marker,
                 version.version,
-                version.installed_at.format("%Y-%m-%d")
+                version.installed_at.format("%Y-%m-%d"),
+                size,
+                installer,
+                unverified,
+                version.channel
             ));
         }
     } else {
@@ -185,7 +1098,12 @@ fn format_version_list(
         for release in available.iter().take(5) {
             let tag = release["tag_name"].as_str().unwrap_or("Unknown");
             let date = release["published_at"].as_str().unwrap_or("Unknown");
-            output.push_str(&format!("  📦 {} ({})\n", tag, &date[..10]));
+            output.push_str(&format!(
+                "  📦 {} ({}){}\n",
+                sanitize_terminal_text(tag),
+                &date[..10],
+                describe_available_release(tag, current.map(|c| c.version.as_str()))
+            ));
         }
     } else {
         output.push_str("\n🌐 No version information available (network required)\n");
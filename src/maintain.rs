@@ -0,0 +1,358 @@
+//! `maintain`: the whole hygiene pass in one command.
+//!
+//! Checks for an update and installs it if newer, verifies the installed
+//! binary and the stored versions, prunes the cache and removes old
+//! versions. Each step is recorded with its timing, including those skipped
+//! because an earlier step made them pointless. A failing step doesn't undo
+//! the ones before it and doesn't stop the ones after it; it is reported, and
+//! the exit code follows the worst outcome. The steps are a trait so tests
+//! can inject failures.
+
+use crate::{
+    Args, CheckReport, CleanupReport, Config, IntegrityStatus, UpdaterError, VersionManager,
+    check_for_update, default_jobs, hash_file, prune_cache, run_update, sanitize_terminal_text,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Instant;
+use ureq::Agent;
+
+/// The work behind each step of a `maintain` run.
+pub trait Maintenance {
+    fn check(&mut self) -> Result<CheckReport, UpdaterError>;
+    fn update(&mut self) -> Result<(), UpdaterError>;
+    /// Integrity of the current version, including the copy installed from it.
+    fn verify_binary(&mut self) -> Result<IntegrityStatus, UpdaterError>;
+    fn verify_storage(&mut self) -> Result<Vec<(String, IntegrityStatus)>, UpdaterError>;
+    fn prune_cache(&mut self) -> Result<Vec<PathBuf>, UpdaterError>;
+    fn cleanup(&mut self) -> Result<CleanupReport, UpdaterError>;
+}
+
+/// Steps backed by the real storage, network and install targets.
+pub struct LiveMaintenance<'a> {
+    pub args: &'a Args,
+    pub client: &'a Agent,
+    pub base_url: &'a str,
+    pub version_manager: &'a VersionManager,
+}
+
+impl Maintenance for LiveMaintenance<'_> {
+    fn check(&mut self) -> Result<CheckReport, UpdaterError> {
+        check_for_update(self.version_manager, self.client, self.base_url)
+    }
+
+    fn update(&mut self) -> Result<(), UpdaterError> {
+        run_update(self.args, self.client, self.base_url, None, false)
+    }
+
+    fn verify_binary(&mut self) -> Result<IntegrityStatus, UpdaterError> {
+        let current = self
+            .version_manager
+            .get_current_version()?
+            .ok_or_else(|| UpdaterError::VersionNotFound("current".to_string()))?;
+        let status = self
+            .version_manager
+            .verify_version_integrity(&current.version)?;
+        // The stored copy may be intact while the installed one was replaced
+        if status == IntegrityStatus::Verified
+            && let Some(recorded) = &current.binary_sha256
+            && current.install_path.exists()
+            && hash_file(&current.install_path)? != *recorded
+        {
+            return Ok(IntegrityStatus::BinaryModified);
+        }
+        Ok(status)
+    }
+
+    fn verify_storage(&mut self) -> Result<Vec<(String, IntegrityStatus)>, UpdaterError> {
+        let jobs = self.args.jobs.map_or_else(default_jobs, usize::from);
+        self.version_manager.verify_storage_with_jobs(jobs)
+    }
+
+    fn prune_cache(&mut self) -> Result<Vec<PathBuf>, UpdaterError> {
+        prune_cache(self.version_manager.cache_dir())
+    }
+
+    fn cleanup(&mut self) -> Result<CleanupReport, UpdaterError> {
+        self.version_manager
+            .cleanup_old_versions_by(self.args.keep_versions, Config::load()?.cleanup_order)
+    }
+}
+
+/// How a step ended, from best to worst.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum StepOutcome {
+    /// Ran and found nothing to do or nothing wrong.
+    Ok,
+    /// Ran and changed something.
+    Changed,
+    /// Not run, because an earlier step made it pointless.
+    Skipped,
+    /// Ran, but found something worth attention.
+    Warning,
+    Failed,
+}
+
+impl std::fmt::Display for StepOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StepOutcome::Ok => "ok",
+            StepOutcome::Changed => "changed",
+            StepOutcome::Skipped => "skipped",
+            StepOutcome::Warning => "warning",
+            StepOutcome::Failed => "failed",
+        })
+    }
+}
+
+/// One recorded step.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StepResult {
+    pub step: String,
+    pub outcome: StepOutcome,
+    pub detail: String,
+    pub seconds: f64,
+}
+
+/// Runs steps one after another, timing and recording each.
+#[derive(Debug, Default)]
+pub struct StepRunner {
+    steps: Vec<StepResult>,
+}
+
+impl StepRunner {
+    /// Run `step`; an error is recorded as a failure rather than returned.
+    pub fn run(
+        &mut self,
+        step: &str,
+        f: impl FnOnce() -> Result<(StepOutcome, String), UpdaterError>,
+    ) -> StepOutcome {
+        let started = Instant::now();
+        let (outcome, detail) = f().unwrap_or_else(|e| (StepOutcome::Failed, e.to_string()));
+        self.steps.push(StepResult {
+            step: step.to_string(),
+            outcome,
+            detail,
+            seconds: started.elapsed().as_secs_f64(),
+        });
+        outcome
+    }
+
+    /// Record `step` as skipped for `reason`.
+    pub fn skip(&mut self, step: &str, reason: &str) -> StepOutcome {
+        self.steps.push(StepResult {
+            step: step.to_string(),
+            outcome: StepOutcome::Skipped,
+            detail: reason.to_string(),
+            seconds: 0.0,
+        });
+        StepOutcome::Skipped
+    }
+
+    pub fn finish(self) -> MaintainReport {
+        MaintainReport { steps: self.steps }
+    }
+}
+
+/// Every step of a `maintain` run.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MaintainReport {
+    pub steps: Vec<StepResult>,
+}
+
+impl MaintainReport {
+    /// The worst outcome of any step.
+    pub fn worst(&self) -> StepOutcome {
+        self.steps
+            .iter()
+            .map(|step| step.outcome)
+            .max()
+            .unwrap_or(StepOutcome::Ok)
+    }
+
+    /// Process exit code: 0 when every step went fine, 1 when a step only
+    /// warned, 2 when a step failed.
+    pub fn exit_code(&self) -> i32 {
+        match self.worst() {
+            StepOutcome::Ok | StepOutcome::Changed | StepOutcome::Skipped => 0,
+            StepOutcome::Warning => 1,
+            StepOutcome::Failed => 2,
+        }
+    }
+
+    /// The outcome of `step`, if it was recorded.
+    pub fn outcome(&self, step: &str) -> Option<StepOutcome> {
+        self.steps
+            .iter()
+            .find(|result| result.step == step)
+            .map(|result| result.outcome)
+    }
+}
+
+impl std::fmt::Display for MaintainReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows: Vec<[String; 4]> = self
+            .steps
+            .iter()
+            .map(|step| {
+                [
+                    step.step.clone(),
+                    step.outcome.to_string(),
+                    format!("{:.1}s", step.seconds),
+                    sanitize_terminal_text(&step.detail),
+                ]
+            })
+            .collect();
+        let header = ["STEP", "RESULT", "TIME", "DETAIL"].map(String::from);
+        let width = |column: usize| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        };
+        let widths = [width(0), width(1), width(2)];
+        for row in std::iter::once(&header).chain(&rows) {
+            let line = format!(
+                "{:w0$}  {:w1$}  {:>w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2]
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        write!(f, "\nOverall: {}", self.worst())
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+/// Run every step against `steps` and report how each went.
+pub fn maintain(steps: &mut dyn Maintenance) -> MaintainReport {
+    let mut runner = StepRunner::default();
+
+    let mut update_available = None;
+    runner.run("check", || {
+        let report = steps.check()?;
+        update_available = Some(report.update_available);
+        Ok((StepOutcome::Ok, report.to_string()))
+    });
+
+    let updated = match update_available {
+        None => runner.skip("update", "check failed"),
+        Some(false) => runner.skip("update", "already up to date"),
+        Some(true) => runner.run("update", || {
+            steps.update()?;
+            Ok((
+                StepOutcome::Changed,
+                "installed the latest release".to_string(),
+            ))
+        }),
+    };
+
+    // A failed update may have left things half-done, so it is verified too
+    if updated == StepOutcome::Skipped {
+        runner.skip("verify-binary", "nothing changed");
+    } else {
+        runner.run("verify-binary", || {
+            let status = steps.verify_binary()?;
+            let outcome = match status {
+                IntegrityStatus::Verified => StepOutcome::Ok,
+                IntegrityStatus::Unsigned => StepOutcome::Warning,
+                _ => StepOutcome::Failed,
+            };
+            Ok((outcome, status.to_string()))
+        });
+    }
+
+    runner.run("verify-storage", || {
+        let results = steps.verify_storage()?;
+        let bad: Vec<&str> = results
+            .iter()
+            .filter(|(_, status)| {
+                !matches!(
+                    status,
+                    IntegrityStatus::Verified | IntegrityStatus::Unsigned
+                )
+            })
+            .map(|(version, _)| version.as_str())
+            .collect();
+        let unsigned = results
+            .iter()
+            .filter(|(_, status)| *status == IntegrityStatus::Unsigned)
+            .count();
+        Ok(if !bad.is_empty() {
+            (
+                StepOutcome::Failed,
+                format!(
+                    "{} of {} failed verification: {}",
+                    bad.len(),
+                    plural(results.len(), "stored version"),
+                    bad.join(", ")
+                ),
+            )
+        } else if unsigned > 0 {
+            (
+                StepOutcome::Warning,
+                format!(
+                    "{} of {} unsigned",
+                    unsigned,
+                    plural(results.len(), "stored version")
+                ),
+            )
+        } else {
+            (
+                StepOutcome::Ok,
+                format!("{} verified", plural(results.len(), "stored version")),
+            )
+        })
+    });
+
+    runner.run("prune-cache", || {
+        let removed = steps.prune_cache()?;
+        Ok(if removed.is_empty() {
+            (StepOutcome::Ok, "nothing to prune".to_string())
+        } else {
+            (
+                StepOutcome::Changed,
+                format!("removed {}", plural(removed.len(), "stale file")),
+            )
+        })
+    });
+
+    runner.run("cleanup", || {
+        let report = steps.cleanup()?;
+        let removed = plural(report.removed.len(), "old version");
+        Ok(if report.nothing_cleaned() {
+            (
+                StepOutcome::Failed,
+                format!(
+                    "could not remove any of {} due for cleanup",
+                    plural(report.failed.len(), "old version")
+                ),
+            )
+        } else if !report.failed.is_empty() {
+            (
+                StepOutcome::Warning,
+                format!(
+                    "removed {}; {} could not be removed",
+                    removed,
+                    report.failed.len()
+                ),
+            )
+        } else if report.removed.is_empty() {
+            (StepOutcome::Ok, "nothing to remove".to_string())
+        } else {
+            (StepOutcome::Changed, format!("removed {}", removed))
+        })
+    });
+
+    runner.finish()
+}
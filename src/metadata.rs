@@ -0,0 +1,284 @@
+//! The metadata files kept in each version directory.
+//!
+//! What describes the release is kept apart from what describes this
+//! machine's copy of it, so the same version saved on two hosts yields
+//! byte-identical version directories apart from `install.json`:
+//!
+//! - `release.json`: tag, version, release date, download URL, checksums,
+//!   the stored binary's hash and an excerpt of the release notes.
+//! - `notes.md`: the release notes in full, named by `notes_file` in
+//!   `release.json`. Readers fall back to the excerpt when it's missing.
+//! - `install.json`: when and where it was installed, the HMAC made with this
+//!   machine's key, the completion files installed for this user, the
+//!   scanner it passed, the user who saved it and, for an adopted binary,
+//!   how its version was established.
+//!
+//! All are read into and written from a single [`VersionInfo`], whose
+//! `release_notes` holds the full notes. Versions saved before the split
+//! kept everything in `metadata.json`, and those saved before schema 4 kept
+//! the notes in `release.json`; storage migration moves both to the current
+//! files, and [`read`] still understands either left behind. Versions saved
+//! before schema 5 recorded no channel; migration infers it from the version.
+
+use crate::{Channel, ChecksumAlgorithm, CompletionFile, UpdaterError, VersionInfo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Metadata identical on every host that saved the release.
+pub const RELEASE_FILE: &str = "release.json";
+
+/// Metadata about this host's install.
+pub const INSTALL_FILE: &str = "install.json";
+
+/// Combined metadata written before the split.
+pub const LEGACY_FILE: &str = "metadata.json";
+
+/// Full release notes.
+pub const NOTES_FILE: &str = "notes.md";
+
+/// First schema recording each version's channel.
+pub const CHANNEL_SCHEMA_VERSION: u32 = 5;
+
+/// Longest excerpt of the notes kept in `release.json`.
+pub const NOTES_EXCERPT_BYTES: usize = 280;
+
+/// Contents of `release.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReleaseMetadata {
+    pub schema_version: u32,
+    pub version: String,
+    pub tag_name: String,
+    pub release_date: DateTime<Utc>,
+    pub download_url: String,
+    pub checksum: String,
+    /// The start of the notes, or all of them before schema 4.
+    pub release_notes: String,
+    /// File in the version directory holding the notes in full.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes_file: Option<String>,
+    #[serde(default)]
+    pub asset_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verified_algorithms: Vec<ChecksumAlgorithm>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+    #[serde(default)]
+    pub channel: Channel,
+}
+
+/// Contents of `install.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstallMetadata {
+    pub installed_at: DateTime<Utc>,
+    pub install_path: PathBuf,
+    /// Made with this machine's integrity key, so it differs between hosts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac: Option<String>,
+    /// Destinations are under this user's home directory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completions: Vec<CompletionFile>,
+    /// Scanners are configured per host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanned: Option<String>,
+    /// Who saved it here, which in a shared store may be another user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_by: Option<String>,
+    /// How an adopted binary's version was established on this host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<crate::adopt::Provenance>,
+}
+
+/// The start of `notes`, cut at a line end where there is one, for `release.json`.
+pub fn excerpt(notes: &str) -> String {
+    if notes.len() <= NOTES_EXCERPT_BYTES {
+        return notes.to_string();
+    }
+    let mut end = NOTES_EXCERPT_BYTES;
+    while !notes.is_char_boundary(end) {
+        end -= 1;
+    }
+    let start = &notes[..end];
+    let start = start
+        .rfind('\n')
+        .map_or(start, |line_end| &start[..line_end]);
+    format!("{}…", start.trim_end())
+}
+
+/// Split `info` into its release and install parts, leaving only an excerpt
+/// of the notes in the release part.
+pub fn split(info: &VersionInfo) -> (ReleaseMetadata, InstallMetadata) {
+    let info = info.clone();
+    let has_notes = !info.release_notes.is_empty();
+    (
+        ReleaseMetadata {
+            schema_version: info.schema_version,
+            version: info.version,
+            tag_name: info.tag_name,
+            release_date: info.release_date,
+            download_url: info.download_url,
+            checksum: info.checksum,
+            release_notes: excerpt(&info.release_notes),
+            notes_file: has_notes.then(|| NOTES_FILE.to_string()),
+            asset_size: info.asset_size,
+            binary_sha256: info.binary_sha256,
+            verified_algorithms: info.verified_algorithms,
+            build_id: info.build_id,
+            channel: info.channel,
+        },
+        InstallMetadata {
+            installed_at: info.installed_at,
+            install_path: info.install_path,
+            hmac: info.hmac,
+            completions: info.completions,
+            scanned: info.scanned,
+            installed_by: info.installed_by,
+            provenance: info.provenance,
+        },
+    )
+}
+
+/// Put the two parts back together; the notes are only the excerpt.
+pub fn join(release: ReleaseMetadata, install: InstallMetadata) -> VersionInfo {
+    VersionInfo {
+        version: release.version,
+        tag_name: release.tag_name,
+        release_date: release.release_date,
+        download_url: release.download_url,
+        checksum: release.checksum,
+        installed_at: install.installed_at,
+        install_path: install.install_path,
+        release_notes: release.release_notes,
+        asset_size: release.asset_size,
+        binary_sha256: release.binary_sha256,
+        hmac: install.hmac,
+        verified_algorithms: release.verified_algorithms,
+        build_id: release.build_id,
+        channel: release.channel,
+        completions: install.completions,
+        scanned: install.scanned,
+        installed_by: install.installed_by,
+        provenance: install.provenance,
+        schema_version: release.schema_version,
+    }
+}
+
+/// Whether `version_dir` has metadata of either kind.
+pub fn is_present(version_dir: &Path) -> bool {
+    version_dir.join(RELEASE_FILE).is_file() || version_dir.join(LEGACY_FILE).is_file()
+}
+
+fn parse<T: serde::de::DeserializeOwned>(
+    version_dir: &Path,
+    name: &str,
+) -> Result<T, UpdaterError> {
+    let path = version_dir.join(name);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|_| UpdaterError::Other(format!("no readable {}", name)))?;
+    serde_json::from_str(&content).map_err(|source| crate::UpdaterError::MetadataError {
+        path: Some(path),
+        source,
+    })
+}
+
+/// Read the metadata of the version stored in `version_dir`.
+pub fn read(version_dir: &Path) -> Result<VersionInfo, UpdaterError> {
+    if !version_dir.join(RELEASE_FILE).exists() && version_dir.join(LEGACY_FILE).exists() {
+        return parse(version_dir, LEGACY_FILE);
+    }
+    let release: ReleaseMetadata = parse(version_dir, RELEASE_FILE)?;
+    let notes = release
+        .notes_file
+        .as_deref()
+        .and_then(|name| read_notes(version_dir, name));
+    let mut info = join(release, parse(version_dir, INSTALL_FILE)?);
+    if let Some(notes) = notes {
+        info.release_notes = notes;
+    }
+    Ok(info)
+}
+
+/// The notes in `name`, which must be a file directly in `version_dir`.
+fn read_notes(version_dir: &Path, name: &str) -> Option<String> {
+    if Path::new(name).file_name() != Some(name.as_ref()) {
+        return None;
+    }
+    std::fs::read_to_string(version_dir.join(name)).ok()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    // A trailing newline, so the files diff cleanly
+    Ok(serde_json::to_string_pretty(value)? + "\n")
+}
+
+/// Write `info` into a version directory that is still being built.
+pub fn write(version_dir: &Path, info: &VersionInfo) -> Result<(), UpdaterError> {
+    let (release, install) = split(info);
+    if release.notes_file.is_some() {
+        std::fs::write(version_dir.join(NOTES_FILE), &info.release_notes)?;
+    }
+    std::fs::write(version_dir.join(RELEASE_FILE), to_json(&release)?)?;
+    std::fs::write(version_dir.join(INSTALL_FILE), to_json(&install)?)?;
+    Ok(())
+}
+
+/// Replace the `metadata.json` of an existing version directory with the
+/// split files.
+///
+/// Each file is written atomically and `metadata.json` is removed last, so
+/// an interruption leaves a directory [`read`] still understands.
+pub fn split_legacy(version_dir: &Path, schema_version: u32) -> Result<(), UpdaterError> {
+    let mut info: VersionInfo = parse(version_dir, LEGACY_FILE)?;
+    upgrade(&mut info, schema_version);
+    let (release, install) = split(&info);
+    crate::write_atomic(
+        &version_dir.join(INSTALL_FILE),
+        to_json(&install)?.as_bytes(),
+    )?;
+    rewrite_release(version_dir, &info, &release)?;
+    std::fs::remove_file(version_dir.join(LEGACY_FILE))?;
+    Ok(())
+}
+
+/// Bring `info`, read from metadata of an older schema, up to `schema_version`.
+fn upgrade(info: &mut VersionInfo, schema_version: u32) {
+    if info.schema_version < CHANNEL_SCHEMA_VERSION {
+        info.channel = Channel::of_version(&info.version);
+    }
+    info.schema_version = schema_version;
+}
+
+/// Rewrite the `release.json` of a version saved before `schema_version`:
+/// notes embedded before schema 4 move out to `notes.md`, and versions saved
+/// before schema 5 get a channel.
+///
+/// Returns whether anything was rewritten. `notes.md` is written before
+/// `release.json` points at it, so an interruption loses nothing.
+pub fn upgrade_release(version_dir: &Path, schema_version: u32) -> Result<bool, UpdaterError> {
+    let release: ReleaseMetadata = parse(version_dir, RELEASE_FILE)?;
+    if release.schema_version >= schema_version {
+        return Ok(false);
+    }
+    let mut info = read(version_dir)?;
+    upgrade(&mut info, schema_version);
+    rewrite_release(version_dir, &info, &split(&info).0)?;
+    Ok(true)
+}
+
+/// Atomically write the notes and then `release.json` of an existing version.
+fn rewrite_release(
+    version_dir: &Path,
+    info: &VersionInfo,
+    release: &ReleaseMetadata,
+) -> Result<(), UpdaterError> {
+    if release.notes_file.is_some() {
+        crate::write_atomic(&version_dir.join(NOTES_FILE), info.release_notes.as_bytes())?;
+    }
+    crate::write_atomic(
+        &version_dir.join(RELEASE_FILE),
+        to_json(release)?.as_bytes(),
+    )?;
+    Ok(())
+}
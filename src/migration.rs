@@ -0,0 +1,277 @@
+//! One-time migration of the storage directory to the current layout.
+//!
+//! Storage layout 1 kept versions directly in `versions/<ver>`. Layout 2 keeps
+//! them per tool in `versions/opencode/<ver>`, with schema 2 metadata, and
+//! records the layout in a `storage_version` file so migration runs only once.
+//! Layout 3 splits each version's `metadata.json` into `release.json` and
+//! `install.json` (schema 3, see [`crate::metadata`]). Layout 4 moves the
+//! release notes out of `release.json` into `notes.md` (schema 4). Layout 5
+//! records each version's release channel (schema 5).
+//!
+//! Moving from layout 1, the new tree is built in a staging directory next to
+//! `versions/` and only swapped in once every version was copied, so a
+//! failure leaves the old layout as it was. Rewriting metadata is done one
+//! version at a time, in an order that leaves every version readable if it
+//! is interrupted.
+
+use crate::{UpdaterError, metadata};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Current storage layout version.
+pub const STORAGE_VERSION: u32 = 5;
+
+/// Current metadata schema version.
+pub const METADATA_SCHEMA_VERSION: u32 = 5;
+
+/// Layout that moved versions into per-tool directories.
+const TOOL_DIR_LAYOUT: u32 = 2;
+
+/// Name of the file recording the storage layout version.
+pub const STORAGE_VERSION_FILE: &str = "storage_version";
+
+/// Subdirectory of `versions/` holding opencode's stored versions.
+pub const TOOL_DIR: &str = "opencode";
+
+const STAGING_DIR: &str = "versions.migrating";
+const LEGACY_DIR: &str = "versions.legacy";
+
+/// Versions moved by [`migrate_storage`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub migrated: Vec<String>,
+}
+
+impl std::fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Migrated {} stored version(s) to the new storage layout",
+            self.migrated.len()
+        )
+    }
+}
+
+/// Layout version recorded in the storage directory, if any.
+pub fn storage_version(storage_dir: &Path) -> Option<u32> {
+    std::fs::read_to_string(storage_dir.join(STORAGE_VERSION_FILE))
+        .ok()
+        .and_then(|content| content.trim().parse().ok())
+}
+
+/// Stored versions in the legacy `versions/<ver>` layout.
+fn legacy_versions(versions_root: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut legacy = Vec::new();
+    if !versions_root.is_dir() {
+        return Ok(legacy);
+    }
+    for entry in std::fs::read_dir(versions_root)? {
+        let path = entry?.path();
+        if path.is_dir() && path.join(metadata::LEGACY_FILE).is_file() {
+            legacy.push(path);
+        }
+    }
+    legacy.sort();
+    Ok(legacy)
+}
+
+/// Migrate the storage directory to the current layout if it is older.
+///
+/// Returns `None` when nothing needed migrating. Safe to call on every start.
+pub fn migrate_storage(storage_dir: &Path) -> Result<Option<MigrationReport>, UpdaterError> {
+    let layout = storage_version(storage_dir);
+    if layout.is_some_and(|version| version >= STORAGE_VERSION) {
+        return Ok(None);
+    }
+
+    let versions_root = storage_dir.join("versions");
+    let mut migrated = match layout {
+        Some(version) if version >= TOOL_DIR_LAYOUT => Vec::new(),
+        _ => move_to_tool_dir(storage_dir, &versions_root)?,
+    };
+    migrated.extend(split_metadata(&versions_root.join(TOOL_DIR))?);
+    migrated.extend(upgrade_releases(&versions_root.join(TOOL_DIR))?);
+    migrated.sort();
+    migrated.dedup();
+
+    // Fresh or already migrated storage only needs the marker
+    if storage_dir.is_dir() {
+        write_marker(storage_dir)?;
+    }
+    Ok((!migrated.is_empty()).then_some(MigrationReport { migrated }))
+}
+
+/// Move layout 1 versions into `versions/opencode/`, returning the versions moved.
+fn move_to_tool_dir(storage_dir: &Path, versions_root: &Path) -> Result<Vec<String>, UpdaterError> {
+    let legacy = legacy_versions(versions_root)?;
+    if legacy.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let staging = storage_dir.join(STAGING_DIR);
+    let result = stage_versions(versions_root, &legacy, &staging);
+    let migrated = match result {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(UpdaterError::StorageError(format!(
+                "migration failed, the old layout was left unchanged: {}",
+                e
+            )));
+        }
+    };
+
+    // Swap the staged tree in, restoring the old one if that fails
+    let backup = storage_dir.join(LEGACY_DIR);
+    let _ = std::fs::remove_dir_all(&backup);
+    std::fs::rename(versions_root, &backup)?;
+    if let Err(e) = std::fs::rename(&staging, versions_root) {
+        std::fs::rename(&backup, versions_root)?;
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(UpdaterError::StorageError(format!(
+            "migration failed, the old layout was left unchanged: {}",
+            e
+        )));
+    }
+
+    repoint_current(storage_dir, versions_root)?;
+    let _ = std::fs::remove_dir_all(&backup);
+
+    Ok(migrated)
+}
+
+/// Split the `metadata.json` of every version in `tool_dir`, returning the
+/// versions split.
+///
+/// A `metadata.json` that doesn't parse is left alone; that directory is
+/// then reported as a foreign entry rather than blocking startup.
+fn split_metadata(tool_dir: &Path) -> Result<Vec<String>, UpdaterError> {
+    let mut split = Vec::new();
+    if !tool_dir.is_dir() {
+        return Ok(split);
+    }
+    for version_dir in legacy_versions(tool_dir)? {
+        match metadata::split_legacy(&version_dir, METADATA_SCHEMA_VERSION) {
+            Ok(()) => split.push(entry_name(&version_dir)?),
+            Err(e) => crate::reporter::warn(
+                "migration",
+                &format!(
+                    "left the metadata of {} as it was: {}",
+                    version_dir.display(),
+                    e
+                ),
+            ),
+        }
+    }
+    Ok(split)
+}
+
+/// Bring the `release.json` of every older version in `tool_dir` up to the
+/// current schema, returning the versions rewritten.
+///
+/// Like [`split_metadata`], a version that can't be read is left alone.
+fn upgrade_releases(tool_dir: &Path) -> Result<Vec<String>, UpdaterError> {
+    let mut upgraded = Vec::new();
+    if !tool_dir.is_dir() {
+        return Ok(upgraded);
+    }
+    for entry in std::fs::read_dir(tool_dir)? {
+        let version_dir = entry?.path();
+        if !version_dir.join(metadata::RELEASE_FILE).is_file() {
+            continue;
+        }
+        match metadata::upgrade_release(&version_dir, METADATA_SCHEMA_VERSION) {
+            Ok(true) => upgraded.push(entry_name(&version_dir)?),
+            Ok(false) => {}
+            Err(e) => crate::reporter::warn(
+                "migration",
+                &format!(
+                    "left the metadata of {} as it was: {}",
+                    version_dir.display(),
+                    e
+                ),
+            ),
+        }
+    }
+    Ok(upgraded)
+}
+
+/// Copy legacy versions into `staging/opencode/<ver>` with current metadata.
+fn stage_versions(
+    versions_root: &Path,
+    legacy: &[PathBuf],
+    staging: &Path,
+) -> Result<Vec<String>, UpdaterError> {
+    if staging.exists() {
+        // Left over from an interrupted migration
+        std::fs::remove_dir_all(staging)?;
+    }
+    let tool_dir = staging.join(TOOL_DIR);
+    std::fs::create_dir_all(&tool_dir)?;
+
+    // Anything else in versions/ (e.g. an existing tool directory) moves along unchanged
+    for entry in std::fs::read_dir(versions_root)? {
+        let path = entry?.path();
+        if !legacy.contains(&path) {
+            copy_recursive(&path, &staging.join(entry_name(&path)?))?;
+        }
+    }
+
+    let mut migrated = Vec::new();
+    for version_dir in legacy {
+        let name = entry_name(version_dir)?;
+        let target = tool_dir.join(&name);
+        copy_recursive(version_dir, &target)?;
+
+        metadata::split_legacy(&target, METADATA_SCHEMA_VERSION)
+            .map_err(|e| UpdaterError::StorageError(format!("{}: {}", target.display(), e)))?;
+
+        migrated.push(name);
+    }
+    Ok(migrated)
+}
+
+/// Point `current` at the migrated copy of the version it referenced.
+fn repoint_current(storage_dir: &Path, versions_root: &Path) -> Result<(), std::io::Error> {
+    let current = storage_dir.join("current");
+    let Ok(target) = std::fs::read_link(&current) else {
+        return Ok(());
+    };
+    let Some(version) = target.file_name() else {
+        return Ok(());
+    };
+    if target.parent() == Some(versions_root) {
+        std::fs::remove_file(&current)?;
+        std::os::unix::fs::symlink(versions_root.join(TOOL_DIR).join(version), &current)?;
+    }
+    Ok(())
+}
+
+/// Record the current layout version in the storage directory.
+pub(crate) fn write_marker(storage_dir: &Path) -> Result<(), std::io::Error> {
+    std::fs::write(
+        storage_dir.join(STORAGE_VERSION_FILE),
+        format!("{}\n", STORAGE_VERSION),
+    )
+}
+
+fn entry_name(path: &Path) -> Result<String, UpdaterError> {
+    Ok(path
+        .file_name()
+        .ok_or_else(|| UpdaterError::StorageError(format!("{} has no file name", path.display())))?
+        .to_string_lossy()
+        .into_owned())
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> Result<(), std::io::Error> {
+    if source.is_dir() {
+        std::fs::create_dir_all(target)?;
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(source, target).map(|_| ())
+    }
+}
@@ -0,0 +1,128 @@
+//! Downloading release archives from a mirror of GitHub's download host.
+//!
+//! Where GitHub's downloads are blocked, a mirror such as an Artifactory
+//! remote repository carries the same files under the same paths. With
+//! `--mirror BASE_URL`, or `mirror` in the config file, a release archive at
+//! `https://github.com/sst/opencode/releases/download/v1.0.0/opencode.zip`
+//! is fetched from `BASE_URL/sst/opencode/releases/download/v1.0.0/opencode.zip`
+//! instead. When the mirror answers 404 or 5xx, the archive comes from
+//! GitHub after all.
+//!
+//! Only the archive is mirrored. Its checksums are still fetched from the
+//! release itself, so an archive the mirror altered fails verification. A
+//! release that publishes no checksum, and has no digest on GitHub, gives
+//! nothing to catch that with, so its archive is downloaded from GitHub.
+//! For the same reason a plain-HTTP mirror is only used with
+//! `--allow-insecure-source`.
+
+use crate::{UpdaterError, sanitize_terminal_text};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+use ureq::http::Uri;
+
+/// Base URL of a mirror, without a trailing slash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mirror(String);
+
+impl FromStr for Mirror {
+    type Err = String;
+
+    /// An `http://` or `https://` URL with a host, optionally with a path.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let base = value.trim().trim_end_matches('/');
+        let invalid = || {
+            format!(
+                "'{}' is not a mirror base URL like https://mirror.example.com/github",
+                value
+            )
+        };
+        let uri: Uri = base.parse().map_err(|_| invalid())?;
+        if !matches!(uri.scheme_str(), Some("http" | "https"))
+            || uri.host().is_none_or(str::is_empty)
+            || uri.query().is_some()
+        {
+            return Err(invalid());
+        }
+        Ok(Mirror(base.to_string()))
+    }
+}
+
+impl std::fmt::Display for Mirror {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Mirror {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mirror {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Mirror {
+    /// Whether the mirror is reached over HTTPS.
+    pub fn is_https(&self) -> bool {
+        self.0
+            .parse::<Uri>()
+            .is_ok_and(|uri| uri.scheme_str() == Some("https"))
+    }
+
+    /// `url` with its scheme and host replaced by the mirror's base, keeping
+    /// the path and query; `None` for a URL without a path to keep.
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        let uri: Uri = url.parse().ok()?;
+        uri.host()?;
+        let path_and_query = uri.path_and_query()?.as_str();
+        (path_and_query != "/").then(|| format!("{}{}", self.0, path_and_query))
+    }
+}
+
+/// Whether a download from the mirror that failed with `error` should be
+/// made from the original URL instead: the mirror answered 404 or 5xx.
+pub fn falls_back(error: &UpdaterError) -> bool {
+    matches!(
+        error,
+        UpdaterError::Http {
+            source: ureq::Error::StatusCode(404 | 500..=599),
+            ..
+        }
+    )
+}
+
+/// Download `filename` from the mirror's copy of `url` with `from_mirror`,
+/// if there is a mirror, or with `from_origin` when there is none or it
+/// doesn't have the file.
+pub fn with_fallback<T>(
+    mirror: Option<&Mirror>,
+    url: &str,
+    filename: &str,
+    from_mirror: impl FnOnce(&str) -> Result<T, UpdaterError>,
+    from_origin: impl FnOnce() -> Result<T, UpdaterError>,
+) -> Result<T, UpdaterError> {
+    let Some(mirrored) = mirror.and_then(|mirror| mirror.rewrite(url)) else {
+        return from_origin();
+    };
+    match from_mirror(&mirrored) {
+        Err(e) if falls_back(&e) => {
+            crate::reporter::warn(
+                "mirror",
+                &format!(
+                    "{}; downloading {} from {} instead",
+                    e,
+                    sanitize_terminal_text(filename),
+                    sanitize_terminal_text(&crate::trace::redact_url(url))
+                ),
+            );
+            from_origin()
+        }
+        result => result,
+    }
+}
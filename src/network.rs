@@ -0,0 +1,268 @@
+//! Per-run accounting of GitHub API requests and downloaded bytes.
+//!
+//! The shared request helpers record into a thread-local tally, so every
+//! call site is counted without threading a counter through. A run reads
+//! the tally before and after and keeps the difference.
+//!
+//! The rate limit GitHub reports with each response is kept as well, so
+//! `doctor` and `-v` can show how much of it is left, and a warning is shown
+//! once when it runs low.
+//!
+//! A run can also be given a budget of API requests (`--api-budget`). Every
+//! helper calling the API spends from it first and is refused once it is
+//! used up, so callers with a way around the API can tell and take it.
+
+use crate::units::{format_count, format_size};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+/// Remaining requests below which the rate limit counts as low.
+pub const LOW_RATE_LIMIT: u64 = 5;
+
+/// GitHub's API rate limit, as reported by its `x-ratelimit-*` headers.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub remaining: u64,
+    pub limit: u64,
+    /// When the window resets, on the local clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+impl RateLimit {
+    /// Read the rate limit headers of a response, if it has them.
+    ///
+    /// `x-ratelimit-reset` is in server time, so it is converted using the
+    /// response's `Date` header rather than the local clock.
+    pub fn from_response<B>(response: &ureq::http::Response<B>) -> Option<Self> {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<i64>().ok())
+        };
+        let remaining = u64::try_from(header("x-ratelimit-remaining")?).ok()?;
+        let limit = u64::try_from(header("x-ratelimit-limit")?).ok()?;
+        let resets_at = header("x-ratelimit-reset").map(|reset| {
+            let server_now = crate::clock::server_date(response).unwrap_or_else(Utc::now);
+            let wait = crate::clock::rate_limit_wait(reset, server_now);
+            Utc::now() + chrono::TimeDelta::seconds(wait.as_secs() as i64)
+        });
+        Some(Self {
+            remaining,
+            limit,
+            resets_at,
+        })
+    }
+
+    /// Whether fewer than [`LOW_RATE_LIMIT`] requests are left.
+    pub fn is_low(&self) -> bool {
+        self.remaining < LOW_RATE_LIMIT
+    }
+
+    /// Warning to show when the rate limit is low.
+    pub fn warning(&self) -> Option<String> {
+        self.is_low().then(|| {
+            format!(
+                "only {} of the hourly GitHub API limit left; requests made with a GitHub token get a far higher limit",
+                self
+            )
+        })
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} requests remaining",
+            format_count(self.remaining),
+            format_count(self.limit)
+        )?;
+        if let Some(resets_at) = self.resets_at {
+            write!(
+                f,
+                ", resets {}",
+                resets_at.with_timezone(&chrono::Local).format("%H:%M")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Network usage of one run.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkUsage {
+    /// Requests made to the GitHub API.
+    pub api_requests: u64,
+    /// Lookups answered from the local cache (or a 304) instead of the network.
+    pub cached_responses: u64,
+    /// Bytes of release assets downloaded.
+    pub downloaded_bytes: u64,
+    /// Requests left in the current rate-limit window, as last reported by GitHub.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_remaining: Option<u64>,
+    /// Size of the rate-limit window, as last reported by GitHub.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_limit: Option<u64>,
+    /// When the rate-limit window resets, on the local clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_reset: Option<DateTime<Utc>>,
+}
+
+impl NetworkUsage {
+    /// Usage accumulated since `earlier` was taken.
+    pub fn since(&self, earlier: &NetworkUsage) -> NetworkUsage {
+        NetworkUsage {
+            api_requests: self.api_requests - earlier.api_requests,
+            cached_responses: self.cached_responses - earlier.cached_responses,
+            downloaded_bytes: self.downloaded_bytes - earlier.downloaded_bytes,
+            rate_limit_remaining: self.rate_limit_remaining,
+            rate_limit_limit: self.rate_limit_limit,
+            rate_limit_reset: self.rate_limit_reset,
+        }
+    }
+
+    /// The rate limit last reported by GitHub, if any response carried one.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        Some(RateLimit {
+            remaining: self.rate_limit_remaining?,
+            limit: self.rate_limit_limit?,
+            resets_at: self.rate_limit_reset,
+        })
+    }
+}
+
+impl std::fmt::Display for NetworkUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} API call{}",
+            format_count(self.api_requests),
+            if self.api_requests == 1 { "" } else { "s" }
+        )?;
+        match self.rate_limit() {
+            Some(rate_limit) if rate_limit.resets_at.is_some() => write!(f, " ({})", rate_limit)?,
+            Some(rate_limit) => write!(
+                f,
+                " ({}/{} remaining this hour)",
+                format_count(rate_limit.remaining),
+                format_count(rate_limit.limit)
+            )?,
+            None => {}
+        }
+        if self.cached_responses > 0 {
+            write!(f, ", {} from cache", format_count(self.cached_responses))?;
+        }
+        write!(f, ", {} downloaded", format_size(self.downloaded_bytes))
+    }
+}
+
+thread_local! {
+    static USAGE: Cell<NetworkUsage> = const {
+        Cell::new(NetworkUsage {
+            api_requests: 0,
+            cached_responses: 0,
+            downloaded_bytes: 0,
+            rate_limit_remaining: None,
+            rate_limit_limit: None,
+            rate_limit_reset: None,
+        })
+    };
+    static WARNED_LOW: Cell<bool> = const { Cell::new(false) };
+    /// The budget, and the request count at which it is used up.
+    static BUDGET: Cell<Option<(u64, u64)>> = const { Cell::new(None) };
+}
+
+fn update(f: impl FnOnce(&mut NetworkUsage)) {
+    USAGE.with(|usage| {
+        let mut value = usage.get();
+        f(&mut value);
+        usage.set(value);
+    });
+}
+
+/// Usage recorded on this thread so far.
+pub fn usage() -> NetworkUsage {
+    USAGE.with(Cell::get)
+}
+
+/// Record a GitHub API response, including the rate limit it reports.
+///
+/// The first time the rate limit is low, a warning is printed.
+pub(crate) fn record_api_response<B>(response: &ureq::http::Response<B>) {
+    let rate_limit = RateLimit::from_response(response);
+    let not_modified = response.status().as_u16() == 304;
+    update(|usage| {
+        usage.api_requests += 1;
+        if not_modified {
+            usage.cached_responses += 1;
+        }
+        if let Some(rate_limit) = rate_limit {
+            usage.rate_limit_remaining = Some(rate_limit.remaining);
+            usage.rate_limit_limit = Some(rate_limit.limit);
+            usage.rate_limit_reset = rate_limit.resets_at;
+        }
+    });
+    if let Some(warning) = rate_limit.and_then(|rate_limit| rate_limit.warning())
+        && !WARNED_LOW.replace(true)
+    {
+        crate::reporter::warn("rate-limit", &warning);
+    }
+}
+
+/// Allow at most `budget` more API requests on this thread, or any number.
+pub fn set_api_budget(budget: Option<u64>) {
+    let spent = usage().api_requests;
+    BUDGET.set(budget.map(|budget| (budget, spent + budget)));
+}
+
+/// API requests left in the budget, if there is one.
+pub fn api_budget_left() -> Option<u64> {
+    let (_, used_up_at) = BUDGET.get()?;
+    Some(used_up_at.saturating_sub(usage().api_requests))
+}
+
+/// Check that the budget allows another API request, to `url`.
+///
+/// Called before the request is made; [`record_api_response`] spends it.
+pub(crate) fn spend_api_request(url: &str) -> Result<(), crate::UpdaterError> {
+    match (BUDGET.get(), api_budget_left()) {
+        (Some((budget, _)), Some(0)) => Err(crate::UpdaterError::ApiBudgetExhausted(format!(
+            "all {} GitHub API request{} allowed by --api-budget are spent; not requesting {}",
+            format_count(budget),
+            if budget == 1 { "" } else { "s" },
+            url
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Record a lookup served from the local cache.
+pub(crate) fn record_cache_hit() {
+    update(|usage| usage.cached_responses += 1);
+}
+
+/// Record downloaded asset bytes.
+pub(crate) fn record_download(bytes: u64) {
+    update(|usage| usage.downloaded_bytes += bytes);
+}
+
+/// Add `delta`, the usage of a helper thread, to this thread's tally.
+///
+/// A rate limit it saw replaces the one recorded here, as the later report.
+pub(crate) fn record_usage(delta: &NetworkUsage) {
+    update(|usage| {
+        usage.api_requests += delta.api_requests;
+        usage.cached_responses += delta.cached_responses;
+        usage.downloaded_bytes += delta.downloaded_bytes;
+        if delta.rate_limit().is_some() {
+            usage.rate_limit_remaining = delta.rate_limit_remaining;
+            usage.rate_limit_limit = delta.rate_limit_limit;
+            usage.rate_limit_reset = delta.rate_limit_reset;
+        }
+    });
+}
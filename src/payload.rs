@@ -0,0 +1,264 @@
+//! Sanity checks on downloaded release assets.
+//!
+//! A captive portal or misconfigured proxy answers any URL with its own HTML
+//! page, which would otherwise surface as a baffling extraction error (or,
+//! without a published checksum, not at all). Archive downloads are checked
+//! for an HTML content type while still at the headers, and for a known
+//! archive signature once downloaded.
+//!
+//! Redirects are followed by [`send`] one hop at a time rather than by the
+//! client, so each hop is a request of its own that the middleware sees, and
+//! a hop to anything but HTTPS is refused before it is requested.
+
+use crate::UpdaterError;
+use ureq::http::header::{AUTHORIZATION, COOKIE, LOCATION};
+use ureq::http::{Request, Response, Uri};
+use ureq::typestate::WithoutBody;
+use ureq::{Agent, Body, RequestBuilder};
+
+/// Redirects followed before a request is abandoned as a loop.
+pub const MAX_REDIRECTS: u32 = 5;
+
+/// File name suffixes treated as archives.
+const ARCHIVE_SUFFIXES: [&str; 7] = [
+    ".zip", ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2",
+];
+
+/// Leading bytes of the archive and compression formats releases use.
+const ARCHIVE_MAGIC: [&[u8]; 5] = [
+    b"PK\x03\x04",
+    b"PK\x05\x06",
+    b"\x1f\x8b",
+    b"\xfd7zXZ\x00",
+    b"BZh",
+];
+
+/// What a download turned out to contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// Starts with a known archive signature.
+    Archive,
+    /// A web page, e.g. a captive portal login.
+    Html,
+    /// Neither; possibly truncated or a format we don't know.
+    Unknown,
+}
+
+/// Whether `filename` names an archive whose payload can be sniffed.
+pub fn is_archive_name(filename: &str) -> bool {
+    ARCHIVE_SUFFIXES
+        .iter()
+        .any(|suffix| filename.ends_with(suffix))
+}
+
+/// Whether a `Content-Type` header announces a web page.
+pub fn is_html_content_type(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    media_type.eq_ignore_ascii_case("text/html")
+        || media_type.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
+/// Leading bytes of a payload read to [`classify`] one kept on disk.
+pub const HEAD_LEN: usize = 1024;
+
+/// Classify a payload by its first bytes.
+pub fn classify(bytes: &[u8]) -> PayloadKind {
+    if ARCHIVE_MAGIC.iter().any(|magic| bytes.starts_with(magic)) {
+        return PayloadKind::Archive;
+    }
+    let start = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let head = String::from_utf8_lossy(&bytes[start..bytes.len().min(start + 256)]).to_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") || head.starts_with("<head")
+    {
+        PayloadKind::Html
+    } else {
+        PayloadKind::Unknown
+    }
+}
+
+/// Error for a web page served in place of `filename`.
+pub fn html_error(filename: &str) -> UpdaterError {
+    UpdaterError::NetworkError(format!(
+        "received an HTML page instead of the release asset {} — are you behind a captive portal or proxy?",
+        crate::sanitize_terminal_text(filename)
+    ))
+}
+
+/// Reject an archive download that is a web page, or unrecognisable and unverified.
+///
+/// A payload with an unknown signature is accepted when `verified` by a
+/// published checksum, since the checksum already proves it is the asset.
+pub fn check_archive(bytes: &[u8], filename: &str, verified: bool) -> Result<(), UpdaterError> {
+    if !is_archive_name(filename) {
+        return Ok(());
+    }
+    match classify(bytes) {
+        PayloadKind::Archive => Ok(()),
+        PayloadKind::Html => Err(html_error(filename)),
+        PayloadKind::Unknown if verified => Ok(()),
+        PayloadKind::Unknown => {
+            let head: Vec<String> = bytes
+                .iter()
+                .take(4)
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            Err(UpdaterError::NetworkError(format!(
+                "{} doesn't look like an archive (starts with {}) and has no checksum to verify it",
+                crate::sanitize_terminal_text(filename),
+                if head.is_empty() {
+                    "nothing".to_string()
+                } else {
+                    head.join(" ")
+                }
+            )))
+        }
+    }
+}
+
+/// Error for a request abandoned after [`MAX_REDIRECTS`] redirects.
+pub fn redirect_loop_error(url: &str) -> UpdaterError {
+    UpdaterError::NetworkError(format!(
+        "redirect loop: {} redirected more than {} times; a proxy or captive portal may be intercepting requests",
+        url, MAX_REDIRECTS
+    ))
+}
+
+/// Where a redirect from `from` to `location` leads, resolved against
+/// `from` if relative; refused unless it is HTTPS.
+pub fn next_hop(from: &Uri, location: &str) -> Result<Uri, UpdaterError> {
+    let from_url = crate::sanitize_terminal_text(&crate::trace::redact_url(&from.to_string()));
+    let location = location.split('#').next().unwrap_or_default();
+    let hop = resolve(from, location).ok_or_else(|| {
+        UpdaterError::NetworkError(format!(
+            "{} redirected to {}, which is not a valid URL",
+            from_url,
+            crate::sanitize_terminal_text(location)
+        ))
+    })?;
+    if hop.scheme_str() != Some("https") {
+        return Err(UpdaterError::NetworkError(format!(
+            "{} redirected to {}, which is not HTTPS; refusing to follow it over an unencrypted connection",
+            from_url,
+            crate::sanitize_terminal_text(&crate::trace::redact_url(&hop.to_string()))
+        )));
+    }
+    Ok(hop)
+}
+
+/// `location` made absolute against `from`.
+fn resolve(from: &Uri, location: &str) -> Option<Uri> {
+    if let Ok(uri) = location.parse::<Uri>()
+        && uri.scheme().is_some()
+    {
+        return Some(uri);
+    }
+    let scheme = from.scheme_str()?;
+    let authority = from.authority()?;
+    let absolute = if let Some(rest) = location.strip_prefix("//") {
+        format!("{}://{}", scheme, rest)
+    } else if location.starts_with('/') {
+        format!("{}://{}{}", scheme, authority, location)
+    } else {
+        let dir = from.path().rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{}://{}{}/{}", scheme, authority, dir, location)
+    };
+    absolute.parse().ok()
+}
+
+/// The redirects one request has been through.
+#[derive(Debug, Clone)]
+pub struct Redirects {
+    requested: Uri,
+    current: Uri,
+    followed: u32,
+}
+
+impl Redirects {
+    /// A request to `uri`, not yet redirected.
+    pub fn new(uri: Uri) -> Self {
+        Self {
+            requested: uri.clone(),
+            current: uri,
+            followed: 0,
+        }
+    }
+
+    /// The URL to request next for a redirect to `location`, checked by
+    /// [`next_hop`]; after [`MAX_REDIRECTS`] of them, a loop is reported.
+    pub fn follow(&mut self, location: &str) -> Result<Uri, UpdaterError> {
+        if self.followed == MAX_REDIRECTS {
+            return Err(redirect_loop_error(&self.requested.to_string()));
+        }
+        let hop = next_hop(&self.current, location)?;
+        self.followed += 1;
+        self.current = hop.clone();
+        Ok(hop)
+    }
+}
+
+/// The `Location` of a response that redirects.
+fn redirect_location(response: &Response<Body>) -> Option<&str> {
+    matches!(response.status().as_u16(), 301 | 302 | 303 | 307 | 308)
+        .then(|| response.headers().get(LOCATION)?.to_str().ok())
+        .flatten()
+}
+
+/// Send `request` with `client`, following its redirects one hop at a time
+/// as [`Redirects`] allows.
+///
+/// Every hop keeps the request's headers and settings, except that
+/// `Authorization` and `Cookie` are only sent to the URL asked for.
+pub fn send(
+    client: &Agent,
+    request: RequestBuilder<WithoutBody>,
+) -> Result<Response<Body>, UpdaterError> {
+    let request = request.config().max_redirects(0).build();
+    let url = request.uri_ref().map(Uri::to_string).unwrap_or_default();
+    let (Some(uri), Some(mut headers), Some(extensions)) = (
+        request.uri_ref().cloned(),
+        request.headers_ref().cloned(),
+        request.extensions_ref().cloned(),
+    ) else {
+        // The client reports what is wrong with the request
+        return request.call().map_err(|e| request_error(&url, e));
+    };
+    let mut response = request.call().map_err(|e| request_error(&url, e))?;
+    headers.remove(AUTHORIZATION);
+    headers.remove(COOKIE);
+    let mut redirects = Redirects::new(uri);
+    while let Some(location) = redirect_location(&response) {
+        let hop = redirects.follow(location)?;
+        let mut next = Request::get(hop.clone())
+            .body(())
+            .map_err(|e| request_error(&url, e.into()))?;
+        *next.headers_mut() = headers.clone();
+        *next.extensions_mut() = extensions.clone();
+        response = client
+            .run(next)
+            .map_err(|e| request_error(&hop.to_string(), e))?;
+    }
+    Ok(response)
+}
+
+/// The error of a request to `url`, keeping the client's error as its
+/// source, and naming redirect loops as such.
+pub fn request_error(url: &str, error: ureq::Error) -> UpdaterError {
+    match error {
+        ureq::Error::TooManyRedirects => redirect_loop_error(url),
+        // Refusals of the allowlist middleware keep their type
+        ureq::Error::Other(other) => match other.downcast::<UpdaterError>() {
+            Ok(refused) => *refused,
+            Err(other) => UpdaterError::Http {
+                url: url.to_string(),
+                source: ureq::Error::Other(other),
+            },
+        },
+        source => UpdaterError::Http {
+            url: url.to_string(),
+            source,
+        },
+    }
+}
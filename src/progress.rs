@@ -0,0 +1,410 @@
+//! Download and install progress display, and Ctrl-C.
+//!
+//! Downloads and the copy to an install target report progress to a
+//! [`ProgressSink`]. With the `progress`
+//! feature (on by default) that is an indicatif bar on stderr; without it the
+//! library builds without indicatif and progress goes to [`NoProgress`].
+//!
+//! Every bar is registered here, the one place that can clear them all. A
+//! bar dropped before it finished, as when a download is unwinding from a
+//! panic, clears itself; [`install_panic_hook`] and
+//! [`install_interrupt_handler`] clear any bar still on screen and show the
+//! cursor again before the panic message prints or the process exits.
+//!
+//! Ctrl-C during [`defer_interrupts`] doesn't exit: it only asks for
+//! cancellation, which the copy to an install target checks between chunks
+//! to remove its half-written file, so a target is never left truncated.
+//!
+//! A download bar shows the speed and time left from a
+//! [`ThroughputEstimator`] the download loop feeds, smoothed so a bursty
+//! link doesn't make them jump, and says how long the download has been
+//! stalled once no bytes arrived for [`STALL_THRESHOLD`].
+
+use crate::UpdaterError;
+use std::io::{IsTerminal, Write};
+#[cfg(feature = "progress")]
+use std::sync::TryLockError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Exit code after Ctrl-C or a termination signal, as a shell reports SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Escape sequence showing the cursor.
+const SHOW_CURSOR: &str = "\x1b[?25h";
+
+/// Time constant of the throughput average: a sample's weight falls to
+/// about a third after this long.
+pub const SMOOTHING_WINDOW: Duration = Duration::from_secs(10);
+
+/// How long no bytes may arrive before a download shows as stalled.
+pub const STALL_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Bars created by [`download_progress`]; dropped bars fail to upgrade.
+#[cfg(feature = "progress")]
+static BARS: Mutex<Vec<indicatif::WeakProgressBar>> = Mutex::new(Vec::new());
+
+/// Sections running under [`defer_interrupts`].
+static DEFERRING: AtomicUsize = AtomicUsize::new(0);
+
+/// Set by Ctrl-C during [`defer_interrupts`].
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Receives progress of a transfer.
+pub trait ProgressSink {
+    /// Bytes transferred so far.
+    fn set_position(&self, position: u64);
+    /// The transfer is complete.
+    fn finish(&self, message: String);
+    /// Whether the transfer should stop, checked between chunks.
+    fn cancelled(&self) -> bool {
+        cancel_requested()
+    }
+}
+
+/// Discards progress.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn set_position(&self, _position: u64) {}
+    fn finish(&self, _message: String) {}
+}
+
+#[cfg(feature = "progress")]
+impl ProgressSink for indicatif::ProgressBar {
+    fn set_position(&self, position: u64) {
+        indicatif::ProgressBar::set_position(self, position);
+    }
+
+    fn finish(&self, message: String) {
+        self.finish_with_message(message);
+    }
+}
+
+/// A registered bar, cleared from the terminal if dropped unfinished.
+#[cfg(feature = "progress")]
+struct Bar(indicatif::ProgressBar);
+
+#[cfg(feature = "progress")]
+impl ProgressSink for Bar {
+    fn set_position(&self, position: u64) {
+        self.0.set_position(position);
+    }
+
+    fn finish(&self, message: String) {
+        self.0.finish_with_message(message);
+    }
+}
+
+#[cfg(feature = "progress")]
+impl Drop for Bar {
+    fn drop(&mut self) {
+        if !self.0.is_finished() {
+            self.0.finish_and_clear();
+        }
+    }
+}
+
+/// Run `f` on the registered bars, unless a thread that panicked holding
+/// them is still in the middle of it.
+#[cfg(feature = "progress")]
+fn with_bars(f: impl FnOnce(&mut Vec<indicatif::WeakProgressBar>)) {
+    let mut bars = match BARS.try_lock() {
+        Ok(bars) => bars,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    f(&mut bars);
+}
+
+/// Bars still drawing on the terminal.
+pub fn active_bars() -> usize {
+    #[cfg(feature = "progress")]
+    {
+        let mut active = 0;
+        with_bars(|bars| {
+            active = bars
+                .iter()
+                .filter_map(indicatif::WeakProgressBar::upgrade)
+                .filter(|bar| !bar.is_finished())
+                .count();
+        });
+        active
+    }
+    #[cfg(not(feature = "progress"))]
+    {
+        0
+    }
+}
+
+/// Clear every unfinished bar and show the cursor again.
+pub fn restore_terminal() {
+    #[cfg(feature = "progress")]
+    with_bars(|bars| {
+        for bar in bars.drain(..).filter_map(|bar| bar.upgrade()) {
+            if !bar.is_finished() {
+                bar.finish_and_clear();
+            }
+        }
+    });
+    let mut stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        let _ = stderr.write_all(SHOW_CURSOR.as_bytes());
+        let _ = stderr.flush();
+    }
+}
+
+/// Restore the terminal before the panic message is printed.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        previous(info);
+    }));
+}
+
+/// Restore the terminal and exit with [`INTERRUPTED_EXIT_CODE`] on Ctrl-C,
+/// SIGTERM or SIGHUP, or only request cancellation during [`defer_interrupts`].
+///
+/// Can only be installed once per process.
+pub fn install_interrupt_handler() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if DEFERRING.load(Ordering::SeqCst) > 0 {
+            if !CANCEL_REQUESTED.swap(true, Ordering::SeqCst) {
+                eprintln!(
+                    "\nInterrupted; stopping once the install in progress is finished or undone"
+                );
+            }
+            return;
+        }
+        restore_terminal();
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    })
+}
+
+/// Whether Ctrl-C asked for cancellation during [`defer_interrupts`].
+pub fn cancel_requested() -> bool {
+    CANCEL_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Run `f`, turning Ctrl-C into a cancellation request it can act on.
+///
+/// If `f` succeeds although cancellation was requested, as when the copy had
+/// already finished, this fails with [`crate::UpdaterError::Interrupted`] so
+/// the run stops there.
+pub fn defer_interrupts<T>(f: impl FnOnce() -> Result<T, UpdaterError>) -> Result<T, UpdaterError> {
+    DEFERRING.fetch_add(1, Ordering::SeqCst);
+    let result = f();
+    DEFERRING.fetch_sub(1, Ordering::SeqCst);
+    match result {
+        Ok(_) if cancel_requested() => Err(crate::UpdaterError::Interrupted(
+            "stopped after the install in progress was finished".to_string(),
+        )),
+        result => result,
+    }
+}
+
+/// Exponential moving average of a transfer's throughput.
+///
+/// Fed the position of the transfer now and then with [`record`](Self::record);
+/// each sample's rate is weighted by how long it covers against
+/// [`SMOOTHING_WINDOW`], so uneven sampling doesn't skew the average.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThroughputEstimator {
+    /// The latest sample: when, and the position then.
+    last: (Instant, u64),
+    /// When the position last moved.
+    last_progress: Instant,
+    /// Smoothed bytes per second, once there is a sample.
+    rate: Option<f64>,
+}
+
+impl ThroughputEstimator {
+    /// An estimator for a transfer that started at `started`.
+    pub fn new(started: Instant) -> Self {
+        Self {
+            last: (started, 0),
+            last_progress: started,
+            rate: None,
+        }
+    }
+
+    /// The transfer was at `position` bytes at `at`.
+    pub fn record(&mut self, at: Instant, position: u64) {
+        let (last_at, last_position) = self.last;
+        let elapsed = at.saturating_duration_since(last_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+        let sample = position.saturating_sub(last_position) as f64 / elapsed;
+        let weight = 1.0 - (-elapsed / SMOOTHING_WINDOW.as_secs_f64()).exp();
+        self.rate = Some(match self.rate {
+            Some(rate) => rate + weight * (sample - rate),
+            None => sample,
+        });
+        if position > last_position {
+            self.last_progress = at;
+        }
+        self.last = (at, position);
+    }
+
+    /// Smoothed throughput in bytes per second, if known.
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        self.rate
+    }
+
+    /// Time left to reach `length` bytes at the smoothed throughput.
+    pub fn eta(&self, length: u64) -> Option<Duration> {
+        let rate = self.rate.filter(|rate| *rate > 0.0)?;
+        let remaining = length.checked_sub(self.last.1).filter(|_| length > 0)?;
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// How long no bytes have arrived as of `now`, once that exceeds
+    /// [`STALL_THRESHOLD`].
+    pub fn stalled_for(&self, now: Instant) -> Option<Duration> {
+        let idle = now.saturating_duration_since(self.last_progress);
+        (idle > STALL_THRESHOLD).then_some(idle)
+    }
+
+    /// Speed and time left, or how long the transfer has been stalled, as of
+    /// `now` for a transfer of `length` bytes (0 if unknown).
+    pub fn describe(&self, now: Instant, length: u64) -> String {
+        if let Some(idle) = self.stalled_for(now) {
+            return format!("stalled for {}s", idle.as_secs());
+        }
+        let Some(rate) = self.rate else {
+            return "starting".to_string();
+        };
+        match self.eta(length) {
+            Some(eta) => format!(
+                "{}, {} left",
+                crate::units::format_rate(rate),
+                format_eta(eta)
+            ),
+            None => crate::units::format_rate(rate),
+        }
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` from an hour on.
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Progress display for downloading `filename` of `length` bytes (0 if
+/// unknown), showing the throughput the download loop records in `throughput`.
+pub fn download_progress(
+    length: u64,
+    filename: &str,
+    throughput: Arc<Mutex<ThroughputEstimator>>,
+) -> Box<dyn ProgressSink> {
+    registered_bar(
+        length,
+        DOWNLOAD_TEMPLATE,
+        format!("Downloading {}", filename),
+        Some(throughput),
+    )
+}
+
+/// Progress display for attempt `attempt` of `attempts` at downloading
+/// `filename`, after the ones before it failed; the bar says which attempt
+/// it is.
+pub fn download_retry_progress(
+    length: u64,
+    filename: &str,
+    throughput: Arc<Mutex<ThroughputEstimator>>,
+    attempt: u32,
+    attempts: u32,
+) -> Box<dyn ProgressSink> {
+    registered_bar(
+        length,
+        &format!("{} attempt {}/{}", DOWNLOAD_TEMPLATE, attempt, attempts),
+        format!("Downloading {}", filename),
+        Some(throughput),
+    )
+}
+
+const DOWNLOAD_TEMPLATE: &str =
+    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {size}/{total_size} ({throughput})";
+
+/// Progress display for copying `length` bytes to the install target `dest`.
+pub fn install_progress(length: u64, dest: &std::path::Path) -> Box<dyn ProgressSink> {
+    registered_bar(
+        length,
+        "{spinner:.green} Installing [{bar:40.cyan/blue}] {size}/{total_size} {wide_msg}",
+        format!("to {}", dest.display()),
+        None,
+    )
+}
+
+/// A bar drawn with `template`, registered to be cleared on exit.
+///
+/// `{throughput}` in the template is drawn from `throughput` on every tick,
+/// so a stall shows while the download loop is blocked reading.
+#[cfg_attr(not(feature = "progress"), allow(unused_variables))]
+fn registered_bar(
+    length: u64,
+    template: &str,
+    message: String,
+    throughput: Option<Arc<Mutex<ThroughputEstimator>>>,
+) -> Box<dyn ProgressSink> {
+    #[cfg(feature = "progress")]
+    {
+        use crate::units;
+        use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+
+        let progress = ProgressBar::new(length);
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template(template)
+                .unwrap()
+                .with_key(
+                    "size",
+                    |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                        let _ = w.write_str(&units::format_size(state.pos()));
+                    },
+                )
+                .with_key(
+                    "total_size",
+                    |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                        let _ = w.write_str(
+                            &state
+                                .len()
+                                .map_or_else(|| "?".to_string(), units::format_size),
+                        );
+                    },
+                )
+                .with_key(
+                    "throughput",
+                    move |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                        if let Some(throughput) = &throughput
+                            && let Ok(throughput) = throughput.lock()
+                        {
+                            let length = state.len().unwrap_or(0);
+                            let _ = w.write_str(&throughput.describe(Instant::now(), length));
+                        }
+                    },
+                )
+                .progress_chars("#>-"),
+        );
+        progress.set_message(message);
+        progress.enable_steady_tick(crate::PROGRESS_INTERVAL);
+        with_bars(|bars| {
+            bars.retain(|bar| bar.upgrade().is_some());
+            bars.push(progress.downgrade());
+        });
+        Box::new(Bar(progress))
+    }
+    #[cfg(not(feature = "progress"))]
+    {
+        Box::new(NoProgress)
+    }
+}
@@ -0,0 +1,90 @@
+//! Questions asked at the terminal.
+//!
+//! Prompts go through the [`Prompter`] trait. With the `interactive` feature
+//! (on by default) they are shown with dialoguer; without it the library
+//! builds without dialoguer and every prompt fails with an error naming the
+//! flag that answers it instead.
+
+use crate::UpdaterError;
+
+/// Asks the user to confirm or choose.
+pub trait Prompter {
+    /// Ask a yes/no question.
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool, UpdaterError>;
+    /// Ask to pick one of `items`; returns its index.
+    fn select(&self, prompt: &str, items: &[String], default: usize)
+    -> Result<usize, UpdaterError>;
+}
+
+/// Prompts on the terminal with dialoguer.
+#[cfg(feature = "interactive")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalPrompter;
+
+#[cfg(feature = "interactive")]
+impl Prompter for TerminalPrompter {
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool, UpdaterError> {
+        Ok(
+            dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt(prompt)
+                .default(default)
+                .interact()
+                .map_err(std::io::Error::from)?,
+        )
+    }
+
+    fn select(
+        &self,
+        prompt: &str,
+        items: &[String],
+        default: usize,
+    ) -> Result<usize, UpdaterError> {
+        Ok(
+            dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt(prompt)
+                .default(default)
+                .items(items)
+                .interact()
+                .map_err(std::io::Error::from)?,
+        )
+    }
+}
+
+/// Refuses every prompt, for builds without the `interactive` feature.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoPrompter;
+
+impl Prompter for NoPrompter {
+    fn confirm(&self, prompt: &str, _default: bool) -> Result<bool, UpdaterError> {
+        Err(unavailable(prompt))
+    }
+
+    fn select(
+        &self,
+        prompt: &str,
+        _items: &[String],
+        _default: usize,
+    ) -> Result<usize, UpdaterError> {
+        Err(unavailable(prompt))
+    }
+}
+
+fn unavailable(prompt: &str) -> UpdaterError {
+    UpdaterError::Other(format!(
+        "can't ask \"{}\": built without the `interactive` feature; pass the answer as a flag",
+        prompt
+    ))
+}
+
+/// Whether prompts can be shown in this build.
+pub const fn available() -> bool {
+    cfg!(feature = "interactive")
+}
+
+/// The prompter for this build.
+pub fn prompter() -> Box<dyn Prompter> {
+    #[cfg(feature = "interactive")]
+    return Box::new(TerminalPrompter);
+    #[cfg(not(feature = "interactive"))]
+    return Box::new(NoPrompter);
+}
@@ -0,0 +1,101 @@
+//! Capping download bandwidth with `--limit-rate`.
+//!
+//! A download that would fill the link is read more slowly instead: after
+//! each chunk the read loop sleeps until the bytes received so far fit the
+//! rate. Reads are kept to a tenth of a second's worth so the pace is even
+//! rather than bursty. Only asset downloads are limited; GitHub API answers
+//! are small.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Smallest read while limited, so a very low rate doesn't mean tiny reads.
+const MIN_READ: usize = 1024;
+
+thread_local! {
+    static LIMIT: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Limit every download of this thread to `bytes_per_sec`; `None` or 0
+/// removes the limit.
+pub fn set_limit(bytes_per_sec: Option<u64>) {
+    LIMIT.set(bytes_per_sec.filter(|&rate| rate > 0));
+}
+
+/// The limit downloads on this thread keep to, in bytes per second.
+pub fn limit() -> Option<u64> {
+    LIMIT.get()
+}
+
+/// Parse `--limit-rate`: bytes per second, with an optional `k`, `m` or `g`
+/// suffix for KiB, MiB or GiB, e.g. `500k` or `2m`. `0` means no limit.
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a rate such as 500k or 2m", value);
+    let trimmed = value.trim();
+    let (number, multiplier) = match trimmed.char_indices().last() {
+        Some((i, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier: u64 = match suffix.to_ascii_lowercase() {
+                'k' => 1 << 10,
+                'm' => 1 << 20,
+                'g' => 1 << 30,
+                _ => return Err(invalid()),
+            };
+            (&trimmed[..i], multiplier)
+        }
+        _ => (trimmed, 1),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(invalid());
+    }
+    let rate = number * multiplier as f64;
+    if rate >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(rate.round() as u64)
+}
+
+/// Keeps one download to a rate.
+#[derive(Debug, Clone, Copy)]
+pub struct Throttle {
+    bytes_per_sec: u64,
+    started: Instant,
+}
+
+impl Throttle {
+    /// A throttle for a download starting at `started`, if this thread has
+    /// a limit.
+    pub fn for_download(started: Instant) -> Option<Self> {
+        limit().map(|bytes_per_sec| Self::new(bytes_per_sec, started))
+    }
+
+    /// A throttle holding a download that started at `started` to
+    /// `bytes_per_sec`.
+    pub fn new(bytes_per_sec: u64, started: Instant) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            started,
+        }
+    }
+
+    /// The most to read at once: a tenth of a second's worth.
+    pub fn max_read(&self) -> usize {
+        usize::try_from(self.bytes_per_sec / 10)
+            .unwrap_or(usize::MAX)
+            .max(MIN_READ)
+    }
+
+    /// How long to wait, at `now`, so that `received` bytes fit the rate.
+    pub fn delay(&self, received: u64, now: Instant) -> Duration {
+        let due = Duration::from_secs_f64(received as f64 / self.bytes_per_sec as f64);
+        (self.started + due).saturating_duration_since(now)
+    }
+
+    /// Sleep until `received` bytes fit the rate.
+    pub fn pace(&self, received: u64) {
+        let delay = self.delay(received, Instant::now());
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+}
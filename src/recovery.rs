@@ -0,0 +1,88 @@
+//! Clearing up after runs that crashed.
+//!
+//! Every run sweeps storage on startup, once it holds the store lock; when
+//! another run holds it, the sweep waits for a later start. It clears:
+//!
+//! - the pid a crashed holder left in the lock file, see [`crate::lock`];
+//! - extraction directories under `tmp/`, see [`scratch::sweep_stale`];
+//! - staging and moved-aside directories of interrupted saves, see
+//!   [`staging::sweep_leftovers`].
+//!
+//! Only leftovers older than [`scratch::STALE_AFTER`] are touched, and never
+//! an extraction directory of a process that is still running.
+
+use crate::{lock, scratch, staging};
+use serde::Serialize;
+use std::path::Path;
+
+/// What the startup sweep cleared up.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Pid of the crashed run that last held the lock.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_lock: Option<u32>,
+    /// Entries removed from the extraction directory.
+    pub tmp_entries: usize,
+    /// Directories of interrupted saves removed.
+    pub unfinished_saves: usize,
+    /// Versions put back from where an interrupted save moved them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub restored_versions: Vec<String>,
+}
+
+impl RecoveryReport {
+    /// Whether nothing was left behind.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for RecoveryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(pid) = self.stale_lock {
+            parts.push(format!("cleared the lock held by crashed pid {}", pid));
+        }
+        if self.tmp_entries > 0 {
+            parts.push(format!(
+                "removed {} stale temporary file(s)",
+                self.tmp_entries
+            ));
+        }
+        if self.unfinished_saves > 0 {
+            parts.push(format!(
+                "removed {} unfinished save(s)",
+                self.unfinished_saves
+            ));
+        }
+        if !self.restored_versions.is_empty() {
+            parts.push(format!(
+                "restored version(s) {}",
+                self.restored_versions.join(", ")
+            ));
+        }
+        if parts.is_empty() {
+            return write!(f, "nothing left behind by crashed runs");
+        }
+        write!(f, "Recovered after a crashed run: {}", parts.join("; "))
+    }
+}
+
+/// Sweep storage holding the lock at `lock_file`, or do nothing if another
+/// run holds it.
+pub fn sweep(
+    lock_file: &Path,
+    versions_dir: &Path,
+    tmp_dir: &Path,
+) -> std::io::Result<RecoveryReport> {
+    let Some(lock) = lock::StoreLock::try_acquire(lock_file)? else {
+        return Ok(RecoveryReport::default());
+    };
+    let leftovers = staging::sweep_leftovers(versions_dir, scratch::STALE_AFTER);
+    Ok(RecoveryReport {
+        stale_lock: lock.stale_holder(),
+        tmp_entries: scratch::sweep_stale(tmp_dir),
+        unfinished_saves: leftovers.removed,
+        restored_versions: leftovers.restored,
+    })
+}
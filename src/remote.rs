@@ -0,0 +1,264 @@
+//! `remote-check`: compare the opencode installed on other machines with the latest release.
+//!
+//! Each host is probed over the user's own `ssh` with a short shell snippet
+//! that reports where opencode is, its permissions and its `--version`.
+//! Nothing is installed remotely. Hosts are probed one by one and a host
+//! that can't be reached only marks its own row. The transport is a trait
+//! so tests can answer probes without a network.
+
+use crate::config::SYSTEM_INSTALL_PATH;
+use crate::{UpdaterError, is_older, sanitize_terminal_text};
+use serde::Serialize;
+use std::process::Command;
+
+/// Runs a shell command on a host and returns its stdout.
+pub trait RemoteTransport {
+    fn run(&self, host: &str, command: &str) -> Result<String, UpdaterError>;
+}
+
+/// Runs commands with the `ssh` binary in batch mode, so a host asking for a
+/// password fails instead of hanging.
+#[derive(Debug, Clone)]
+pub struct SshTransport {
+    pub program: String,
+    pub connect_timeout_secs: u32,
+}
+
+impl Default for SshTransport {
+    fn default() -> Self {
+        Self {
+            program: "ssh".to_string(),
+            connect_timeout_secs: 10,
+        }
+    }
+}
+
+impl RemoteTransport for SshTransport {
+    fn run(&self, host: &str, command: &str) -> Result<String, UpdaterError> {
+        let output = Command::new(&self.program)
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg("-o")
+            .arg(format!("ConnectTimeout={}", self.connect_timeout_secs))
+            .arg("--")
+            .arg(host)
+            .arg(command)
+            .output()
+            .map_err(|e| {
+                UpdaterError::NetworkError(format!("could not run {}: {}", self.program, e))
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().last().unwrap_or("").trim();
+            return Err(UpdaterError::NetworkError(if reason.is_empty() {
+                format!("{} exited with {}", self.program, output.status)
+            } else {
+                reason.to_string()
+            }));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Shell snippet run on each host; prints `key=value` lines read by [`parse_probe`].
+pub fn probe_command() -> String {
+    format!(
+        "p=$(command -v opencode 2>/dev/null || echo {path}); echo \"path=$p\"; \
+         if [ -e \"$p\" ]; then \
+         echo \"mode=$(stat -c %a \"$p\" 2>/dev/null)\"; \
+         echo \"owner=$(stat -c %U \"$p\" 2>/dev/null)\"; \
+         echo \"version=$(\"$p\" --version 2>/dev/null | head -n 1)\"; \
+         fi",
+        path = SYSTEM_INSTALL_PATH
+    )
+}
+
+/// What the probe found on one host.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostProbe {
+    pub install_path: Option<String>,
+    pub exists: bool,
+    /// Octal permission bits, e.g. `755`.
+    pub mode: Option<String>,
+    pub owner: Option<String>,
+    /// `--version` output without a leading `v`; `None` if it printed nothing.
+    pub version: Option<String>,
+}
+
+/// Parse the output of [`probe_command`].
+pub fn parse_probe(output: &str) -> HostProbe {
+    let mut probe = HostProbe::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = (!value.is_empty()).then(|| value.to_string());
+        match key {
+            "path" => probe.install_path = value,
+            "mode" => {
+                probe.exists = true;
+                probe.mode = value;
+            }
+            "owner" => probe.owner = value,
+            "version" => probe.version = value.map(|v| v.trim_start_matches('v').to_string()),
+            _ => {}
+        }
+    }
+    probe
+}
+
+/// How a host compares with the latest release.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HostState {
+    UpToDate,
+    Outdated,
+    NotInstalled,
+    /// Installed, but its version couldn't be compared.
+    Unknown,
+    Unreachable,
+}
+
+impl std::fmt::Display for HostState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HostState::UpToDate => "up to date",
+            HostState::Outdated => "outdated",
+            HostState::NotInstalled => "not installed",
+            HostState::Unknown => "unknown",
+            HostState::Unreachable => "unreachable",
+        })
+    }
+}
+
+/// One row of the comparison.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HostStatus {
+    pub host: String,
+    pub state: HostState,
+    #[serde(flatten)]
+    pub probe: HostProbe,
+    /// Why the host couldn't be probed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Every host compared against the latest release.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RemoteReport {
+    pub latest_tag: String,
+    pub hosts: Vec<HostStatus>,
+}
+
+impl std::fmt::Display for RemoteReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Latest release: {}\n",
+            sanitize_terminal_text(&self.latest_tag)
+        )?;
+        let rows: Vec<[String; 4]> = self
+            .hosts
+            .iter()
+            .map(|host| {
+                let detail = match (&host.error, &host.probe.install_path) {
+                    (Some(error), _) => error.clone(),
+                    (None, Some(path)) if host.probe.exists => format!(
+                        "{} ({} {})",
+                        path,
+                        host.probe.mode.as_deref().unwrap_or("?"),
+                        host.probe.owner.as_deref().unwrap_or("?")
+                    ),
+                    _ => String::new(),
+                };
+                [
+                    host.host.clone(),
+                    host.probe
+                        .version
+                        .clone()
+                        .unwrap_or_else(|| "-".to_string()),
+                    host.state.to_string(),
+                    detail,
+                ]
+                .map(|cell| sanitize_terminal_text(&cell))
+            })
+            .collect();
+        let header = ["HOST", "VERSION", "STATUS", "INSTALL"].map(String::from);
+        let width = |column: usize| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        };
+        let widths = [width(0), width(1), width(2)];
+        let lines: Vec<String> = std::iter::once(&header)
+            .chain(&rows)
+            .map(|row| {
+                format!(
+                    "{:w0$}  {:w1$}  {:w2$}  {}",
+                    row[0],
+                    row[1],
+                    row[2],
+                    row[3],
+                    w0 = widths[0],
+                    w1 = widths[1],
+                    w2 = widths[2]
+                )
+                .trim_end()
+                .to_string()
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Compare one host's probe with the latest release.
+pub fn host_state(probe: &HostProbe, latest_tag: &str) -> HostState {
+    if !probe.exists {
+        return HostState::NotInstalled;
+    }
+    match probe
+        .version
+        .as_deref()
+        .map(|version| is_older(version, latest_tag))
+    {
+        Some(Ok(false)) => HostState::UpToDate,
+        Some(Ok(true)) => HostState::Outdated,
+        _ => HostState::Unknown,
+    }
+}
+
+/// Probe every host and compare it with `latest_tag`.
+pub fn remote_check(
+    transport: &dyn RemoteTransport,
+    hosts: &[String],
+    latest_tag: &str,
+) -> RemoteReport {
+    let command = probe_command();
+    let hosts = hosts
+        .iter()
+        .map(|host| match transport.run(host, &command) {
+            Ok(output) => {
+                let probe = parse_probe(&output);
+                HostStatus {
+                    host: host.clone(),
+                    state: host_state(&probe, latest_tag),
+                    probe,
+                    error: None,
+                }
+            }
+            Err(e) => HostStatus {
+                host: host.clone(),
+                state: HostState::Unreachable,
+                probe: HostProbe::default(),
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+    RemoteReport {
+        latest_tag: latest_tag.to_string(),
+        hosts,
+    }
+}
@@ -0,0 +1,301 @@
+//! User-facing output, rendered according to the selected output mode.
+//!
+//! Library functions return structured reports instead of printing; the
+//! reporter decides whether to show them as text, as JSON, or not at all.
+//!
+//! Results go to stdout and messages to stderr in every mode, so redirecting
+//! stdout captures only what was asked for.
+//!
+//! Warnings about lasting conditions go through a [`WarningRegistry`] under
+//! a key: an identical warning is shown once per run, a key the user
+//! accepted with `--suppress-warning` or `suppress_warnings` is not shown at
+//! all, and when stderr is not a terminal, e.g. under a timer, a key is
+//! repeated at most once per [`WARNING_INTERVAL`] across runs. `doctor`
+//! reports its checks in full regardless.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Keys of the warnings that go through the registry.
+pub const WARNING_KEYS: [&str; 14] = [
+    "corrupt-cache",
+    "checksum-fetch",
+    "checksum-retry",
+    "history",
+    "release-cache",
+    "migration",
+    "clock",
+    "rate-limit",
+    "force",
+    "foreign-target",
+    "unparseable-version",
+    "unsigned-version",
+    "modified-install",
+    "mirror",
+];
+
+/// Shortest time between two showings of a warning across runs, when they
+/// are throttled.
+pub const WARNING_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+/// Which warnings to show.
+#[derive(Debug, Default)]
+pub struct WarningRegistry {
+    suppressed: BTreeSet<String>,
+    /// When each key was last shown by an earlier run, if throttling.
+    throttle: Option<BTreeMap<String, DateTime<Utc>>>,
+    /// Warnings shown by this run.
+    shown: BTreeSet<(String, String)>,
+    /// When each key was first shown by this run.
+    shown_at: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl WarningRegistry {
+    /// A registry that only deduplicates.
+    pub const fn new() -> Self {
+        Self {
+            suppressed: BTreeSet::new(),
+            throttle: None,
+            shown: BTreeSet::new(),
+            shown_at: BTreeMap::new(),
+        }
+    }
+
+    /// Never show the warnings under `keys`.
+    pub fn suppress(mut self, keys: impl IntoIterator<Item = String>) -> Self {
+        self.suppressed.extend(keys);
+        self
+    }
+
+    /// Show a key at most once per [`WARNING_INTERVAL`], given when earlier
+    /// runs last showed each key.
+    pub fn throttle(mut self, last_shown: BTreeMap<String, DateTime<Utc>>) -> Self {
+        self.throttle = Some(last_shown);
+        self
+    }
+
+    /// Whether to show warning `text` under `key` at `now`, recording it if so.
+    pub fn should_show(&mut self, key: &str, text: &str, now: DateTime<Utc>) -> bool {
+        if self.suppressed.contains(key)
+            || self.shown.contains(&(key.to_string(), text.to_string()))
+        {
+            return false;
+        }
+        // Only earlier runs count; another warning under the key in this run is news
+        if let Some(last_shown) = self.throttle.as_ref().and_then(|last| last.get(key))
+            && now - *last_shown < WARNING_INTERVAL
+        {
+            return false;
+        }
+        self.shown.insert((key.to_string(), text.to_string()));
+        self.shown_at.entry(key.to_string()).or_insert(now);
+        true
+    }
+
+    /// When each key was first shown by this run, for the next run to throttle by.
+    pub fn shown_at(&self) -> &BTreeMap<String, DateTime<Utc>> {
+        &self.shown_at
+    }
+}
+
+static WARNINGS: Mutex<WarningRegistry> = Mutex::new(WarningRegistry::new());
+
+/// Use `registry` for the rest of the run.
+pub fn set_warning_registry(registry: WarningRegistry) {
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        *warnings = registry;
+    }
+}
+
+/// Whether this run should show warning `text` under `key` now.
+pub fn should_warn(key: &str, text: &str) -> bool {
+    WARNINGS.lock().map_or(true, |mut warnings| {
+        warnings.should_show(key, text, Utc::now())
+    })
+}
+
+/// Show warning `text` under `key` on stderr, as [`Reporter::warn`] does
+/// for the mode set with [`set_output_mode`], for code without a reporter.
+pub fn warn(key: &str, text: &str) {
+    Reporter::new(output_mode()).warn(key, text);
+}
+
+/// Show routine progress `text`, as [`Reporter::info`] does for the mode
+/// set with [`set_output_mode`], for code without a reporter.
+pub fn info(text: &str) {
+    Reporter::new(output_mode()).info(text);
+}
+
+/// When each key was first shown by this run.
+pub fn warnings_shown() -> BTreeMap<String, DateTime<Utc>> {
+    WARNINGS
+        .lock()
+        .map(|warnings| warnings.shown_at().clone())
+        .unwrap_or_default()
+}
+
+static OUTPUT_MODE: Mutex<OutputMode> = Mutex::new(OutputMode::Human);
+
+/// Show messages from code without a reporter in `mode` for the rest of the
+/// process, e.g. none under `--quiet`.
+pub fn set_output_mode(mode: OutputMode) {
+    if let Ok(mut output_mode) = OUTPUT_MODE.lock() {
+        *output_mode = mode;
+    }
+}
+
+/// The mode set with [`set_output_mode`].
+pub fn output_mode() -> OutputMode {
+    OUTPUT_MODE.lock().map_or(OutputMode::Human, |mode| *mode)
+}
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Name where each download actually comes from, after redirects, for the
+/// rest of the process (`-v`).
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Whether downloads name where they come from.
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// How results and messages are presented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Human-readable results on stdout, messages on stderr.
+    #[default]
+    Human,
+    /// One JSON document per result on stdout; messages go to stderr.
+    Json,
+    /// Only errors are shown.
+    Quiet,
+}
+
+/// Importance of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Routine progress, hidden under `--quiet` and `--json`.
+    Info,
+    /// Something the user should see even in JSON mode, e.g. a warning.
+    Notice,
+}
+
+/// Writes messages and results for the selected output mode.
+pub struct Reporter {
+    mode: OutputMode,
+    stdout: Mutex<Box<dyn Write + Send>>,
+    stderr: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Reporter {
+    /// Reporter writing to the process's stdout and stderr.
+    pub fn new(mode: OutputMode) -> Self {
+        Self::with_writers(
+            mode,
+            Box::new(std::io::stdout()),
+            Box::new(std::io::stderr()),
+        )
+    }
+
+    /// Reporter writing to the given streams, e.g. buffers in tests.
+    pub fn with_writers(
+        mode: OutputMode,
+        stdout: Box<dyn Write + Send>,
+        stderr: Box<dyn Write + Send>,
+    ) -> Self {
+        Self {
+            mode,
+            stdout: Mutex::new(stdout),
+            stderr: Mutex::new(stderr),
+        }
+    }
+
+    /// Select the output mode from `--json` / `--quiet`.
+    pub fn mode_for(json: bool, quiet: bool) -> OutputMode {
+        if quiet {
+            OutputMode::Quiet
+        } else if json {
+            OutputMode::Json
+        } else {
+            OutputMode::Human
+        }
+    }
+
+    /// The selected output mode.
+    pub fn mode(&self) -> OutputMode {
+        self.mode
+    }
+
+    /// Show a message at the given level.
+    pub fn message(&self, level: Level, text: &str) {
+        match (self.mode, level) {
+            (OutputMode::Human, _) | (OutputMode::Json, Level::Notice) => self.write_stderr(text),
+            (OutputMode::Json, Level::Info) | (OutputMode::Quiet, _) => {}
+        }
+    }
+
+    /// Show routine progress.
+    pub fn info(&self, text: &str) {
+        self.message(Level::Info, text);
+    }
+
+    /// Show something the user should notice.
+    pub fn notice(&self, text: &str) {
+        self.message(Level::Notice, text);
+    }
+
+    /// Show warning `text` under `key`, if the registry lets it through.
+    pub fn warn(&self, key: &str, text: &str) {
+        if should_warn(key, text) {
+            self.notice(&format!("Warning: {}", text));
+        }
+    }
+
+    /// Show a structured result: its text form, or a JSON object tagged with `kind`.
+    pub fn result<T: Serialize + std::fmt::Display>(&self, kind: &str, value: &T) {
+        match self.mode {
+            OutputMode::Human => {
+                let text = value.to_string();
+                if !text.is_empty() {
+                    self.write_stdout(&text);
+                }
+            }
+            OutputMode::Json => self.write_stdout(&Self::document(kind, value).to_string()),
+            OutputMode::Quiet => {}
+        }
+    }
+
+    /// The JSON document of a result: its fields, tagged with `kind` and the
+    /// [`crate::schema::SCHEMA_VERSION`].
+    pub fn document<T: Serialize>(kind: &str, value: &T) -> serde_json::Value {
+        let mut document = serde_json::json!({
+            "type": kind,
+            "schema_version": crate::schema::SCHEMA_VERSION,
+        });
+        if let (Some(fields), Ok(serde_json::Value::Object(values))) =
+            (document.as_object_mut(), serde_json::to_value(value))
+        {
+            fields.extend(values);
+        }
+        document
+    }
+
+    fn write_stdout(&self, text: &str) {
+        if let Ok(mut out) = self.stdout.lock() {
+            let _ = writeln!(out, "{}", text);
+        }
+    }
+
+    fn write_stderr(&self, text: &str) {
+        if let Ok(mut err) = self.stderr.lock() {
+            let _ = writeln!(err, "{}", text);
+        }
+    }
+}
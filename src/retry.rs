@@ -0,0 +1,124 @@
+//! Retrying requests that fail for a moment.
+//!
+//! A dropped connection, a timeout or a 5xx answer often passes, so a
+//! download or GitHub API request failing that way is made again, up to
+//! `retry_attempts` times in all (3 unless the config file says otherwise).
+//! The wait doubles before each retry, and is cut short by a random amount
+//! so clients cut off together don't all come back together. Every other
+//! error, such as a 404, fails at once.
+//!
+//! A retried download starts over; its progress bar says which attempt it
+//! is.
+
+use crate::UpdaterError;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// Attempts a request gets unless the config file says otherwise.
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+/// How often, and how patiently, requests are made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first included; 1 never retries.
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each one after it.
+    pub base_delay: Duration,
+    /// Longest wait before a retry.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The policy requests follow unless [`set_policy`] says otherwise.
+    pub const DEFAULT: Self = Self {
+        attempts: DEFAULT_ATTEMPTS,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(10),
+    };
+
+    /// Wait before retry number `retry`, 1 for the first: the base delay
+    /// doubled `retry - 1` times, at most [`Self::max_delay`], less up to
+    /// half of it at random.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let full = self
+            .base_delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        full / 2 + full.mul_f64(jitter() / 2.0)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+thread_local! {
+    static POLICY: Cell<RetryPolicy> = const { Cell::new(RetryPolicy::DEFAULT) };
+}
+
+/// Follow `policy` for every request of this thread.
+pub fn set_policy(policy: RetryPolicy) {
+    POLICY.set(policy);
+}
+
+/// The policy requests on this thread follow.
+pub fn policy() -> RetryPolicy {
+    POLICY.get()
+}
+
+/// Whether a request that failed with `error` is worth making again: it
+/// couldn't connect, timed out, lost its connection or got a 5xx answer.
+pub fn is_retryable(error: &UpdaterError) -> bool {
+    match error {
+        UpdaterError::Http { source, .. } => match source {
+            ureq::Error::StatusCode(status) => (500..=599).contains(status),
+            // Offline, or a name that doesn't exist; neither passes in seconds
+            ureq::Error::HostNotFound => false,
+            source => crate::is_transient_request_error(source),
+        },
+        UpdaterError::ApiStatus { status, .. } => (500..=599).contains(status),
+        _ => false,
+    }
+}
+
+/// Run `request`, given the attempt number from 1, until it succeeds, fails
+/// in a way retrying won't help, or runs out of attempts. Each retry is
+/// announced as progress, with the error that led to it.
+pub fn with_retries<T>(
+    mut request: impl FnMut(u32) -> Result<T, UpdaterError>,
+) -> Result<T, UpdaterError> {
+    let policy = policy();
+    let mut attempt = 1;
+    loop {
+        match request(attempt) {
+            Err(e)
+                if attempt < policy.attempts
+                    && is_retryable(&e)
+                    && !crate::progress::cancel_requested() =>
+            {
+                let delay = policy.delay(attempt);
+                crate::reporter::info(&format!(
+                    "{}; retrying in {:.1}s (attempt {} of {})",
+                    e,
+                    delay.as_secs_f64(),
+                    attempt + 1,
+                    policy.attempts
+                ));
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A number in `[0, 1)`, different on every call.
+fn jitter() -> f64 {
+    use std::hash::BuildHasher;
+
+    // Each RandomState is keyed afresh, which is all the randomness needed
+    let bits = std::collections::hash_map::RandomState::new().hash_one(0u8);
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,96 @@
+//! Rolling releases such as `nightly`, whose tag never changes.
+//!
+//! A rolling tag is a name such as `nightly` that doesn't start with a
+//! version number. Its builds are told apart by the asset's published checksum, or by the asset's
+//! `updated_at` when no checksum is published, and stored under names like
+//! `nightly-20240512-1a2b3c4`.
+
+use crate::{Asset, VersionInfo, calculate_sha256, parse_version};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Number of identity hex characters used in stored version names.
+const SHORT_HASH_LEN: usize = 7;
+
+/// Whether a release tag names a rolling release rather than a version.
+pub fn is_rolling_tag(tag: &str) -> bool {
+    parse_version(tag).is_err() && !looks_versioned(tag)
+}
+
+/// Whether a tag starts like a version but isn't one [`crate::Version`]
+/// parses, such as `v0.6.0_hotfix`. Such releases are installed under their
+/// tag and compared as text.
+pub fn is_unparseable_version(tag: &str) -> bool {
+    parse_version(tag).is_err() && looks_versioned(tag)
+}
+
+fn looks_versioned(tag: &str) -> bool {
+    tag.trim_start_matches('v')
+        .starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// One build published under a rolling tag.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RollingBuild {
+    pub tag: String,
+    pub built_at: DateTime<Utc>,
+    /// Published SHA-256 of the asset, or a hash of its name, size and `updated_at`.
+    pub build_id: String,
+}
+
+impl RollingBuild {
+    /// Describe the build behind a rolling release, or `None` for a versioned release.
+    ///
+    /// `checksum` is the asset's published SHA-256, if any.
+    pub fn from_release(
+        release: &serde_json::Value,
+        asset: &Asset,
+        checksum: Option<&str>,
+    ) -> Option<Self> {
+        let tag = release["tag_name"].as_str()?;
+        if !is_rolling_tag(tag) {
+            return None;
+        }
+
+        let published_at = release["published_at"]
+            .as_str()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|dt| dt.with_timezone(&Utc));
+        let built_at = asset.updated_at.or(published_at).unwrap_or_else(Utc::now);
+
+        let build_id = match checksum {
+            Some(checksum) if !checksum.is_empty() => checksum.to_string(),
+            _ => calculate_sha256(
+                format!("{}\n{}\n{}", asset.name, asset.size, built_at.to_rfc3339()).as_bytes(),
+            ),
+        };
+
+        Some(Self {
+            tag: tag.to_string(),
+            built_at,
+            build_id,
+        })
+    }
+
+    /// Name of the stored version directory, e.g. `nightly-20240512-1a2b3c4`.
+    pub fn version_name(&self) -> String {
+        let slug: String = self
+            .tag
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let short_hash: String = self.build_id.chars().take(SHORT_HASH_LEN).collect();
+        format!("{}-{}-{}", slug, self.built_at.format("%Y%m%d"), short_hash)
+    }
+
+    /// Whether a stored version is this exact build.
+    pub fn matches(&self, info: &VersionInfo) -> bool {
+        info.tag_name == self.tag && info.build_id.as_deref() == Some(self.build_id.as_str())
+    }
+}
@@ -0,0 +1,135 @@
+//! The software bill of materials some releases publish.
+//!
+//! With `--sbom`, a release's `sbom.spdx.json` asset is downloaded with the
+//! binary, checked against any checksum published for it, and stored as
+//! `versions/<version>/sbom.spdx.json`. `sbom <version>` summarises a stored
+//! one. Only SPDX JSON is understood, and only as far as the summary needs:
+//! the document's name and version, its packages and their licenses.
+
+use crate::{Checksum, UpdaterError, sanitize_terminal_text};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use ureq::Agent;
+
+/// Name of the SBOM asset, and of the stored copy.
+pub const SBOM_FILE: &str = "sbom.spdx.json";
+
+/// License fields that name no license.
+const NO_LICENSE: [&str; 2] = ["NOASSERTION", "NONE"];
+
+/// What `sbom <version>` shows of a stored SBOM.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SbomSummary {
+    pub version: String,
+    /// `spdxVersion` of the document, e.g. `SPDX-2.3`.
+    pub spdx_version: String,
+    pub name: Option<String>,
+    pub package_count: usize,
+    /// License identifiers in the packages' concluded and declared licenses.
+    pub licenses: BTreeSet<String>,
+}
+
+impl std::fmt::Display for SbomSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "SBOM for opencode {} ({})",
+            self.version,
+            sanitize_terminal_text(&self.spdx_version)
+        )?;
+        if let Some(name) = &self.name {
+            writeln!(f, "  Document: {}", sanitize_terminal_text(name))?;
+        }
+        writeln!(f, "  Packages: {}", self.package_count)?;
+        let licenses: Vec<String> = self
+            .licenses
+            .iter()
+            .map(|license| sanitize_terminal_text(license))
+            .collect();
+        write!(
+            f,
+            "  Licenses: {}",
+            if licenses.is_empty() {
+                "none declared".to_string()
+            } else {
+                licenses.join(", ")
+            }
+        )
+    }
+}
+
+/// Summarise the SPDX JSON document `content`, the SBOM of `version`.
+pub fn summarize(version: &str, content: &str) -> Result<SbomSummary, UpdaterError> {
+    let document: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| UpdaterError::InvalidManifest(format!("SBOM is not valid JSON: {}", e)))?;
+    let spdx_version = document["spdxVersion"].as_str().ok_or_else(|| {
+        UpdaterError::InvalidManifest(
+            "SBOM is not an SPDX JSON document: it has no spdxVersion".to_string(),
+        )
+    })?;
+    let packages = document["packages"]
+        .as_array()
+        .map_or(&[][..], Vec::as_slice);
+
+    let licenses = packages
+        .iter()
+        .flat_map(|package| {
+            ["licenseConcluded", "licenseDeclared"]
+                .into_iter()
+                .filter_map(|field| package[field].as_str())
+        })
+        .flat_map(license_ids)
+        .collect();
+    Ok(SbomSummary {
+        version: version.to_string(),
+        spdx_version: spdx_version.to_string(),
+        name: document["name"].as_str().map(str::to_string),
+        package_count: packages.len(),
+        licenses,
+    })
+}
+
+/// The license identifiers in an SPDX license expression, e.g. the `MIT` and
+/// `Apache-2.0` of `(MIT OR Apache-2.0)`.
+pub fn license_ids(expression: &str) -> impl Iterator<Item = String> + '_ {
+    expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|token| {
+            !token.is_empty()
+                && !["AND", "OR", "WITH"].contains(token)
+                && !NO_LICENSE.contains(token)
+        })
+        .map(str::to_string)
+}
+
+/// Download the SBOM published with a release, verified against its
+/// checksums when the release lists any.
+///
+/// Returns `None` when the release has no SBOM.
+pub fn download(
+    client: &Agent,
+    assets: &[serde_json::Value],
+    retry_delay: std::time::Duration,
+) -> Result<Option<Vec<u8>>, UpdaterError> {
+    let Some(asset) = crate::find_asset(assets, SBOM_FILE) else {
+        return Ok(None);
+    };
+    let url = asset["browser_download_url"].as_str().ok_or_else(|| {
+        UpdaterError::InvalidManifest("SBOM asset has no download URL".to_string())
+    })?;
+    let checksums: Vec<Checksum> = crate::fetch_asset_checksums(client, assets, SBOM_FILE)?;
+    let bytes = crate::download_verified(client, url, SBOM_FILE, &checksums, retry_delay)?;
+
+    // Stored only if `sbom` will be able to read it
+    summarize("", &String::from_utf8_lossy(&bytes))?;
+    Ok(Some(bytes))
+}
+
+/// Error for a release without an SBOM under `--require-sbom`.
+pub fn missing(tag: &str) -> UpdaterError {
+    UpdaterError::Refused(format!(
+        "{} publishes no {}; not installing it because --require-sbom was given",
+        sanitize_terminal_text(tag),
+        SBOM_FILE
+    ))
+}
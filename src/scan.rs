@@ -0,0 +1,118 @@
+//! Scanning downloaded binaries before they are installed.
+//!
+//! With `scan_command` in the config, the extracted binary is handed to that
+//! command once its checksum is verified, and only installed if the command
+//! exits 0. The command runs through `sh -c` with the binary's path appended
+//! as its last argument, so it can carry its own options. A scanner that
+//! doesn't finish within `scan_timeout` is killed, along with anything it
+//! started, and counts as a rejection. `--skip-scan` bypasses it.
+
+use crate::UpdaterError;
+use std::io::{Read, Seek};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long a scanner may run when `scan_timeout` isn't set.
+pub const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Scanner output quoted in a rejection, from the end.
+const MAX_OUTPUT_BYTES: usize = 4096;
+
+/// How often a running scanner is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A configured scanner.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl Scanner {
+    /// What is recorded in a version's metadata after a clean scan.
+    pub fn passed(&self) -> String {
+        format!("{} ok", self.command)
+    }
+
+    /// Scan `binary`; any outcome but a zero exit is an error quoting the output.
+    pub fn scan(&self, binary: &Path) -> Result<(), UpdaterError> {
+        // Output goes to a file rather than a pipe, so a scanner that leaves
+        // children behind can't keep us waiting for end of file
+        let mut output = tempfile::tempfile()?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", self.command))
+            .arg("opencode-updater-scan")
+            .arg(binary)
+            .stdin(Stdio::null())
+            .stdout(output.try_clone()?)
+            .stderr(output.try_clone()?)
+            .process_group(0)
+            .spawn()
+            .map_err(|e| rejected(&self.command, &format!("could not run it: {}", e), ""))?;
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if started.elapsed() >= self.timeout {
+                let group = rustix::process::Pid::from_child(&child);
+                let _ = rustix::process::kill_process_group(group, rustix::process::Signal::KILL);
+                let _ = child.wait();
+                break None;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+
+        let mut text = String::new();
+        output.rewind()?;
+        output.read_to_string(&mut text).ok();
+        match status {
+            Some(status) if status.success() => Ok(()),
+            Some(status) => Err(rejected(
+                &self.command,
+                &format!("exited with {}", status),
+                &text,
+            )),
+            None => Err(rejected(
+                &self.command,
+                &format!("timed out after {}s", self.timeout.as_secs()),
+                &text,
+            )),
+        }
+    }
+}
+
+/// Error for a scan that didn't pass.
+fn rejected(command: &str, reason: &str, output: &str) -> UpdaterError {
+    let output = output.trim();
+    let start = output
+        .char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| output.len() - i <= MAX_OUTPUT_BYTES)
+        .unwrap_or(output.len());
+    let mut message = format!(
+        "scanner `{}` {}; not installing",
+        crate::sanitize_terminal_text(command),
+        reason
+    );
+    if !output.is_empty() {
+        message.push_str(":\n");
+        if start > 0 {
+            message.push_str("…\n");
+        }
+        message.push_str(&crate::sanitize_terminal_text(&output[start..]));
+    }
+    UpdaterError::ScanRejected(message)
+}
+
+/// Warning shown when `--skip-scan` bypasses a configured scanner.
+pub fn skip_warning(command: &str) -> String {
+    format!(
+        "WARNING: --skip-scan given; installing without running the required scanner `{}`",
+        crate::sanitize_terminal_text(command)
+    )
+}
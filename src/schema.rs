@@ -0,0 +1,82 @@
+//! JSON Schemas of the `--json` outputs, printed by `schema`.
+//!
+//! Schemas are generated from the structs the outputs are serialized from,
+//! so the two can't drift apart. Every document carries `schema_version`,
+//! [`SCHEMA_VERSION`]. Adding a field is compatible. Removing or renaming a
+//! field, or changing its type or meaning, is a breaking change and bumps the
+//! version.
+
+use crate::{
+    CheckReport, VersionList, error_report::ErrorDocument, history::HistoryEntry, status::Status,
+};
+use clap::ValueEnum;
+
+/// Version of the output formats, in every document as `schema_version`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// An output a schema describes.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    /// `--list-versions --json`
+    List,
+    /// `--check --json`
+    Check,
+    /// `status.json` in the storage directory
+    Status,
+    /// Each line of `history --json`
+    History,
+    /// A failure under `--json`, on stderr
+    Error,
+}
+
+impl SchemaKind {
+    pub const ALL: [SchemaKind; 5] = [
+        SchemaKind::List,
+        SchemaKind::Check,
+        SchemaKind::Status,
+        SchemaKind::History,
+        SchemaKind::Error,
+    ];
+
+    /// `type` of the documents, for outputs written by the [`crate::Reporter`].
+    pub fn document_type(self) -> Option<&'static str> {
+        match self {
+            SchemaKind::List => Some("list"),
+            SchemaKind::Check => Some("check"),
+            SchemaKind::Status => None,
+            SchemaKind::History => Some("history_entry"),
+            SchemaKind::Error => Some("error"),
+        }
+    }
+}
+
+/// The JSON Schema of the documents of `kind`.
+pub fn schema(kind: SchemaKind) -> serde_json::Value {
+    let mut schema = match kind {
+        SchemaKind::List => schemars::schema_for!(VersionList),
+        SchemaKind::Check => schemars::schema_for!(CheckReport),
+        SchemaKind::Status => schemars::schema_for!(Status),
+        SchemaKind::History => schemars::schema_for!(HistoryEntry),
+        SchemaKind::Error => schemars::schema_for!(ErrorDocument),
+    }
+    .to_value();
+
+    // Added around the struct's own fields when the document is written
+    let mut envelope = vec![(
+        "schema_version",
+        serde_json::json!({ "const": SCHEMA_VERSION }),
+    )];
+    if let Some(document_type) = kind.document_type() {
+        envelope.push(("type", serde_json::json!({ "const": document_type })));
+    }
+    for (name, property) in envelope {
+        schema["properties"][name] = property;
+        if let Some(required) = schema["required"].as_array_mut() {
+            required.retain(|field| field != name);
+            required.push(name.into());
+        } else {
+            schema["required"] = serde_json::json!([name]);
+        }
+    }
+    schema
+}
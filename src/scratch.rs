@@ -0,0 +1,118 @@
+//! Temporary directories for extracting downloads.
+//!
+//! Archives are extracted under `storage_dir/tmp/` so the extracted binary
+//! can be renamed into `versions/` instead of copied across filesystems, and
+//! so a small tmpfs `/tmp` doesn't run out of space. The system temp dir is
+//! only used when the storage filesystem is the one short on space.
+//!
+//! Extraction directories are named `extract-<pid>-<random>`, so a sweep can
+//! tell the ones a running process is still using from those of a crashed one.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Leftovers older than this are from crashed runs and removed on startup.
+pub const STALE_AFTER: Duration = Duration::from_secs(3600);
+
+/// Start of an extraction directory's name.
+pub const EXTRACT_PREFIX: &str = "extract-";
+
+/// Rough free space extraction needs, as a multiple of the archive size.
+const EXTRACT_SPACE_FACTOR: u64 = 4;
+
+/// Bytes available to unprivileged users on the filesystem holding `path`.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let stats = rustix::fs::statvfs(path).ok()?;
+    Some(stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+/// Free space to ask for before extracting an archive of `archive_size` bytes.
+pub fn space_needed(archive_size: u64) -> u64 {
+    archive_size.saturating_mul(EXTRACT_SPACE_FACTOR)
+}
+
+/// Where to create the extraction directory.
+///
+/// An explicit `--tmp-dir` always wins. Otherwise `storage_tmp` is used
+/// unless its filesystem has less than `needed` bytes free and the system
+/// temp dir has more.
+pub fn choose_root(storage_tmp: &Path, needed: u64, override_dir: Option<&Path>) -> PathBuf {
+    if let Some(dir) = override_dir {
+        return dir.to_path_buf();
+    }
+    // The tmp dir may not exist yet; its storage directory does
+    let probe = storage_tmp.parent().unwrap_or(storage_tmp);
+    let system_tmp = std::env::temp_dir();
+    match (available_space(probe), available_space(&system_tmp)) {
+        (Some(storage_free), Some(system_free))
+            if storage_free < needed && system_free > storage_free =>
+        {
+            system_tmp
+        }
+        _ => storage_tmp.to_path_buf(),
+    }
+}
+
+/// Prefix for an extraction directory of this process.
+pub fn extract_prefix() -> String {
+    format!("{}{}-", EXTRACT_PREFIX, std::process::id())
+}
+
+/// Pid of the process that created the extraction directory `name`.
+pub fn owner_pid(name: &str) -> Option<u32> {
+    name.strip_prefix(EXTRACT_PREFIX)?
+        .split_once('-')?
+        .0
+        .parse()
+        .ok()
+}
+
+/// Remove entries in `tmp_root` last modified more than [`STALE_AFTER`] ago,
+/// except extraction directories of processes still running.
+///
+/// Returns how many were removed. Entries that can't be removed are skipped.
+pub fn sweep_stale(tmp_root: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(tmp_root) else {
+        return 0;
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > STALE_AFTER);
+        let live =
+            owner_pid(&entry.file_name().to_string_lossy()).is_some_and(crate::lock::is_running);
+        if !stale || live {
+            continue;
+        }
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        if result.is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Move a file, renaming when source and target share a filesystem.
+///
+/// Returns `true` if it was renamed and `false` if it had to be copied.
+pub fn move_file(from: &Path, to: &Path) -> Result<bool, std::io::Error> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            std::fs::copy(from, to)?;
+            std::fs::remove_file(from)?;
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
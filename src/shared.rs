@@ -0,0 +1,157 @@
+//! Version storage shared by every user of a machine.
+//!
+//! With `storage_dir` set in the system config, [`SYSTEM_CONFIG_PATH`], the
+//! stored versions, the scratch space they are extracted in and the
+//! integrity key protecting them live there rather than in each user's data
+//! directory, so a release one user downloaded is there for the others to
+//! roll back to. Config, history, status, target records and the `current`
+//! link stay in each user's home.
+//!
+//! Directories created in the shared store are group-writable and setgid, so
+//! everything below them belongs to one group; files are group-writable and
+//! world-readable, and the integrity key is readable by the group only.
+//! `shared_group` names that group; without it the store's own group is used.
+//! Only what the current user owns is adjusted, since nothing else can be.
+
+use crate::UpdaterError;
+use serde::Serialize;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// System-wide config file; only its storage settings are read.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/opencode-updater/config.toml";
+
+/// Mode of directories in the shared store.
+pub const DIR_MODE: u32 = 0o2775;
+
+/// Mode of files in the shared store.
+pub const FILE_MODE: u32 = 0o664;
+
+/// Mode of executables in the shared store.
+pub const EXEC_MODE: u32 = 0o775;
+
+/// Mode of the shared integrity key.
+pub const KEY_MODE: u32 = 0o640;
+
+/// A configured shared store.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SharedStorage {
+    pub dir: PathBuf,
+    /// Group that owns everything in it, by name or number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// Applies the shared store's ownership and modes to what this user creates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sharing {
+    gid: Option<u32>,
+}
+
+impl Sharing {
+    /// Resolve the configured group.
+    pub fn new(storage: &SharedStorage) -> Result<Self, UpdaterError> {
+        let gid = match &storage.group {
+            Some(group) => Some(group_id(group).ok_or_else(|| {
+                UpdaterError::InvalidConfig(format!(
+                    "shared_group '{}' is not a group on this system",
+                    crate::sanitize_terminal_text(group)
+                ))
+            })?),
+            None => None,
+        };
+        Ok(Self { gid })
+    }
+
+    /// Create `dir` if needed and give it the shared ownership and mode.
+    pub fn prepare_dir(&self, dir: &Path) -> Result<(), UpdaterError> {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            UpdaterError::PermissionError(format!(
+                "could not create {} in the shared store: {}; \
+                 an administrator needs to create it, owned by the shared group with mode 2775",
+                dir.display(),
+                e
+            ))
+        })?;
+        self.adjust(dir, DIR_MODE)
+    }
+
+    /// Give everything under `dir`, and `dir` itself, the shared ownership and modes.
+    pub fn share_tree(&self, dir: &Path) -> Result<(), UpdaterError> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.share_tree(&entry.path())?;
+            } else if file_type.is_file() {
+                let executable = entry.metadata()?.permissions().mode() & 0o111 != 0;
+                self.adjust(
+                    &entry.path(),
+                    if executable { EXEC_MODE } else { FILE_MODE },
+                )?;
+            }
+        }
+        self.adjust(dir, DIR_MODE)
+    }
+
+    /// Make the integrity key readable by the shared group.
+    pub fn share_key(&self, key: &Path) -> Result<(), UpdaterError> {
+        self.adjust(key, KEY_MODE)
+    }
+
+    /// Give a file the shared ownership and mode, so every user can update it.
+    pub fn share_file(&self, path: &Path) -> Result<(), UpdaterError> {
+        self.adjust(path, FILE_MODE)
+    }
+
+    fn adjust(&self, path: &Path, mode: u32) -> Result<(), UpdaterError> {
+        let metadata = std::fs::symlink_metadata(path)?;
+        if metadata.uid() != rustix::process::getuid().as_raw() {
+            return Ok(());
+        }
+        // Changing the group can clear the setgid bit, so the mode goes after it
+        let regroup = self.gid.filter(|&gid| metadata.gid() != gid);
+        if let Some(gid) = regroup {
+            rustix::fs::chown(path, None, Some(rustix::fs::Gid::from_raw(gid))).map_err(|e| {
+                UpdaterError::PermissionError(format!(
+                    "could not give {} to group {}: {}; are you a member of it?",
+                    path.display(),
+                    gid,
+                    e
+                ))
+            })?;
+        }
+        if regroup.is_some() || metadata.permissions().mode() & 0o7777 != mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    }
+}
+
+/// Fields of the first line of a passwd-style `file` that `matches`.
+fn lookup(file: &str, matches: impl Fn(&[&str]) -> bool) -> Option<Vec<String>> {
+    let content = std::fs::read_to_string(file).ok()?;
+    content
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && matches(fields))
+        .map(|fields| fields.into_iter().map(str::to_string).collect())
+}
+
+/// Numeric id of `group`, which may already be a number.
+pub fn group_id(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+    lookup("/etc/group", |fields| fields[0] == group)?[2]
+        .parse()
+        .ok()
+}
+
+/// Login name of the user running this process, recorded with each install.
+pub fn current_user() -> String {
+    let uid = rustix::process::getuid().as_raw();
+    lookup("/etc/passwd", |fields| fields[2] == uid.to_string())
+        .map(|fields| fields[0].clone())
+        .unwrap_or_else(|| format!("uid {}", uid))
+}
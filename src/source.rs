@@ -0,0 +1,192 @@
+//! Where the latest release comes from.
+//!
+//! By default it is the GitHub API. Mirrors and some upstreams publish only a
+//! static manifest instead, a `latest.json` like
+//!
+//! ```json
+//! {"version": "1.2.0", "url": "https://example.com/opencode-linux-x64.zip", "sha256": "…"}
+//! ```
+//!
+//! configured with `source = { type = "manifest", url = "…" }`. The manifest
+//! is mapped to a release with that one asset, its checksum given inline as
+//! the asset's `digest`, so updates and checks run against it unchanged.
+//! Only the latest release is known that way; commands looking up other
+//! releases, like `--changelog` or `--list-versions`, still ask GitHub.
+//! The manifest names one release, so the configured channel and any version
+//! constraint don't apply.
+
+use crate::{
+    Channel, HandlesErrorStatus, UpdaterError, VersionConstraint, encoding, network, payload,
+    sanitize_terminal_text,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ureq::Agent;
+
+/// Something the latest release can be fetched from.
+pub trait ReleaseSource {
+    /// The latest release, shaped like the GitHub API's.
+    fn latest_release(&self, client: &Agent) -> Result<serde_json::Value, UpdaterError>;
+}
+
+/// The GitHub API at `api_base`, honouring `--api-budget`.
+pub struct GitHubSource {
+    pub api_base: String,
+    /// Where releases are cached.
+    pub cache_dir: PathBuf,
+    /// Releases followed; GitHub's latest release is never a prerelease, so
+    /// the prerelease channel looks through the release list instead.
+    pub channel: Channel,
+    /// Releases allowed; with a constraint the whole release list is
+    /// searched for the newest one allowed.
+    pub constraint: Option<VersionConstraint>,
+}
+
+impl ReleaseSource for GitHubSource {
+    fn latest_release(&self, client: &Agent) -> Result<serde_json::Value, UpdaterError> {
+        if let Some(constraint) = &self.constraint {
+            let releases = crate::fetch_release_pages(client, &self.api_base)?;
+            return constraint
+                .newest(&releases, self.channel)
+                .cloned()
+                .ok_or_else(|| {
+                    UpdaterError::VersionNotFound(format!(
+                        "no release on the {} channel matches {}",
+                        self.channel, constraint
+                    ))
+                });
+        }
+        if self.channel == Channel::Stable {
+            return crate::budget::fetch_latest_release(client, &self.api_base, &self.cache_dir);
+        }
+        let releases = crate::fetch_all_releases(client, &self.api_base)?;
+        crate::channel::latest(&releases, self.channel)
+            .cloned()
+            .ok_or_else(|| {
+                UpdaterError::GitHubApiError(format!("no release on the {} channel", self.channel))
+            })
+    }
+}
+
+/// A static manifest describing the latest release.
+pub struct ManifestSource {
+    pub url: String,
+}
+
+impl ReleaseSource for ManifestSource {
+    fn latest_release(&self, client: &Agent) -> Result<serde_json::Value, UpdaterError> {
+        let request = client
+            .get(&self.url)
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .extension(HandlesErrorStatus);
+        let response = payload::send(client, request)?;
+        if response.status() != 200 {
+            return Err(UpdaterError::NetworkError(format!(
+                "manifest {} answered HTTP {}",
+                self.url,
+                response.status().as_u16()
+            )));
+        }
+        let content = encoding::read_text(response, &self.url)?;
+        network::record_download(content.len() as u64);
+        let manifest: serde_json::Value = serde_json::from_str(&content).map_err(|e| {
+            UpdaterError::InvalidManifest(format!("{} is not JSON: {}", self.url, e))
+        })?;
+        release_from_manifest(&manifest, &self.url)
+    }
+}
+
+/// `source` in the config file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SourceConfig {
+    Github,
+    Manifest { url: String },
+}
+
+impl SourceConfig {
+    /// The source this config describes; GitHub is reached at `api_base`
+    /// and followed on `channel`, within `constraint`.
+    pub fn release_source(
+        &self,
+        api_base: &str,
+        cache_dir: &Path,
+        channel: Channel,
+        constraint: Option<VersionConstraint>,
+    ) -> Box<dyn ReleaseSource> {
+        match self {
+            SourceConfig::Github => Box::new(GitHubSource {
+                api_base: api_base.to_string(),
+                cache_dir: cache_dir.to_path_buf(),
+                channel,
+                constraint,
+            }),
+            SourceConfig::Manifest { url } => Box::new(ManifestSource { url: url.clone() }),
+        }
+    }
+}
+
+/// The release described by `manifest`, read from `manifest_url`.
+///
+/// `version`, `url` and `sha256` are required; `published_at` (RFC 3339) and
+/// `notes` are taken when present.
+pub fn release_from_manifest(
+    manifest: &serde_json::Value,
+    manifest_url: &str,
+) -> Result<serde_json::Value, UpdaterError> {
+    let field = |name: &str| {
+        manifest[name]
+            .as_str()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| {
+                UpdaterError::InvalidManifest(format!(
+                    "{} has no \"{}\" string",
+                    manifest_url, name
+                ))
+            })
+    };
+    let version = field("version")?;
+    let url = field("url")?;
+    let sha256 = field("sha256")?;
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(UpdaterError::InvalidManifest(format!(
+            "{} has a \"sha256\" that is not 64 hex digits",
+            manifest_url
+        )));
+    }
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| name.ends_with(".zip") || name.ends_with(".tar.gz"))
+        .ok_or_else(|| {
+            UpdaterError::InvalidManifest(format!(
+                "{} has a \"url\" that is not a .zip or .tar.gz archive: {}",
+                manifest_url,
+                sanitize_terminal_text(url)
+            ))
+        })?;
+
+    let tag = if version.starts_with('v') {
+        version.to_string()
+    } else {
+        format!("v{}", version)
+    };
+    let mut release = serde_json::json!({
+        "tag_name": tag,
+        "name": tag,
+        "body": manifest["notes"].as_str().unwrap_or_default(),
+        "assets": [{
+            "name": name,
+            "browser_download_url": url,
+            "digest": format!("sha256:{}", sha256.to_lowercase()),
+        }],
+    });
+    if let Some(published_at) = manifest["published_at"].as_str() {
+        release["published_at"] = published_at.into();
+    }
+    Ok(release)
+}
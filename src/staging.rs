@@ -0,0 +1,188 @@
+//! Transactional saves of a version directory.
+//!
+//! A version is built under `versions/.tmp-<version>-<random>` and renamed to
+//! `versions/<version>` only once its binary, completions and metadata are
+//! written and synced, so a failure or crash part way never leaves a
+//! half-written version behind. An existing directory of the same name is
+//! moved aside to `.old-<version>-<random>` first, and put back if the new one
+//! can't take its place.
+//!
+//! Saves hold the store lock, so whoever holds it next knows any staging or
+//! moved-aside directory left over belongs to a run that crashed:
+//! [`sweep_leftovers`] removes them, or puts back a moved-aside version that
+//! never got replaced.
+
+use crate::UpdaterError;
+use serde::Serialize;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Prefix of directories a version is built in.
+const STAGING_PREFIX: &str = ".tmp-";
+
+/// Prefix of directories a replaced version is moved to.
+const REPLACED_PREFIX: &str = ".old-";
+
+/// The points a save passes through, each of which can be made to fail with
+/// the `fault-injection` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStep {
+    /// The binary is in the staging directory and executable.
+    Binary,
+    /// Completion files are copied.
+    Completions,
+    /// `release.json` and `install.json` are written.
+    Metadata,
+    /// Everything staged is flushed to disk.
+    Sync,
+    /// An existing version was moved aside and the staged one not yet renamed in.
+    Replace,
+}
+
+impl std::fmt::Display for SaveStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SaveStep::Binary => "binary",
+            SaveStep::Completions => "completions",
+            SaveStep::Metadata => "metadata",
+            SaveStep::Sync => "sync",
+            SaveStep::Replace => "replace",
+        })
+    }
+}
+
+/// Error for a failure injected after `step`.
+#[cfg(feature = "fault-injection")]
+pub fn injected_failure(step: SaveStep) -> UpdaterError {
+    UpdaterError::StorageError(format!("injected failure after the {} step", step))
+}
+
+/// Whether a versions directory entry is left over from an interrupted save.
+pub fn is_leftover(name: &str) -> bool {
+    name.starts_with(STAGING_PREFIX) || name.starts_with(REPLACED_PREFIX)
+}
+
+/// What [`sweep_leftovers`] cleared up.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeftoverSweep {
+    /// Staging and moved-aside directories removed.
+    pub removed: usize,
+    /// Versions whose moved-aside directory was put back.
+    pub restored: Vec<String>,
+}
+
+/// Clear up the leftovers of interrupted saves in `versions_dir` last
+/// modified more than `stale_after` ago; call it holding the store lock.
+///
+/// A moved-aside directory whose version no longer exists is the only copy
+/// of it, so it is renamed back rather than removed. Entries that can't be
+/// cleared are skipped.
+pub fn sweep_leftovers(versions_dir: &Path, stale_after: std::time::Duration) -> LeftoverSweep {
+    let mut sweep = LeftoverSweep::default();
+    let Ok(entries) = std::fs::read_dir(versions_dir) else {
+        return sweep;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > stale_after);
+        if !is_leftover(&name) || !stale || !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+
+        let replaced = name
+            .strip_prefix(REPLACED_PREFIX)
+            .and_then(|rest| rest.rsplit_once('-'))
+            .map(|(version, _)| version.to_string())
+            .filter(|version| {
+                !version.is_empty()
+                    && std::fs::symlink_metadata(versions_dir.join(version)).is_err()
+                    && crate::metadata::is_present(&entry.path())
+            });
+        match replaced {
+            Some(version) => {
+                if std::fs::rename(entry.path(), versions_dir.join(&version)).is_ok() {
+                    sweep.restored.push(version);
+                }
+            }
+            None => {
+                if std::fs::remove_dir_all(entry.path()).is_ok() {
+                    sweep.removed += 1;
+                }
+            }
+        }
+    }
+    sweep.restored.sort();
+    sweep
+}
+
+/// Create the directory `version` is built in; it is removed when dropped.
+pub fn staging_dir(versions_dir: &Path, version: &str) -> std::io::Result<tempfile::TempDir> {
+    tempfile::Builder::new()
+        .prefix(&format!("{}{}-", STAGING_PREFIX, version))
+        .tempdir_in(versions_dir)
+}
+
+/// Flush every file and directory under `dir`, and `dir` itself, to disk.
+pub fn sync_tree(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            sync_tree(&entry.path())?;
+        } else {
+            std::fs::File::open(entry.path())?.sync_all()?;
+        }
+    }
+    std::fs::File::open(dir)?.sync_all()
+}
+
+/// Rename the staged directory to `dest`, replacing any directory already there.
+///
+/// `fail_at` is consulted between moving the old directory aside and renaming
+/// the new one in. Whatever fails, `dest` ends up holding either the old
+/// directory or the new one, and nothing else is left in its parent.
+pub fn commit(
+    staged: tempfile::TempDir,
+    dest: &Path,
+    fail_at: impl Fn(SaveStep) -> Result<(), UpdaterError>,
+) -> Result<(), UpdaterError> {
+    let parent = dest.parent().unwrap_or(Path::new("."));
+
+    if std::fs::symlink_metadata(dest).is_err() {
+        std::fs::rename(staged.path(), dest)?;
+    } else {
+        let name = dest.file_name().unwrap_or_default().to_string_lossy();
+        // Renaming a directory over an empty one is allowed, and dropping
+        // this removes the old version once the new one is in place
+        let replaced = tempfile::Builder::new()
+            .prefix(&format!("{}{}-", REPLACED_PREFIX, name))
+            .tempdir_in(parent)?;
+        std::fs::rename(dest, replaced.path())?;
+
+        let renamed =
+            fail_at(SaveStep::Replace).and_then(|()| Ok(std::fs::rename(staged.path(), dest)?));
+        if let Err(e) = renamed {
+            if let Err(restore) = std::fs::rename(replaced.path(), dest) {
+                // Keep the only remaining copy of the old version
+                let kept = replaced.keep();
+                return Err(UpdaterError::StorageError(format!(
+                    "{}; the previous {} could not be restored from {}: {}",
+                    e,
+                    dest.display(),
+                    kept.display(),
+                    restore
+                )));
+            }
+            return Err(e);
+        }
+    }
+
+    // Dropping `staged` now finds nothing left to remove
+    std::fs::File::open(parent)?.sync_all()?;
+    Ok(())
+}
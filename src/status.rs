@@ -0,0 +1,127 @@
+//! `status.json` in the storage directory: what past runs learned that later
+//! commands and other tools may want, currently the GitHub API rate limit,
+//! an update waiting for `resume-install` and when each warning was last shown.
+//!
+//! The file is rewritten atomically and its directory synced, so readers
+//! never see it half-written, and every rewrite bumps `revision`, so a reader
+//! polling it can tell a new document from one it has seen. Some network
+//! filesystems still briefly show an empty file around the rename, so
+//! [`load`] retries a document that doesn't parse once.
+
+use crate::UpdaterError;
+use crate::network::RateLimit;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Contents of `status.json`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Format of the file, see [`crate::schema`].
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Incremented by every rewrite.
+    #[serde(default)]
+    pub revision: u64,
+    /// Rate limit reported by the last GitHub API response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// When `rate_limit` was reported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_seen_at: Option<DateTime<Utc>>,
+    /// An update that was downloaded and stored, but not installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_install: Option<PendingInstall>,
+    /// When each warning key was last shown, see [`crate::reporter`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub warnings_shown: BTreeMap<String, DateTime<Utc>>,
+}
+
+/// An update a run without a terminal stored but couldn't install, because
+/// installing needed a sudo password.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct PendingInstall {
+    /// The stored version to install.
+    pub version: String,
+    /// Names of the install targets the run was updating.
+    pub targets: Vec<String>,
+    pub staged_at: DateTime<Utc>,
+}
+
+impl std::fmt::Display for PendingInstall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "update {} staged, privileged install required — run `opencode-updater resume-install`",
+            self.version
+        )
+    }
+}
+
+/// How long to wait before reading a status file that didn't parse again.
+pub const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Load the status file; a missing or unreadable file is an empty status.
+pub fn load(path: &Path) -> Status {
+    let parse =
+        || -> Option<Status> { serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok() };
+    if !path.exists() {
+        return Status::default();
+    }
+    parse()
+        .or_else(|| {
+            std::thread::sleep(RETRY_DELAY);
+            parse()
+        })
+        .unwrap_or_default()
+}
+
+/// Change the status file with `f`, keeping the rest of it.
+fn update(path: &Path, f: impl FnOnce(&mut Status)) -> Result<(), UpdaterError> {
+    let mut status = load(path);
+    f(&mut status);
+    status.schema_version = crate::schema::SCHEMA_VERSION;
+    status.revision += 1;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    crate::write_atomic(path, serde_json::to_string_pretty(&status)?.as_bytes())?;
+    Ok(())
+}
+
+/// Record the latest rate limit, keeping the rest of the file.
+pub fn record_rate_limit(path: &Path, rate_limit: RateLimit) -> Result<(), UpdaterError> {
+    update(path, |status| {
+        status.rate_limit = Some(rate_limit);
+        status.rate_limit_seen_at = Some(Utc::now());
+    })
+}
+
+/// Record an update waiting for `resume-install`, replacing any earlier one.
+pub fn record_pending_install(path: &Path, pending: &PendingInstall) -> Result<(), UpdaterError> {
+    update(path, |status| {
+        status.pending_install = Some(pending.clone())
+    })
+}
+
+/// Record when warnings were shown, keeping keys not shown this time.
+pub fn record_warnings_shown(
+    path: &Path,
+    shown: &BTreeMap<String, DateTime<Utc>>,
+) -> Result<(), UpdaterError> {
+    if shown.is_empty() {
+        return Ok(());
+    }
+    update(path, |status| {
+        status
+            .warnings_shown
+            .extend(shown.iter().map(|(key, at)| (key.clone(), *at)))
+    })
+}
+
+/// Forget the update waiting for `resume-install`.
+pub fn clear_pending_install(path: &Path) -> Result<(), UpdaterError> {
+    update(path, |status| status.pending_install = None)
+}
@@ -0,0 +1,209 @@
+//! `--format` templates for scriptable, one-line-per-item output.
+//!
+//! A template is plain text with `{placeholder}` fields. `{{` and `}}` are
+//! literal braces, and `\t`, `\n` and `\\` are escapes, so a template passed in
+//! single quotes from a shell can still produce tab-separated columns.
+
+use crate::{HistoryEntry, UpdaterError, VersionInfo, sanitize_terminal_text};
+
+/// Placeholders available for installed versions.
+pub const INSTALLED_PLACEHOLDERS: &[&str] = &[
+    "version",
+    "tag",
+    "installed_at",
+    "release_date",
+    "size",
+    "current",
+];
+
+/// Placeholders available for releases published upstream.
+pub const RELEASE_PLACEHOLDERS: &[&str] =
+    &["tag", "version", "published_at", "prerelease", "installed"];
+
+/// Placeholders available for history entries.
+pub const HISTORY_PLACEHOLDERS: &[&str] = &[
+    "started_at",
+    "outcome",
+    "from_version",
+    "to_version",
+    "size",
+    "error_kind",
+    "total_seconds",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+/// A parsed `--format` template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse a template, rejecting placeholders not in `valid`.
+    pub fn parse(text: &str, valid: &[&str]) -> Result<Self, UpdaterError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(UpdaterError::Other(format!(
+                                    "Unclosed placeholder '{{{}' in format (use '{{{{' for a literal brace)",
+                                    name
+                                )));
+                            }
+                        }
+                    }
+                    if !valid.contains(&name.as_str()) {
+                        return Err(UpdaterError::Other(format!(
+                            "Unknown placeholder {{{}}}; valid placeholders: {}",
+                            name,
+                            valid
+                                .iter()
+                                .map(|name| format!("{{{}}}", name))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name));
+                }
+                '}' => {
+                    return Err(UpdaterError::Other(
+                        "Unmatched '}' in format (use '}}' for a literal brace)".to_string(),
+                    ));
+                }
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Render one line, looking up each placeholder's value with `value`.
+    ///
+    /// Values are stripped of control characters, tabs and newlines so each
+    /// item stays on one line with its columns intact.
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(text) => text.clone(),
+                Part::Field(name) => {
+                    sanitize_terminal_text(&value(name)).replace(['\t', '\n'], " ")
+                }
+            })
+            .collect()
+    }
+}
+
+fn yes_no(flag: bool) -> String {
+    if flag { "yes" } else { "no" }.to_string()
+}
+
+/// Render installed versions, one line each.
+pub fn render_installed(
+    template: &Template,
+    installed: &[VersionInfo],
+    current: Option<&VersionInfo>,
+) -> Vec<String> {
+    installed
+        .iter()
+        .map(|info| {
+            template.render(|name| match name {
+                "version" => info.version.clone(),
+                "tag" => info.tag_name.clone(),
+                "installed_at" => info.installed_at.to_rfc3339(),
+                "release_date" => info.release_date.to_rfc3339(),
+                "size" => info.asset_size.map(|s| s.to_string()).unwrap_or_default(),
+                "current" => yes_no(current.is_some_and(|c| c.version == info.version)),
+                _ => String::new(),
+            })
+        })
+        .collect()
+}
+
+/// Render releases from the GitHub API, one line each.
+pub fn render_releases(
+    template: &Template,
+    releases: &[serde_json::Value],
+    installed: &[VersionInfo],
+) -> Vec<String> {
+    releases
+        .iter()
+        .map(|release| {
+            let tag = release["tag_name"].as_str().unwrap_or_default();
+            template.render(|name| match name {
+                "tag" => tag.to_string(),
+                "version" => tag.trim_start_matches('v').to_string(),
+                "published_at" => release["published_at"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                "prerelease" => yes_no(release["prerelease"].as_bool().unwrap_or(false)),
+                "installed" => yes_no(installed.iter().any(|info| info.tag_name == tag)),
+                _ => String::new(),
+            })
+        })
+        .collect()
+}
+
+/// Render history entries, one line each.
+pub fn render_history(template: &Template, entries: &[HistoryEntry]) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| {
+            template.render(|name| match name {
+                "started_at" => entry.started_at.to_rfc3339(),
+                "outcome" => match entry.outcome {
+                    crate::Outcome::Success => "success".to_string(),
+                    crate::Outcome::Failure => "failure".to_string(),
+                    crate::Outcome::Staged => "staged".to_string(),
+                },
+                "from_version" => entry.from_version.clone().unwrap_or_default(),
+                "to_version" => entry.to_version.clone().unwrap_or_default(),
+                "size" => entry.asset_size.map(|s| s.to_string()).unwrap_or_default(),
+                "error_kind" => entry.error_kind.clone().unwrap_or_default(),
+                "total_seconds" => entry
+                    .timings
+                    .map(|timings| format!("{:.3}", timings.total()))
+                    .unwrap_or_default(),
+                _ => String::new(),
+            })
+        })
+        .collect()
+}
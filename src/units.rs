@@ -0,0 +1,120 @@
+//! Formatting of byte sizes and counts shown to the user.
+//!
+//! Every size goes through [`format_size`] so the same quantity reads the
+//! same everywhere: binary units (KiB, MiB, GiB) by default, or decimal ones
+//! (kB, MB, GB) with `--si`. Counts get thousands separators following the
+//! locale in `LC_ALL`, `LC_NUMERIC` or `LANG`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Base used to scale byte sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeUnits {
+    /// Powers of 1024: KiB, MiB, GiB.
+    #[default]
+    Binary,
+    /// Powers of 1000: kB, MB, GB (`--si`).
+    Decimal,
+}
+
+impl SizeUnits {
+    fn base(self) -> f64 {
+        match self {
+            SizeUnits::Binary => 1024.0,
+            SizeUnits::Decimal => 1000.0,
+        }
+    }
+
+    fn suffixes(self) -> [&'static str; 4] {
+        match self {
+            SizeUnits::Binary => ["KiB", "MiB", "GiB", "TiB"],
+            SizeUnits::Decimal => ["kB", "MB", "GB", "TB"],
+        }
+    }
+}
+
+static DECIMAL: AtomicBool = AtomicBool::new(false);
+
+/// Select the units used by [`format_size`] for the rest of the process.
+pub fn set_units(units: SizeUnits) {
+    DECIMAL.store(units == SizeUnits::Decimal, Ordering::Relaxed);
+}
+
+/// Units selected with [`set_units`].
+pub fn units() -> SizeUnits {
+    if DECIMAL.load(Ordering::Relaxed) {
+        SizeUnits::Decimal
+    } else {
+        SizeUnits::Binary
+    }
+}
+
+/// A byte size in the selected units, e.g. `45.2 MiB` or `512 B`.
+pub fn format_size(bytes: u64) -> String {
+    format_size_in(bytes, units())
+}
+
+/// A byte size in the given units.
+pub fn format_size_in(bytes: u64, units: SizeUnits) -> String {
+    let base = units.base();
+    if (bytes as f64) < base {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut suffix = "B";
+    for next in units.suffixes() {
+        if value < base {
+            break;
+        }
+        value /= base;
+        suffix = next;
+    }
+    format!("{:.1} {}", value, suffix)
+}
+
+/// A signed size difference, e.g. `+1.5 MiB` or `-512 B`.
+pub fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_size(delta.unsigned_abs()))
+}
+
+/// A transfer rate, e.g. `3.0 MiB/s`.
+pub fn format_rate(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_size(bytes_per_sec.max(0.0) as u64))
+}
+
+/// Thousands separator for a locale name such as `de_DE.UTF-8`.
+pub fn thousands_separator(locale: &str) -> char {
+    let language = locale.split(['_', '.', '@']).next().unwrap_or("");
+    match language {
+        "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el" => '.',
+        "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "nb" | "nn" | "fi" | "uk" | "hu" => ' ',
+        _ => ',',
+    }
+}
+
+fn locale_separator() -> char {
+    ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map_or(',', |locale| thousands_separator(&locale))
+}
+
+/// A count with thousands separators for the current locale, e.g. `4,987`.
+pub fn format_count(count: u64) -> String {
+    format_count_with(count, locale_separator())
+}
+
+/// A count grouped in thousands with `separator`.
+pub fn format_count_with(count: u64, separator: char) -> String {
+    let digits = count.to_string();
+    let mut output = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            output.push(separator);
+        }
+        output.push(digit);
+    }
+    output
+}
@@ -0,0 +1,220 @@
+//! Release versions, parsed: `major.minor.patch` with an optional
+//! prerelease suffix such as `rc.1`.
+//!
+//! Versions are stored and shown as strings, as their releases tag them;
+//! [`Version`] is what they are compared and sorted by. A leading `v` is
+//! accepted and dropped, so `v1.2.3` and `1.2.3` are the same version.
+//!
+//! Some old tags have two components, like `v0.1`, and some builds four,
+//! like `0.4.1.2`. A missing patch is zero, so `0.1` is `0.1.0`; components
+//! after the patch are compared after it, so `0.4.1.2` is newer than
+//! `0.4.1`, which is the same as `0.4.1.0`.
+//!
+//! Components are plain decimal numbers; leading zeros are dropped, so
+//! `001.2.3` is `1.2.3`. Build metadata after a `+`, as in `1.2.3+build5`,
+//! is kept and shown but, as semver has it, plays no part in comparing.
+
+use crate::UpdaterError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// A `major.minor.patch` version, with a prerelease suffix such as `rc.1`
+/// when it has one.
+///
+/// Ordered as semver orders them: a prerelease comes before the release it
+/// leads up to, so `0.4.0-rc.1 < 0.4.0`, and build metadata is ignored, so
+/// `1.2.3+build5 == 1.2.3`. Serialized as its string form.
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Components after the patch, such as the `2` of `0.4.1.2`, without
+    /// trailing zeros.
+    pub extra: Vec<u64>,
+    /// What follows the `-`, such as `alpha.1`.
+    pub prerelease: Option<String>,
+    /// What follows the `+`, such as `build5`.
+    pub build: Option<String>,
+}
+
+impl Version {
+    /// The numeric part, `(major, minor, patch)`.
+    pub fn triple(&self) -> (u64, u64, u64) {
+        (self.major, self.minor, self.patch)
+    }
+
+    /// Whether this is a prerelease, such as `1.0.0-rc.1`.
+    pub fn is_prerelease(&self) -> bool {
+        self.prerelease.is_some()
+    }
+}
+
+impl FromStr for Version {
+    type Err = UpdaterError;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let clean = version.trim_start_matches('v');
+        let invalid = |reason: String| {
+            UpdaterError::InvalidVersionFormat(format!("{} ({})", version, reason))
+        };
+        let (clean, build) = match clean.split_once('+') {
+            Some((clean, build)) if valid_identifiers(build, true) => {
+                (clean, Some(build.to_string()))
+            }
+            Some((_, build)) => {
+                return Err(invalid(format!("build metadata '{}' is malformed", build)));
+            }
+            None => (clean, None),
+        };
+        let (numbers, prerelease) = match clean.split_once('-') {
+            Some((numbers, suffix)) if valid_identifiers(suffix, false) => {
+                (numbers, Some(suffix.to_string()))
+            }
+            Some((_, suffix)) => {
+                return Err(invalid(format!("prerelease '{}' is malformed", suffix)));
+            }
+            None => (clean, None),
+        };
+        let mut parts = numbers
+            .split('.')
+            .map(|part| parse_component(part).map_err(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if parts.len() < 2 {
+            return Err(invalid("it needs at least major.minor".to_string()));
+        }
+        parts.resize(parts.len().max(3), 0);
+        let mut extra = parts.split_off(3);
+        while extra.last() == Some(&0) {
+            extra.pop();
+        }
+
+        Ok(Self {
+            major: parts[0],
+            minor: parts[1],
+            patch: parts[2],
+            extra,
+            prerelease,
+            build,
+        })
+    }
+}
+
+/// One numeric component: decimal digits only, no sign, fitting a `u64`.
+fn parse_component(part: &str) -> Result<u64, String> {
+    if part.is_empty() {
+        return Err("empty component".to_string());
+    }
+    if !part.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("'{}' is not a number", part));
+    }
+    part.parse().map_err(|_| format!("'{}' is too large", part))
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        for part in &self.extra {
+            write!(f, ".{}", part)?;
+        }
+        if let Some(prerelease) = &self.prerelease {
+            write!(f, "-{}", prerelease)?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{}", build)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.triple()
+            .cmp(&other.triple())
+            .then_with(|| self.extra.cmp(&other.extra))
+            .then_with(|| match (&self.prerelease, &other.prerelease) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_prereleases(a, b),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Version {}
+
+impl std::hash::Hash for Version {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.triple().hash(state);
+        self.extra.hash(state);
+        self.prerelease.hash(state);
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Semver precedence of two prerelease suffixes: identifier by identifier,
+/// numbers numerically and below words, and a shorter list first when one is
+/// a prefix of the other.
+fn compare_prereleases(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let order = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+        if order.is_ne() {
+            return order;
+        }
+    }
+}
+
+/// Whether `suffix` is a semver prerelease or, with `build`, build metadata:
+/// dot-separated identifiers of ASCII letters, digits and hyphens. Numeric
+/// prerelease identifiers can't have leading zeros; build ones can.
+fn valid_identifiers(suffix: &str, build: bool) -> bool {
+    suffix.split('.').all(|identifier| {
+        !identifier.is_empty()
+            && identifier
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !(!build
+                && identifier.len() > 1
+                && identifier.starts_with('0')
+                && identifier.chars().all(|c| c.is_ascii_digit()))
+    })
+}
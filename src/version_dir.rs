@@ -0,0 +1,72 @@
+//! Names of version directories.
+//!
+//! On a case-insensitive filesystem, the default on macOS, `v0.6.0-RC1` and
+//! `v0.6.0-rc1` would be one directory, and saving the second would replace
+//! the first. A version with uppercase letters is therefore stored under its
+//! lowercase form plus a short hash of the version itself, which no other
+//! version shares. Versions already lowercase, nearly all of them, keep their
+//! own name. The version in a directory's metadata is the source of truth:
+//! lookups go through it rather than assuming the directory name is the
+//! version. Versions saved before the scheme are still found under their own
+//! name.
+//!
+//! Versions come from release tags, which the updater doesn't control, so
+//! one that could name a path outside the versions directory, such as
+//! `../../etc`, is refused by [`validate`] before it is stored or rolled
+//! back to.
+
+use crate::{UpdaterError, calculate_sha256, sanitize_terminal_text};
+
+/// Hex digits of the hash suffix.
+const HASH_LEN: usize = 8;
+
+/// Checks that `version` can name a directory of its own: not empty, `.`
+/// or containing `..`, and without path separators or control characters.
+pub fn validate(version: &str) -> Result<(), UpdaterError> {
+    let reason = if version.is_empty() || version == "." {
+        "not a directory name"
+    } else if version.contains("..") {
+        "contains '..'"
+    } else if version.contains(['/', '\\']) {
+        "contains a path separator"
+    } else if version.chars().any(char::is_control) {
+        "contains control characters"
+    } else {
+        return Ok(());
+    };
+    Err(UpdaterError::InvalidVersionFormat(format!(
+        "{} ({})",
+        sanitize_terminal_text(version),
+        reason
+    )))
+}
+
+/// Directory name `version` is saved under.
+pub fn dir_name(version: &str) -> String {
+    let lower = version.to_lowercase();
+    if lower == version {
+        return lower;
+    }
+    format!(
+        "{}~{}",
+        lower,
+        &calculate_sha256(version.as_bytes())[..HASH_LEN]
+    )
+}
+
+/// Names `version` may be stored under: its own directory name, then the
+/// version itself for versions saved before [`dir_name`].
+pub fn candidates(version: &str) -> Vec<String> {
+    let name = dir_name(version);
+    if name == version {
+        return vec![name];
+    }
+    vec![name, version.to_string()]
+}
+
+/// Whether a directory called `name` may hold `version`.
+pub fn names(name: &str, version: &str) -> bool {
+    candidates(version)
+        .iter()
+        .any(|candidate| candidate == name)
+}
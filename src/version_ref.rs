@@ -0,0 +1,166 @@
+//! Version arguments: an exact version or a keyword resolved on demand.
+//!
+//! Every option that takes a version accepts `latest`, `installed` (or
+//! `current`) and `previous` as well. Keywords are resolved against a
+//! [`VersionSource`], so resolution can be tested without the network or a
+//! real storage directory.
+
+use crate::{UpdaterError, VersionInfo, VersionManager};
+use std::str::FromStr;
+use ureq::Agent;
+
+/// A version as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionRef {
+    /// The newest release on GitHub, or the newest stored version when
+    /// GitHub can't be asked.
+    Latest,
+    /// The active version (`installed` or `current`).
+    Installed,
+    /// The stored version installed most recently before the active one.
+    Previous,
+    /// A specific version or tag, e.g. `1.0.72` or `v1.0.72`.
+    Exact(String),
+}
+
+impl FromStr for VersionRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err("Version must not be empty".to_string()),
+            "latest" => Ok(Self::Latest),
+            "installed" | "current" => Ok(Self::Installed),
+            "previous" => Ok(Self::Previous),
+            exact => Ok(Self::Exact(exact.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for VersionRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Latest => write!(f, "latest"),
+            Self::Installed => write!(f, "installed"),
+            Self::Previous => write!(f, "previous"),
+            Self::Exact(version) => write!(f, "{}", version),
+        }
+    }
+}
+
+/// A version with the release tag it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedVersion {
+    /// Version as stored, e.g. `1.0.72`.
+    pub version: String,
+    /// Release tag, e.g. `v1.0.72`.
+    pub tag: String,
+}
+
+impl ResolvedVersion {
+    fn from_info(info: &VersionInfo) -> Self {
+        Self {
+            version: info.version.clone(),
+            tag: info.tag_name.clone(),
+        }
+    }
+
+    fn from_tag(tag: &str) -> Self {
+        Self {
+            version: tag.trim_start_matches('v').to_string(),
+            tag: tag.to_string(),
+        }
+    }
+}
+
+/// Where keywords are looked up.
+pub trait VersionSource {
+    /// Tag of the newest release.
+    fn latest_tag(&self) -> Result<String, UpdaterError>;
+    /// The active version, if any.
+    fn installed(&self) -> Result<Option<VersionInfo>, UpdaterError>;
+    /// Stored versions, newest install first.
+    fn stored(&self) -> Result<Vec<VersionInfo>, UpdaterError>;
+}
+
+impl VersionRef {
+    /// Resolve to a concrete version, explaining when a keyword has no answer.
+    pub fn resolve(&self, source: &dyn VersionSource) -> Result<ResolvedVersion, UpdaterError> {
+        match self {
+            Self::Latest => match source.latest_tag() {
+                Ok(tag) => Ok(ResolvedVersion::from_tag(&tag)),
+                // Offline, the newest stored version is the latest one known
+                Err(e) => source
+                    .stored()?
+                    .iter()
+                    .max_by(|a, b| {
+                        a.parsed()
+                            .cmp(&b.parsed())
+                            .then_with(|| a.installed_at.cmp(&b.installed_at))
+                    })
+                    .map(ResolvedVersion::from_info)
+                    .ok_or_else(|| {
+                        UpdaterError::Other(format!(
+                            "`latest` can't be resolved: {}, and no version is stored",
+                            e
+                        ))
+                    }),
+            },
+            Self::Installed => source
+                .installed()?
+                .map(|info| ResolvedVersion::from_info(&info))
+                .ok_or_else(|| {
+                    UpdaterError::Other(
+                        "`installed` can't be resolved: opencode is not installed".to_string(),
+                    )
+                }),
+            Self::Previous => {
+                let current = source.installed()?.map(|info| info.version);
+                source
+                    .stored()?
+                    .iter()
+                    .find(|info| Some(&info.version) != current.as_ref())
+                    .map(ResolvedVersion::from_info)
+                    .ok_or_else(|| {
+                        UpdaterError::Other(
+                            "`previous` can't be resolved: no other version is stored".to_string(),
+                        )
+                    })
+            }
+            Self::Exact(version) if version.starts_with('v') => {
+                Ok(ResolvedVersion::from_tag(version))
+            }
+            Self::Exact(version) => Ok(ResolvedVersion {
+                version: version.clone(),
+                tag: format!("v{}", version),
+            }),
+        }
+    }
+}
+
+/// Resolves keywords against storage and the GitHub API.
+pub struct LiveVersions<'a> {
+    pub version_manager: &'a VersionManager,
+    pub client: &'a Agent,
+    pub base_url: &'a str,
+}
+
+impl VersionSource for LiveVersions<'_> {
+    fn latest_tag(&self) -> Result<String, UpdaterError> {
+        let release = crate::fetch_release(self.client, self.base_url)?;
+        Ok(release["tag_name"]
+            .as_str()
+            .ok_or_else(|| {
+                UpdaterError::InvalidManifest("Latest release has no tag_name".to_string())
+            })?
+            .to_string())
+    }
+
+    fn installed(&self) -> Result<Option<VersionInfo>, UpdaterError> {
+        self.version_manager.get_current_version()
+    }
+
+    fn stored(&self) -> Result<Vec<VersionInfo>, UpdaterError> {
+        self.version_manager.list_installed_versions()
+    }
+}